@@ -0,0 +1,197 @@
+//! Throughput of the sharded server, compared to a baseline server built
+//! before the key space was sharded, which locked it with a single mutex.
+//!
+//! For each number of runtime worker threads, each server binary is started
+//! with that many threads, and as many clients as threads times
+//! `CLIENTS_PER_THREAD` send `SET` and `GET` commands on random keys for
+//! `DURATION`. The number of commands per second is reported.
+//!
+//! The baseline is given by the `MINI_REDIS_BASELINE` environment variable,
+//! the path to a `mini-redis-server` binary built from the commit preceding
+//! sharding. For instance, from the repository:
+//!
+//! ```text
+//! git worktree add /tmp/baseline <commit>^
+//! cargo build --release --bin mini-redis-server \
+//!     --manifest-path /tmp/baseline/rust/mini-redis/Cargo.toml
+//! MINI_REDIS_BASELINE=/tmp/baseline/rust/mini-redis/target/release/mini-redis-server \
+//!     cargo bench --bench sharding
+//! ```
+//!
+//! Without it, only the sharded server is measured.
+//!
+//! Sharding can only pay off when several worker threads run on different
+//! cores at the same time. With a single core, the threads never contend for
+//! the lock, and the benchmark only measures the overhead of sharding, about
+//! 10% with one worker thread. Whether throughput scales with the number of
+//! cores must be checked on a multi-core machine.
+
+use mini_redis::clients::Client;
+
+use rand::Rng;
+use std::env;
+use std::net::{SocketAddr, TcpListener};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::runtime::{self, Runtime};
+
+/// How long the clients send commands to each server.
+const DURATION: Duration = Duration::from_secs(2);
+
+/// Number of client connections per worker thread of the server.
+const CLIENTS_PER_THREAD: usize = 4;
+
+/// Number of distinct keys the clients use.
+const KEYS: usize = 10_000;
+
+fn main() {
+    let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+    let sharded = env!("CARGO_BIN_EXE_mini-redis-server");
+    let baseline = env::var("MINI_REDIS_BASELINE").ok();
+
+    println!("{} cores available", cores);
+    if baseline.is_none() {
+        println!("MINI_REDIS_BASELINE is not set, only the sharded server is measured");
+    }
+    println!(
+        "{:>8} {:>14} {:>14} {:>8}",
+        "threads", "single mutex", "sharded", "speedup"
+    );
+
+    let mut threads = 1;
+    while threads <= cores.max(4) {
+        let sharded = run(sharded, threads);
+
+        match &baseline {
+            Some(baseline) => {
+                let single = run(baseline, threads);
+                println!(
+                    "{:>8} {:>10.0} op/s {:>10.0} op/s {:>7.2}x",
+                    threads,
+                    single,
+                    sharded,
+                    sharded / single
+                );
+            }
+            None => println!("{:>8} {:>14} {:>10.0} op/s", threads, "-", sharded),
+        }
+
+        threads *= 2;
+    }
+}
+
+/// Returns the number of commands per second served by the server binary at
+/// `path`, running on `threads` worker threads.
+fn run(path: &str, threads: usize) -> f64 {
+    let server = Server::start(path, threads);
+    let clients = runtime(threads);
+    let addr = server.addr;
+    let ops = Arc::new(AtomicU64::new(0));
+
+    clients.block_on(async {
+        // Fill the key space first, so `GET` finds values.
+        let mut client = connect(addr).await;
+        for i in 0..KEYS {
+            client.set(&key(i), "value".into()).await.unwrap();
+        }
+
+        let start = Instant::now();
+        let deadline = start + DURATION;
+
+        let tasks: Vec<_> = (0..threads * CLIENTS_PER_THREAD)
+            .map(|_| tokio::spawn(send_commands(addr, deadline, ops.clone())))
+            .collect();
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        ops.load(Ordering::Relaxed) as f64 / start.elapsed().as_secs_f64()
+    })
+}
+
+/// Send `SET` and `GET` commands on random keys until `deadline`, counting
+/// them in `ops`.
+async fn send_commands(addr: SocketAddr, deadline: Instant, ops: Arc<AtomicU64>) {
+    let mut client = Client::connect(addr).await.unwrap();
+    let mut count = 0;
+
+    while Instant::now() < deadline {
+        let key = key(rand::thread_rng().gen_range(0..KEYS));
+
+        client.set(&key, "value".into()).await.unwrap();
+        client.get(&key).await.unwrap();
+        count += 2;
+    }
+
+    ops.fetch_add(count, Ordering::Relaxed);
+}
+
+/// Connect to the server at `addr` once it listens.
+async fn connect(addr: SocketAddr) -> Client {
+    for _ in 0..500 {
+        if let Ok(client) = Client::connect(addr).await {
+            return client;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    panic!("the server is not listening on {}", addr);
+}
+
+fn key(i: usize) -> String {
+    format!("key:{}", i)
+}
+
+fn runtime(threads: usize) -> Runtime {
+    runtime::Builder::new_multi_thread()
+        .worker_threads(threads)
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+/// Server process, killed when dropped.
+struct Server {
+    process: Child,
+    addr: SocketAddr,
+}
+
+impl Server {
+    /// Start the server binary at `path` on a free port, with `threads`
+    /// worker threads.
+    ///
+    /// The server runs in the temporary directory, so it does not load any
+    /// snapshot from the working directory.
+    fn start(path: &str, threads: usize) -> Server {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let process = Command::new(path)
+            .args(["--port", &port.to_string()])
+            .env("TOKIO_WORKER_THREADS", threads.to_string())
+            .current_dir(env::temp_dir())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap_or_else(|err| panic!("failed to start {}: {}", path, err));
+
+        Server {
+            process,
+            addr: SocketAddr::from(([127, 0, 0, 1], port)),
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
@@ -1,10 +1,11 @@
-use mini_redis::{clients::Client, DEFAULT_PORT};
+use mini_redis::{clients::Client, cmd::ExpireCondition, tls, DEFAULT_PORT};
 
 use bytes::Bytes;
 use clap::{Parser, Subcommand};
 use std::num::ParseIntError;
+use std::path::PathBuf;
 use std::str;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -22,6 +23,37 @@ struct Cli {
 
     #[arg(long, default_value_t = DEFAULT_PORT)]
     port: u16,
+
+    /// Unix socket to connect to, instead of the hostname and port
+    #[arg(short = 's', long, conflicts_with = "tls")]
+    socket: Option<PathBuf>,
+
+    /// Number of the database to issue the command against
+    #[arg(short = 'n', long, default_value_t = 0)]
+    db: usize,
+
+    /// Connect using TLS
+    #[arg(long, requires = "cacert")]
+    tls: bool,
+
+    /// Name the certificate of the server must be valid for [default: the
+    /// hostname]
+    #[arg(long)]
+    sni: Option<String>,
+
+    /// PEM file of the certificate authorities trusted to issue the
+    /// certificate of the server
+    #[arg(long)]
+    cacert: Option<PathBuf>,
+
+    /// PEM file of the certificate presented to servers verifying their
+    /// clients
+    #[arg(long, requires = "key")]
+    cert: Option<PathBuf>,
+
+    /// PEM file of the private key of the client certificate
+    #[arg(long, requires = "cert")]
+    key: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -47,6 +79,186 @@ enum Command {
         #[arg(value_parser = duration_from_ms_str)]
         expires: Option<Duration>,
     },
+    /// Set a timeout on key, in seconds.
+    Expire {
+        /// Name of key to expire
+        key: String,
+
+        /// Number of seconds after which the key is deleted
+        seconds: u64,
+
+        /// Only set the timeout if the condition holds: nx, xx, gt or lt
+        condition: Option<ExpireCondition>,
+    },
+    /// Set a timeout on key, in milliseconds.
+    Pexpire {
+        /// Name of key to expire
+        key: String,
+
+        /// Number of milliseconds after which the key is deleted
+        #[arg(value_parser = duration_from_ms_str)]
+        milliseconds: Duration,
+
+        /// Only set the timeout if the condition holds: nx, xx, gt or lt
+        condition: Option<ExpireCondition>,
+    },
+    /// Set key to expire at a Unix time, in seconds.
+    Expireat {
+        /// Name of key to expire
+        key: String,
+
+        /// Unix time at which the key is deleted
+        timestamp: u64,
+
+        /// Only set the timeout if the condition holds: nx, xx, gt or lt
+        condition: Option<ExpireCondition>,
+    },
+    /// Set key to expire at a Unix time, in milliseconds.
+    Pexpireat {
+        /// Name of key to expire
+        key: String,
+
+        /// Unix time at which the key is deleted, in milliseconds
+        #[arg(value_parser = duration_from_ms_str)]
+        timestamp: Duration,
+
+        /// Only set the timeout if the condition holds: nx, xx, gt or lt
+        condition: Option<ExpireCondition>,
+    },
+    /// Get the time to live of key, in seconds.
+    Ttl {
+        /// Name of key
+        key: String,
+    },
+    /// Get the time to live of key, in milliseconds.
+    Pttl {
+        /// Name of key
+        key: String,
+    },
+    /// Remove the timeout of key.
+    Persist {
+        /// Name of key
+        key: String,
+    },
+    /// Increment the integer stored at key by one.
+    Incr {
+        /// Name of key to increment
+        key: String,
+    },
+    /// Decrement the integer stored at key by one.
+    Decr {
+        /// Name of key to decrement
+        key: String,
+    },
+    /// Increment the integer stored at key by increment.
+    Incrby {
+        /// Name of key to increment
+        key: String,
+
+        /// Amount to add, which may be negative
+        #[arg(allow_hyphen_values = true)]
+        increment: i64,
+    },
+    /// Decrement the integer stored at key by decrement.
+    Decrby {
+        /// Name of key to decrement
+        key: String,
+
+        /// Amount to subtract, which may be negative
+        #[arg(allow_hyphen_values = true)]
+        decrement: i64,
+    },
+    /// Increment the floating point number stored at key by increment.
+    Incrbyfloat {
+        /// Name of key to increment
+        key: String,
+
+        /// Amount to add, which may be negative
+        #[arg(allow_hyphen_values = true)]
+        increment: f64,
+    },
+    /// Append a value to the string stored at key.
+    Append {
+        /// Name of key to append to
+        key: String,
+
+        /// Value to append
+        value: Bytes,
+    },
+    /// Get the length of the string stored at key.
+    Strlen {
+        /// Name of key
+        key: String,
+    },
+    /// Remove one or more keys.
+    Del {
+        /// Names of keys to remove
+        #[arg(required = true)]
+        keys: Vec<String>,
+    },
+    /// Count how many of the given keys exist.
+    Exists {
+        /// Names of keys
+        #[arg(required = true)]
+        keys: Vec<String>,
+    },
+    /// List the keys matching a glob-style pattern.
+    Keys {
+        /// Pattern the keys must match
+        pattern: String,
+    },
+    /// Iterate over the keys, starting at cursor.
+    Scan {
+        /// Cursor returned by the previous call, 0 to start
+        cursor: u64,
+
+        /// Only return keys matching this glob-style pattern
+        #[arg(long = "match")]
+        pattern: Option<String>,
+
+        /// Number of keys to visit
+        #[arg(long)]
+        count: Option<u64>,
+
+        /// Only return keys holding a value of this type
+        #[arg(long = "type")]
+        kind: Option<String>,
+    },
+    /// Get the type of the value stored at key.
+    Type {
+        /// Name of key
+        key: String,
+    },
+    /// Rename key to newkey.
+    Rename {
+        /// Name of key to rename
+        key: String,
+
+        /// New name of the key
+        newkey: String,
+    },
+    /// Move key to the database numbered db.
+    Move {
+        /// Name of key to move
+        key: String,
+
+        /// Number of the destination database
+        db: usize,
+    },
+    /// Swap the data of two databases.
+    Swapdb {
+        /// Number of the first database
+        index1: usize,
+
+        /// Number of the second database
+        index2: usize,
+    },
+    /// Get the number of keys.
+    Dbsize,
+    /// Remove all keys of the database.
+    Flushdb,
+    /// Remove all keys of all databases.
+    Flushall,
     ///  Publisher to send a message to a specific channel.
     Publish {
         /// Name of channel
@@ -80,10 +292,26 @@ async fn main() -> mini_redis::Result<()> {
     let cli = Cli::parse();
 
     // Get the remote address to connect to
-    let addr = format!("{}:{}", cli.host, cli.port);
+    let addr = match &cli.socket {
+        Some(path) => format!("unix://{}", path.display()),
+        None => format!("{}:{}", cli.host, cli.port),
+    };
 
     // Establish a connection
-    let mut client = Client::connect(&addr).await?;
+    let mut client = match &cli.cacert {
+        Some(cacert) if cli.tls => {
+            let identity = cli.cert.as_deref().zip(cli.key.as_deref());
+            let config = tls::client_config(cacert, identity)?;
+            let server_name = cli.sni.as_deref().unwrap_or(&cli.host);
+
+            Client::connect_tls(&addr, server_name, config).await?
+        }
+        _ => Client::connect_addr(&addr).await?,
+    };
+
+    if cli.db != 0 {
+        client.select(cli.db).await?;
+    }
 
     // Process the requested command
     match cli.command {
@@ -122,6 +350,123 @@ async fn main() -> mini_redis::Result<()> {
             client.set_expires(&key, value, expires).await?;
             println!("OK");
         }
+        Command::Expire {
+            key,
+            seconds,
+            condition,
+        } => {
+            let expiration = Duration::from_secs(seconds);
+            let set = client.expire(&key, expiration, condition).await?;
+            println!("(integer) {}", set as i64);
+        }
+        Command::Pexpire {
+            key,
+            milliseconds,
+            condition,
+        } => {
+            let set = client.expire(&key, milliseconds, condition).await?;
+            println!("(integer) {}", set as i64);
+        }
+        Command::Expireat {
+            key,
+            timestamp,
+            condition,
+        } => {
+            let when = UNIX_EPOCH + Duration::from_secs(timestamp);
+            let set = client.expire_at(&key, when, condition).await?;
+            println!("(integer) {}", set as i64);
+        }
+        Command::Pexpireat {
+            key,
+            timestamp,
+            condition,
+        } => {
+            let when: SystemTime = UNIX_EPOCH + timestamp;
+            let set = client.expire_at(&key, when, condition).await?;
+            println!("(integer) {}", set as i64);
+        }
+        Command::Ttl { key } => {
+            println!("(integer) {}", client.ttl(&key).await?);
+        }
+        Command::Pttl { key } => {
+            println!("(integer) {}", client.pttl(&key).await?);
+        }
+        Command::Persist { key } => {
+            let persisted = client.persist(&key).await?;
+            println!("(integer) {}", persisted as i64);
+        }
+        Command::Incr { key } => {
+            println!("(integer) {}", client.incr(&key).await?);
+        }
+        Command::Decr { key } => {
+            println!("(integer) {}", client.decr(&key).await?);
+        }
+        Command::Incrby { key, increment } => {
+            println!("(integer) {}", client.incrby(&key, increment).await?);
+        }
+        Command::Decrby { key, decrement } => {
+            println!("(integer) {}", client.decrby(&key, decrement).await?);
+        }
+        Command::Incrbyfloat { key, increment } => {
+            println!("\"{}\"", client.incrbyfloat(&key, increment).await?);
+        }
+        Command::Append { key, value } => {
+            println!("(integer) {}", client.append(&key, value).await?);
+        }
+        Command::Strlen { key } => {
+            println!("(integer) {}", client.strlen(&key).await?);
+        }
+        Command::Del { keys } => {
+            println!("(integer) {}", client.del(&keys).await?);
+        }
+        Command::Exists { keys } => {
+            println!("(integer) {}", client.exists(&keys).await?);
+        }
+        Command::Keys { pattern } => {
+            for (i, key) in client.keys(&pattern).await?.iter().enumerate() {
+                println!("{}) \"{}\"", i + 1, key);
+            }
+        }
+        Command::Scan {
+            cursor,
+            pattern,
+            count,
+            kind,
+        } => {
+            let (cursor, keys) = client
+                .scan(cursor, pattern.as_deref(), count, kind.as_deref())
+                .await?;
+            println!("cursor: {}", cursor);
+            for (i, key) in keys.iter().enumerate() {
+                println!("{}) \"{}\"", i + 1, key);
+            }
+        }
+        Command::Type { key } => {
+            println!("{}", client.key_type(&key).await?);
+        }
+        Command::Rename { key, newkey } => {
+            client.rename(&key, &newkey).await?;
+            println!("OK");
+        }
+        Command::Move { key, db } => {
+            let moved = client.move_key(&key, db).await?;
+            println!("(integer) {}", moved as i64);
+        }
+        Command::Swapdb { index1, index2 } => {
+            client.swapdb(index1, index2).await?;
+            println!("OK");
+        }
+        Command::Dbsize => {
+            println!("(integer) {}", client.dbsize().await?);
+        }
+        Command::Flushdb => {
+            client.flushdb().await?;
+            println!("OK");
+        }
+        Command::Flushall => {
+            client.flushall().await?;
+            println!("OK");
+        }
         Command::Publish { channel, message } => {
             client.publish(&channel, message).await?;
             println!("Publish OK");
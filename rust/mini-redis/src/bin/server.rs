@@ -1,14 +1,18 @@
 //! mini-redis server.
 //!
 //! This file is the entry point for the server implemented in the library. It
-//! performs command line parsing and passes the arguments on to
-//! `mini_redis::server`.
+//! loads the configuration file, if any, applies the command line arguments on
+//! top, and passes the resulting configuration on to `mini_redis::server`.
 //!
 //! The `clap` crate is used for parsing arguments.
 
-use mini_redis::{server, DEFAULT_PORT};
+use mini_redis::cluster::ClusterNode;
+use mini_redis::config::{EvictionPolicy, FsyncPolicy, LogLevel};
+use mini_redis::{server, Config};
 
 use clap::Parser;
+use std::path::PathBuf;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio::signal;
 
@@ -21,6 +25,9 @@ use opentelemetry::sdk::trace as sdktrace;
 #[cfg(feature = "otel")]
 // For passing along the same XrayId across services
 use opentelemetry_aws::trace::XrayPropagator;
+#[cfg(not(feature = "otel"))]
+use tracing_subscriber::EnvFilter;
+
 #[cfg(feature = "otel")]
 // The `Ext` traits are to allow the Registry to accept the
 // OpenTelemetry-specific types (such as `OpenTelemetryLayer`)
@@ -30,34 +37,253 @@ use tracing_subscriber::{
 
 #[tokio::main]
 pub async fn main() -> mini_redis::Result<()> {
-    set_up_logging()?;
-
     let cli = Cli::parse();
-    let port = cli.port.unwrap_or(DEFAULT_PORT);
 
-    // Bind a TCP listener
-    let listener = TcpListener::bind(&format!("127.0.0.1:{}", port)).await?;
+    // Command line arguments take precedence over the configuration file
+    let mut config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    if let Some(bind) = cli.bind {
+        config.bind = bind;
+    }
+    if let Some(port) = cli.port {
+        config.port = port;
+    }
+    if let Some(unixsocket) = cli.unixsocket {
+        config.unixsocket = Some(unixsocket);
+    }
+    if let Some(unixsocketperm) = cli.unixsocketperm {
+        config.set("unixsocketperm", &unixsocketperm)?;
+    }
+    if let Some(maxclients) = cli.maxclients {
+        config.maxclients = maxclients;
+    }
+    if let Some(timeout) = cli.timeout {
+        config.timeout = Some(Duration::from_secs(timeout)).filter(|timeout| !timeout.is_zero());
+    }
+    if let Some(maxmemory) = cli.maxmemory {
+        config.set("maxmemory", &maxmemory)?;
+    }
+    if let Some(maxmemory_policy) = cli.maxmemory_policy {
+        config.maxmemory_policy = maxmemory_policy;
+    }
+    if let Some(maxmemory_samples) = cli.maxmemory_samples {
+        config.maxmemory_samples = maxmemory_samples;
+    }
+    if let Some(loglevel) = cli.loglevel {
+        config.loglevel = loglevel;
+    }
+    if cli.appendonly {
+        config.appendonly = true;
+    }
+    if let Some(databases) = cli.databases {
+        config.databases = databases;
+    }
+    if let Some(shards) = cli.shards {
+        config.shards = shards;
+    }
+    if let Some(appendfilename) = cli.appendfilename {
+        config.appendfilename = appendfilename;
+    }
+    if let Some(appendfsync) = cli.appendfsync {
+        config.appendfsync = appendfsync;
+    }
+    if let Some(dbfilename) = cli.dbfilename {
+        config.dbfilename = dbfilename;
+    }
+    if let Some(lua_time_limit) = cli.lua_time_limit {
+        config.lua_time_limit = Duration::from_millis(lua_time_limit);
+    }
+    if let Some(replicaof) = cli.replicaof {
+        let port = replicaof[1]
+            .parse()
+            .map_err(|_| format!("invalid master port '{}'", replicaof[1]))?;
+        config.replicaof = Some((replicaof[0].clone(), port));
+    }
+    if let Some(replica_read_only) = cli.replica_read_only {
+        config.replica_read_only = replica_read_only;
+    }
+    if cli.cluster_enabled {
+        config.cluster_enabled = true;
+    }
+    if !cli.cluster_node.is_empty() {
+        config.cluster_nodes = cli.cluster_node;
+    }
+    if let Some(tls_cert_file) = cli.tls_cert_file {
+        config.tls_cert_file = Some(tls_cert_file);
+    }
+    if let Some(tls_key_file) = cli.tls_key_file {
+        config.tls_key_file = Some(tls_key_file);
+    }
+    if let Some(tls_ca_cert_file) = cli.tls_ca_cert_file {
+        config.tls_ca_cert_file = Some(tls_ca_cert_file);
+    }
+    if cli.tls_auth_clients {
+        config.tls_auth_clients = true;
+    }
+    if cli.tls_replication {
+        config.tls_replication = true;
+    }
 
-    server::run(listener, signal::ctrl_c()).await;
+    set_up_logging(config.loglevel)?;
 
-    Ok(())
+    // Like Redis, port 0 disables TCP, leaving only the Unix socket
+    if config.port == 0 {
+        return server::run_unix(config, signal::ctrl_c()).await;
+    }
+
+    // Bind a TCP listener
+    let listener = TcpListener::bind((config.bind.as_str(), config.port)).await?;
+
+    server::run_with_config(listener, config, signal::ctrl_c()).await
 }
 
 #[derive(Parser, Debug)]
 #[command(name = "mini-redis-server", version, author, about = "A Redis server")]
 struct Cli {
+    /// Configuration file in the redis.conf format. Command line arguments
+    /// override the settings of the file
+    config: Option<PathBuf>,
+
+    /// Address of the interface to listen on [default: 127.0.0.1]
+    #[arg(long)]
+    bind: Option<String>,
+
+    /// Port to listen on, or 0 to only listen on the Unix socket
+    /// [default: 6379]
     #[arg(long)]
     port: Option<u16>,
+
+    /// Path of a Unix socket to listen on, in addition to the port
+    #[arg(long)]
+    unixsocket: Option<PathBuf>,
+
+    /// Permissions of the Unix socket in octal, such as 770
+    #[arg(long)]
+    unixsocketperm: Option<String>,
+
+    /// Maximum number of clients connected at the same time [default: 250]
+    #[arg(long)]
+    maxclients: Option<usize>,
+
+    /// Close the connection of a client idle for this many seconds, or never
+    /// if 0 [default: 0]
+    #[arg(long)]
+    timeout: Option<u64>,
+
+    /// Maximum memory used by the keys, such as 100mb, or no limit if 0
+    /// [default: 0]
+    #[arg(long)]
+    maxmemory: Option<String>,
+
+    /// How keys are evicted once maxmemory is reached: noeviction,
+    /// allkeys-lru, volatile-lru, allkeys-lfu, volatile-lfu, allkeys-random,
+    /// volatile-random or volatile-ttl [default: noeviction]
+    #[arg(long)]
+    maxmemory_policy: Option<EvictionPolicy>,
+
+    /// Number of keys sampled to find the key to evict [default: 5]
+    #[arg(long)]
+    maxmemory_samples: Option<usize>,
+
+    /// Minimum level of the messages logged: debug, verbose, notice or
+    /// warning [default: notice]. RUST_LOG takes precedence when set
+    #[arg(long)]
+    loglevel: Option<LogLevel>,
+
+    /// Number of databases, selected with SELECT [default: 16]
+    #[arg(long)]
+    databases: Option<usize>,
+
+    /// Number of independently locked shards the key space is split into
+    /// [default: 16]
+    #[arg(long)]
+    shards: Option<usize>,
+
+    /// Append every command modifying the data to a file, and load the file
+    /// on startup
+    #[arg(long)]
+    appendonly: bool,
+
+    /// Path of the append-only file [default: appendonly.aof]
+    #[arg(long)]
+    appendfilename: Option<PathBuf>,
+
+    /// When to flush the append-only file to disk: always, everysec or no
+    /// [default: everysec]
+    #[arg(long)]
+    appendfsync: Option<FsyncPolicy>,
+
+    /// Path of the snapshot file written by SAVE and BGSAVE, and loaded on
+    /// startup when the append-only file is disabled [default: dump.rdb]
+    #[arg(long)]
+    dbfilename: Option<PathBuf>,
+
+    /// Maximum time in milliseconds a Lua script may run before it is aborted
+    /// [default: 5000]
+    #[arg(long)]
+    lua_time_limit: Option<u64>,
+
+    /// Start as a replica of the server listening on HOST and PORT
+    #[arg(long, num_args = 2, value_names = ["HOST", "PORT"])]
+    replicaof: Option<Vec<String>>,
+
+    /// Reject commands modifying the data when running as a replica
+    /// [default: true]
+    #[arg(long)]
+    replica_read_only: Option<bool>,
+
+    /// Run as a node of a cluster
+    #[arg(long)]
+    cluster_enabled: bool,
+
+    /// A node of the cluster and the hash slots it serves, such as
+    /// 127.0.0.1:7000=0-5460. Repeat for every node, including this one
+    #[arg(long, value_name = "ADDR=SLOTS")]
+    cluster_node: Vec<ClusterNode>,
+
+    /// PEM file of the certificate chain of the server. With
+    /// --tls-key-file, only TLS connections are accepted
+    #[arg(long)]
+    tls_cert_file: Option<PathBuf>,
+
+    /// PEM file of the private key of the server certificate
+    #[arg(long)]
+    tls_key_file: Option<PathBuf>,
+
+    /// PEM file of the certificate authorities trusted to issue the
+    /// certificates of clients and of the master
+    #[arg(long)]
+    tls_ca_cert_file: Option<PathBuf>,
+
+    /// Require clients to present a certificate issued by one of the
+    /// authorities of --tls-ca-cert-file
+    #[arg(long)]
+    tls_auth_clients: bool,
+
+    /// Connect to the master using TLS when running as a replica
+    #[arg(long)]
+    tls_replication: bool,
+}
+
+/// Returns the filter selecting the messages to log: the one given by the
+/// `RUST_LOG` environment variable if set, or `loglevel` otherwise.
+fn log_filter(loglevel: LogLevel) -> EnvFilter {
+    EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::default().add_directive(loglevel.level_filter().into()))
 }
 
 #[cfg(not(feature = "otel"))]
-fn set_up_logging() -> mini_redis::Result<()> {
+fn set_up_logging(loglevel: LogLevel) -> mini_redis::Result<()> {
     // See https://docs.rs/tracing for more info
-    tracing_subscriber::fmt::try_init()
+    tracing_subscriber::fmt()
+        .with_env_filter(log_filter(loglevel))
+        .try_init()
 }
 
 #[cfg(feature = "otel")]
-fn set_up_logging() -> Result<(), TryInitError> {
+fn set_up_logging(loglevel: LogLevel) -> Result<(), TryInitError> {
     // Set the global propagator to X-Ray propagator
     // Note: If you need to pass the x-amzn-trace-id across services in the same trace,
     // you will need this line. However, this requires additional code not pictured here.
@@ -81,8 +307,8 @@ fn set_up_logging() -> Result<(), TryInitError> {
     let opentelemetry = tracing_opentelemetry::layer().with_tracer(tracer);
 
     // Parse an `EnvFilter` configuration from the `RUST_LOG`
-    // environment variable.
-    let filter = EnvFilter::from_default_env();
+    // environment variable, falling back to the configured log level.
+    let filter = log_filter(loglevel);
 
     // Use the tracing subscriber `Registry`, or any other subscriber
     // that impls `LookupSpan`
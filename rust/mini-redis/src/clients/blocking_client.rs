@@ -3,17 +3,24 @@
 //! Provides a blocking connect and methods for issuing the supported commands.
 
 use bytes::Bytes;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::ToSocketAddrs;
 use tokio::runtime::Runtime;
+use tokio_rustls::rustls::ClientConfig;
 
 pub use crate::clients::Message;
+use crate::Protocol;
 
 /// Established connection with a Redis server.
 ///
 /// Backed by a single `TcpStream`, `BlockingClient` provides basic network
 /// client functionality (no pooling, retrying, ...). Connections are
-/// established using the [`connect`](fn@connect) function.
+/// established using the [`connect`](fn@connect) function,
+/// [`connect_tls`](fn@connect_tls) for servers accepting TLS connections, or
+/// [`connect_unix`](fn@connect_unix) for servers listening on a Unix socket.
 ///
 /// Requests are issued using the various methods of `Client`.
 pub struct BlockingClient {
@@ -80,6 +87,115 @@ impl BlockingClient {
         Ok(BlockingClient { inner, rt })
     }
 
+    /// Establish a connection with the Redis server listening on the Unix
+    /// socket at `path`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::BlockingClient;
+    ///
+    /// fn main() {
+    ///     let client = match BlockingClient::connect_unix("/tmp/mini-redis.sock") {
+    ///         Ok(client) => client,
+    ///         Err(_) => panic!("failed to establish connection"),
+    ///     };
+    /// # drop(client);
+    /// }
+    /// ```
+    pub fn connect_unix(path: impl AsRef<Path>) -> crate::Result<BlockingClient> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        let inner = rt.block_on(crate::clients::Client::connect_unix(path))?;
+
+        Ok(BlockingClient { inner, rt })
+    }
+
+    /// Establish a connection with the Redis server at `addr`, either a
+    /// `host:port` address or the path of a Unix socket prefixed with
+    /// `unix://`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::BlockingClient;
+    ///
+    /// fn main() {
+    ///     let client = match BlockingClient::connect_addr("unix:///tmp/mini-redis.sock") {
+    ///         Ok(client) => client,
+    ///         Err(_) => panic!("failed to establish connection"),
+    ///     };
+    /// # drop(client);
+    /// }
+    /// ```
+    pub fn connect_addr(addr: &str) -> crate::Result<BlockingClient> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        let inner = rt.block_on(crate::clients::Client::connect_addr(addr))?;
+
+        Ok(BlockingClient { inner, rt })
+    }
+
+    /// Establish a TLS connection with the Redis server located at `addr`.
+    ///
+    /// See [`Client::connect_tls`](crate::clients::Client::connect_tls) for
+    /// the meaning of `server_name` and `config`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_redis::{clients::BlockingClient, tls};
+    ///
+    /// fn main() {
+    ///     let config = tls::client_config("ca.crt".as_ref(), None).unwrap();
+    ///     let client = match BlockingClient::connect_tls("localhost:6379", "localhost", config) {
+    ///         Ok(client) => client,
+    ///         Err(_) => panic!("failed to establish connection"),
+    ///     };
+    /// # drop(client);
+    /// }
+    /// ```
+    pub fn connect_tls<T: ToSocketAddrs>(
+        addr: T,
+        server_name: &str,
+        config: Arc<ClientConfig>,
+    ) -> crate::Result<BlockingClient> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        let inner = rt.block_on(crate::clients::Client::connect_tls(
+            addr,
+            server_name,
+            config,
+        ))?;
+
+        Ok(BlockingClient { inner, rt })
+    }
+
+    /// Switch the connection to the given protocol version.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::{clients::BlockingClient, Protocol};
+    ///
+    /// fn main() {
+    ///     let mut client = BlockingClient::connect("localhost:6379").unwrap();
+    ///
+    ///     client.hello(Protocol::Resp3).unwrap();
+    /// }
+    /// ```
+    pub fn hello(&mut self, protocol: Protocol) -> crate::Result<()> {
+        self.rt.block_on(self.inner.hello(protocol))
+    }
+
     /// Get the value of key.
     ///
     /// If the key does not exist the special value `None` is returned.
@@ -180,6 +296,58 @@ impl BlockingClient {
             .block_on(self.inner.set_expires(key, value, expiration))
     }
 
+    /// Set `fields` of the hash stored at `key` to their respective values.
+    ///
+    /// Returns the number of fields that were added.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::BlockingClient;
+    ///
+    /// fn main() {
+    ///     let mut client = BlockingClient::connect("localhost:6379").unwrap();
+    ///
+    ///     let fields = vec![("user".to_string(), "alice".into())];
+    ///     client.hset("session", fields).unwrap();
+    ///
+    ///     let val = client.hget("session", "user").unwrap().unwrap();
+    ///     assert_eq!(val, "alice");
+    /// }
+    /// ```
+    pub fn hset(&mut self, key: &str, fields: Vec<(String, Bytes)>) -> crate::Result<u64> {
+        self.rt.block_on(self.inner.hset(key, fields))
+    }
+
+    /// Get the value of `field` in the hash stored at `key`.
+    ///
+    /// Returns `None` if either the field or the key does not exist.
+    pub fn hget(&mut self, key: &str, field: &str) -> crate::Result<Option<Bytes>> {
+        self.rt.block_on(self.inner.hget(key, field))
+    }
+
+    /// Remove `fields` from the hash stored at `key`.
+    ///
+    /// Returns the number of fields that were removed.
+    pub fn hdel(&mut self, key: &str, fields: &[String]) -> crate::Result<u64> {
+        self.rt.block_on(self.inner.hdel(key, fields))
+    }
+
+    /// Get all fields and values of the hash stored at `key`.
+    pub fn hgetall(&mut self, key: &str) -> crate::Result<HashMap<String, Bytes>> {
+        self.rt.block_on(self.inner.hgetall(key))
+    }
+
+    /// Increment the integer stored in `field` of the hash stored at `key` by
+    /// `increment`.
+    ///
+    /// Returns the value of the field after the increment.
+    pub fn hincrby(&mut self, key: &str, field: &str, increment: i64) -> crate::Result<i64> {
+        self.rt.block_on(self.inner.hincrby(key, field, increment))
+    }
+
     /// Posts `message` to the given `channel`.
     ///
     /// Returns the number of subscribers currently listening on the channel.
@@ -219,6 +387,19 @@ impl BlockingClient {
             rt: self.rt,
         })
     }
+
+    /// Subscribes the client to channels matching the specified glob-style
+    /// patterns, such as `news.*`.
+    ///
+    /// Same as `subscribe`, the function consumes `self` and returns a
+    /// `BlockingSubscriber`.
+    pub fn psubscribe(self, patterns: Vec<String>) -> crate::Result<BlockingSubscriber> {
+        let subscriber = self.rt.block_on(self.inner.psubscribe(patterns))?;
+        Ok(BlockingSubscriber {
+            inner: subscriber,
+            rt: self.rt,
+        })
+    }
 }
 
 impl BlockingSubscriber {
@@ -227,6 +408,11 @@ impl BlockingSubscriber {
         self.inner.get_subscribed()
     }
 
+    /// Returns the set of patterns currently subscribed to.
+    pub fn get_subscribed_patterns(&self) -> &[String] {
+        self.inner.get_subscribed_patterns()
+    }
+
     /// Receive the next message published on a subscribed channel, waiting if
     /// necessary.
     ///
@@ -253,6 +439,17 @@ impl BlockingSubscriber {
     pub fn unsubscribe(&mut self, channels: &[String]) -> crate::Result<()> {
         self.rt.block_on(self.inner.unsubscribe(channels))
     }
+
+    /// Subscribe to a list of new patterns
+    pub fn psubscribe(&mut self, patterns: &[String]) -> crate::Result<()> {
+        self.rt.block_on(self.inner.psubscribe(patterns))
+    }
+
+    /// Unsubscribe from a list of patterns. An empty list unsubscribes from
+    /// all patterns.
+    pub fn punsubscribe(&mut self, patterns: &[String]) -> crate::Result<()> {
+        self.rt.block_on(self.inner.punsubscribe(patterns))
+    }
 }
 
 impl Iterator for SubscriberIterator {
@@ -1,50 +1,113 @@
-use crate::clients::Client;
+use crate::clients::{Client, Pending, Pipeline, Responses};
 use crate::Result;
 
 use bytes::Bytes;
+use std::collections::HashMap;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::oneshot;
 
 // Enum used to message pass the requested command from the `BufferedClient` handle
+//
+// Each variant carries the arguments of the command along with a
+// `oneshot::Sender`. `oneshot::Sender` is a channel type that sends a
+// **single** value. It is used here to send the response received from the
+// connection back to the original requester. As commands respond with
+// different types, each variant has its own sender type.
 #[derive(Debug)]
 enum Command {
-    Get(String),
-    Set(String, Bytes),
+    Get(String, Responder<Option<Bytes>>),
+    Set(String, Bytes, Responder<()>),
+    HSet(String, Vec<(String, Bytes)>, Responder<u64>),
+    HGet(String, String, Responder<Option<Bytes>>),
+    HDel(String, Vec<String>, Responder<u64>),
+    HGetAll(String, Responder<HashMap<String, Bytes>>),
+    HIncrBy(String, String, i64, Responder<i64>),
 }
 
-// Message type sent over the channel to the connection task.
-//
-// `Command` is the command to forward to the connection.
-//
-// `oneshot::Sender` is a channel type that sends a **single** value. It is used
-// here to send the response received from the connection back to the original
-// requester.
-type Message = (Command, oneshot::Sender<Result<Option<Bytes>>>);
+// Sends the response of a command back to the requester.
+type Responder<T> = oneshot::Sender<Result<T>>;
+
+// Sends the reply to a command queued in a pipeline back to the requester,
+// once the pipeline has been sent. If sending the pipeline failed, the
+// requester receives the error instead.
+type Respond = Box<dyn FnOnce(std::result::Result<&mut Responses, &crate::Error>) + Send>;
+
+/// Maximum number of requests sent to the server in a single pipeline.
+const MAX_PIPELINE: usize = 64;
 
 /// Receive commands sent through the channel and forward them to client. The
 /// response is returned back to the caller via a `oneshot`.
-async fn run(mut client: Client, mut rx: Receiver<Message>) {
+///
+/// Requests received while the previous batch is in flight accumulate in the
+/// channel. They are then sent to the server together, as a single pipeline,
+/// instead of waiting for a round trip each.
+async fn run(mut client: Client, mut rx: Receiver<Command>) {
     // Repeatedly pop messages from the channel. A return value of `None`
     // indicates that all `BufferedClient` handles have dropped and there will never be
     // another message sent on the channel.
-    while let Some((cmd, tx)) = rx.recv().await {
-        // The command is forwarded to the connection
-        let response = match cmd {
-            Command::Get(key) => client.get(&key).await,
-            Command::Set(key, value) => client.set(&key, value).await.map(|_| None),
-        };
+    while let Some(cmd) = rx.recv().await {
+        let mut pipeline = Pipeline::new();
+        let mut responders = vec![queue(&mut pipeline, cmd)];
+
+        // Take the other requests already waiting, without waiting for more.
+        while responders.len() < MAX_PIPELINE {
+            match rx.try_recv() {
+                Ok(cmd) => responders.push(queue(&mut pipeline, cmd)),
+                Err(_) => break,
+            }
+        }
+
+        // The replies are sent back to the callers in the order their
+        // requests were received.
+        match client.send_pipeline(pipeline).await {
+            Ok(mut responses) => {
+                for respond in responders {
+                    respond(Ok(&mut responses));
+                }
+            }
+            Err(err) => {
+                for respond in responders {
+                    respond(Err(&err));
+                }
+            }
+        }
+    }
+}
 
-        // Send the response back to the caller.
-        //
-        // Failing to send the message indicates the `rx` half dropped
-        // before receiving the message. This is a normal runtime event.
-        let _ = tx.send(response);
+/// Add the command to `pipeline`, returning the function which sends its
+/// reply back to the requester.
+fn queue(pipeline: &mut Pipeline, cmd: Command) -> Respond {
+    match cmd {
+        Command::Get(key, tx) => respond(pipeline.get(&key), tx),
+        Command::Set(key, value, tx) => respond(pipeline.set(&key, value), tx),
+        Command::HSet(key, fields, tx) => respond(pipeline.hset(&key, fields), tx),
+        Command::HGet(key, field, tx) => respond(pipeline.hget(&key, &field), tx),
+        Command::HDel(key, fields, tx) => respond(pipeline.hdel(&key, &fields), tx),
+        Command::HGetAll(key, tx) => respond(pipeline.hgetall(&key), tx),
+        Command::HIncrBy(key, field, increment, tx) => {
+            respond(pipeline.hincrby(&key, &field, increment), tx)
+        }
     }
 }
 
+/// Returns the function sending the reply to `pending` through `tx`.
+///
+/// Failing to send the response indicates the `rx` half dropped before
+/// receiving the message. This is a normal runtime event.
+fn respond<T: Send + 'static>(pending: Pending<T>, tx: Responder<T>) -> Respond {
+    Box::new(move |responses| {
+        let res = match responses {
+            Ok(responses) => responses.take(pending),
+            Err(err) => Err(err.to_string().into()),
+        };
+
+        let _ = tx.send(res);
+    })
+}
+
 #[derive(Clone)]
 pub struct BufferedClient {
-    tx: Sender<Message>,
+    tx: Sender<Command>,
 }
 
 impl BufferedClient {
@@ -62,6 +125,10 @@ impl BufferedClient {
     /// Redis connection. When the response is received, it is forwarded to the
     /// original requester.
     ///
+    /// Commands pushed while the connection task waits for a response are
+    /// coalesced: the task sends them all at once as a [`Pipeline`], so
+    /// concurrent requests share a single round trip.
+    ///
     /// The returned `BufferedClient` handle may be cloned before passing the new handle to
     /// separate tasks.
     pub fn buffer(client: Client) -> BufferedClient {
@@ -81,20 +148,7 @@ impl BufferedClient {
     /// Same as `Client::get` but requests are **buffered** until the associated
     /// connection has the ability to send the request.
     pub async fn get(&mut self, key: &str) -> Result<Option<Bytes>> {
-        // Initialize a new `Get` command to send via the channel.
-        let get = Command::Get(key.into());
-
-        // Initialize a new oneshot to be used to receive the response back from the connection.
-        let (tx, rx) = oneshot::channel();
-
-        // Send the request
-        self.tx.send((get, tx)).await?;
-
-        // Await the response
-        match rx.await {
-            Ok(res) => res,
-            Err(err) => Err(err.into()),
-        }
+        self.request(|tx| Command::Get(key.into(), tx)).await
     }
 
     /// Set `key` to hold the given `value`.
@@ -102,18 +156,66 @@ impl BufferedClient {
     /// Same as `Client::set` but requests are **buffered** until the associated
     /// connection has the ability to send the request
     pub async fn set(&mut self, key: &str, value: Bytes) -> Result<()> {
-        // Initialize a new `Set` command to send via the channel.
-        let set = Command::Set(key.into(), value);
+        self.request(|tx| Command::Set(key.into(), value, tx)).await
+    }
+
+    /// Set `fields` of the hash stored at `key` to their respective values.
+    ///
+    /// Same as `Client::hset` but requests are **buffered** until the
+    /// associated connection has the ability to send the request.
+    pub async fn hset(&mut self, key: &str, fields: Vec<(String, Bytes)>) -> Result<u64> {
+        self.request(|tx| Command::HSet(key.into(), fields, tx))
+            .await
+    }
+
+    /// Get the value of `field` in the hash stored at `key`.
+    ///
+    /// Same as `Client::hget` but requests are **buffered** until the
+    /// associated connection has the ability to send the request.
+    pub async fn hget(&mut self, key: &str, field: &str) -> Result<Option<Bytes>> {
+        self.request(|tx| Command::HGet(key.into(), field.into(), tx))
+            .await
+    }
+
+    /// Remove `fields` from the hash stored at `key`.
+    ///
+    /// Same as `Client::hdel` but requests are **buffered** until the
+    /// associated connection has the ability to send the request.
+    pub async fn hdel(&mut self, key: &str, fields: &[String]) -> Result<u64> {
+        self.request(|tx| Command::HDel(key.into(), fields.to_vec(), tx))
+            .await
+    }
 
+    /// Get all fields and values of the hash stored at `key`.
+    ///
+    /// Same as `Client::hgetall` but requests are **buffered** until the
+    /// associated connection has the ability to send the request.
+    pub async fn hgetall(&mut self, key: &str) -> Result<HashMap<String, Bytes>> {
+        self.request(|tx| Command::HGetAll(key.into(), tx)).await
+    }
+
+    /// Increment the integer stored in `field` of the hash stored at `key` by
+    /// `increment`.
+    ///
+    /// Same as `Client::hincrby` but requests are **buffered** until the
+    /// associated connection has the ability to send the request.
+    pub async fn hincrby(&mut self, key: &str, field: &str, increment: i64) -> Result<i64> {
+        self.request(|tx| Command::HIncrBy(key.into(), field.into(), increment, tx))
+            .await
+    }
+
+    /// Send the command built by `cmd` to the connection task and wait for
+    /// the response.
+    async fn request<T>(&mut self, cmd: impl FnOnce(Responder<T>) -> Command) -> Result<T> {
         // Initialize a new oneshot to be used to receive the response back from the connection.
         let (tx, rx) = oneshot::channel();
 
         // Send the request
-        self.tx.send((set, tx)).await?;
+        self.tx.send(cmd(tx)).await?;
 
         // Await the response
         match rx.await {
-            Ok(res) => res.map(|_| ()),
+            Ok(res) => res,
             Err(err) => Err(err.into()),
         }
     }
@@ -2,14 +2,34 @@
 //!
 //! Provides an async connect and methods for issuing the supported commands.
 
-use crate::cmd::{Get, Ping, Publish, Set, Subscribe, Unsubscribe};
-use crate::{Connection, Frame};
+use crate::clients::pipeline::{self, Pipeline, Responses};
+use crate::cluster::SlotRange;
+use crate::cmd::{
+    parse_group_id, parse_group_read_id, parse_new_id, parse_range_end, parse_range_start,
+    parse_read_id,
+};
+use crate::cmd::{
+    Append, BPop, BgRewriteAof, BgSave, Cluster, Config, DbSize, Del, Eval, EvalSha, Exec, Exists,
+    Expiration, Expire, ExpireCondition, FlushAll, FlushDb, Get, GetDel, GetEx, HDel, HGet,
+    HGetAll, HIncrBy, HSet, Hello, IncrBy, IncrByFloat, Info, Keys, LLen, LRange, Move, Multi,
+    PSubscribe, PUnsubscribe, Persist, Ping, Pop, Publish, Push, Rename, ReplicaOf, Save, Scan,
+    Script, Select, Set, SetCondition, StrLen, Subscribe, SwapDb, Ttl, TtlUnit, Type, Unsubscribe,
+    Unwatch, Watch, XAck, XAdd, XClaim, XGroup, XLen, XPending, XRange, XRead, XReadGroup, XTrim,
+    ZAdd, ZRange, ZRangeByScore, ZRank, ZRem,
+};
+use crate::db::Side;
+use crate::{tls, Connection, Frame, Protocol};
 
 use async_stream::try_stream;
 use bytes::Bytes;
+use std::collections::HashMap;
+use std::convert::TryInto;
 use std::io::{Error, ErrorKind};
-use std::time::Duration;
-use tokio::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpStream, ToSocketAddrs, UnixStream};
+use tokio_rustls::rustls::ClientConfig;
 use tokio_stream::Stream;
 use tracing::{debug, instrument};
 
@@ -17,12 +37,14 @@ use tracing::{debug, instrument};
 ///
 /// Backed by a single `TcpStream`, `Client` provides basic network client
 /// functionality (no pooling, retrying, ...). Connections are established using
-/// the [`connect`](fn@connect) function.
+/// the [`connect`](fn@connect) function, [`connect_tls`](fn@connect_tls) for
+/// servers accepting TLS connections, or [`connect_unix`](fn@connect_unix) for
+/// servers listening on a Unix socket.
 ///
 /// Requests are issued using the various methods of `Client`.
 pub struct Client {
     /// The TCP connection decorated with the redis protocol encoder / decoder
-    /// implemented using a buffered `TcpStream`.
+    /// implemented using a buffered `TcpStream`, or TLS stream.
     ///
     /// When `Listener` receives an inbound connection, the `TcpStream` is
     /// passed to `Connection::new`, which initializes the associated buffers.
@@ -42,11 +64,72 @@ pub struct Subscriber {
 
     /// The set of channels to which the `Subscriber` is currently subscribed.
     subscribed_channels: Vec<String>,
+
+    /// The set of patterns to which the `Subscriber` is currently subscribed.
+    subscribed_patterns: Vec<String>,
+}
+
+/// A transaction being built.
+///
+/// Created by [`Client::transaction`]. Commands are queued locally and sent
+/// to the server in a `MULTI` ... `EXEC` block by [`exec`](Transaction::exec).
+/// Dropping the `Transaction` without calling `exec` does not send anything.
+pub struct Transaction<'a> {
+    /// The client the transaction is executed on.
+    client: &'a mut Client,
+
+    /// Frames of the queued commands.
+    commands: Vec<Frame>,
+}
+
+/// An entry of a stream, as returned by [`Client::xrange`] and
+/// [`Client::xread`].
+#[derive(Debug, Clone)]
+pub struct StreamEntry {
+    /// ID of the entry, such as `1700000000000-0`.
+    pub id: String,
+
+    /// Field-value pairs of the entry, in the order they were given.
+    pub fields: Vec<(String, Bytes)>,
+}
+
+/// Summary of the entries pending in a consumer group, as returned by
+/// [`Client::xpending`].
+#[derive(Debug, Clone)]
+pub struct PendingSummary {
+    /// Number of pending entries.
+    pub count: u64,
+
+    /// Smallest and greatest IDs of the pending entries, if any.
+    pub ids: Option<(String, String)>,
+
+    /// Number of entries pending for each consumer with at least one.
+    pub consumers: Vec<(String, u64)>,
+}
+
+/// An entry pending in a consumer group, as returned by
+/// [`Client::xpending_range`].
+#[derive(Debug, Clone)]
+pub struct PendingEntry {
+    /// ID of the entry.
+    pub id: String,
+
+    /// Name of the consumer the entry was delivered to.
+    pub consumer: String,
+
+    /// Time since the entry was last delivered.
+    pub idle: Duration,
+
+    /// Number of times the entry was delivered.
+    pub delivery_count: u64,
 }
 
 /// A message received on a subscribed channel.
 #[derive(Debug, Clone)]
 pub struct Message {
+    /// The pattern the channel matched, if the message was received through a
+    /// pattern subscription.
+    pub pattern: Option<String>,
     pub channel: String,
     pub content: Bytes,
 }
@@ -82,7 +165,95 @@ impl Client {
 
         // Initialize the connection state. This allocates read/write buffers to
         // perform redis protocol frame parsing.
-        let connection = Connection::new(socket);
+        let connection = Connection::new(Box::new(socket) as _);
+
+        Ok(Client { connection })
+    }
+
+    /// Establish a connection with the Redis server listening on the Unix
+    /// socket at `path`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let client = match Client::connect_unix("/tmp/mini-redis.sock").await {
+    ///         Ok(client) => client,
+    ///         Err(_) => panic!("failed to establish connection"),
+    ///     };
+    /// # drop(client);
+    /// }
+    /// ```
+    pub async fn connect_unix(path: impl AsRef<Path>) -> crate::Result<Client> {
+        let socket = UnixStream::connect(path).await?;
+        let connection = Connection::new(Box::new(socket) as _);
+
+        Ok(Client { connection })
+    }
+
+    /// Establish a connection with the Redis server at `addr`, either a
+    /// `host:port` address or the path of a Unix socket prefixed with
+    /// `unix://`.
+    ///
+    /// This lets applications take the address of the server from their
+    /// configuration, whichever way the server is reached.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let client = match Client::connect_addr("unix:///tmp/mini-redis.sock").await {
+    ///         Ok(client) => client,
+    ///         Err(_) => panic!("failed to establish connection"),
+    ///     };
+    /// # drop(client);
+    /// }
+    /// ```
+    pub async fn connect_addr(addr: &str) -> crate::Result<Client> {
+        match addr.strip_prefix("unix://") {
+            Some(path) => Client::connect_unix(path).await,
+            None => Client::connect(addr).await,
+        }
+    }
+
+    /// Establish a TLS connection with the Redis server located at `addr`.
+    ///
+    /// The certificate of the server must be valid for `server_name`, which is
+    /// either a DNS name or an IP address. `config` holds the certificate
+    /// authorities trusted to issue it, and the certificate of the client if
+    /// the server requires one. It is usually built using
+    /// [`tls::client_config`](crate::tls::client_config).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_redis::{clients::Client, tls};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let config = tls::client_config("ca.crt".as_ref(), None).unwrap();
+    ///     let client = match Client::connect_tls("localhost:6379", "localhost", config).await {
+    ///         Ok(client) => client,
+    ///         Err(_) => panic!("failed to establish connection"),
+    ///     };
+    /// # drop(client);
+    /// }
+    /// ```
+    pub async fn connect_tls<T: ToSocketAddrs>(
+        addr: T,
+        server_name: &str,
+        config: Arc<ClientConfig>,
+    ) -> crate::Result<Client> {
+        let socket = TcpStream::connect(addr).await?;
+        let stream = tls::connect(socket, server_name, config).await?;
+
+        let connection = Connection::new(Box::new(stream) as _);
 
         Ok(Client { connection })
     }
@@ -122,6 +293,47 @@ impl Client {
         }
     }
 
+    /// Switch the connection to the given protocol version.
+    ///
+    /// Connections start out speaking RESP2. After switching to RESP3, the
+    /// server replies using the RESP3 frame types. The typed methods of
+    /// `Client` accept replies in either version.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    /// ```no_run
+    /// use mini_redis::{clients::Client, Protocol};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.hello(Protocol::Resp3).await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn hello(&mut self, protocol: Protocol) -> crate::Result<()> {
+        let protover = match protocol {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        };
+
+        let frame = Hello::new(Some(protover)).into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        // The server replies with a summary of itself, encoded using the new
+        // protocol version. A RESP2 server flattens the map into an array.
+        match self.read_response().await? {
+            Frame::Map(_) | Frame::Array(_) => {
+                self.connection.set_protocol(protocol);
+                Ok(())
+            }
+            frame => Err(frame.to_error()),
+        }
+    }
+
     /// Get the value of key.
     ///
     /// If the key does not exist the special value `None` is returned.
@@ -251,30 +463,56 @@ impl Client {
         self.set_cmd(Set::new(key, value, Some(expiration))).await
     }
 
-    /// The core `SET` logic, used by both `set` and `set_expires.
-    async fn set_cmd(&mut self, cmd: Set) -> crate::Result<()> {
-        // Convert the `Set` command into a frame
-        let frame = cmd.into_frame();
+    /// Set `key` to hold the given `value`, only if `condition` holds.
+    ///
+    /// With `SetCondition::Nx`, the key is only set if it does not exist yet,
+    /// which makes it suitable for locks. If `expiration` is set, the value
+    /// expires after the given duration. Returns `true` if the key was set.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    /// use mini_redis::cmd::SetCondition;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let ttl = Some(Duration::from_secs(30));
+    ///     let locked = client
+    ///         .set_if("lock", "token".into(), SetCondition::Nx, ttl)
+    ///         .await
+    ///         .unwrap();
+    ///     println!("Got = {:?}", locked);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn set_if(
+        &mut self,
+        key: &str,
+        value: Bytes,
+        condition: SetCondition,
+        expiration: Option<Duration>,
+    ) -> crate::Result<bool> {
+        let frame = Set::new(key, value, expiration)
+            .condition(condition)
+            .into_frame();
 
         debug!(request = ?frame);
 
-        // Write the frame to the socket. This writes the full frame to the
-        // socket, waiting if necessary.
         self.connection.write_frame(&frame).await?;
 
-        // Wait for the response from the server. On success, the server
-        // responds simply with `OK`. Any other response indicates an error.
-        match self.read_response().await? {
-            Frame::Simple(response) if response == "OK" => Ok(()),
-            frame => Err(frame.to_error()),
-        }
+        pipeline::conditional(self.read_response().await?)
     }
 
-    /// Posts `message` to the given `channel`.
+    /// Set `key` to hold the given `value`, and return its previous value.
     ///
-    /// Returns the number of subscribers currently listening on the channel.
-    /// There is no guarantee that these subscribers receive the message as they
-    /// may disconnect at any time.
+    /// Any time to live associated with the key is discarded. An error is
+    /// returned if the key holds a value that is not a string.
     ///
     /// # Examples
     ///
@@ -287,132 +525,3119 @@ impl Client {
     /// async fn main() {
     ///     let mut client = Client::connect("localhost:6379").await.unwrap();
     ///
-    ///     let val = client.publish("foo", "bar".into()).await.unwrap();
-    ///     println!("Got = {:?}", val);
+    ///     let previous = client.getset("foo", "bar".into()).await.unwrap();
+    ///     println!("Got = {:?}", previous);
     /// }
     /// ```
     #[instrument(skip(self))]
-    pub async fn publish(&mut self, channel: &str, message: Bytes) -> crate::Result<u64> {
-        // Convert the `Publish` command into a frame
-        let frame = Publish::new(channel, message).into_frame();
+    pub async fn getset(&mut self, key: &str, value: Bytes) -> crate::Result<Option<Bytes>> {
+        let frame = Set::new(key, value, None).get().into_frame();
 
         debug!(request = ?frame);
 
-        // Write the frame to the socket
         self.connection.write_frame(&frame).await?;
 
-        // Read the response
-        match self.read_response().await? {
-            Frame::Integer(response) => Ok(response),
-            frame => Err(frame.to_error()),
-        }
+        pipeline::value(self.read_response().await?)
     }
 
-    /// Subscribes the client to the specified channels.
+    /// Get the value of key and delete the key.
     ///
-    /// Once a client issues a subscribe command, it may no longer issue any
-    /// non-pub/sub commands. The function consumes `self` and returns a `Subscriber`.
+    /// Returns `None` if the key does not exist.
     ///
-    /// The `Subscriber` value is used to receive messages as well as manage the
-    /// list of channels the client is subscribed to.
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.getdel("foo").await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
     #[instrument(skip(self))]
-    pub async fn subscribe(mut self, channels: Vec<String>) -> crate::Result<Subscriber> {
-        // Issue the subscribe command to the server and wait for confirmation.
-        // The client will then have been transitioned into the "subscriber"
-        // state and may only issue pub/sub commands from that point on.
-        self.subscribe_cmd(&channels).await?;
-
-        // Return the `Subscriber` type
-        Ok(Subscriber {
-            client: self,
-            subscribed_channels: channels,
-        })
-    }
-
-    /// The core `SUBSCRIBE` logic, used by misc subscribe fns
-    async fn subscribe_cmd(&mut self, channels: &[String]) -> crate::Result<()> {
-        // Convert the `Subscribe` command into a frame
-        let frame = Subscribe::new(channels.to_vec()).into_frame();
+    pub async fn getdel(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+        let frame = GetDel::new(key).into_frame();
 
         debug!(request = ?frame);
 
-        // Write the frame to the socket
         self.connection.write_frame(&frame).await?;
 
-        // For each channel being subscribed to, the server responds with a
-        // message confirming subscription to that channel.
-        for channel in channels {
-            // Read the response
-            let response = self.read_response().await?;
-
-            // Verify it is confirmation of subscription.
-            match response {
-                Frame::Array(ref frame) => match frame.as_slice() {
-                    // The server responds with an array frame in the form of:
-                    //
-                    // ```
-                    // [ "subscribe", channel, num-subscribed ]
-                    // ```
-                    //
-                    // where channel is the name of the channel and
-                    // num-subscribed is the number of channels that the client
-                    // is currently subscribed to.
-                    [subscribe, schannel, ..]
-                        if *subscribe == "subscribe" && *schannel == channel => {}
-                    _ => return Err(response.to_error()),
-                },
-                frame => return Err(frame.to_error()),
-            };
-        }
-
-        Ok(())
+        pipeline::value(self.read_response().await?)
     }
 
-    /// Reads a response frame from the socket.
+    /// Get the value of key and change its expiration.
     ///
-    /// If an `Error` frame is received, it is converted to `Err`.
-    async fn read_response(&mut self) -> crate::Result<Frame> {
-        let response = self.connection.read_frame().await?;
-
-        debug!(?response);
+    /// The key expires after `expiration`. If `expiration` is `None`, the
+    /// time to live of the key is removed instead. Returns `None` if the key
+    /// does not exist.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     // Extend the session by one hour
+    ///     let ttl = Some(Duration::from_secs(3600));
+    ///     let val = client.getex("session", ttl).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn getex(
+        &mut self,
+        key: &str,
+        expiration: Option<Duration>,
+    ) -> crate::Result<Option<Bytes>> {
+        let frame = GetEx::new(key, expiration).into_frame();
 
-        match response {
-            // Error frames are converted to `Err`
-            Some(Frame::Error(msg)) => Err(msg.into()),
-            Some(frame) => Ok(frame),
-            None => {
-                // Receiving `None` here indicates the server has closed the
-                // connection without sending a frame. This is unexpected and is
-                // represented as a "connection reset by peer" error.
-                let err = Error::new(ErrorKind::ConnectionReset, "connection reset by server");
+        debug!(request = ?frame);
 
-                Err(err.into())
-            }
-        }
-    }
-}
+        self.connection.write_frame(&frame).await?;
 
-impl Subscriber {
-    /// Returns the set of channels currently subscribed to.
-    pub fn get_subscribed(&self) -> &[String] {
-        &self.subscribed_channels
+        pipeline::value(self.read_response().await?)
     }
 
-    /// Receive the next message published on a subscribed channel, waiting if
-    /// necessary.
+    /// Increment the integer stored at `key` by one.
     ///
-    /// `None` indicates the subscription has been terminated.
-    pub async fn next_message(&mut self) -> crate::Result<Option<Message>> {
-        match self.client.connection.read_frame().await? {
-            Some(mframe) => {
-                debug!(?mframe);
-
-                match mframe {
-                    Frame::Array(ref frame) => match frame.as_slice() {
-                        [message, channel, content] if *message == "message" => Ok(Some(Message {
-                            channel: channel.to_string(),
-                            content: Bytes::from(content.to_string()),
-                        })),
+    /// A missing key is set to `0` before performing the operation. Returns
+    /// the value of the key after the increment.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.incr("visits").await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn incr(&mut self, key: &str) -> crate::Result<i64> {
+        self.incrby_cmd(IncrBy::new(key, 1)).await
+    }
+
+    /// Increment the integer stored at `key` by `increment`.
+    ///
+    /// A missing key is set to `0` before performing the operation. The
+    /// increment may be negative. Returns the value of the key after the
+    /// increment.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.incrby("visits", 10).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn incrby(&mut self, key: &str, increment: i64) -> crate::Result<i64> {
+        self.incrby_cmd(IncrBy::new(key, increment)).await
+    }
+
+    /// Decrement the integer stored at `key` by one.
+    ///
+    /// A missing key is set to `0` before performing the operation. Returns
+    /// the value of the key after the decrement.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.decr("stock").await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn decr(&mut self, key: &str) -> crate::Result<i64> {
+        self.incrby_cmd(IncrBy::decr(key, 1)).await
+    }
+
+    /// Decrement the integer stored at `key` by `decrement`.
+    ///
+    /// A missing key is set to `0` before performing the operation. Returns
+    /// the value of the key after the decrement.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.decrby("stock", 10).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn decrby(&mut self, key: &str, decrement: i64) -> crate::Result<i64> {
+        self.incrby_cmd(IncrBy::decr(key, decrement)).await
+    }
+
+    /// The core `INCRBY` logic, used by `incr`, `incrby`, `decr` and `decrby`.
+    async fn incrby_cmd(&mut self, cmd: IncrBy) -> crate::Result<i64> {
+        let frame = cmd.into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(value) => Ok(value),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Increment the floating point number stored at `key` by `increment`.
+    ///
+    /// A missing key is set to `0` before performing the operation. The
+    /// increment may be negative. Returns the value of the key after the
+    /// increment.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.incrbyfloat("balance", 10.5).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn incrbyfloat(&mut self, key: &str, increment: f64) -> crate::Result<f64> {
+        let frame = IncrByFloat::new(key, increment).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::float(self.read_response().await?)
+    }
+
+    /// Append `value` to the string stored at `key`.
+    ///
+    /// The key is created if it does not exist. Returns the length of the
+    /// string after the append.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.append("log", "line".into()).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn append(&mut self, key: &str, value: Bytes) -> crate::Result<u64> {
+        let frame = Append::new(key, value).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(len) => Ok(len.try_into()?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Returns the length of the string stored at `key`.
+    ///
+    /// A key that does not exist is treated as an empty string.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.strlen("log").await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn strlen(&mut self, key: &str) -> crate::Result<u64> {
+        let frame = StrLen::new(key).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(len) => Ok(len.try_into()?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Remove `keys`.
+    ///
+    /// Keys that do not exist are ignored. Returns the number of keys that
+    /// were removed.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.del(&["foo".to_string(), "bar".to_string()]).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn del(&mut self, keys: &[String]) -> crate::Result<u64> {
+        let frame = Del::new(keys).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(removed) => Ok(removed.try_into()?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Returns the number of `keys` that exist.
+    ///
+    /// A key given several times is counted as many times.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.exists(&["foo".to_string()]).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn exists(&mut self, keys: &[String]) -> crate::Result<u64> {
+        let frame = Exists::new(keys).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(count) => Ok(count.try_into()?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Returns all keys matching the glob-style `pattern`.
+    ///
+    /// All keys are visited at once, which blocks the server on large
+    /// databases. Use `scan` to iterate over the keys incrementally.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.keys("user:*").await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn keys(&mut self, pattern: &str) -> crate::Result<Vec<String>> {
+        let frame = Keys::new(pattern).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::strings(self.read_response().await?)
+    }
+
+    /// Continue iterating over the keys at `cursor`.
+    ///
+    /// An iteration starts with a cursor of `0`, and is complete once the
+    /// returned cursor is `0`. About `count` keys are visited per call, and
+    /// only those matching `pattern` and holding a value of type `kind` are
+    /// returned. Returns the cursor to use for the next call, along with the
+    /// keys.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let mut cursor = 0;
+    ///     loop {
+    ///         let (next, keys) = client
+    ///             .scan(cursor, Some("user:*"), Some(100), None)
+    ///             .await
+    ///             .unwrap();
+    ///         println!("Got = {:?}", keys);
+    ///
+    ///         if next == 0 {
+    ///             break;
+    ///         }
+    ///         cursor = next;
+    ///     }
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn scan(
+        &mut self,
+        cursor: u64,
+        pattern: Option<&str>,
+        count: Option<u64>,
+        kind: Option<&str>,
+    ) -> crate::Result<(u64, Vec<String>)> {
+        let mut scan = Scan::new(cursor);
+        if let Some(pattern) = pattern {
+            scan = scan.pattern(pattern);
+        }
+        if let Some(count) = count {
+            scan = scan.count(count);
+        }
+        if let Some(kind) = kind {
+            scan = scan.kind(kind);
+        }
+
+        let frame = scan.into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::scan(self.read_response().await?)
+    }
+
+    /// Returns the type of the value stored at `key`: `string`, `list`,
+    /// `hash`, `zset`, or `none` if the key does not exist.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.key_type("foo").await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn key_type(&mut self, key: &str) -> crate::Result<String> {
+        let frame = Type::new(key).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(kind) => Ok(kind),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Rename `key` to `newkey`.
+    ///
+    /// Any value stored at `newkey` is overwritten. An error is returned if
+    /// `key` does not exist.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.rename("foo", "bar").await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn rename(&mut self, key: &str, newkey: &str) -> crate::Result<()> {
+        let frame = Rename::new(key, newkey).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::ok(self.read_response().await?)
+    }
+
+    /// Select the database numbered `index` for the subsequent commands of
+    /// the connection.
+    ///
+    /// New connections operate on database `0`. An error is returned if there
+    /// is no such database.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.select(1).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn select(&mut self, index: usize) -> crate::Result<()> {
+        let frame = Select::new(index).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::ok(self.read_response().await?)
+    }
+
+    /// Move `key` from the selected database to the database numbered `db`.
+    ///
+    /// Returns `true` if the key was moved, and `false` if it does not exist
+    /// or already exists in the destination database.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.move_key("foo", 1).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn move_key(&mut self, key: &str, db: usize) -> crate::Result<bool> {
+        let frame = Move::new(key, db).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::boolean(self.read_response().await?)
+    }
+
+    /// Swap the data of the databases numbered `index1` and `index2`.
+    ///
+    /// Connections keep the database they selected, and see the data of the
+    /// other database from then on.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.swapdb(0, 1).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn swapdb(&mut self, index1: usize, index2: usize) -> crate::Result<()> {
+        let frame = SwapDb::new(index1, index2).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::ok(self.read_response().await?)
+    }
+
+    /// Returns the number of keys in the selected database.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.dbsize().await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn dbsize(&mut self) -> crate::Result<u64> {
+        let frame = DbSize::new().into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(len) => Ok(len.try_into()?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Remove all keys of the selected database.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.flushdb().await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn flushdb(&mut self) -> crate::Result<()> {
+        let frame = FlushDb::new().into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::ok(self.read_response().await?)
+    }
+
+    /// Remove all keys of all databases.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.flushall().await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn flushall(&mut self) -> crate::Result<()> {
+        let frame = FlushAll::new().into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::ok(self.read_response().await?)
+    }
+
+    /// The core `SET` logic, used by both `set` and `set_expires.
+    async fn set_cmd(&mut self, cmd: Set) -> crate::Result<()> {
+        // Convert the `Set` command into a frame
+        let frame = cmd.into_frame();
+
+        debug!(request = ?frame);
+
+        // Write the frame to the socket. This writes the full frame to the
+        // socket, waiting if necessary.
+        self.connection.write_frame(&frame).await?;
+
+        // Wait for the response from the server. On success, the server
+        // responds simply with `OK`. Any other response indicates an error.
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Set a timeout on `key`, after which it is deleted.
+    ///
+    /// If `condition` is set, the timeout is only changed if the condition
+    /// holds. Returns `true` if the timeout was set, or `false` if the key
+    /// does not exist or the condition does not hold.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    /// use mini_redis::cmd::ExpireCondition;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.set("session", "alice".into()).await.unwrap();
+    ///     client.expire("session", Duration::from_secs(60), None).await.unwrap();
+    ///
+    ///     // Only extends the timeout
+    ///     let ttl = Duration::from_secs(30);
+    ///     let set = client
+    ///         .expire("session", ttl, Some(ExpireCondition::Gt))
+    ///         .await
+    ///         .unwrap();
+    ///     assert!(!set);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn expire(
+        &mut self,
+        key: &str,
+        expiration: Duration,
+        condition: Option<ExpireCondition>,
+    ) -> crate::Result<bool> {
+        self.expire_cmd(Expire::new(key, Expiration::from(expiration), condition))
+            .await
+    }
+
+    /// Set `key` to expire at the given time.
+    ///
+    /// Same as `expire`, but with an absolute time. A time in the past deletes
+    /// the key.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let when = SystemTime::now() + Duration::from_secs(3600);
+    ///     client.expire_at("session", when, None).await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn expire_at(
+        &mut self,
+        key: &str,
+        when: SystemTime,
+        condition: Option<ExpireCondition>,
+    ) -> crate::Result<bool> {
+        let expiration = Expiration::from(when);
+
+        self.expire_cmd(Expire::new(key, expiration, condition))
+            .await
+    }
+
+    /// The core `PEXPIRE` logic, used by both `expire` and `expire_at`.
+    async fn expire_cmd(&mut self, cmd: Expire) -> crate::Result<bool> {
+        let frame = cmd.into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::boolean(self.read_response().await?)
+    }
+
+    /// Returns the remaining time to live of `key`, in seconds.
+    ///
+    /// Returns `-2` if the key does not exist, and `-1` if it has no timeout.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let ttl = client.ttl("session").await.unwrap();
+    ///     println!("Got = {:?}", ttl);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn ttl(&mut self, key: &str) -> crate::Result<i64> {
+        self.ttl_cmd(Ttl::new(key, TtlUnit::Seconds)).await
+    }
+
+    /// Returns the remaining time to live of `key`, in milliseconds.
+    ///
+    /// Returns `-2` if the key does not exist, and `-1` if it has no timeout.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let ttl = client.pttl("session").await.unwrap();
+    ///     println!("Got = {:?}", ttl);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn pttl(&mut self, key: &str) -> crate::Result<i64> {
+        self.ttl_cmd(Ttl::new(key, TtlUnit::Milliseconds)).await
+    }
+
+    /// The core `TTL` logic, used by both `ttl` and `pttl`.
+    async fn ttl_cmd(&mut self, cmd: Ttl) -> crate::Result<i64> {
+        let frame = cmd.into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(ttl) => Ok(ttl),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Remove the timeout of `key`, so that it never expires.
+    ///
+    /// Returns `true` if the timeout was removed, or `false` if the key does
+    /// not exist or has no timeout.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let persisted = client.persist("session").await.unwrap();
+    ///     println!("Got = {:?}", persisted);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn persist(&mut self, key: &str) -> crate::Result<bool> {
+        let frame = Persist::new(key).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::boolean(self.read_response().await?)
+    }
+
+    /// Insert all `values` at the head of the list stored at `key`.
+    ///
+    /// The list is created if it does not exist. Returns the length of the
+    /// list after the push.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let len = client.lpush("jobs", vec!["a".into(), "b".into()]).await.unwrap();
+    ///     assert_eq!(len, 2);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn lpush(&mut self, key: &str, values: Vec<Bytes>) -> crate::Result<u64> {
+        self.push_cmd(Push::new(key, values, Side::Left)).await
+    }
+
+    /// Insert all `values` at the tail of the list stored at `key`.
+    ///
+    /// The list is created if it does not exist. Returns the length of the
+    /// list after the push.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let len = client.rpush("jobs", vec!["a".into(), "b".into()]).await.unwrap();
+    ///     assert_eq!(len, 2);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn rpush(&mut self, key: &str, values: Vec<Bytes>) -> crate::Result<u64> {
+        self.push_cmd(Push::new(key, values, Side::Right)).await
+    }
+
+    /// The core `LPUSH`/`RPUSH` logic, used by both `lpush` and `rpush`.
+    async fn push_cmd(&mut self, cmd: Push) -> crate::Result<u64> {
+        let frame = cmd.into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(len) => Ok(len.try_into()?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Remove and return the first element of the list stored at `key`.
+    ///
+    /// Returns `None` if the key does not exist.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.lpop("jobs").await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn lpop(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+        self.pop_cmd(Pop::new(key, Side::Left)).await
+    }
+
+    /// Remove and return the last element of the list stored at `key`.
+    ///
+    /// Returns `None` if the key does not exist.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.rpop("jobs").await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn rpop(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+        self.pop_cmd(Pop::new(key, Side::Right)).await
+    }
+
+    /// The core `LPOP`/`RPOP` logic, used by both `lpop` and `rpop`.
+    async fn pop_cmd(&mut self, cmd: Pop) -> crate::Result<Option<Bytes>> {
+        let frame = cmd.into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Bulk(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Remove and return the first element of the first non-empty list among
+    /// `keys`, blocking until one is available.
+    ///
+    /// If all lists are empty, the server holds on to the request until
+    /// another client pushes to one of the keys. `None` is returned if nothing
+    /// was pushed within `timeout`. A zero `timeout` blocks indefinitely.
+    ///
+    /// On success, the name of the key the value was popped from is returned
+    /// along with the value.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let keys = vec!["jobs".to_string()];
+    ///     let val = client.blpop(&keys, Duration::from_secs(5)).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn blpop(
+        &mut self,
+        keys: &[String],
+        timeout: Duration,
+    ) -> crate::Result<Option<(String, Bytes)>> {
+        self.bpop_cmd(BPop::new(keys, timeout, Side::Left)).await
+    }
+
+    /// Remove and return the last element of the first non-empty list among
+    /// `keys`, blocking until one is available.
+    ///
+    /// See [`blpop`](Client::blpop) for details.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let keys = vec!["jobs".to_string()];
+    ///     let val = client.brpop(&keys, Duration::from_secs(5)).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn brpop(
+        &mut self,
+        keys: &[String],
+        timeout: Duration,
+    ) -> crate::Result<Option<(String, Bytes)>> {
+        self.bpop_cmd(BPop::new(keys, timeout, Side::Right)).await
+    }
+
+    /// The core `BLPOP`/`BRPOP` logic, used by both `blpop` and `brpop`.
+    async fn bpop_cmd(&mut self, cmd: BPop) -> crate::Result<Option<(String, Bytes)>> {
+        let frame = cmd.into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Array(frames) if frames.len() == 2 => {
+                let mut frames = frames.into_iter();
+
+                match (frames.next(), frames.next()) {
+                    (Some(Frame::Bulk(key)), Some(Frame::Bulk(value))) => {
+                        Ok(Some((String::from_utf8(key.to_vec())?, value)))
+                    }
+                    _ => Err("protocol error; invalid BLPOP response".into()),
+                }
+            }
+            Frame::Null => Ok(None),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Returns the elements of the list stored at `key` between the `start` and
+    /// `stop` indices, both inclusive.
+    ///
+    /// Negative indices count from the end of the list, so `lrange(key, 0, -1)`
+    /// returns the whole list.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let values = client.lrange("jobs", 0, -1).await.unwrap();
+    ///     println!("Got = {:?}", values);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn lrange(&mut self, key: &str, start: i64, stop: i64) -> crate::Result<Vec<Bytes>> {
+        let frame = LRange::new(key, start, stop).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::values(self.read_response().await?)
+    }
+
+    /// Returns the length of the list stored at `key`.
+    ///
+    /// A key that does not exist is treated as an empty list.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let len = client.llen("jobs").await.unwrap();
+    ///     println!("Got = {:?}", len);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn llen(&mut self, key: &str) -> crate::Result<u64> {
+        let frame = LLen::new(key).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(len) => Ok(len.try_into()?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Set `fields` of the hash stored at `key` to their respective values.
+    ///
+    /// The hash is created if `key` does not exist. Fields already present in
+    /// the hash are overwritten. Returns the number of fields that were added.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let fields = vec![("user".to_string(), "alice".into())];
+    ///     let added = client.hset("session", fields).await.unwrap();
+    ///     println!("Added = {:?}", added);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn hset(&mut self, key: &str, fields: Vec<(String, Bytes)>) -> crate::Result<u64> {
+        let fields = fields
+            .into_iter()
+            .map(|(field, value)| (Bytes::from(field), value))
+            .collect();
+        let frame = HSet::new(key, fields).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(added) => Ok(added.try_into()?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Get the value of `field` in the hash stored at `key`.
+    ///
+    /// Returns `None` if either the field or the key does not exist.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.hget("session", "user").await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn hget(&mut self, key: &str, field: &str) -> crate::Result<Option<Bytes>> {
+        let frame = HGet::new(key, Bytes::copy_from_slice(field.as_bytes())).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Bulk(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Remove `fields` from the hash stored at `key`.
+    ///
+    /// Returns the number of fields that were removed. Fields that do not
+    /// exist are ignored.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let removed = client.hdel("session", &["user".to_string()]).await.unwrap();
+    ///     println!("Removed = {:?}", removed);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn hdel(&mut self, key: &str, fields: &[String]) -> crate::Result<u64> {
+        let fields = fields
+            .iter()
+            .map(|field| Bytes::copy_from_slice(field.as_bytes()))
+            .collect();
+        let frame = HDel::new(key, fields).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(removed) => Ok(removed.try_into()?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Get all fields and values of the hash stored at `key`.
+    ///
+    /// A key that does not exist is treated as an empty hash.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let fields = client.hgetall("session").await.unwrap();
+    ///     println!("Got = {:?}", fields);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn hgetall(&mut self, key: &str) -> crate::Result<HashMap<String, Bytes>> {
+        let frame = HGetAll::new(key).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::hash(self.read_response().await?)
+    }
+
+    /// Increment the integer stored in `field` of the hash stored at `key` by
+    /// `increment`.
+    ///
+    /// A missing field is set to `0` before performing the operation. Returns
+    /// the value of the field after the increment.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let hits = client.hincrby("session", "hits", 1).await.unwrap();
+    ///     println!("Got = {:?}", hits);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn hincrby(&mut self, key: &str, field: &str, increment: i64) -> crate::Result<i64> {
+        let frame =
+            HIncrBy::new(key, Bytes::copy_from_slice(field.as_bytes()), increment).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(value) => Ok(value),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Add `members` with their scores to the sorted set stored at `key`.
+    ///
+    /// The sorted set is created if `key` does not exist. Members already in
+    /// the set have their score updated. Returns the number of members that
+    /// were added.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let members = vec![(10.0, "alice".into()), (20.0, "bob".into())];
+    ///     let added = client.zadd("scores", members).await.unwrap();
+    ///     println!("Added = {:?}", added);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn zadd(&mut self, key: &str, members: Vec<(f64, Bytes)>) -> crate::Result<u64> {
+        let frame = ZAdd::new(key, members).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(added) => Ok(added.try_into()?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Returns the members of the sorted set stored at `key` between the
+    /// `start` and `stop` indices, both inclusive, ordered from the lowest to
+    /// the highest score.
+    ///
+    /// Negative indices count from the end of the set, so `zrange(key, -3,
+    /// -1)` returns the three members with the highest scores.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let members = client.zrange("scores", 0, -1).await.unwrap();
+    ///     println!("Got = {:?}", members);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn zrange(&mut self, key: &str, start: i64, stop: i64) -> crate::Result<Vec<Bytes>> {
+        let frame = ZRange::new(key, start, stop, false).into_frame();
+        self.members_cmd(frame).await
+    }
+
+    /// Same as [`zrange`](Client::zrange), but each member is returned along
+    /// with its score.
+    #[instrument(skip(self))]
+    pub async fn zrange_withscores(
+        &mut self,
+        key: &str,
+        start: i64,
+        stop: i64,
+    ) -> crate::Result<Vec<(Bytes, f64)>> {
+        let frame = ZRange::new(key, start, stop, true).into_frame();
+        self.scored_members_cmd(frame).await
+    }
+
+    /// Returns the members of the sorted set stored at `key` with a score
+    /// between `min` and `max`, both inclusive, ordered from the lowest to the
+    /// highest score.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let members = client.zrangebyscore("scores", 10.0, f64::INFINITY).await.unwrap();
+    ///     println!("Got = {:?}", members);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn zrangebyscore(
+        &mut self,
+        key: &str,
+        min: f64,
+        max: f64,
+    ) -> crate::Result<Vec<Bytes>> {
+        let frame = ZRangeByScore::new(key, min, max, false).into_frame();
+        self.members_cmd(frame).await
+    }
+
+    /// Same as [`zrangebyscore`](Client::zrangebyscore), but each member is
+    /// returned along with its score.
+    #[instrument(skip(self))]
+    pub async fn zrangebyscore_withscores(
+        &mut self,
+        key: &str,
+        min: f64,
+        max: f64,
+    ) -> crate::Result<Vec<(Bytes, f64)>> {
+        let frame = ZRangeByScore::new(key, min, max, true).into_frame();
+        self.scored_members_cmd(frame).await
+    }
+
+    /// Returns the rank of `member` in the sorted set stored at `key`.
+    ///
+    /// The rank is the zero-based position of the member, ordered from the
+    /// lowest to the highest score. Returns `None` if either the member or the
+    /// key does not exist.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let rank = client.zrank("scores", "alice".into()).await.unwrap();
+    ///     println!("Got = {:?}", rank);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn zrank(&mut self, key: &str, member: Bytes) -> crate::Result<Option<u64>> {
+        let frame = ZRank::new(key, member).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(rank) => Ok(Some(rank.try_into()?)),
+            Frame::Null => Ok(None),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Remove `members` from the sorted set stored at `key`.
+    ///
+    /// Returns the number of members that were removed. Members that do not
+    /// exist are ignored.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let removed = client.zrem("scores", vec!["alice".into()]).await.unwrap();
+    ///     println!("Removed = {:?}", removed);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn zrem(&mut self, key: &str, members: Vec<Bytes>) -> crate::Result<u64> {
+        let frame = ZRem::new(key, members).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(removed) => Ok(removed.try_into()?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Send a sorted set range query and read back the members.
+    async fn members_cmd(&mut self, frame: Frame) -> crate::Result<Vec<Bytes>> {
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::values(self.read_response().await?)
+    }
+
+    /// Send a sorted set range query using `WITHSCORES` and read back the
+    /// members along with their scores.
+    ///
+    /// RESP3 connections receive the scores as doubles, while RESP2
+    /// connections receive them as bulk strings.
+    async fn scored_members_cmd(&mut self, frame: Frame) -> crate::Result<Vec<(Bytes, f64)>> {
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        let frames = match self.read_response().await? {
+            Frame::Array(frames) if frames.len() % 2 == 0 => frames,
+            frame => return Err(frame.to_error()),
+        };
+
+        let mut frames = frames.into_iter();
+        let mut members = vec![];
+
+        while let (Some(member), Some(score)) = (frames.next(), frames.next()) {
+            let score = match score {
+                Frame::Double(score) => score,
+                Frame::Bulk(score) => std::str::from_utf8(&score)?.parse()?,
+                frame => return Err(frame.to_error()),
+            };
+
+            match member {
+                Frame::Bulk(member) => members.push((member, score)),
+                frame => return Err(frame.to_error()),
+            }
+        }
+
+        Ok(members)
+    }
+
+    /// Append an entry made of `fields` to the stream stored at `key`.
+    ///
+    /// `id` is the ID of the new entry. It is generated from the current time
+    /// when `*` is given, and must otherwise be greater than the ID of the
+    /// last entry. With `maxlen`, the oldest entries are removed so at most
+    /// `maxlen` entries remain.
+    ///
+    /// Returns the ID of the new entry.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.xadd("events", "*", vec![("kind".to_string(), "login".into())], None).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xadd(
+        &mut self,
+        key: &str,
+        id: &str,
+        fields: Vec<(String, Bytes)>,
+        maxlen: Option<usize>,
+    ) -> crate::Result<String> {
+        let fields = fields
+            .into_iter()
+            .map(|(field, value)| (Bytes::from(field.into_bytes()), value))
+            .collect();
+        let frame = XAdd::new(key, parse_new_id(id)?, fields, maxlen).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::string(self.read_response().await?)
+    }
+
+    /// Returns the entries of the stream stored at `key` with an ID between
+    /// `start` and `end`, both inclusive, up to `count` entries.
+    ///
+    /// `-` and `+` are the smallest and greatest possible IDs. An ID without
+    /// a sequence number, such as `1700000000000`, includes all the entries
+    /// of that millisecond, and an ID prefixed with `(` is excluded.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.xrange("events", "-", "+", Some(10)).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xrange(
+        &mut self,
+        key: &str,
+        start: &str,
+        end: &str,
+        count: Option<usize>,
+    ) -> crate::Result<Vec<StreamEntry>> {
+        let start = parse_range_start(start)?;
+        let end = parse_range_end(end)?;
+        let frame = XRange::new(key, start, end, count).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::stream_entries(self.read_response().await?)
+    }
+
+    /// Returns the number of entries in the stream stored at `key`.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.xlen("events").await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xlen(&mut self, key: &str) -> crate::Result<u64> {
+        let frame = XLen::new(key).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(len) => Ok(len.try_into()?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Remove the oldest entries of the stream stored at `key`, so at most
+    /// `maxlen` entries remain.
+    ///
+    /// Returns the number of entries removed.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.xtrim("events", 1000).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xtrim(&mut self, key: &str, maxlen: usize) -> crate::Result<u64> {
+        let frame = XTrim::new(key, maxlen).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(removed) => Ok(removed.try_into()?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Read the entries added to streams after the given IDs, up to `count`
+    /// entries per stream.
+    ///
+    /// `streams` holds the key of each stream along with the ID after which
+    /// entries are returned. The ID `$` only returns entries added after the
+    /// request is received.
+    ///
+    /// With `block`, the server holds on to the request until an entry is
+    /// added if no stream has any, for at most `block`. A zero `block` waits
+    /// indefinitely.
+    ///
+    /// Returns, for each stream with entries, its key and the entries.
+    /// Streams without entries are omitted, so an empty vector is returned if
+    /// nothing was read.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.xread(&[("events", "$")], None, Some(Duration::from_secs(5))).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xread(
+        &mut self,
+        streams: &[(&str, &str)],
+        count: Option<usize>,
+        block: Option<Duration>,
+    ) -> crate::Result<Vec<(String, Vec<StreamEntry>)>> {
+        let keys = streams.iter().map(|(key, _)| key.to_string()).collect();
+        let ids = streams
+            .iter()
+            .map(|(_, id)| parse_read_id(id))
+            .collect::<crate::Result<_>>()?;
+        let frame = XRead::new(keys, ids, count, block).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::streams(self.read_response().await?)
+    }
+
+    /// Create the consumer group `group` of the stream stored at `key`.
+    ///
+    /// The group delivers the entries with an ID greater than `id`. With the
+    /// special ID `$`, only entries added from now on are delivered. If the
+    /// key does not exist, an empty stream is created if `mkstream` is set,
+    /// and an error is returned otherwise.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.xgroup_create("events", "workers", "$", true).await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xgroup_create(
+        &mut self,
+        key: &str,
+        group: &str,
+        id: &str,
+        mkstream: bool,
+    ) -> crate::Result<()> {
+        let frame = XGroup::create(key, group, parse_group_id(id)?, mkstream).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::ok(self.read_response().await?)
+    }
+
+    /// Set the ID of the last entry delivered to the consumer group `group`
+    /// of the stream stored at `key`, so the entries with a greater ID are
+    /// delivered next. The special ID `$` is the ID of the last entry.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.xgroup_setid("events", "workers", "0").await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xgroup_setid(&mut self, key: &str, group: &str, id: &str) -> crate::Result<()> {
+        let frame = XGroup::setid(key, group, parse_group_id(id)?).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::ok(self.read_response().await?)
+    }
+
+    /// Remove the consumer group `group` of the stream stored at `key`.
+    ///
+    /// Returns `true` if the group existed.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.xgroup_destroy("events", "workers").await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xgroup_destroy(&mut self, key: &str, group: &str) -> crate::Result<bool> {
+        let frame = XGroup::destroy(key, group).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::boolean(self.read_response().await?)
+    }
+
+    /// Create the consumer `consumer` in the consumer group `group` of the
+    /// stream stored at `key`. Consumers are also created when they first
+    /// read from the group.
+    ///
+    /// Returns `true` if the consumer was created, `false` if it already
+    /// existed.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.xgroup_createconsumer("events", "workers", "worker-1").await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xgroup_createconsumer(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+    ) -> crate::Result<bool> {
+        let frame = XGroup::create_consumer(key, group, consumer).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::boolean(self.read_response().await?)
+    }
+
+    /// Remove the consumer `consumer` from the consumer group `group` of the
+    /// stream stored at `key`. The entries pending for the consumer are no
+    /// longer pending.
+    ///
+    /// Returns the number of entries which were pending for the consumer.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.xgroup_delconsumer("events", "workers", "worker-1").await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xgroup_delconsumer(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+    ) -> crate::Result<u64> {
+        let frame = XGroup::del_consumer(key, group, consumer).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::unsigned(self.read_response().await?)
+    }
+
+    /// Read streams as `consumer` of the consumer group `group`, up to
+    /// `count` entries per stream.
+    ///
+    /// `streams` holds the key of each stream along with where to read it
+    /// from. The special ID `>` reads the entries never delivered to the
+    /// group, which are then pending for the consumer until acknowledged with
+    /// [`xack`](Client::xack), unless `noack` is set. Any other ID reads the
+    /// entries already pending for the consumer with a greater ID, such as
+    /// after a restart.
+    ///
+    /// With `block`, the server holds on to the request until an entry is
+    /// added if there is none to read, for at most `block`. A zero `block`
+    /// waits indefinitely.
+    ///
+    /// Returns, for each stream read, its key and the entries. An empty
+    /// vector is returned if nothing was read.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client
+    ///         .xreadgroup("workers", "worker-1", &[("events", ">")], Some(10), Some(Duration::from_secs(5)), false)
+    ///         .await
+    ///         .unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xreadgroup(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        streams: &[(&str, &str)],
+        count: Option<usize>,
+        block: Option<Duration>,
+        noack: bool,
+    ) -> crate::Result<Vec<(String, Vec<StreamEntry>)>> {
+        let streams = streams
+            .iter()
+            .map(|(key, id)| Ok((key.to_string(), parse_group_read_id(id)?)))
+            .collect::<crate::Result<_>>()?;
+        let frame = XReadGroup::new(group, consumer, streams, count, block, noack).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::streams(self.read_response().await?)
+    }
+
+    /// Acknowledge the entries `ids` pending in the consumer group `group` of
+    /// the stream stored at `key`.
+    ///
+    /// Returns the number of entries which were pending.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.xack("events", "workers", &["1700000000000-0"]).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xack(&mut self, key: &str, group: &str, ids: &[&str]) -> crate::Result<u64> {
+        let ids = ids
+            .iter()
+            .map(|id| parse_range_start(id))
+            .collect::<crate::Result<_>>()?;
+        let frame = XAck::new(key, group, ids).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::unsigned(self.read_response().await?)
+    }
+
+    /// Returns a summary of the entries pending in the consumer group `group`
+    /// of the stream stored at `key`.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.xpending("events", "workers").await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xpending(&mut self, key: &str, group: &str) -> crate::Result<PendingSummary> {
+        let frame = XPending::summary(key, group).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::pending_summary(self.read_response().await?)
+    }
+
+    /// Returns the entries pending in the consumer group `group` of the
+    /// stream stored at `key`, with an ID between `start` and `end`, up to
+    /// `count` entries. With `consumer`, only the entries pending for that
+    /// consumer are returned.
+    ///
+    /// IDs are given as for [`xrange`](Client::xrange).
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.xpending_range("events", "workers", "-", "+", 10, None).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xpending_range(
+        &mut self,
+        key: &str,
+        group: &str,
+        start: &str,
+        end: &str,
+        count: usize,
+        consumer: Option<&str>,
+    ) -> crate::Result<Vec<PendingEntry>> {
+        let start = parse_range_start(start)?;
+        let end = parse_range_end(end)?;
+        let consumer = consumer.map(str::to_string);
+        let frame = XPending::range(key, group, start, end, count, consumer).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::pending_entries(self.read_response().await?)
+    }
+
+    /// Transfer the entries `ids` pending in the consumer group `group` of
+    /// the stream stored at `key` to `consumer`, if they were not delivered
+    /// for at least `min_idle`.
+    ///
+    /// This is how a consumer takes over the entries of another consumer
+    /// which failed. Each claimed entry counts as delivered again.
+    ///
+    /// Returns the claimed entries.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client
+    ///         .xclaim("events", "workers", "worker-2", Duration::from_secs(60), &["1700000000000-0"])
+    ///         .await
+    ///         .unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn xclaim(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle: Duration,
+        ids: &[&str],
+    ) -> crate::Result<Vec<StreamEntry>> {
+        let ids = ids
+            .iter()
+            .map(|id| parse_range_start(id))
+            .collect::<crate::Result<_>>()?;
+        let min_idle_ms = min_idle.as_millis() as u64;
+        let frame =
+            XClaim::new(key, group, consumer, min_idle_ms, ids, Default::default()).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::stream_entries(self.read_response().await?)
+    }
+
+    /// Ask the server to compact its append-only file.
+    ///
+    /// The rewrite happens in the background. This returns once it has been
+    /// started. Fails if the server does not have persistence enabled.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.bgrewriteaof().await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn bgrewriteaof(&mut self) -> crate::Result<()> {
+        let frame = BgRewriteAof::new().into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(_) => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Ask the server to write a snapshot of its data to disk.
+    ///
+    /// Returns once the snapshot is on disk. The server does not process any
+    /// other command in the meantime; prefer `bgsave` on a busy server.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.save().await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn save(&mut self) -> crate::Result<()> {
+        let frame = Save::new().into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Ask the server to write a snapshot of its data to disk in the
+    /// background.
+    ///
+    /// Returns once the snapshot has been taken, before it is written. Fails
+    /// if a background save is already in progress.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.bgsave().await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn bgsave(&mut self) -> crate::Result<()> {
+        let frame = BgSave::new().into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(_) => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Run the Lua `script` on the server.
+    ///
+    /// `keys` and `args` are available to the script as the `KEYS` and `ARGV`
+    /// tables. Returns the value returned by the script. The script is cached
+    /// by the server, and can be run again with `evalsha`.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let script = "return redis.call('SET', KEYS[1], ARGV[1])";
+    ///     let keys = ["foo".to_string()];
+    ///     let val = client.eval(script, &keys, vec!["bar".into()]).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn eval(
+        &mut self,
+        script: &str,
+        keys: &[String],
+        args: Vec<Bytes>,
+    ) -> crate::Result<Frame> {
+        let script = Bytes::copy_from_slice(script.as_bytes());
+        let frame = Eval::new(script, keys.to_vec(), args).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        self.read_response().await
+    }
+
+    /// Run the Lua script cached on the server with the given SHA1 digest.
+    ///
+    /// Fails with a `NOSCRIPT` error if the script is not cached.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let sha1 = client.script_load("return 1").await.unwrap();
+    ///     let val = client.evalsha(&sha1, &[], vec![]).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn evalsha(
+        &mut self,
+        sha1: &str,
+        keys: &[String],
+        args: Vec<Bytes>,
+    ) -> crate::Result<Frame> {
+        let frame = EvalSha::new(sha1, keys.to_vec(), args).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        self.read_response().await
+    }
+
+    /// Cache the Lua `script` on the server without running it.
+    ///
+    /// Returns the SHA1 digest of the script, to pass to `evalsha`.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let sha1 = client.script_load("return 1").await.unwrap();
+    ///     println!("Got = {:?}", sha1);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn script_load(&mut self, script: &str) -> crate::Result<String> {
+        let frame = Script::load(Bytes::copy_from_slice(script.as_bytes())).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Bulk(sha1) => Ok(String::from_utf8(sha1.to_vec())?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Check whether the scripts with the given SHA1 digests are cached on
+    /// the server.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let sha1 = client.script_load("return 1").await.unwrap();
+    ///     let exists = client.script_exists(&[sha1]).await.unwrap();
+    ///     assert_eq!(vec![true], exists);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn script_exists(&mut self, sha1s: &[String]) -> crate::Result<Vec<bool>> {
+        let frame = Script::exists(sha1s.to_vec()).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Array(frames) => frames
+                .into_iter()
+                .map(|frame| match frame {
+                    Frame::Integer(exists) => Ok(exists == 1),
+                    frame => Err(frame.to_error()),
+                })
+                .collect(),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Remove all scripts from the server's script cache.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.script_flush().await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn script_flush(&mut self) -> crate::Result<()> {
+        let frame = Script::flush().into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Make the server a replica of the master listening on `host` and `port`.
+    ///
+    /// The server replaces its data with the master's, then applies every
+    /// command modifying the data executed by the master. The synchronization
+    /// happens in the background: the call returns before it completes.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6380").await.unwrap();
+    ///
+    ///     client.replicaof("localhost", 6379).await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn replicaof(&mut self, host: &str, port: u16) -> crate::Result<()> {
+        self.replicaof_cmd(ReplicaOf::new(host, port)).await
+    }
+
+    /// Stop following the master, and promote the server to master.
+    ///
+    /// The server keeps its data, and accepts commands modifying it again.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6380").await.unwrap();
+    ///
+    ///     client.replicaof_no_one().await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn replicaof_no_one(&mut self) -> crate::Result<()> {
+        self.replicaof_cmd(ReplicaOf::no_one()).await
+    }
+
+    /// Core `REPLICAOF` logic, used by both `replicaof` and
+    /// `replicaof_no_one`.
+    async fn replicaof_cmd(&mut self, cmd: ReplicaOf) -> crate::Result<()> {
+        let frame = cmd.into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Get information about the server.
+    ///
+    /// Returns the requested `section`, or all sections, as `field:value`
+    /// lines. Only the `replication` section is supported.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let info = client.info(Some("replication")).await.unwrap();
+    ///     println!("{}", info);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn info(&mut self, section: Option<&str>) -> crate::Result<String> {
+        let frame = Info::new(section.map(str::to_string)).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Bulk(info) | Frame::Verbatim(_, info) => {
+                Ok(String::from_utf8_lossy(&info).into_owned())
+            }
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Get the settings of the server matching the glob-style `pattern`.
+    ///
+    /// Returns the value of each matching setting, by name.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let settings = client.config_get("max*").await.unwrap();
+    ///     println!("Got = {:?}", settings);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn config_get(&mut self, pattern: &str) -> crate::Result<HashMap<String, String>> {
+        let frame = Config::get(pattern).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::hash(self.read_response().await?)?
+            .into_iter()
+            .map(|(name, value)| Ok((name, String::from_utf8(value.to_vec())?)))
+            .collect()
+    }
+
+    /// Change the setting `name` of the server to `value`.
+    ///
+    /// Only some settings can be changed while the server runs, such as
+    /// `maxclients` and `timeout`. The change is lost when the server
+    /// restarts, unless written to the configuration file with
+    /// [`config_rewrite`](Client::config_rewrite).
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.config_set("maxclients", "1000").await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn config_set(&mut self, name: &str, value: &str) -> crate::Result<()> {
+        let frame = Config::set(name, value).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::ok(self.read_response().await?)
+    }
+
+    /// Write the current settings of the server to the configuration file it
+    /// was started with.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.config_rewrite().await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn config_rewrite(&mut self) -> crate::Result<()> {
+        let frame = Config::rewrite().into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        pipeline::ok(self.read_response().await?)
+    }
+
+    /// Get the hash slots served by each node of the cluster.
+    ///
+    /// Returns an error if the server does not run in cluster mode. This is
+    /// used by [`ClusterClient`](crate::clients::ClusterClient) to find the
+    /// node serving a key.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:7000").await.unwrap();
+    ///
+    ///     for range in client.cluster_slots().await.unwrap() {
+    ///         println!("{:?} -> {}:{}", range.slots, range.host, range.port);
+    ///     }
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn cluster_slots(&mut self) -> crate::Result<Vec<SlotRange>> {
+        let frame = Cluster::slots().into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        let ranges = match self.read_response().await? {
+            Frame::Array(ranges) => ranges,
+            frame => return Err(frame.to_error()),
+        };
+
+        ranges
+            .into_iter()
+            .map(|range| {
+                let entries = match range {
+                    Frame::Array(entries) => entries,
+                    frame => return Err(frame.to_error()),
+                };
+
+                match &entries[..] {
+                    [Frame::Integer(start), Frame::Integer(end), Frame::Array(node), ..] => {
+                        match &node[..] {
+                            [Frame::Bulk(host), Frame::Integer(port), Frame::Bulk(id), ..] => {
+                                Ok(SlotRange {
+                                    slots: (*start).try_into()?..=(*end).try_into()?,
+                                    host: String::from_utf8(host.to_vec())?,
+                                    port: (*port).try_into()?,
+                                    id: String::from_utf8(id.to_vec())?,
+                                })
+                            }
+                            _ => Err("protocol error; invalid CLUSTER SLOTS node".into()),
+                        }
+                    }
+                    _ => Err("protocol error; invalid CLUSTER SLOTS range".into()),
+                }
+            })
+            .collect()
+    }
+
+    /// Send all commands queued in `pipeline` and read their replies.
+    ///
+    /// The commands are written to the socket together, and the replies are
+    /// read once they are all sent, saving a round trip per command. A command
+    /// failing does not prevent the others from running: its error is
+    /// returned when its reply is taken from the returned [`Responses`].
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::{Client, Pipeline};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let mut pipeline = Pipeline::new();
+    ///     let len = pipeline.rpush("jobs", vec!["a".into()]);
+    ///     let job = pipeline.lpop("jobs");
+    ///
+    ///     let mut responses = client.send_pipeline(pipeline).await.unwrap();
+    ///     println!("Len = {:?}", responses.take(len));
+    ///     println!("Got = {:?}", responses.take(job));
+    /// }
+    /// ```
+    #[instrument(skip(self, pipeline), fields(len = pipeline.len()))]
+    pub async fn send_pipeline(&mut self, pipeline: Pipeline) -> crate::Result<Responses> {
+        let frames = pipeline.frames();
+
+        debug!(requests = ?frames);
+
+        self.connection.write_frames(frames).await?;
+
+        let mut replies = Vec::with_capacity(frames.len());
+
+        for _ in 0..frames.len() {
+            replies.push(self.read_reply().await?);
+        }
+
+        Ok(Responses::new(replies))
+    }
+
+    /// Watch `keys` for modifications.
+    ///
+    /// The next transaction executed with [`Transaction::exec`] is aborted if
+    /// any of the keys is modified by another client in the meantime. All keys
+    /// are unwatched once a transaction is executed.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.watch(&["balance".to_string()]).await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn watch(&mut self, keys: &[String]) -> crate::Result<()> {
+        let frame = Watch::new(keys.to_vec()).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Stop watching all keys watched with `watch`.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     client.watch(&["balance".to_string()]).await.unwrap();
+    ///     client.unwatch().await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn unwatch(&mut self) -> crate::Result<()> {
+        let frame = Unwatch::new().into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Start building a transaction.
+    ///
+    /// Commands are added to the returned [`Transaction`] and sent to the
+    /// server when [`Transaction::exec`] is called. They are then executed
+    /// atomically: no other client observes the key space in the middle of the
+    /// transaction.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let responses = client
+    ///         .transaction()
+    ///         .set("foo", "bar".into())
+    ///         .get("foo")
+    ///         .exec()
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     println!("Got = {:?}", responses);
+    /// }
+    /// ```
+    pub fn transaction(&mut self) -> Transaction<'_> {
+        Transaction {
+            client: self,
+            commands: vec![],
+        }
+    }
+
+    /// Posts `message` to the given `channel`.
+    ///
+    /// Returns the number of subscribers currently listening on the channel.
+    /// There is no guarantee that these subscribers receive the message as they
+    /// may disconnect at any time.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = Client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let val = client.publish("foo", "bar".into()).await.unwrap();
+    ///     println!("Got = {:?}", val);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn publish(&mut self, channel: &str, message: Bytes) -> crate::Result<u64> {
+        // Convert the `Publish` command into a frame
+        let frame = Publish::new(channel, message).into_frame();
+
+        debug!(request = ?frame);
+
+        // Write the frame to the socket
+        self.connection.write_frame(&frame).await?;
+
+        // Read the response
+        match self.read_response().await? {
+            Frame::Integer(response) => Ok(response.try_into()?),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Subscribes the client to the specified channels.
+    ///
+    /// Once a client issues a subscribe command, it may no longer issue any
+    /// non-pub/sub commands. The function consumes `self` and returns a `Subscriber`.
+    ///
+    /// The `Subscriber` value is used to receive messages as well as manage the
+    /// list of channels the client is subscribed to.
+    #[instrument(skip(self))]
+    pub async fn subscribe(mut self, channels: Vec<String>) -> crate::Result<Subscriber> {
+        // Issue the subscribe command to the server and wait for confirmation.
+        // The client will then have been transitioned into the "subscriber"
+        // state and may only issue pub/sub commands from that point on.
+        let frame = Subscribe::new(channels.clone()).into_frame();
+        self.subscribe_cmd(frame, "subscribe", &channels).await?;
+
+        // Return the `Subscriber` type
+        Ok(Subscriber {
+            client: self,
+            subscribed_channels: channels,
+            subscribed_patterns: vec![],
+        })
+    }
+
+    /// Subscribes the client to channels matching the specified glob-style
+    /// patterns, such as `news.*`.
+    ///
+    /// Same as `subscribe`, the function consumes `self` and returns a
+    /// `Subscriber`. Messages received through a pattern subscription carry
+    /// the pattern they matched.
+    #[instrument(skip(self))]
+    pub async fn psubscribe(mut self, patterns: Vec<String>) -> crate::Result<Subscriber> {
+        let frame = PSubscribe::new(patterns.clone()).into_frame();
+        self.subscribe_cmd(frame, "psubscribe", &patterns).await?;
+
+        Ok(Subscriber {
+            client: self,
+            subscribed_channels: vec![],
+            subscribed_patterns: patterns,
+        })
+    }
+
+    /// The core `SUBSCRIBE` and `PSUBSCRIBE` logic, used by misc subscribe
+    /// fns.
+    ///
+    /// `frame` is the command subscribing to `channels`, and `kind` the name
+    /// of the command, which the server uses in its confirmations.
+    async fn subscribe_cmd(
+        &mut self,
+        frame: Frame,
+        kind: &str,
+        channels: &[String],
+    ) -> crate::Result<()> {
+        debug!(request = ?frame);
+
+        // Write the frame to the socket
+        self.connection.write_frame(&frame).await?;
+
+        // For each channel being subscribed to, the server responds with a
+        // message confirming subscription to that channel.
+        for channel in channels {
+            // Read the response
+            let response = self.read_response().await?;
+
+            // Verify it is confirmation of subscription. With RESP3, it is
+            // received as a push frame.
+            match response {
+                Frame::Array(ref frame) | Frame::Push(ref frame) => match frame.as_slice() {
+                    // The server responds with an array frame in the form of:
+                    //
+                    // ```
+                    // [ "subscribe", channel, num-subscribed ]
+                    // ```
+                    //
+                    // where channel is the name of the channel and
+                    // num-subscribed is the number of channels and patterns
+                    // that the client is currently subscribed to.
+                    [subscribe, schannel, ..] if *subscribe == kind && *schannel == channel => {}
+                    _ => return Err(response.to_error()),
+                },
+                frame => return Err(frame.to_error()),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// The core `UNSUBSCRIBE` and `PUNSUBSCRIBE` logic.
+    ///
+    /// `frame` is the command unsubscribing from `channels`, and `kind` the
+    /// name of the command. Channels are removed from `subscribed` as the
+    /// server confirms them.
+    async fn unsubscribe_cmd(
+        &mut self,
+        frame: Frame,
+        kind: &str,
+        channels: &[String],
+        subscribed: &mut Vec<String>,
+    ) -> crate::Result<()> {
+        debug!(request = ?frame);
+
+        // Write the frame to the socket
+        self.connection.write_frame(&frame).await?;
+
+        // if the input channel list is empty, server acknowledges as unsubscribing
+        // from all subscribed channels, so we assert that the unsubscribe list received
+        // matches the client subscribed one
+        let num = if channels.is_empty() {
+            subscribed.len()
+        } else {
+            channels.len()
+        };
+
+        // Read the response
+        for _ in 0..num {
+            let response = self.read_response().await?;
+
+            match response {
+                Frame::Array(ref frame) | Frame::Push(ref frame) => match frame.as_slice() {
+                    [unsubscribe, channel, ..] if *unsubscribe == kind => {
+                        let len = subscribed.len();
+
+                        if len == 0 {
+                            // There must be at least one channel
+                            return Err(response.to_error());
+                        }
+
+                        // unsubscribed channel should exist in the subscribed list at this point
+                        subscribed.retain(|c| *channel != &c[..]);
+
+                        // Only a single channel should be removed from the
+                        // list of subscribed channels.
+                        if subscribed.len() != len - 1 {
+                            return Err(response.to_error());
+                        }
+                    }
+                    _ => return Err(response.to_error()),
+                },
+                frame => return Err(frame.to_error()),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Reads a response frame from the socket.
+    ///
+    /// If an `Error` frame is received, it is converted to `Err`.
+    async fn read_response(&mut self) -> crate::Result<Frame> {
+        match self.read_reply().await? {
+            // Error frames are converted to `Err`
+            Frame::Error(msg) => Err(msg.into()),
+            frame => Ok(frame),
+        }
+    }
+
+    /// Reads a response frame from the socket, including `Error` frames.
+    async fn read_reply(&mut self) -> crate::Result<Frame> {
+        let response = self.connection.read_frame().await?;
+
+        debug!(?response);
+
+        match response {
+            Some(frame) => Ok(frame),
+            None => {
+                // Receiving `None` here indicates the server has closed the
+                // connection without sending a frame. This is unexpected and is
+                // represented as a "connection reset by peer" error.
+                let err = Error::new(ErrorKind::ConnectionReset, "connection reset by server");
+
+                Err(err.into())
+            }
+        }
+    }
+}
+
+impl Subscriber {
+    /// Returns the set of channels currently subscribed to.
+    pub fn get_subscribed(&self) -> &[String] {
+        &self.subscribed_channels
+    }
+
+    /// Returns the set of patterns currently subscribed to.
+    pub fn get_subscribed_patterns(&self) -> &[String] {
+        &self.subscribed_patterns
+    }
+
+    /// Receive the next message published on a subscribed channel, or on a
+    /// channel matching a subscribed pattern, waiting if necessary.
+    ///
+    /// `None` indicates the subscription has been terminated.
+    pub async fn next_message(&mut self) -> crate::Result<Option<Message>> {
+        match self.client.connection.read_frame().await? {
+            Some(mframe) => {
+                debug!(?mframe);
+
+                match mframe {
+                    Frame::Array(ref frame) | Frame::Push(ref frame) => match frame.as_slice() {
+                        [message, channel, content] if *message == "message" => Ok(Some(Message {
+                            pattern: None,
+                            channel: channel.to_string(),
+                            content: Bytes::from(content.to_string()),
+                        })),
+                        [message, pattern, channel, content] if *message == "pmessage" => {
+                            Ok(Some(Message {
+                                pattern: Some(pattern.to_string()),
+                                channel: channel.to_string(),
+                                content: Bytes::from(content.to_string()),
+                            }))
+                        }
                         _ => Err(mframe.to_error()),
                     },
                     frame => Err(frame.to_error()),
@@ -446,7 +3671,10 @@ impl Subscriber {
     #[instrument(skip(self))]
     pub async fn subscribe(&mut self, channels: &[String]) -> crate::Result<()> {
         // Issue the subscribe command
-        self.client.subscribe_cmd(channels).await?;
+        let frame = Subscribe::new(channels.to_vec()).into_frame();
+        self.client
+            .subscribe_cmd(frame, "subscribe", channels)
+            .await?;
 
         // Update the set of subscribed channels.
         self.subscribed_channels
@@ -460,49 +3688,192 @@ impl Subscriber {
     pub async fn unsubscribe(&mut self, channels: &[String]) -> crate::Result<()> {
         let frame = Unsubscribe::new(channels).into_frame();
 
-        debug!(request = ?frame);
+        self.client
+            .unsubscribe_cmd(
+                frame,
+                "unsubscribe",
+                channels,
+                &mut self.subscribed_channels,
+            )
+            .await
+    }
 
-        // Write the frame to the socket
-        self.client.connection.write_frame(&frame).await?;
+    /// Subscribe to a list of new patterns
+    #[instrument(skip(self))]
+    pub async fn psubscribe(&mut self, patterns: &[String]) -> crate::Result<()> {
+        let frame = PSubscribe::new(patterns.to_vec()).into_frame();
+        self.client
+            .subscribe_cmd(frame, "psubscribe", patterns)
+            .await?;
 
-        // if the input channel list is empty, server acknowledges as unsubscribing
-        // from all subscribed channels, so we assert that the unsubscribe list received
-        // matches the client subscribed one
-        let num = if channels.is_empty() {
-            self.subscribed_channels.len()
-        } else {
-            channels.len()
-        };
+        self.subscribed_patterns
+            .extend(patterns.iter().map(Clone::clone));
 
-        // Read the response
-        for _ in 0..num {
-            let response = self.client.read_response().await?;
+        Ok(())
+    }
 
-            match response {
-                Frame::Array(ref frame) => match frame.as_slice() {
-                    [unsubscribe, channel, ..] if *unsubscribe == "unsubscribe" => {
-                        let len = self.subscribed_channels.len();
+    /// Unsubscribe from a list of patterns. An empty list unsubscribes from
+    /// all patterns.
+    #[instrument(skip(self))]
+    pub async fn punsubscribe(&mut self, patterns: &[String]) -> crate::Result<()> {
+        let frame = PUnsubscribe::new(patterns).into_frame();
 
-                        if len == 0 {
-                            // There must be at least one channel
-                            return Err(response.to_error());
-                        }
+        self.client
+            .unsubscribe_cmd(
+                frame,
+                "punsubscribe",
+                patterns,
+                &mut self.subscribed_patterns,
+            )
+            .await
+    }
+}
 
-                        // unsubscribed channel should exist in the subscribed list at this point
-                        self.subscribed_channels.retain(|c| *channel != &c[..]);
+impl Transaction<'_> {
+    /// Queue a `GET` of `key`.
+    pub fn get(mut self, key: &str) -> Self {
+        self.commands.push(Get::new(key).into_frame());
+        self
+    }
 
-                        // Only a single channel should be removed from the
-                        // list of subscribed channels.
-                        if self.subscribed_channels.len() != len - 1 {
-                            return Err(response.to_error());
-                        }
-                    }
-                    _ => return Err(response.to_error()),
-                },
-                frame => return Err(frame.to_error()),
-            };
+    /// Queue a `SET` of `key` to `value`.
+    pub fn set(mut self, key: &str, value: Bytes) -> Self {
+        self.commands.push(Set::new(key, value, None).into_frame());
+        self
+    }
+
+    /// Queue a `SET` of `key` to `value`, expiring after `expiration`.
+    pub fn set_expires(mut self, key: &str, value: Bytes, expiration: Duration) -> Self {
+        self.commands
+            .push(Set::new(key, value, Some(expiration)).into_frame());
+        self
+    }
+
+    /// Queue a `LPUSH` of `values` to the list stored at `key`.
+    pub fn lpush(mut self, key: &str, values: Vec<Bytes>) -> Self {
+        self.commands
+            .push(Push::new(key, values, Side::Left).into_frame());
+        self
+    }
+
+    /// Queue a `RPUSH` of `values` to the list stored at `key`.
+    pub fn rpush(mut self, key: &str, values: Vec<Bytes>) -> Self {
+        self.commands
+            .push(Push::new(key, values, Side::Right).into_frame());
+        self
+    }
+
+    /// Queue a `LPOP` from the list stored at `key`.
+    pub fn lpop(mut self, key: &str) -> Self {
+        self.commands.push(Pop::new(key, Side::Left).into_frame());
+        self
+    }
+
+    /// Queue a `RPOP` from the list stored at `key`.
+    pub fn rpop(mut self, key: &str) -> Self {
+        self.commands.push(Pop::new(key, Side::Right).into_frame());
+        self
+    }
+
+    /// Queue a `HSET` of `fields` in the hash stored at `key`.
+    pub fn hset(mut self, key: &str, fields: Vec<(String, Bytes)>) -> Self {
+        let fields = fields
+            .into_iter()
+            .map(|(field, value)| (Bytes::from(field), value))
+            .collect();
+        self.commands.push(HSet::new(key, fields).into_frame());
+        self
+    }
+
+    /// Queue a `HGET` of `field` in the hash stored at `key`.
+    pub fn hget(mut self, key: &str, field: &str) -> Self {
+        self.commands
+            .push(HGet::new(key, Bytes::copy_from_slice(field.as_bytes())).into_frame());
+        self
+    }
+
+    /// Queue a `HINCRBY` of `field` in the hash stored at `key`.
+    pub fn hincrby(mut self, key: &str, field: &str, increment: i64) -> Self {
+        let field = Bytes::copy_from_slice(field.as_bytes());
+        self.commands
+            .push(HIncrBy::new(key, field, increment).into_frame());
+        self
+    }
+
+    /// Queue a `DEL` of `keys`.
+    pub fn del(mut self, keys: &[String]) -> Self {
+        self.commands.push(Del::new(keys).into_frame());
+        self
+    }
+
+    /// Queue an `INCRBY` of `key` by `increment`.
+    pub fn incrby(mut self, key: &str, increment: i64) -> Self {
+        self.commands.push(IncrBy::new(key, increment).into_frame());
+        self
+    }
+
+    /// Queue a `ZADD` of `members` to the sorted set stored at `key`.
+    pub fn zadd(mut self, key: &str, members: Vec<(f64, Bytes)>) -> Self {
+        self.commands.push(ZAdd::new(key, members).into_frame());
+        self
+    }
+
+    /// Queue a `ZREM` of `members` from the sorted set stored at `key`.
+    pub fn zrem(mut self, key: &str, members: Vec<Bytes>) -> Self {
+        self.commands.push(ZRem::new(key, members).into_frame());
+        self
+    }
+
+    /// Send the queued commands to the server and execute them atomically.
+    ///
+    /// Returns the response of each command, in order. Commands failing at
+    /// execution time, such as a `GET` of a list, are represented by an
+    /// `Error` frame and do not prevent the other commands from running.
+    ///
+    /// Returns `None` if a key watched with [`Client::watch`] was modified
+    /// since it was watched, in which case no command is executed.
+    pub async fn exec(self) -> crate::Result<Option<Vec<Frame>>> {
+        let num = self.commands.len();
+
+        let mut frames = Vec::with_capacity(num + 2);
+        frames.push(Multi::new().into_frame());
+        frames.extend(self.commands);
+        frames.push(Exec::new().into_frame());
+
+        debug!(requests = ?frames);
+
+        // All frames are written before any response is read. The server
+        // replies to each of them in order.
+        self.client.connection.write_frames(&frames).await?;
+
+        match self.client.read_response().await? {
+            Frame::Simple(response) if response == "OK" => {}
+            frame => return Err(frame.to_error()),
         }
 
-        Ok(())
+        // Each command is acknowledged with `QUEUED`. If one of them is
+        // rejected, the remaining responses are still read, so the connection
+        // stays usable, and the server discards the transaction.
+        let mut error = None;
+
+        for _ in 0..num {
+            match self.client.read_response().await {
+                Ok(Frame::Simple(response)) if response == "QUEUED" => {}
+                Ok(frame) => error = error.or_else(|| Some(frame.to_error())),
+                Err(err) => error = error.or(Some(err)),
+            }
+        }
+
+        let response = self.client.read_response().await;
+
+        if let Some(err) = error {
+            return Err(err);
+        }
+
+        match response? {
+            Frame::Array(responses) => Ok(Some(responses)),
+            Frame::Null => Ok(None),
+            frame => Err(frame.to_error()),
+        }
     }
 }
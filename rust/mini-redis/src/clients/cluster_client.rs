@@ -0,0 +1,338 @@
+use crate::clients::{Client, Pending, Pipeline};
+use crate::cluster::{self, SLOTS};
+use crate::Result;
+
+use bytes::Bytes;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Maximum number of redirects followed for a single command.
+const MAX_REDIRECTS: usize = 5;
+
+/// Client for a cluster of mini-redis servers.
+///
+/// Each command is sent to the node serving the hash slot of its key. The
+/// client caches the slot map reported by `CLUSTER SLOTS`, and keeps one
+/// connection per node. When a node replies with a `MOVED` redirect, the slot
+/// map is reloaded and the command is sent again to the new node. `ASK`
+/// redirects, received while a slot is migrated, are followed for that
+/// command only.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mini_redis::clients::ClusterClient;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut client = ClusterClient::connect("localhost:7000").await.unwrap();
+///
+///     // Both keys share the `{user:1}` hash tag, so they are stored on the
+///     // same node.
+///     client.set("{user:1}:name", "alice".into()).await.unwrap();
+///     client.set("{user:1}:email", "alice@example.com".into()).await.unwrap();
+/// }
+/// ```
+pub struct ClusterClient {
+    /// Connections to the nodes, by address.
+    nodes: HashMap<String, Client>,
+
+    /// Address of the node serving each slot.
+    slots: Vec<Option<String>>,
+}
+
+/// Redirect replied by a node which does not serve a slot.
+enum Redirect {
+    /// The slot is served by the node at the given address.
+    Moved(String),
+
+    /// The slot is being migrated to the node at the given address, which
+    /// has the key.
+    Ask(String),
+}
+
+impl ClusterClient {
+    /// Connect to the cluster through the node listening at `addr`.
+    ///
+    /// The slot map is loaded from that node. Connections to the other nodes
+    /// are established when a command is first sent to them.
+    pub async fn connect(addr: &str) -> Result<ClusterClient> {
+        let mut client = ClusterClient {
+            nodes: HashMap::new(),
+            slots: vec![None; SLOTS as usize],
+        };
+
+        client.refresh_slots(addr).await?;
+
+        Ok(client)
+    }
+
+    /// Get the value of a key.
+    ///
+    /// Same as `Client::get` but the request is sent to the node serving
+    /// `key`.
+    pub async fn get(&mut self, key: &str) -> Result<Option<Bytes>> {
+        self.send(key, |pipeline| pipeline.get(key)).await
+    }
+
+    /// Set `key` to hold the given `value`.
+    ///
+    /// Same as `Client::set` but the request is sent to the node serving
+    /// `key`.
+    pub async fn set(&mut self, key: &str, value: Bytes) -> Result<()> {
+        self.send(key, |pipeline| pipeline.set(key, value.clone()))
+            .await
+    }
+
+    /// Set `key` to hold the given `value`, expiring after `expiration`.
+    ///
+    /// Same as `Client::set_expires` but the request is sent to the node
+    /// serving `key`.
+    pub async fn set_expires(
+        &mut self,
+        key: &str,
+        value: Bytes,
+        expiration: Duration,
+    ) -> Result<()> {
+        self.send(key, |pipeline| {
+            pipeline.set_expires(key, value.clone(), expiration)
+        })
+        .await
+    }
+
+    /// Insert `values` at the head of the list stored at `key`.
+    ///
+    /// Same as `Client::lpush` but the request is sent to the node serving
+    /// `key`.
+    pub async fn lpush(&mut self, key: &str, values: Vec<Bytes>) -> Result<u64> {
+        self.send(key, |pipeline| pipeline.lpush(key, values.clone()))
+            .await
+    }
+
+    /// Insert `values` at the tail of the list stored at `key`.
+    ///
+    /// Same as `Client::rpush` but the request is sent to the node serving
+    /// `key`.
+    pub async fn rpush(&mut self, key: &str, values: Vec<Bytes>) -> Result<u64> {
+        self.send(key, |pipeline| pipeline.rpush(key, values.clone()))
+            .await
+    }
+
+    /// Remove and return the first element of the list stored at `key`.
+    ///
+    /// Same as `Client::lpop` but the request is sent to the node serving
+    /// `key`.
+    pub async fn lpop(&mut self, key: &str) -> Result<Option<Bytes>> {
+        self.send(key, |pipeline| pipeline.lpop(key)).await
+    }
+
+    /// Remove and return the last element of the list stored at `key`.
+    ///
+    /// Same as `Client::rpop` but the request is sent to the node serving
+    /// `key`.
+    pub async fn rpop(&mut self, key: &str) -> Result<Option<Bytes>> {
+        self.send(key, |pipeline| pipeline.rpop(key)).await
+    }
+
+    /// Get the elements of the list stored at `key` between `start` and
+    /// `stop`.
+    ///
+    /// Same as `Client::lrange` but the request is sent to the node serving
+    /// `key`.
+    pub async fn lrange(&mut self, key: &str, start: i64, stop: i64) -> Result<Vec<Bytes>> {
+        self.send(key, |pipeline| pipeline.lrange(key, start, stop))
+            .await
+    }
+
+    /// Get the length of the list stored at `key`.
+    ///
+    /// Same as `Client::llen` but the request is sent to the node serving
+    /// `key`.
+    pub async fn llen(&mut self, key: &str) -> Result<u64> {
+        self.send(key, |pipeline| pipeline.llen(key)).await
+    }
+
+    /// Set `fields` of the hash stored at `key` to their respective values.
+    ///
+    /// Same as `Client::hset` but the request is sent to the node serving
+    /// `key`.
+    pub async fn hset(&mut self, key: &str, fields: Vec<(String, Bytes)>) -> Result<u64> {
+        self.send(key, |pipeline| pipeline.hset(key, fields.clone()))
+            .await
+    }
+
+    /// Get the value of `field` in the hash stored at `key`.
+    ///
+    /// Same as `Client::hget` but the request is sent to the node serving
+    /// `key`.
+    pub async fn hget(&mut self, key: &str, field: &str) -> Result<Option<Bytes>> {
+        self.send(key, |pipeline| pipeline.hget(key, field)).await
+    }
+
+    /// Remove `fields` from the hash stored at `key`.
+    ///
+    /// Same as `Client::hdel` but the request is sent to the node serving
+    /// `key`.
+    pub async fn hdel(&mut self, key: &str, fields: &[String]) -> Result<u64> {
+        self.send(key, |pipeline| pipeline.hdel(key, fields)).await
+    }
+
+    /// Get all fields and values of the hash stored at `key`.
+    ///
+    /// Same as `Client::hgetall` but the request is sent to the node serving
+    /// `key`.
+    pub async fn hgetall(&mut self, key: &str) -> Result<HashMap<String, Bytes>> {
+        self.send(key, |pipeline| pipeline.hgetall(key)).await
+    }
+
+    /// Increment `field` of the hash stored at `key` by `increment`.
+    ///
+    /// Same as `Client::hincrby` but the request is sent to the node serving
+    /// `key`.
+    pub async fn hincrby(&mut self, key: &str, field: &str, increment: i64) -> Result<i64> {
+        self.send(key, |pipeline| pipeline.hincrby(key, field, increment))
+            .await
+    }
+
+    /// Increment the integer stored at `key` by `increment`.
+    ///
+    /// Same as `Client::incrby` but the request is sent to the node serving
+    /// `key`.
+    pub async fn incrby(&mut self, key: &str, increment: i64) -> Result<i64> {
+        self.send(key, |pipeline| pipeline.incrby(key, increment))
+            .await
+    }
+
+    /// Add `members` with their scores to the sorted set stored at `key`.
+    ///
+    /// Same as `Client::zadd` but the request is sent to the node serving
+    /// `key`.
+    pub async fn zadd(&mut self, key: &str, members: Vec<(f64, Bytes)>) -> Result<u64> {
+        self.send(key, |pipeline| pipeline.zadd(key, members.clone()))
+            .await
+    }
+
+    /// Get the rank of `member` in the sorted set stored at `key`.
+    ///
+    /// Same as `Client::zrank` but the request is sent to the node serving
+    /// `key`.
+    pub async fn zrank(&mut self, key: &str, member: Bytes) -> Result<Option<u64>> {
+        self.send(key, |pipeline| pipeline.zrank(key, member.clone()))
+            .await
+    }
+
+    /// Remove `members` from the sorted set stored at `key`.
+    ///
+    /// Same as `Client::zrem` but the request is sent to the node serving
+    /// `key`.
+    pub async fn zrem(&mut self, key: &str, members: Vec<Bytes>) -> Result<u64> {
+        self.send(key, |pipeline| pipeline.zrem(key, members.clone()))
+            .await
+    }
+
+    /// Send the command queued by `queue` to the node serving `key`,
+    /// following redirects.
+    ///
+    /// `queue` is called again for each redirect, as the command is sent in a
+    /// new pipeline. After an `ASK` redirect, the pipeline starts with
+    /// `ASKING`.
+    async fn send<T>(
+        &mut self,
+        key: &str,
+        queue: impl Fn(&mut Pipeline) -> Pending<T>,
+    ) -> Result<T> {
+        let slot = cluster::key_hash_slot(key.as_bytes());
+
+        let mut addr = match &self.slots[slot as usize] {
+            Some(addr) => addr.clone(),
+            None => return Err(format!("hash slot {} is not served", slot).into()),
+        };
+        let mut asking = false;
+
+        for _ in 0..=MAX_REDIRECTS {
+            let mut pipeline = Pipeline::new();
+            let asking_reply = if asking {
+                Some(pipeline.command(vec![Bytes::from("asking")]))
+            } else {
+                None
+            };
+            let reply = queue(&mut pipeline);
+
+            let client = self.node(&addr).await?;
+            let mut responses = match client.send_pipeline(pipeline).await {
+                Ok(responses) => responses,
+                Err(err) => {
+                    // Reconnect on the next command
+                    self.nodes.remove(&addr);
+                    return Err(err);
+                }
+            };
+
+            if let Some(asking_reply) = asking_reply {
+                responses.take(asking_reply)?;
+            }
+
+            let err = match responses.take(reply) {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+
+            match Redirect::parse(&err.to_string()) {
+                Some(Redirect::Moved(to)) => {
+                    // Slots usually move in batches, so the whole map is
+                    // reloaded rather than just this slot.
+                    self.refresh_slots(&to).await?;
+                    addr = to;
+                    asking = false;
+                }
+                Some(Redirect::Ask(to)) => {
+                    addr = to;
+                    asking = true;
+                }
+                None => return Err(err),
+            }
+        }
+
+        Err(format!("too many redirects for key '{}'", key).into())
+    }
+
+    /// Reload the slot map from the node at `addr`.
+    async fn refresh_slots(&mut self, addr: &str) -> Result<()> {
+        let ranges = self.node(addr).await?.cluster_slots().await?;
+
+        self.slots = vec![None; SLOTS as usize];
+
+        for range in ranges {
+            let addr = format!("{}:{}", range.host, range.port);
+
+            for slot in range.slots {
+                self.slots[slot as usize] = Some(addr.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the connection to the node at `addr`, connecting to it if
+    /// needed.
+    async fn node(&mut self, addr: &str) -> Result<&mut Client> {
+        match self.nodes.entry(addr.to_string()) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(Client::connect(addr).await?)),
+        }
+    }
+}
+
+impl Redirect {
+    /// Parse a `MOVED <slot> <addr>` or `ASK <slot> <addr>` error.
+    fn parse(msg: &str) -> Option<Redirect> {
+        let mut parts = msg.split(' ');
+
+        match (parts.next()?, parts.next()?, parts.next()?) {
+            ("MOVED", _, addr) => Some(Redirect::Moved(addr.to_string())),
+            ("ASK", _, addr) => Some(Redirect::Ask(addr.to_string())),
+            _ => None,
+        }
+    }
+}
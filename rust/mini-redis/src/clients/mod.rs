@@ -1,8 +1,16 @@
 mod client;
-pub use client::{Client, Message, Subscriber};
+pub use client::{
+    Client, Message, PendingEntry, PendingSummary, StreamEntry, Subscriber, Transaction,
+};
+
+mod pipeline;
+pub use pipeline::{Pending, Pipeline, Responses};
 
 mod blocking_client;
 pub use blocking_client::BlockingClient;
 
 mod buffered_client;
 pub use buffered_client::BufferedClient;
+
+mod cluster_client;
+pub use cluster_client::ClusterClient;
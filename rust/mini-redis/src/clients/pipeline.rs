@@ -0,0 +1,659 @@
+//! Batches of commands sent to the server in a single write.
+//!
+//! Commands are queued in a [`Pipeline`], which is sent with
+//! [`Client::send_pipeline`](crate::clients::Client::send_pipeline). The
+//! server replies to each command in order, and the replies are collected in
+//! [`Responses`]. Queuing a command returns a [`Pending`] handle used to
+//! retrieve its reply, converted to the same type the equivalent `Client`
+//! method returns.
+
+use crate::clients::{PendingEntry, PendingSummary, StreamEntry};
+use crate::cmd::{
+    Append, Del, Exists, Expiration, Expire, ExpireCondition, Get, GetDel, GetEx, HDel, HGet,
+    HGetAll, HIncrBy, HSet, IncrBy, IncrByFloat, LLen, LRange, Move, Persist, Pop, Publish, Push,
+    Rename, Set, SetCondition, StrLen, Ttl, TtlUnit, XLen, XTrim, ZAdd, ZRank, ZRem,
+};
+use crate::db::Side;
+use crate::Frame;
+
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// A batch of commands to send to the server.
+///
+/// Nothing is sent until the pipeline is passed to
+/// [`Client::send_pipeline`](crate::clients::Client::send_pipeline). Unlike a
+/// transaction, other clients may run commands in between the commands of a
+/// pipeline.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mini_redis::clients::{Client, Pipeline};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut client = Client::connect("localhost:6379").await.unwrap();
+///
+///     let mut pipeline = Pipeline::new();
+///     let set = pipeline.set("foo", "bar".into());
+///     let get = pipeline.get("foo");
+///
+///     let mut responses = client.send_pipeline(pipeline).await.unwrap();
+///
+///     responses.take(set).unwrap();
+///     let value = responses.take(get).unwrap();
+///     println!("Got = {:?}", value);
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    /// Frames of the queued commands, in the order they are sent.
+    frames: Vec<Frame>,
+}
+
+/// Handle to the reply of a command queued in a [`Pipeline`].
+///
+/// Passed to [`Responses::take`] to retrieve the reply, as a `T`.
+pub struct Pending<T> {
+    /// Position of the command in the pipeline.
+    index: usize,
+
+    /// Converts the reply frame to the type returned to the caller.
+    convert: fn(Frame) -> crate::Result<T>,
+
+    _p: PhantomData<fn() -> T>,
+}
+
+/// Replies to the commands of a [`Pipeline`], in the order they were queued.
+#[derive(Debug)]
+pub struct Responses {
+    /// Reply frames. Replies are replaced with `None` once taken.
+    frames: Vec<Option<Frame>>,
+}
+
+impl Pipeline {
+    /// Create an empty pipeline.
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    /// Returns the number of queued commands.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns `true` if no command is queued.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Queue an arbitrary command, given as its name followed by its
+    /// arguments.
+    ///
+    /// The reply is returned as is.
+    pub fn command(&mut self, args: Vec<Bytes>) -> Pending<Frame> {
+        let frame = Frame::Array(args.into_iter().map(Frame::Bulk).collect());
+        self.queue(frame, Ok)
+    }
+
+    /// Queue a `GET` of `key`. See [`Client::get`](crate::clients::Client::get).
+    pub fn get(&mut self, key: &str) -> Pending<Option<Bytes>> {
+        self.queue(Get::new(key).into_frame(), value)
+    }
+
+    /// Queue a `SET` of `key` to `value`. See
+    /// [`Client::set`](crate::clients::Client::set).
+    pub fn set(&mut self, key: &str, value: Bytes) -> Pending<()> {
+        self.queue(Set::new(key, value, None).into_frame(), ok)
+    }
+
+    /// Queue a `SET` of `key` to `value`, expiring after `expiration`. See
+    /// [`Client::set_expires`](crate::clients::Client::set_expires).
+    pub fn set_expires(&mut self, key: &str, value: Bytes, expiration: Duration) -> Pending<()> {
+        self.queue(Set::new(key, value, Some(expiration)).into_frame(), ok)
+    }
+
+    /// Queue a conditional `SET` of `key` to `value`. See
+    /// [`Client::set_if`](crate::clients::Client::set_if).
+    pub fn set_if(
+        &mut self,
+        key: &str,
+        value: Bytes,
+        condition: SetCondition,
+        expiration: Option<Duration>,
+    ) -> Pending<bool> {
+        let set = Set::new(key, value, expiration).condition(condition);
+        self.queue(set.into_frame(), conditional)
+    }
+
+    /// Queue a `GETSET` of `key` to `value`. See
+    /// [`Client::getset`](crate::clients::Client::getset).
+    pub fn getset(&mut self, key: &str, new_value: Bytes) -> Pending<Option<Bytes>> {
+        self.queue(Set::new(key, new_value, None).get().into_frame(), value)
+    }
+
+    /// Queue a `GETDEL` of `key`. See
+    /// [`Client::getdel`](crate::clients::Client::getdel).
+    pub fn getdel(&mut self, key: &str) -> Pending<Option<Bytes>> {
+        self.queue(GetDel::new(key).into_frame(), value)
+    }
+
+    /// Queue a `GETEX` of `key`. See
+    /// [`Client::getex`](crate::clients::Client::getex).
+    pub fn getex(&mut self, key: &str, expiration: Option<Duration>) -> Pending<Option<Bytes>> {
+        self.queue(GetEx::new(key, expiration).into_frame(), value)
+    }
+
+    /// Queue a `PEXPIRE` of `key`. See
+    /// [`Client::expire`](crate::clients::Client::expire).
+    pub fn expire(
+        &mut self,
+        key: &str,
+        expiration: Duration,
+        condition: Option<ExpireCondition>,
+    ) -> Pending<bool> {
+        let expire = Expire::new(key, Expiration::from(expiration), condition);
+
+        self.queue(expire.into_frame(), boolean)
+    }
+
+    /// Queue a `TTL` of `key`. See [`Client::ttl`](crate::clients::Client::ttl).
+    pub fn ttl(&mut self, key: &str) -> Pending<i64> {
+        self.queue(Ttl::new(key, TtlUnit::Seconds).into_frame(), integer)
+    }
+
+    /// Queue a `PTTL` of `key`. See
+    /// [`Client::pttl`](crate::clients::Client::pttl).
+    pub fn pttl(&mut self, key: &str) -> Pending<i64> {
+        self.queue(Ttl::new(key, TtlUnit::Milliseconds).into_frame(), integer)
+    }
+
+    /// Queue a `PERSIST` of `key`. See
+    /// [`Client::persist`](crate::clients::Client::persist).
+    pub fn persist(&mut self, key: &str) -> Pending<bool> {
+        self.queue(Persist::new(key).into_frame(), boolean)
+    }
+
+    /// Queue an `INCRBY` of `key` by `increment`. See
+    /// [`Client::incrby`](crate::clients::Client::incrby).
+    pub fn incrby(&mut self, key: &str, increment: i64) -> Pending<i64> {
+        self.queue(IncrBy::new(key, increment).into_frame(), integer)
+    }
+
+    /// Queue a `DECRBY` of `key` by `decrement`. See
+    /// [`Client::decrby`](crate::clients::Client::decrby).
+    pub fn decrby(&mut self, key: &str, decrement: i64) -> Pending<i64> {
+        self.queue(IncrBy::decr(key, decrement).into_frame(), integer)
+    }
+
+    /// Queue an `INCRBYFLOAT` of `key` by `increment`. See
+    /// [`Client::incrbyfloat`](crate::clients::Client::incrbyfloat).
+    pub fn incrbyfloat(&mut self, key: &str, increment: f64) -> Pending<f64> {
+        self.queue(IncrByFloat::new(key, increment).into_frame(), float)
+    }
+
+    /// Queue an `APPEND` of `value` to the string stored at `key`. See
+    /// [`Client::append`](crate::clients::Client::append).
+    pub fn append(&mut self, key: &str, value: Bytes) -> Pending<u64> {
+        self.queue(Append::new(key, value).into_frame(), unsigned)
+    }
+
+    /// Queue a `STRLEN` of the string stored at `key`. See
+    /// [`Client::strlen`](crate::clients::Client::strlen).
+    pub fn strlen(&mut self, key: &str) -> Pending<u64> {
+        self.queue(StrLen::new(key).into_frame(), unsigned)
+    }
+
+    /// Queue a `DEL` of `keys`. See [`Client::del`](crate::clients::Client::del).
+    pub fn del(&mut self, keys: &[String]) -> Pending<u64> {
+        self.queue(Del::new(keys).into_frame(), unsigned)
+    }
+
+    /// Queue an `EXISTS` of `keys`. See
+    /// [`Client::exists`](crate::clients::Client::exists).
+    pub fn exists(&mut self, keys: &[String]) -> Pending<u64> {
+        self.queue(Exists::new(keys).into_frame(), unsigned)
+    }
+
+    /// Queue a `RENAME` of `key` to `newkey`. See
+    /// [`Client::rename`](crate::clients::Client::rename).
+    pub fn rename(&mut self, key: &str, newkey: &str) -> Pending<()> {
+        self.queue(Rename::new(key, newkey).into_frame(), ok)
+    }
+
+    /// Queue a `MOVE` of `key` to the database numbered `db`. See
+    /// [`Client::move_key`](crate::clients::Client::move_key).
+    pub fn move_key(&mut self, key: &str, db: usize) -> Pending<bool> {
+        self.queue(Move::new(key, db).into_frame(), boolean)
+    }
+
+    /// Queue a `LPUSH` of `values` to the list stored at `key`. See
+    /// [`Client::lpush`](crate::clients::Client::lpush).
+    pub fn lpush(&mut self, key: &str, values: Vec<Bytes>) -> Pending<u64> {
+        self.queue(Push::new(key, values, Side::Left).into_frame(), unsigned)
+    }
+
+    /// Queue a `RPUSH` of `values` to the list stored at `key`. See
+    /// [`Client::rpush`](crate::clients::Client::rpush).
+    pub fn rpush(&mut self, key: &str, values: Vec<Bytes>) -> Pending<u64> {
+        self.queue(Push::new(key, values, Side::Right).into_frame(), unsigned)
+    }
+
+    /// Queue a `LPOP` from the list stored at `key`. See
+    /// [`Client::lpop`](crate::clients::Client::lpop).
+    pub fn lpop(&mut self, key: &str) -> Pending<Option<Bytes>> {
+        self.queue(Pop::new(key, Side::Left).into_frame(), value)
+    }
+
+    /// Queue a `RPOP` from the list stored at `key`. See
+    /// [`Client::rpop`](crate::clients::Client::rpop).
+    pub fn rpop(&mut self, key: &str) -> Pending<Option<Bytes>> {
+        self.queue(Pop::new(key, Side::Right).into_frame(), value)
+    }
+
+    /// Queue a `LRANGE` of the list stored at `key`. See
+    /// [`Client::lrange`](crate::clients::Client::lrange).
+    pub fn lrange(&mut self, key: &str, start: i64, stop: i64) -> Pending<Vec<Bytes>> {
+        self.queue(LRange::new(key, start, stop).into_frame(), values)
+    }
+
+    /// Queue a `LLEN` of the list stored at `key`. See
+    /// [`Client::llen`](crate::clients::Client::llen).
+    pub fn llen(&mut self, key: &str) -> Pending<u64> {
+        self.queue(LLen::new(key).into_frame(), unsigned)
+    }
+
+    /// Queue a `HSET` of `fields` in the hash stored at `key`. See
+    /// [`Client::hset`](crate::clients::Client::hset).
+    pub fn hset(&mut self, key: &str, fields: Vec<(String, Bytes)>) -> Pending<u64> {
+        let fields = fields
+            .into_iter()
+            .map(|(field, value)| (Bytes::from(field), value))
+            .collect();
+        self.queue(HSet::new(key, fields).into_frame(), unsigned)
+    }
+
+    /// Queue a `HGET` of `field` in the hash stored at `key`. See
+    /// [`Client::hget`](crate::clients::Client::hget).
+    pub fn hget(&mut self, key: &str, field: &str) -> Pending<Option<Bytes>> {
+        let frame = HGet::new(key, Bytes::copy_from_slice(field.as_bytes())).into_frame();
+        self.queue(frame, value)
+    }
+
+    /// Queue a `HDEL` of `fields` from the hash stored at `key`. See
+    /// [`Client::hdel`](crate::clients::Client::hdel).
+    pub fn hdel(&mut self, key: &str, fields: &[String]) -> Pending<u64> {
+        let fields = fields
+            .iter()
+            .map(|field| Bytes::copy_from_slice(field.as_bytes()))
+            .collect();
+        self.queue(HDel::new(key, fields).into_frame(), unsigned)
+    }
+
+    /// Queue a `HGETALL` of the hash stored at `key`. See
+    /// [`Client::hgetall`](crate::clients::Client::hgetall).
+    pub fn hgetall(&mut self, key: &str) -> Pending<HashMap<String, Bytes>> {
+        self.queue(HGetAll::new(key).into_frame(), hash)
+    }
+
+    /// Queue a `HINCRBY` of `field` in the hash stored at `key`. See
+    /// [`Client::hincrby`](crate::clients::Client::hincrby).
+    pub fn hincrby(&mut self, key: &str, field: &str, increment: i64) -> Pending<i64> {
+        let frame =
+            HIncrBy::new(key, Bytes::copy_from_slice(field.as_bytes()), increment).into_frame();
+        self.queue(frame, integer)
+    }
+
+    /// Queue a `ZADD` of `members` to the sorted set stored at `key`. See
+    /// [`Client::zadd`](crate::clients::Client::zadd).
+    pub fn zadd(&mut self, key: &str, members: Vec<(f64, Bytes)>) -> Pending<u64> {
+        self.queue(ZAdd::new(key, members).into_frame(), unsigned)
+    }
+
+    /// Queue a `ZRANK` of `member` in the sorted set stored at `key`. See
+    /// [`Client::zrank`](crate::clients::Client::zrank).
+    pub fn zrank(&mut self, key: &str, member: Bytes) -> Pending<Option<u64>> {
+        self.queue(ZRank::new(key, member).into_frame(), rank)
+    }
+
+    /// Queue a `ZREM` of `members` from the sorted set stored at `key`. See
+    /// [`Client::zrem`](crate::clients::Client::zrem).
+    pub fn zrem(&mut self, key: &str, members: Vec<Bytes>) -> Pending<u64> {
+        self.queue(ZRem::new(key, members).into_frame(), unsigned)
+    }
+
+    /// Queue a `XLEN` of the stream stored at `key`. See
+    /// [`Client::xlen`](crate::clients::Client::xlen).
+    pub fn xlen(&mut self, key: &str) -> Pending<u64> {
+        self.queue(XLen::new(key).into_frame(), unsigned)
+    }
+
+    /// Queue a `XTRIM` of the stream stored at `key` to `maxlen` entries. See
+    /// [`Client::xtrim`](crate::clients::Client::xtrim).
+    pub fn xtrim(&mut self, key: &str, maxlen: usize) -> Pending<u64> {
+        self.queue(XTrim::new(key, maxlen).into_frame(), unsigned)
+    }
+
+    /// Queue a `PUBLISH` of `message` to `channel`. See
+    /// [`Client::publish`](crate::clients::Client::publish).
+    pub fn publish(&mut self, channel: &str, message: Bytes) -> Pending<u64> {
+        self.queue(Publish::new(channel, message).into_frame(), unsigned)
+    }
+
+    /// Returns the frames of the queued commands.
+    pub(super) fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Queue `frame`, whose reply is converted with `convert`.
+    fn queue<T>(&mut self, frame: Frame, convert: fn(Frame) -> crate::Result<T>) -> Pending<T> {
+        let index = self.frames.len();
+        self.frames.push(frame);
+
+        Pending {
+            index,
+            convert,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl Responses {
+    /// Create a `Responses` from the reply frames of a pipeline.
+    pub(super) fn new(frames: Vec<Frame>) -> Responses {
+        Responses {
+            frames: frames.into_iter().map(Some).collect(),
+        }
+    }
+
+    /// Returns the number of replies.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns `true` if the pipeline had no command.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Take the reply to the command `pending` was returned for.
+    ///
+    /// An error reply from the server is returned as `Err`, without affecting
+    /// the replies to the other commands. `Err` is also returned if the reply
+    /// was already taken.
+    pub fn take<T>(&mut self, pending: Pending<T>) -> crate::Result<T> {
+        match self.frames.get_mut(pending.index).and_then(Option::take) {
+            Some(Frame::Error(msg)) => Err(msg.into()),
+            Some(frame) => (pending.convert)(frame),
+            None => Err("reply not found; already taken or from another pipeline".into()),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Pending<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Pending")
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+/// Convert the `OK` reply of a command.
+pub(super) fn ok(frame: Frame) -> crate::Result<()> {
+    match frame {
+        Frame::Simple(response) if response == "OK" => Ok(()),
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// Convert the reply of a conditional `SET`: `OK` if the key was set, or
+/// null.
+pub(super) fn conditional(frame: Frame) -> crate::Result<bool> {
+    match frame {
+        Frame::Simple(response) if response == "OK" => Ok(true),
+        Frame::Null => Ok(false),
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// Convert the reply of a command returning a value, or null.
+pub(super) fn value(frame: Frame) -> crate::Result<Option<Bytes>> {
+    match frame {
+        Frame::Simple(value) => Ok(Some(value.into())),
+        Frame::Bulk(value) => Ok(Some(value)),
+        Frame::Null => Ok(None),
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// Convert an integer reply.
+fn integer(frame: Frame) -> crate::Result<i64> {
+    match frame {
+        Frame::Integer(value) => Ok(value),
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// Convert a floating point number sent as a string, such as the reply of
+/// `INCRBYFLOAT`.
+pub(super) fn float(frame: Frame) -> crate::Result<f64> {
+    match frame {
+        Frame::Bulk(value) => Ok(std::str::from_utf8(&value)?.parse()?),
+        Frame::Double(value) => Ok(value),
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// Convert a bulk string holding text, such as the ID returned by `XADD`.
+pub(super) fn string(frame: Frame) -> crate::Result<String> {
+    match frame {
+        Frame::Bulk(value) => Ok(String::from_utf8(value.to_vec())?),
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// Convert an integer reply used as a boolean, such as the reply of
+/// `EXPIRE`.
+pub(super) fn boolean(frame: Frame) -> crate::Result<bool> {
+    match frame {
+        Frame::Integer(value) => Ok(value == 1),
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// Convert an integer reply which cannot be negative, such as a length.
+pub(super) fn unsigned(frame: Frame) -> crate::Result<u64> {
+    match frame {
+        Frame::Integer(value) => Ok(value.try_into()?),
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// Convert the reply of `ZRANK`.
+fn rank(frame: Frame) -> crate::Result<Option<u64>> {
+    match frame {
+        Frame::Integer(rank) => Ok(Some(rank.try_into()?)),
+        Frame::Null => Ok(None),
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// Convert an array of values, such as the reply of `LRANGE`.
+pub(super) fn values(frame: Frame) -> crate::Result<Vec<Bytes>> {
+    match frame {
+        Frame::Array(values) => values
+            .into_iter()
+            .map(|value| match value {
+                Frame::Bulk(value) => Ok(value),
+                frame => Err(frame.to_error()),
+            })
+            .collect(),
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// Convert an array of strings, such as the reply of `KEYS`.
+pub(super) fn strings(frame: Frame) -> crate::Result<Vec<String>> {
+    values(frame)?
+        .into_iter()
+        .map(|value| Ok(String::from_utf8(value.to_vec())?))
+        .collect()
+}
+
+/// Convert the reply of `SCAN`: the next cursor, and an array of keys.
+pub(super) fn scan(frame: Frame) -> crate::Result<(u64, Vec<String>)> {
+    match frame {
+        Frame::Array(frames) if frames.len() == 2 => {
+            let mut frames = frames.into_iter();
+            let cursor = match value(frames.next().unwrap())? {
+                Some(cursor) => std::str::from_utf8(&cursor)?.parse()?,
+                None => return Err("protocol error; expected cursor".into()),
+            };
+            Ok((cursor, strings(frames.next().unwrap())?))
+        }
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// Convert an array of stream entries, such as the reply of `XRANGE`.
+pub(super) fn stream_entries(frame: Frame) -> crate::Result<Vec<StreamEntry>> {
+    let entries = match frame {
+        Frame::Array(entries) => entries,
+        frame => return Err(frame.to_error()),
+    };
+
+    entries
+        .into_iter()
+        .map(|entry| match entry {
+            Frame::Array(parts) if parts.len() == 2 => {
+                let mut parts = parts.into_iter();
+                let id = string(parts.next().unwrap())?;
+
+                let mut values = values(parts.next().unwrap())?.into_iter();
+                let mut fields = vec![];
+                while let (Some(field), Some(value)) = (values.next(), values.next()) {
+                    fields.push((String::from_utf8(field.to_vec())?, value));
+                }
+
+                Ok(StreamEntry { id, fields })
+            }
+            _ => Err("protocol error; invalid stream entry".into()),
+        })
+        .collect()
+}
+
+/// Convert the reply of `XREAD`: the entries read from each stream, or `Null`
+/// if none was read.
+pub(super) fn streams(frame: Frame) -> crate::Result<Vec<(String, Vec<StreamEntry>)>> {
+    let streams = match frame {
+        Frame::Array(streams) => streams,
+        Frame::Null => return Ok(vec![]),
+        frame => return Err(frame.to_error()),
+    };
+
+    streams
+        .into_iter()
+        .map(|stream| match stream {
+            Frame::Array(parts) if parts.len() == 2 => {
+                let mut parts = parts.into_iter();
+                let key = string(parts.next().unwrap())?;
+                Ok((key, stream_entries(parts.next().unwrap())?))
+            }
+            _ => Err("protocol error; invalid XREAD response".into()),
+        })
+        .collect()
+}
+
+/// Convert the summary reply of `XPENDING`.
+pub(super) fn pending_summary(frame: Frame) -> crate::Result<PendingSummary> {
+    let mut parts = match frame {
+        Frame::Array(parts) if parts.len() == 4 => parts.into_iter(),
+        frame => return Err(frame.to_error()),
+    };
+
+    let count = unsigned(parts.next().unwrap())?;
+    let ids = match (parts.next().unwrap(), parts.next().unwrap()) {
+        (Frame::Null, Frame::Null) => None,
+        (first, last) => Some((string(first)?, string(last)?)),
+    };
+    let consumers = match parts.next().unwrap() {
+        Frame::Null => vec![],
+        Frame::Array(consumers) => consumers
+            .into_iter()
+            .map(|consumer| {
+                let mut strings = strings(consumer)?.into_iter();
+                match (strings.next(), strings.next(), strings.next()) {
+                    (Some(name), Some(count), None) => Ok((name, count.parse()?)),
+                    _ => Err("protocol error; invalid XPENDING response".into()),
+                }
+            })
+            .collect::<crate::Result<_>>()?,
+        frame => return Err(frame.to_error()),
+    };
+
+    Ok(PendingSummary {
+        count,
+        ids,
+        consumers,
+    })
+}
+
+/// Convert the reply of `XPENDING` given a range.
+pub(super) fn pending_entries(frame: Frame) -> crate::Result<Vec<PendingEntry>> {
+    let entries = match frame {
+        Frame::Array(entries) => entries,
+        frame => return Err(frame.to_error()),
+    };
+
+    entries
+        .into_iter()
+        .map(|entry| match entry {
+            Frame::Array(parts) if parts.len() == 4 => {
+                let mut parts = parts.into_iter();
+                Ok(PendingEntry {
+                    id: string(parts.next().unwrap())?,
+                    consumer: string(parts.next().unwrap())?,
+                    idle: Duration::from_millis(unsigned(parts.next().unwrap())?),
+                    delivery_count: unsigned(parts.next().unwrap())?,
+                })
+            }
+            _ => Err("protocol error; invalid XPENDING response".into()),
+        })
+        .collect()
+}
+
+/// Convert the reply of `HGETALL`.
+///
+/// RESP3 connections receive a map, while RESP2 connections receive an array
+/// of alternating fields and values.
+pub(super) fn hash(frame: Frame) -> crate::Result<HashMap<String, Bytes>> {
+    let entries = match frame {
+        Frame::Map(entries) => entries,
+        Frame::Array(frames) if frames.len() % 2 == 0 => {
+            let mut frames = frames.into_iter();
+            let mut entries = vec![];
+
+            while let (Some(field), Some(value)) = (frames.next(), frames.next()) {
+                entries.push((field, value));
+            }
+
+            entries
+        }
+        frame => return Err(frame.to_error()),
+    };
+
+    entries
+        .into_iter()
+        .map(|entry| match entry {
+            (Frame::Bulk(field), Frame::Bulk(value)) => {
+                Ok((String::from_utf8(field.to_vec())?, value))
+            }
+            _ => Err("protocol error; invalid HGETALL response".into()),
+        })
+        .collect()
+}
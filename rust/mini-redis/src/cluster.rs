@@ -0,0 +1,368 @@
+//! Cluster mode.
+//!
+//! The key space is split into 16384 hash slots, and each node of the cluster
+//! serves a subset of them. The slot of a key is the CRC16 of the key modulo
+//! 16384. When the key contains a hash tag, a non-empty substring between the
+//! first `{` and the next `}`, only the hash tag is hashed. This lets
+//! applications store related keys in the same slot, on the same node.
+//!
+//! A node receiving a command for a key it does not serve replies with a
+//! `MOVED <slot> <host>:<port>` error naming the node serving the slot.
+//! While a slot is migrated from one node to another, the source node replies
+//! with `ASK <slot> <host>:<port>` for keys it no longer has. The client is
+//! then expected to send `ASKING` to the target node, followed by the
+//! command.
+//!
+//! There is no gossip between the nodes: each node is configured with the
+//! slots served by every node, and the assignments are changed on each node
+//! with `CLUSTER SETSLOT`.
+
+use crate::scripting;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// Number of hash slots the key space is split into.
+pub const SLOTS: u16 = 16384;
+
+/// A node of the cluster and the slots it serves, as configured when the
+/// server starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterNode {
+    /// Address clients use to connect to the node, as `host:port`.
+    pub addr: String,
+
+    /// Slots served by the node.
+    pub slots: Vec<RangeInclusive<u16>>,
+}
+
+/// A range of slots served by a node, as reported by `CLUSTER SLOTS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotRange {
+    /// The slots in the range.
+    pub slots: RangeInclusive<u16>,
+
+    /// Host of the node serving the slots.
+    pub host: String,
+
+    /// Port of the node serving the slots.
+    pub port: u16,
+
+    /// ID of the node serving the slots.
+    pub id: String,
+}
+
+/// Assignment of the slots to the nodes, as seen by this node.
+#[derive(Debug)]
+pub(crate) struct Topology {
+    /// All nodes of the cluster, including this one.
+    nodes: Vec<Node>,
+
+    /// Index of this node in `nodes`.
+    myself: usize,
+
+    /// Index in `nodes` of the node serving each slot, if any.
+    owners: Vec<Option<usize>>,
+
+    /// Slots served by this node which are being migrated to another node.
+    migrating: HashMap<u16, usize>,
+
+    /// Slots served by another node which are being migrated to this node.
+    importing: HashMap<u16, usize>,
+}
+
+/// A node known to this node.
+#[derive(Debug)]
+pub(crate) struct Node {
+    /// Node ID, derived from the address so all nodes agree on it.
+    pub(crate) id: String,
+
+    pub(crate) host: String,
+    pub(crate) port: u16,
+}
+
+/// Returns the hash slot of `key`.
+///
+/// # Examples
+///
+/// ```
+/// use mini_redis::cluster::key_hash_slot;
+///
+/// assert_eq!(12739, key_hash_slot(b"123456789"));
+///
+/// // Keys sharing a hash tag are stored in the same slot
+/// assert_eq!(key_hash_slot(b"{user:1}:name"), key_hash_slot(b"{user:1}:email"));
+/// ```
+pub fn key_hash_slot(key: &[u8]) -> u16 {
+    let key = match hash_tag(key) {
+        Some(tag) => tag,
+        None => key,
+    };
+
+    crc16(key) % SLOTS
+}
+
+/// Returns the hash tag of `key`, if any.
+fn hash_tag(key: &[u8]) -> Option<&[u8]> {
+    let start = key.iter().position(|&b| b == b'{')? + 1;
+    let len = key[start..].iter().position(|&b| b == b'}')?;
+
+    if len == 0 {
+        None
+    } else {
+        Some(&key[start..start + len])
+    }
+}
+
+/// CRC16, in the XMODEM variant used by Redis Cluster.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+impl Topology {
+    /// Create the topology of the cluster `nodes`, as seen by the node
+    /// listening on `myself`.
+    ///
+    /// If `myself` is not one of `nodes`, it is added without any slot.
+    pub(crate) fn new(myself: &str, nodes: &[ClusterNode]) -> crate::Result<Topology> {
+        let mut topology = Topology {
+            nodes: vec![],
+            myself: 0,
+            owners: vec![None; SLOTS as usize],
+            migrating: HashMap::new(),
+            importing: HashMap::new(),
+        };
+
+        for node in nodes {
+            let index = topology.nodes.len();
+            topology.nodes.push(Node::new(&node.addr)?);
+
+            for range in &node.slots {
+                for slot in range.clone() {
+                    match topology.owners.get_mut(slot as usize) {
+                        Some(Some(_)) => {
+                            return Err(format!("slot {} is assigned to two nodes", slot).into())
+                        }
+                        Some(owner) => *owner = Some(index),
+                        None => return Err(format!("invalid slot {}", slot).into()),
+                    }
+                }
+            }
+        }
+
+        let id = node_id(myself);
+        topology.myself = match topology.nodes.iter().position(|node| node.id == id) {
+            Some(index) => index,
+            None => {
+                topology.nodes.push(Node::new(myself)?);
+                topology.nodes.len() - 1
+            }
+        };
+
+        Ok(topology)
+    }
+
+    /// Check whether a command accessing keys in `slot` may be executed by
+    /// this node.
+    ///
+    /// `asking` is set if the client sent `ASKING` before the command.
+    /// `keys_exist` is only called while the slot is migrated away, to check
+    /// whether the keys were migrated yet.
+    ///
+    /// Returns the error to reply with if the command must be sent to another
+    /// node.
+    pub(crate) fn route(
+        &self,
+        slot: u16,
+        asking: bool,
+        keys_exist: impl FnOnce() -> bool,
+    ) -> Result<(), String> {
+        match self.owners[slot as usize] {
+            Some(owner) if owner == self.myself => match self.migrating.get(&slot) {
+                Some(&target) if !keys_exist() => {
+                    Err(format!("ASK {} {}", slot, self.nodes[target].addr()))
+                }
+                _ => Ok(()),
+            },
+            _ if asking && self.importing.contains_key(&slot) => Ok(()),
+            Some(owner) => Err(format!("MOVED {} {}", slot, self.nodes[owner].addr())),
+            None => Err("CLUSTERDOWN Hash slot not served".to_string()),
+        }
+    }
+
+    /// Returns this node.
+    pub(crate) fn myself(&self) -> &Node {
+        &self.nodes[self.myself]
+    }
+
+    /// Returns all nodes of the cluster, including this one.
+    pub(crate) fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Returns the ranges of consecutive slots served by the same node, along
+    /// with the index of the node.
+    pub(crate) fn ranges(&self) -> Vec<(RangeInclusive<u16>, usize)> {
+        let mut ranges: Vec<(RangeInclusive<u16>, usize)> = vec![];
+
+        for (slot, owner) in self.owners.iter().enumerate() {
+            let (slot, owner) = match owner {
+                Some(owner) => (slot as u16, *owner),
+                None => continue,
+            };
+
+            match ranges.last_mut() {
+                Some((range, last)) if *last == owner && *range.end() + 1 == slot => {
+                    *range = *range.start()..=slot;
+                }
+                _ => ranges.push((slot..=slot, owner)),
+            }
+        }
+
+        ranges
+    }
+
+    /// Returns the number of slots served by a node.
+    pub(crate) fn assigned_slots(&self) -> usize {
+        self.owners.iter().filter(|owner| owner.is_some()).count()
+    }
+
+    /// Start migrating `slot`, served by this node, to the node `id`.
+    pub(crate) fn set_migrating(&mut self, slot: u16, id: &str) -> Result<(), String> {
+        let target = self.find(id)?;
+
+        if self.owners[slot as usize] != Some(self.myself) {
+            return Err(format!("ERR I'm not the owner of hash slot {}", slot));
+        }
+
+        self.migrating.insert(slot, target);
+        Ok(())
+    }
+
+    /// Start importing `slot` from the node `id`.
+    pub(crate) fn set_importing(&mut self, slot: u16, id: &str) -> Result<(), String> {
+        let source = self.find(id)?;
+
+        if self.owners[slot as usize] == Some(self.myself) {
+            return Err(format!("ERR I'm already the owner of hash slot {}", slot));
+        }
+
+        self.importing.insert(slot, source);
+        Ok(())
+    }
+
+    /// Stop migrating or importing `slot`.
+    pub(crate) fn set_stable(&mut self, slot: u16) {
+        self.migrating.remove(&slot);
+        self.importing.remove(&slot);
+    }
+
+    /// Assign `slot` to the node `id`, ending any migration of the slot.
+    pub(crate) fn set_node(&mut self, slot: u16, id: &str) -> Result<(), String> {
+        let owner = self.find(id)?;
+
+        self.owners[slot as usize] = Some(owner);
+        self.set_stable(slot);
+        Ok(())
+    }
+
+    /// Returns the index of the node `id`.
+    fn find(&self, id: &str) -> Result<usize, String> {
+        self.nodes
+            .iter()
+            .position(|node| node.id.eq_ignore_ascii_case(id))
+            .ok_or_else(|| format!("ERR I don't know about node {}", id))
+    }
+}
+
+impl Node {
+    fn new(addr: &str) -> crate::Result<Node> {
+        let (host, port) = addr
+            .rsplit_once(':')
+            .ok_or_else(|| format!("invalid node address '{}'", addr))?;
+        let port = port
+            .parse()
+            .map_err(|_| format!("invalid node address '{}'", addr))?;
+
+        Ok(Node {
+            id: node_id(addr),
+            host: host.to_string(),
+            port,
+        })
+    }
+
+    /// Returns the address of the node, as `host:port`.
+    pub(crate) fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+/// Returns the ID of the node listening on `addr`.
+fn node_id(addr: &str) -> String {
+    scripting::sha1(addr.as_bytes())
+}
+
+/// Parses `host:port=slots`, where `slots` is a comma separated list of slots
+/// and ranges of slots, such as `127.0.0.1:7000=0-5460,16383`.
+impl FromStr for ClusterNode {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<ClusterNode> {
+        let (addr, slots) = s
+            .split_once('=')
+            .ok_or_else(|| format!("invalid cluster node '{}', expected host:port=slots", s))?;
+
+        let slots = slots
+            .split(',')
+            .map(|range| {
+                let (start, end) = range.split_once('-').unwrap_or((range, range));
+
+                match (start.parse::<u16>(), end.parse::<u16>()) {
+                    (Ok(start), Ok(end)) if start <= end && end < SLOTS => Ok(start..=end),
+                    _ => Err(format!("invalid slot range '{}'", range).into()),
+                }
+            })
+            .collect::<crate::Result<_>>()?;
+
+        Ok(ClusterNode {
+            addr: addr.to_string(),
+            slots,
+        })
+    }
+}
+
+impl fmt::Display for ClusterNode {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}=", self.addr)?;
+
+        for (i, range) in self.slots.iter().enumerate() {
+            if i > 0 {
+                ",".fmt(fmt)?;
+            }
+
+            if range.start() == range.end() {
+                write!(fmt, "{}", range.start())?;
+            } else {
+                write!(fmt, "{}-{}", range.start(), range.end())?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+use crate::db::State;
+use crate::{Frame, Parse};
+
+use bytes::Bytes;
+use tracing::instrument;
+
+/// Rewrite the append-only file in the background.
+///
+/// The append-only file grows with every command modifying the key space,
+/// even when the commands overwrite each other. Rewriting replaces the file
+/// with the minimal set of commands needed to recreate the current key space.
+/// Commands received while the rewrite is in progress are not lost.
+#[derive(Debug, Default)]
+pub struct BgRewriteAof;
+
+impl BgRewriteAof {
+    /// Create a new `BgRewriteAof` command.
+    pub fn new() -> BgRewriteAof {
+        BgRewriteAof
+    }
+
+    /// Parse a `BgRewriteAof` instance from a received frame.
+    ///
+    /// The `BGREWRITEAOF` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing a single entry.
+    ///
+    /// ```text
+    /// BGREWRITEAOF
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<BgRewriteAof> {
+        Ok(BgRewriteAof)
+    }
+
+    /// Execute the `BgRewriteAof` command against the locked database state.
+    ///
+    /// Responds once the rewrite has been started. An error is returned if
+    /// persistence is disabled.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.rewrite_aof() {
+            Ok(()) => Frame::Simple("Background append only file rewriting started".to_string()),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `BgRewriteAof` command to
+    /// send to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("bgrewriteaof".as_bytes()));
+        frame
+    }
+}
@@ -0,0 +1,329 @@
+use crate::cluster::{self, Topology, SLOTS};
+use crate::db::State;
+use crate::{Connection, Frame, Parse};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Inspect and change the cluster configuration of the node.
+#[derive(Debug)]
+pub struct Cluster {
+    subcommand: ClusterSubcommand,
+}
+
+#[derive(Debug)]
+enum ClusterSubcommand {
+    /// Describe the cluster, one entry per range of slots
+    Slots,
+
+    /// Describe the cluster, one entry per node
+    Shards,
+
+    /// Report the state of the cluster
+    Info,
+
+    /// Return the ID of the node
+    MyId,
+
+    /// Return the slot of a key
+    KeySlot(String),
+
+    /// Change the state of a slot
+    SetSlot(u16, SetSlot),
+}
+
+/// Changes made by `CLUSTER SETSLOT`.
+#[derive(Debug)]
+enum SetSlot {
+    /// The slot is being migrated from this node to the given node
+    Migrating(String),
+
+    /// The slot is being migrated from the given node to this node
+    Importing(String),
+
+    /// The slot is no longer migrated
+    Stable,
+
+    /// The slot is now served by the given node
+    Node(String),
+}
+
+/// Allow the next command to access a slot being imported by this node.
+///
+/// Sent by clients following an `ASK` redirect, right before the command.
+#[derive(Debug, Default)]
+pub struct Asking;
+
+impl Cluster {
+    /// Create a new `Cluster` command which returns the ranges of slots
+    /// served by each node.
+    pub fn slots() -> Cluster {
+        Cluster {
+            subcommand: ClusterSubcommand::Slots,
+        }
+    }
+
+    /// Parse a `Cluster` instance from a received frame.
+    ///
+    /// The `CLUSTER` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing a subcommand and its arguments.
+    ///
+    /// ```text
+    /// CLUSTER SLOTS
+    /// CLUSTER SHARDS
+    /// CLUSTER INFO
+    /// CLUSTER MYID
+    /// CLUSTER KEYSLOT key
+    /// CLUSTER SETSLOT slot MIGRATING|IMPORTING|NODE node-id
+    /// CLUSTER SETSLOT slot STABLE
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Cluster> {
+        let subcommand = match &parse.next_string()?.to_lowercase()[..] {
+            "slots" => ClusterSubcommand::Slots,
+            "shards" => ClusterSubcommand::Shards,
+            "info" => ClusterSubcommand::Info,
+            "myid" => ClusterSubcommand::MyId,
+            "keyslot" => ClusterSubcommand::KeySlot(parse.next_string()?),
+            "setslot" => {
+                let slot = parse_slot(parse)?;
+
+                let change = match &parse.next_string()?.to_lowercase()[..] {
+                    "migrating" => SetSlot::Migrating(parse.next_string()?),
+                    "importing" => SetSlot::Importing(parse.next_string()?),
+                    "stable" => SetSlot::Stable,
+                    "node" => SetSlot::Node(parse.next_string()?),
+                    change => {
+                        return Err(format!(
+                            "protocol error; invalid CLUSTER SETSLOT action '{}'",
+                            change
+                        )
+                        .into())
+                    }
+                };
+
+                ClusterSubcommand::SetSlot(slot, change)
+            }
+            subcommand => {
+                return Err(format!(
+                    "protocol error; unknown CLUSTER subcommand '{}'",
+                    subcommand
+                )
+                .into())
+            }
+        };
+
+        Ok(Cluster { subcommand })
+    }
+
+    /// Execute the `Cluster` command against the locked database state.
+    ///
+    /// Responds with an error if cluster mode is disabled.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        state
+            .with_cluster(|topology| self.execute_on(topology))
+            .unwrap_or_else(|| {
+                Frame::Error("ERR This instance has cluster support disabled".to_string())
+            })
+    }
+
+    /// Execute the `Cluster` command against the topology of the cluster.
+    fn execute_on(self, topology: &mut Topology) -> Frame {
+        match self.subcommand {
+            ClusterSubcommand::Slots => slots_frame(topology),
+            ClusterSubcommand::Shards => shards_frame(topology),
+            ClusterSubcommand::Info => {
+                let assigned = topology.assigned_slots();
+                let info = format!(
+                    "cluster_enabled:1\r\n\
+                     cluster_state:{}\r\n\
+                     cluster_slots_assigned:{}\r\n\
+                     cluster_known_nodes:{}\r\n",
+                    if assigned == SLOTS as usize {
+                        "ok"
+                    } else {
+                        "fail"
+                    },
+                    assigned,
+                    topology.nodes().len()
+                );
+
+                Frame::Bulk(Bytes::from(info))
+            }
+            ClusterSubcommand::KeySlot(key) => {
+                Frame::Integer(cluster::key_hash_slot(key.as_bytes()) as i64)
+            }
+            ClusterSubcommand::MyId => {
+                Frame::Bulk(Bytes::from(topology.myself().id.clone().into_bytes()))
+            }
+            ClusterSubcommand::SetSlot(slot, change) => {
+                let res = match change {
+                    SetSlot::Migrating(id) => topology.set_migrating(slot, &id),
+                    SetSlot::Importing(id) => topology.set_importing(slot, &id),
+                    SetSlot::Stable => {
+                        topology.set_stable(slot);
+                        Ok(())
+                    }
+                    SetSlot::Node(id) => topology.set_node(slot, &id),
+                };
+
+                match res {
+                    Ok(()) => Frame::Simple("OK".to_string()),
+                    Err(msg) => Frame::Error(msg),
+                }
+            }
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Cluster` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("cluster".as_bytes()));
+
+        match self.subcommand {
+            ClusterSubcommand::Slots => frame.push_bulk(Bytes::from("slots".as_bytes())),
+            ClusterSubcommand::Shards => frame.push_bulk(Bytes::from("shards".as_bytes())),
+            ClusterSubcommand::Info => frame.push_bulk(Bytes::from("info".as_bytes())),
+            ClusterSubcommand::MyId => frame.push_bulk(Bytes::from("myid".as_bytes())),
+            ClusterSubcommand::KeySlot(key) => {
+                frame.push_bulk(Bytes::from("keyslot".as_bytes()));
+                frame.push_bulk(Bytes::from(key.into_bytes()));
+            }
+            ClusterSubcommand::SetSlot(slot, change) => {
+                frame.push_bulk(Bytes::from("setslot".as_bytes()));
+                frame.push_int(slot as i64);
+
+                let (action, id) = match change {
+                    SetSlot::Migrating(id) => ("migrating", Some(id)),
+                    SetSlot::Importing(id) => ("importing", Some(id)),
+                    SetSlot::Stable => ("stable", None),
+                    SetSlot::Node(id) => ("node", Some(id)),
+                };
+
+                frame.push_bulk(Bytes::from(action.as_bytes()));
+                if let Some(id) = id {
+                    frame.push_bulk(Bytes::from(id.into_bytes()));
+                }
+            }
+        }
+
+        frame
+    }
+}
+
+impl Asking {
+    /// Create a new `Asking` command.
+    pub fn new() -> Asking {
+        Asking
+    }
+
+    /// Parse an `Asking` instance from a received frame.
+    ///
+    /// The `ASKING` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing a single entry.
+    ///
+    /// ```text
+    /// ASKING
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Asking> {
+        Ok(Asking)
+    }
+
+    /// Flag the connection so the next command may access a slot being
+    /// imported.
+    #[instrument(skip(self, asking, dst))]
+    pub(crate) async fn apply(self, asking: &mut bool, dst: &mut Connection) -> crate::Result<()> {
+        *asking = true;
+
+        let response = Frame::Simple("OK".to_string());
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+}
+
+/// Parse a slot number.
+fn parse_slot(parse: &mut Parse) -> crate::Result<u16> {
+    match parse.next_int()? {
+        slot if slot < SLOTS as u64 => Ok(slot as u16),
+        _ => Err("ERR Invalid or out of range slot".into()),
+    }
+}
+
+/// Build the reply to `CLUSTER SLOTS`.
+fn slots_frame(topology: &Topology) -> Frame {
+    let ranges = topology
+        .ranges()
+        .into_iter()
+        .map(|(range, owner)| {
+            let node = &topology.nodes()[owner];
+
+            Frame::Array(vec![
+                Frame::Integer(*range.start() as i64),
+                Frame::Integer(*range.end() as i64),
+                Frame::Array(vec![
+                    Frame::Bulk(Bytes::from(node.host.clone().into_bytes())),
+                    Frame::Integer(node.port as i64),
+                    Frame::Bulk(Bytes::from(node.id.clone().into_bytes())),
+                ]),
+            ])
+        })
+        .collect();
+
+    Frame::Array(ranges)
+}
+
+/// Build the reply to `CLUSTER SHARDS`. Each node is its own shard, as there
+/// are no replicas in cluster mode.
+fn shards_frame(topology: &Topology) -> Frame {
+    let ranges = topology.ranges();
+
+    let shards = topology
+        .nodes()
+        .iter()
+        .enumerate()
+        .map(|(index, node)| {
+            let slots = ranges
+                .iter()
+                .filter(|(_, owner)| *owner == index)
+                .flat_map(|(range, _)| {
+                    vec![
+                        Frame::Integer(*range.start() as i64),
+                        Frame::Integer(*range.end() as i64),
+                    ]
+                })
+                .collect();
+
+            let node = Frame::Map(vec![
+                (bulk("id"), bulk(&node.id)),
+                (bulk("port"), Frame::Integer(node.port as i64)),
+                (bulk("ip"), bulk(&node.host)),
+                (bulk("endpoint"), bulk(&node.host)),
+                (bulk("role"), bulk("master")),
+                (bulk("health"), bulk("online")),
+            ]);
+
+            Frame::Map(vec![
+                (bulk("slots"), Frame::Array(slots)),
+                (bulk("nodes"), Frame::Array(vec![node])),
+            ])
+        })
+        .collect();
+
+    Frame::Array(shards)
+}
+
+fn bulk(value: &str) -> Frame {
+    Frame::Bulk(Bytes::copy_from_slice(value.as_bytes()))
+}
@@ -0,0 +1,167 @@
+use crate::cmd::{Parse, ParseError};
+use crate::db::State;
+use crate::Frame;
+
+use bytes::Bytes;
+use tracing::instrument;
+
+/// Read or change the settings of the server.
+///
+/// Only some settings can be changed while the server runs. The others are
+/// only read on startup, from the configuration file or the command line.
+#[derive(Debug)]
+pub struct Config {
+    subcommand: ConfigSubcommand,
+}
+
+#[derive(Debug)]
+enum ConfigSubcommand {
+    /// Read the settings matching any of the glob-style patterns
+    Get(Vec<String>),
+
+    /// Change settings, given as pairs of names and values
+    Set(Vec<(String, String)>),
+
+    /// Write the current settings to the configuration file
+    Rewrite,
+}
+
+impl Config {
+    /// Create a new `Config` command which reads the settings matching
+    /// `pattern`.
+    pub fn get(pattern: impl ToString) -> Config {
+        Config {
+            subcommand: ConfigSubcommand::Get(vec![pattern.to_string()]),
+        }
+    }
+
+    /// Create a new `Config` command which changes the setting `name` to
+    /// `value`.
+    pub fn set(name: impl ToString, value: impl ToString) -> Config {
+        Config {
+            subcommand: ConfigSubcommand::Set(vec![(name.to_string(), value.to_string())]),
+        }
+    }
+
+    /// Create a new `Config` command which writes the current settings to the
+    /// configuration file.
+    pub fn rewrite() -> Config {
+        Config {
+            subcommand: ConfigSubcommand::Rewrite,
+        }
+    }
+
+    /// Parse a `Config` instance from a received frame.
+    ///
+    /// The `CONFIG` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing a subcommand and its arguments.
+    ///
+    /// ```text
+    /// CONFIG GET pattern [pattern ...]
+    /// CONFIG SET name value [name value ...]
+    /// CONFIG REWRITE
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Config> {
+        let subcommand = match &parse.next_string()?.to_lowercase()[..] {
+            "get" => {
+                let mut patterns = vec![parse.next_string()?];
+
+                loop {
+                    match parse.next_string() {
+                        Ok(pattern) => patterns.push(pattern),
+                        Err(ParseError::EndOfStream) => break,
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+
+                ConfigSubcommand::Get(patterns)
+            }
+            "set" => {
+                let mut params = vec![(parse.next_string()?, parse.next_string()?)];
+
+                loop {
+                    match parse.next_string() {
+                        Ok(name) => params.push((name, parse.next_string()?)),
+                        Err(ParseError::EndOfStream) => break,
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+
+                ConfigSubcommand::Set(params)
+            }
+            "rewrite" => ConfigSubcommand::Rewrite,
+            subcommand => {
+                return Err(
+                    format!("protocol error; unknown CONFIG subcommand '{}'", subcommand).into(),
+                )
+            }
+        };
+
+        Ok(Config { subcommand })
+    }
+
+    /// Execute the `Config` command against the locked database state.
+    ///
+    /// `GET` responds with a map of the matching settings to their values,
+    /// sent as an array of alternating names and values to RESP2 clients.
+    /// `SET` and `REWRITE` respond with `OK`.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let res = match self.subcommand {
+            ConfigSubcommand::Get(patterns) => {
+                return Frame::Map(
+                    state
+                        .config_get(&patterns)
+                        .into_iter()
+                        .map(|(name, value)| {
+                            (
+                                Frame::Bulk(Bytes::from(name)),
+                                Frame::Bulk(Bytes::from(value)),
+                            )
+                        })
+                        .collect(),
+                )
+            }
+            ConfigSubcommand::Set(params) => state.config_set(&params),
+            ConfigSubcommand::Rewrite => state.config_rewrite(),
+        };
+
+        match res {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Config` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("config".as_bytes()));
+
+        match self.subcommand {
+            ConfigSubcommand::Get(patterns) => {
+                frame.push_bulk(Bytes::from("get".as_bytes()));
+                for pattern in patterns {
+                    frame.push_bulk(Bytes::from(pattern.into_bytes()));
+                }
+            }
+            ConfigSubcommand::Set(params) => {
+                frame.push_bulk(Bytes::from("set".as_bytes()));
+                for (name, value) in params {
+                    frame.push_bulk(Bytes::from(name.into_bytes()));
+                    frame.push_bulk(Bytes::from(value.into_bytes()));
+                }
+            }
+            ConfigSubcommand::Rewrite => {
+                frame.push_bulk(Bytes::from("rewrite".as_bytes()));
+            }
+        }
+
+        frame
+    }
+}
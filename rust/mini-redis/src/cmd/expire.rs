@@ -0,0 +1,477 @@
+use crate::cmd::{Parse, ParseError};
+use crate::db::{unix_time_ms, State};
+use crate::Frame;
+
+use bytes::Bytes;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+use tracing::instrument;
+
+/// Set a timeout on `key`, after which the key is deleted.
+///
+/// The timeout is either relative to now (`EXPIRE`, `PEXPIRE`), or an
+/// absolute Unix time (`EXPIREAT`, `PEXPIREAT`). A timeout in the past deletes
+/// the key right away.
+///
+/// # Options
+///
+/// * NX -- Set the timeout only if the key has none.
+/// * XX -- Set the timeout only if the key already has one.
+/// * GT -- Set the timeout only if it is later than the current one.
+/// * LT -- Set the timeout only if it is earlier than the current one.
+///
+/// A key without timeout is considered to have an infinite time to live by
+/// `GT` and `LT`.
+#[derive(Debug, Clone)]
+pub struct Expire {
+    /// Name of the key
+    key: String,
+
+    /// When the key expires
+    expiration: Expiration,
+
+    /// Condition to check before setting the timeout
+    condition: Option<ExpireCondition>,
+}
+
+/// Condition under which `EXPIRE` and its variants set the timeout of a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpireCondition {
+    /// Only if the key has no timeout.
+    Nx,
+
+    /// Only if the key has a timeout.
+    Xx,
+
+    /// Only if the new timeout is later than the current one.
+    Gt,
+
+    /// Only if the new timeout is earlier than the current one.
+    Lt,
+}
+
+/// A timeout, as received by one of the variants of `EXPIRE`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Expiration {
+    /// Number of seconds from now, as used by `EXPIRE`.
+    Seconds(i64),
+
+    /// Number of milliseconds from now, as used by `PEXPIRE`.
+    Milliseconds(i64),
+
+    /// Unix time in seconds, as used by `EXPIREAT`.
+    UnixSeconds(i64),
+
+    /// Unix time in milliseconds, as used by `PEXPIREAT`.
+    UnixMilliseconds(i64),
+}
+
+/// Returns the remaining time to live of `key`.
+///
+/// Responds with `-2` if the key does not exist and `-1` if it has no
+/// timeout.
+#[derive(Debug)]
+pub struct Ttl {
+    /// Name of the key
+    key: String,
+
+    /// How the time to live is reported
+    unit: TtlUnit,
+}
+
+/// How `TTL` and its variants report the time to live of a key.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum TtlUnit {
+    /// Remaining seconds, as returned by `TTL`.
+    Seconds,
+
+    /// Remaining milliseconds, as returned by `PTTL`.
+    Milliseconds,
+
+    /// Unix time in seconds at which the key expires, as returned by
+    /// `EXPIRETIME`.
+    UnixSeconds,
+
+    /// Unix time in milliseconds at which the key expires, as returned by
+    /// `PEXPIRETIME`.
+    UnixMilliseconds,
+}
+
+/// Remove the timeout of `key`, so that it never expires.
+#[derive(Debug, Clone)]
+pub struct Persist {
+    /// Name of the key
+    key: String,
+}
+
+impl Expire {
+    /// Create a new `Expire` command which sets the timeout of `key`.
+    pub(crate) fn new(
+        key: impl ToString,
+        expiration: Expiration,
+        condition: Option<ExpireCondition>,
+    ) -> Expire {
+        Expire {
+            key: key.to_string(),
+            expiration,
+            condition,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `Expire` instance from a received frame.
+    ///
+    /// The `EXPIRE`, `PEXPIRE`, `EXPIREAT` or `PEXPIREAT` string has already
+    /// been consumed. `expiration` builds the timeout from the received
+    /// number, depending on which of them was received.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing three or four entries.
+    ///
+    /// ```text
+    /// EXPIRE key seconds [NX|XX|GT|LT]
+    /// PEXPIRE key milliseconds [NX|XX|GT|LT]
+    /// EXPIREAT key unix-time-seconds [NX|XX|GT|LT]
+    /// PEXPIREAT key unix-time-milliseconds [NX|XX|GT|LT]
+    /// ```
+    pub(crate) fn parse_frames(
+        parse: &mut Parse,
+        expiration: fn(i64) -> Expiration,
+    ) -> crate::Result<Expire> {
+        use ParseError::EndOfStream;
+
+        let key = parse.next_string()?;
+        let expiration = expiration(parse.next_signed_int()?);
+
+        let condition = match parse.next_string() {
+            Ok(condition) => Some(condition.parse()?),
+            Err(EndOfStream) => None,
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Expire {
+            key,
+            expiration,
+            condition,
+        })
+    }
+
+    /// Execute the `Expire` command against the locked database state.
+    ///
+    /// Responds with `1` if the timeout was set, or `0` if the key does not
+    /// exist or the condition does not hold.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let when = match self.expiration.instant() {
+            Some(when) => when,
+            None => {
+                return Frame::Error(format!(
+                    "ERR invalid expire time in '{}' command",
+                    self.get_name()
+                ))
+            }
+        };
+
+        let set = state.expire(&self.key, when, self.condition);
+
+        Frame::Integer(set as i64)
+    }
+
+    /// Returns the command name
+    pub(crate) fn get_name(&self) -> &'static str {
+        match self.expiration {
+            Expiration::Seconds(_) => "expire",
+            Expiration::Milliseconds(_) => "pexpire",
+            Expiration::UnixSeconds(_) => "expireat",
+            Expiration::UnixMilliseconds(_) => "pexpireat",
+        }
+    }
+
+    /// Returns the command with its timeout given as a Unix time, sent as
+    /// `PEXPIREAT`.
+    ///
+    /// This is what is propagated, so that the key expires at the same time
+    /// when the command is replayed later on, rather than when its relative
+    /// timeout elapses again.
+    pub(crate) fn to_absolute(&self) -> Expire {
+        Expire {
+            expiration: self.expiration.to_absolute(),
+            ..self.clone()
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding an `Expire` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from(self.get_name().as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.expiration.value().to_string()));
+        if let Some(condition) = self.condition {
+            frame.push_bulk(Bytes::from(condition.as_str().as_bytes()));
+        }
+        frame
+    }
+}
+
+impl Expiration {
+    /// Returns the number received with the command, in its own unit.
+    pub(crate) fn value(self) -> i64 {
+        match self {
+            Expiration::Seconds(value)
+            | Expiration::Milliseconds(value)
+            | Expiration::UnixSeconds(value)
+            | Expiration::UnixMilliseconds(value) => value,
+        }
+    }
+
+    /// Returns the name of the equivalent `SET` and `GETEX` option.
+    pub(crate) fn option(self) -> &'static str {
+        match self {
+            Expiration::Seconds(_) => "ex",
+            Expiration::Milliseconds(_) => "px",
+            Expiration::UnixSeconds(_) => "exat",
+            Expiration::UnixMilliseconds(_) => "pxat",
+        }
+    }
+
+    /// Returns the same timeout as a Unix time in milliseconds.
+    ///
+    /// Timeouts that overflow are returned as they are, and rejected when
+    /// executed.
+    pub(crate) fn to_absolute(self) -> Expiration {
+        self.unix_millis(unix_time_ms() as i64)
+            .map(Expiration::UnixMilliseconds)
+            .unwrap_or(self)
+    }
+
+    /// Returns the `Instant` at which the key expires, which may be in the
+    /// past.
+    ///
+    /// Returns `None` if the timeout overflows, including when the Unix time
+    /// at which the key expires cannot be represented in milliseconds, like
+    /// Redis.
+    pub(crate) fn instant(self) -> Option<Instant> {
+        let now = Instant::now();
+        let unix_now = unix_time_ms() as i64;
+
+        offset(now, self.unix_millis(unix_now)?.checked_sub(unix_now)?)
+    }
+
+    /// Returns the Unix time in milliseconds at which the key expires, with
+    /// relative timeouts starting at `unix_now`, or `None` if it overflows.
+    fn unix_millis(self, unix_now: i64) -> Option<i64> {
+        match self {
+            Expiration::Seconds(secs) => secs.checked_mul(1000)?.checked_add(unix_now),
+            Expiration::Milliseconds(millis) => millis.checked_add(unix_now),
+            Expiration::UnixSeconds(secs) => secs.checked_mul(1000),
+            Expiration::UnixMilliseconds(millis) => Some(millis),
+        }
+    }
+}
+
+/// Relative timeout, sent as `PEXPIRE`. Durations too long to be represented
+/// in milliseconds are clamped.
+impl From<Duration> for Expiration {
+    fn from(src: Duration) -> Expiration {
+        Expiration::Milliseconds(src.as_millis().min(i64::MAX as u128) as i64)
+    }
+}
+
+/// Absolute timeout, sent as `PEXPIREAT`. Times before the Unix epoch are
+/// sent as the epoch, which deletes the key all the same.
+impl From<SystemTime> for Expiration {
+    fn from(src: SystemTime) -> Expiration {
+        let millis = src
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+
+        Expiration::UnixMilliseconds(millis.min(i64::MAX as u128) as i64)
+    }
+}
+
+/// Returns `now` moved by `millis` milliseconds, which may be negative.
+///
+/// Instants too far in the past to be represented are clamped to `now`, as
+/// they expire the key all the same.
+fn offset(now: Instant, millis: i64) -> Option<Instant> {
+    let duration = Duration::from_millis(millis.unsigned_abs());
+
+    if millis >= 0 {
+        now.checked_add(duration)
+    } else {
+        Some(now.checked_sub(duration).unwrap_or(now))
+    }
+}
+
+impl ExpireCondition {
+    fn as_str(self) -> &'static str {
+        match self {
+            ExpireCondition::Nx => "nx",
+            ExpireCondition::Xx => "xx",
+            ExpireCondition::Gt => "gt",
+            ExpireCondition::Lt => "lt",
+        }
+    }
+}
+
+impl FromStr for ExpireCondition {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<ExpireCondition> {
+        match &s.to_lowercase()[..] {
+            "nx" => Ok(ExpireCondition::Nx),
+            "xx" => Ok(ExpireCondition::Xx),
+            "gt" => Ok(ExpireCondition::Gt),
+            "lt" => Ok(ExpireCondition::Lt),
+            _ => Err(format!("protocol error; unsupported option '{}'", s).into()),
+        }
+    }
+}
+
+impl Ttl {
+    /// Create a new `Ttl` command which fetches the time to live of `key`.
+    pub(crate) fn new(key: impl ToString, unit: TtlUnit) -> Ttl {
+        Ttl {
+            key: key.to_string(),
+            unit,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Ttl` instance from a received frame.
+    ///
+    /// The `TTL`, `PTTL`, `EXPIRETIME` or `PEXPIRETIME` string has already
+    /// been consumed. `unit` is determined by which of them was received.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing two entries.
+    ///
+    /// ```text
+    /// TTL key
+    /// PTTL key
+    /// EXPIRETIME key
+    /// PEXPIRETIME key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, unit: TtlUnit) -> crate::Result<Ttl> {
+        let key = parse.next_string()?;
+
+        Ok(Ttl { key, unit })
+    }
+
+    /// Execute the `Ttl` command against the locked database state.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let ttl = match state.ttl(&self.key) {
+            Some(Some(ttl)) => ttl,
+            Some(None) => return Frame::Integer(-1),
+            None => return Frame::Integer(-2),
+        };
+
+        let expires_at = || {
+            SystemTime::now()
+                .checked_add(ttl)
+                .and_then(|when| when.duration_since(UNIX_EPOCH).ok())
+                .unwrap_or(Duration::MAX)
+        };
+
+        // Values too large to be represented are saturated rather than
+        // wrapped.
+        let value = match self.unit {
+            // Rounded to the nearest second, like Redis
+            TtlUnit::Seconds => ttl.saturating_add(Duration::from_millis(500)).as_secs() as u128,
+            TtlUnit::Milliseconds => ttl.as_millis(),
+            TtlUnit::UnixSeconds => expires_at().as_secs() as u128,
+            TtlUnit::UnixMilliseconds => expires_at().as_millis(),
+        };
+
+        Frame::Integer(i64::try_from(value).unwrap_or(i64::MAX))
+    }
+
+    /// Returns the command name
+    pub(crate) fn get_name(&self) -> &'static str {
+        match self.unit {
+            TtlUnit::Seconds => "ttl",
+            TtlUnit::Milliseconds => "pttl",
+            TtlUnit::UnixSeconds => "expiretime",
+            TtlUnit::UnixMilliseconds => "pexpiretime",
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Ttl` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from(self.get_name().as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl Persist {
+    /// Create a new `Persist` command which removes the timeout of `key`.
+    pub fn new(key: impl ToString) -> Persist {
+        Persist {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Persist` instance from a received frame.
+    ///
+    /// The `PERSIST` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing two entries.
+    ///
+    /// ```text
+    /// PERSIST key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Persist> {
+        let key = parse.next_string()?;
+
+        Ok(Persist { key })
+    }
+
+    /// Execute the `Persist` command against the locked database state.
+    ///
+    /// Responds with `1` if the timeout was removed, or `0` if the key does
+    /// not exist or has no timeout.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        Frame::Integer(state.persist(&self.key) as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Persist` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("persist".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
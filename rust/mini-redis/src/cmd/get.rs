@@ -1,7 +1,10 @@
-use crate::{Connection, Db, Frame, Parse};
+use crate::cmd::{Expiration, ParseError};
+use crate::db::State;
+use crate::{Frame, Parse};
 
 use bytes::Bytes;
-use tracing::{debug, instrument};
+use std::time::Duration;
+use tracing::instrument;
 
 /// Get the value of key.
 ///
@@ -56,38 +59,261 @@ impl Get {
         Ok(Get { key })
     }
 
-    /// Apply the `Get` command to the specified `Db` instance.
+    /// Execute the `Get` command against the locked database state.
     ///
-    /// The response is written to `dst`. This is called by the server in order
-    /// to execute a received command.
-    #[instrument(skip(self, db, dst))]
-    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+    /// Returns the response to send back to the client.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
         // Get the value from the shared database state
-        let response = if let Some(value) = db.get(&self.key) {
+        match state.get(&self.key) {
             // If a value is present, it is written to the client in "bulk"
             // format.
-            Frame::Bulk(value)
-        } else {
+            Ok(Some(value)) => Frame::Bulk(value),
             // If there is no value, `Null` is written.
-            Frame::Null
+            Ok(None) => Frame::Null,
+            // The key holds a value that is not a string.
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Get` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("get".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+/// Get the value of key and delete the key.
+///
+/// This command is similar to GET, except for the fact that it also deletes
+/// the key on success.
+#[derive(Debug, Clone)]
+pub struct GetDel {
+    /// Name of the key to get
+    key: String,
+}
+
+/// Get the value of key and optionally set its expiration.
+///
+/// # Options
+///
+/// * EX `seconds` -- Set the specified expire time, in seconds.
+/// * PX `milliseconds` -- Set the specified expire time, in milliseconds.
+/// * EXAT `timestamp` -- Set the specified Unix time at which the key will
+///   expire, in seconds.
+/// * PXAT `timestamp` -- Set the specified Unix time at which the key will
+///   expire, in milliseconds.
+/// * PERSIST -- Remove the time to live associated with the key.
+#[derive(Debug, Clone)]
+pub struct GetEx {
+    /// Name of the key to get
+    key: String,
+
+    /// Change made to the expiration of the key, if any
+    expire: Option<GetExExpire>,
+}
+
+/// Change made by `GETEX` to the expiration of the key.
+#[derive(Debug, Clone, Copy)]
+enum GetExExpire {
+    /// Expire the key as given
+    Expire(Expiration),
+
+    /// Remove the expiration of the key
+    Persist,
+}
+
+impl GetDel {
+    /// Create a new `GetDel` command which fetches and deletes `key`.
+    pub fn new(key: impl ToString) -> GetDel {
+        GetDel {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `GetDel` instance from a received frame.
+    ///
+    /// The `GETDEL` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing two entries.
+    ///
+    /// ```text
+    /// GETDEL key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<GetDel> {
+        let key = parse.next_string()?;
+
+        Ok(GetDel { key })
+    }
+
+    /// Execute the `GetDel` command against the locked database state.
+    ///
+    /// Responds with the value of the key, or `Null` if it does not exist.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.getdel(&self.key) {
+            Ok(Some(value)) => Frame::Bulk(value),
+            Ok(None) => Frame::Null,
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `GetDel` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("getdel".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl GetEx {
+    /// Create a new `GetEx` command which fetches `key` and sets it to expire
+    /// after `expire`.
+    ///
+    /// If `expire` is `None`, the expiration of the key is removed instead.
+    pub fn new(key: impl ToString, expire: Option<Duration>) -> GetEx {
+        let expire = match expire {
+            Some(expire) => GetExExpire::Expire(Expiration::from(expire)),
+            None => GetExExpire::Persist,
+        };
+
+        GetEx {
+            key: key.to_string(),
+            expire: Some(expire),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `GetEx` instance from a received frame.
+    ///
+    /// The `GETEX` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing two to four entries.
+    ///
+    /// ```text
+    /// GETEX key [EX seconds|PX milliseconds|EXAT unix-time-seconds|
+    ///     PXAT unix-time-milliseconds|PERSIST]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<GetEx> {
+        use ParseError::EndOfStream;
+
+        let key = parse.next_string()?;
+
+        let option = match parse.next_string() {
+            Ok(option) => option.to_uppercase(),
+            Err(EndOfStream) => return Ok(GetEx { key, expire: None }),
+            Err(err) => return Err(err.into()),
+        };
+
+        let expiration = match &option[..] {
+            "EX" => Expiration::Seconds,
+            "PX" => Expiration::Milliseconds,
+            "EXAT" => Expiration::UnixSeconds,
+            "PXAT" => Expiration::UnixMilliseconds,
+            "PERSIST" => {
+                return Ok(GetEx {
+                    key,
+                    expire: Some(GetExExpire::Persist),
+                })
+            }
+            _ => return Err(format!("protocol error; invalid `GETEX` option '{}'", option).into()),
+        };
+
+        let expire = GetExExpire::Expire(expiration(parse.next_signed_int()?));
+
+        Ok(GetEx {
+            key,
+            expire: Some(expire),
+        })
+    }
+
+    /// Execute the `GetEx` command against the locked database state.
+    ///
+    /// Responds with the value of the key, or `Null` if it does not exist.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let value = match state.get(&self.key) {
+            Ok(Some(value)) => value,
+            Ok(None) => return Frame::Null,
+            Err(err) => return err.into(),
         };
 
-        debug!(?response);
+        match self.expire {
+            Some(GetExExpire::Expire(expire)) => {
+                let when = match expire.instant() {
+                    Some(when) if expire.value() > 0 => when,
+                    _ => {
+                        return Frame::Error(
+                            "ERR invalid expire time in 'getex' command".to_string(),
+                        )
+                    }
+                };
 
-        // Write the response back to the client
-        dst.write_frame(&response).await?;
+                state.expire(&self.key, when, None);
+            }
+            Some(GetExExpire::Persist) => {
+                state.persist(&self.key);
+            }
+            None => {}
+        }
 
-        Ok(())
+        Frame::Bulk(value)
+    }
+
+    /// Returns the command with its expiration given as a Unix time, sent as
+    /// `PXAT`, so that replaying it later does not restart the timeout.
+    pub(crate) fn to_absolute(&self) -> GetEx {
+        let expire = match self.expire {
+            Some(GetExExpire::Expire(expire)) => Some(GetExExpire::Expire(expire.to_absolute())),
+            expire => expire,
+        };
+
+        GetEx {
+            expire,
+            ..self.clone()
+        }
     }
 
     /// Converts the command into an equivalent `Frame`.
     ///
-    /// This is called by the client when encoding a `Get` command to send to
+    /// This is called by the client when encoding a `GetEx` command to send to
     /// the server.
     pub(crate) fn into_frame(self) -> Frame {
         let mut frame = Frame::array();
-        frame.push_bulk(Bytes::from("get".as_bytes()));
+        frame.push_bulk(Bytes::from("getex".as_bytes()));
         frame.push_bulk(Bytes::from(self.key.into_bytes()));
+
+        match self.expire {
+            Some(GetExExpire::Expire(expire)) => {
+                frame.push_bulk(Bytes::from(expire.option().as_bytes()));
+                frame.push_int(expire.value());
+            }
+            Some(GetExExpire::Persist) => frame.push_bulk(Bytes::from("persist".as_bytes())),
+            None => {}
+        }
+
         frame
     }
 }
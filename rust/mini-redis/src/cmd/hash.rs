@@ -0,0 +1,389 @@
+use crate::cmd::{Parse, ParseError};
+use crate::db::State;
+use crate::Frame;
+
+use bytes::Bytes;
+use tracing::instrument;
+
+/// Sets the specified fields to their respective values in the hash stored at
+/// key.
+///
+/// If key does not exist, a new key holding a hash is created. Fields that
+/// already exist in the hash are overwritten.
+#[derive(Debug, Clone)]
+pub struct HSet {
+    /// Name of the hash
+    key: String,
+
+    /// Fields to set, along with their values
+    fields: Vec<(Bytes, Bytes)>,
+}
+
+/// Returns the value associated with field in the hash stored at key.
+#[derive(Debug)]
+pub struct HGet {
+    /// Name of the hash
+    key: String,
+
+    /// Name of the field
+    field: Bytes,
+}
+
+/// Removes the specified fields from the hash stored at key.
+///
+/// Fields that do not exist within the hash are ignored. Deleting all fields
+/// of a hash removes the key.
+#[derive(Debug, Clone)]
+pub struct HDel {
+    /// Name of the hash
+    key: String,
+
+    /// Fields to remove
+    fields: Vec<Bytes>,
+}
+
+/// Returns all fields and values of the hash stored at key.
+#[derive(Debug)]
+pub struct HGetAll {
+    /// Name of the hash
+    key: String,
+}
+
+/// Increments the number stored at field in the hash stored at key by
+/// increment.
+///
+/// If the field does not exist, it is set to `0` before performing the
+/// operation. The increment may be negative.
+#[derive(Debug, Clone)]
+pub struct HIncrBy {
+    /// Name of the hash
+    key: String,
+
+    /// Name of the field
+    field: Bytes,
+
+    /// Amount to add to the field
+    increment: i64,
+}
+
+impl HSet {
+    /// Create a new `HSet` command which sets `fields` of the hash stored at
+    /// `key`.
+    pub fn new(key: impl ToString, fields: Vec<(Bytes, Bytes)>) -> HSet {
+        HSet {
+            key: key.to_string(),
+            fields,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `HSet` instance from a received frame.
+    ///
+    /// The `HSET` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least four entries.
+    ///
+    /// ```text
+    /// HSET key field value [field value ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HSet> {
+        let key = parse.next_string()?;
+
+        // At least one field is required
+        let mut fields = vec![(parse.next_bytes()?, parse.next_bytes()?)];
+
+        loop {
+            match parse.next_bytes() {
+                Ok(field) => fields.push((field, parse.next_bytes()?)),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(HSet { key, fields })
+    }
+
+    /// Execute the `HSet` command against the locked database state.
+    ///
+    /// Responds with the number of fields that were added.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.hset(self.key, self.fields) {
+            Ok(added) => Frame::Integer(added as i64),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `HSet` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hset".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        for (field, value) in self.fields {
+            frame.push_bulk(field);
+            frame.push_bulk(value);
+        }
+        frame
+    }
+}
+
+impl HGet {
+    /// Create a new `HGet` command which fetches `field` of the hash stored at
+    /// `key`.
+    pub fn new(key: impl ToString, field: Bytes) -> HGet {
+        HGet {
+            key: key.to_string(),
+            field,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `HGet` instance from a received frame.
+    ///
+    /// The `HGET` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing three entries.
+    ///
+    /// ```text
+    /// HGET key field
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HGet> {
+        let key = parse.next_string()?;
+        let field = parse.next_bytes()?;
+
+        Ok(HGet { key, field })
+    }
+
+    /// Execute the `HGet` command against the locked database state.
+    ///
+    /// Responds with the value of the field, or `Null` if either the field or
+    /// the key does not exist.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.hget(&self.key, &self.field) {
+            Ok(Some(value)) => Frame::Bulk(value),
+            Ok(None) => Frame::Null,
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `HGet` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hget".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.field);
+        frame
+    }
+}
+
+impl HDel {
+    /// Create a new `HDel` command which removes `fields` from the hash stored
+    /// at `key`.
+    pub fn new(key: impl ToString, fields: Vec<Bytes>) -> HDel {
+        HDel {
+            key: key.to_string(),
+            fields,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `HDel` instance from a received frame.
+    ///
+    /// The `HDEL` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least three entries.
+    ///
+    /// ```text
+    /// HDEL key field [field ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HDel> {
+        let key = parse.next_string()?;
+
+        // At least one field is required
+        let mut fields = vec![parse.next_bytes()?];
+
+        loop {
+            match parse.next_bytes() {
+                Ok(field) => fields.push(field),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(HDel { key, fields })
+    }
+
+    /// Execute the `HDel` command against the locked database state.
+    ///
+    /// Responds with the number of fields that were removed.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.hdel(&self.key, &self.fields) {
+            Ok(removed) => Frame::Integer(removed as i64),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `HDel` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hdel".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        for field in self.fields {
+            frame.push_bulk(field);
+        }
+        frame
+    }
+}
+
+impl HGetAll {
+    /// Create a new `HGetAll` command which fetches all fields of the hash
+    /// stored at `key`.
+    pub fn new(key: impl ToString) -> HGetAll {
+        HGetAll {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `HGetAll` instance from a received frame.
+    ///
+    /// The `HGETALL` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing two entries.
+    ///
+    /// ```text
+    /// HGETALL key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HGetAll> {
+        let key = parse.next_string()?;
+
+        Ok(HGetAll { key })
+    }
+
+    /// Execute the `HGetAll` command against the locked database state.
+    ///
+    /// Responds with a map of fields to values. RESP2 connections receive the
+    /// map flattened into an array of alternating fields and values.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.hgetall(&self.key) {
+            Ok(fields) => Frame::Map(
+                fields
+                    .into_iter()
+                    .map(|(field, value)| (Frame::Bulk(field), Frame::Bulk(value)))
+                    .collect(),
+            ),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `HGetAll` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hgetall".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl HIncrBy {
+    /// Create a new `HIncrBy` command which increments `field` of the hash
+    /// stored at `key` by `increment`.
+    pub fn new(key: impl ToString, field: Bytes, increment: i64) -> HIncrBy {
+        HIncrBy {
+            key: key.to_string(),
+            field,
+            increment,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `HIncrBy` instance from a received frame.
+    ///
+    /// The `HINCRBY` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing four entries.
+    ///
+    /// ```text
+    /// HINCRBY key field increment
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<HIncrBy> {
+        let key = parse.next_string()?;
+        let field = parse.next_bytes()?;
+        let increment = parse.next_signed_int()?;
+
+        Ok(HIncrBy {
+            key,
+            field,
+            increment,
+        })
+    }
+
+    /// Execute the `HIncrBy` command against the locked database state.
+    ///
+    /// Responds with the value of the field after the increment.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.hincrby(self.key, self.field, self.increment) {
+            Ok(value) => Frame::Integer(value),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `HIncrBy` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hincrby".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.field);
+        frame.push_int(self.increment);
+        frame
+    }
+}
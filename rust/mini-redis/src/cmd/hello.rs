@@ -0,0 +1,136 @@
+use crate::cmd::{Parse, ParseError};
+use crate::frame::Protocol;
+use crate::{Connection, Frame};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Switch to a different protocol version and return a summary of the server.
+///
+/// Connections start out speaking RESP2. `HELLO 3` switches the connection to
+/// RESP3, after which replies may use the RESP3 frame types and pub/sub
+/// messages are delivered as push frames. `HELLO` without a version keeps the
+/// current protocol and only returns the server summary.
+///
+/// # Options
+///
+/// The `AUTH` and `SETNAME` options are accepted for compatibility with
+/// clients that always send them. `mini-redis` has neither authentication nor
+/// client names, so their values are ignored.
+#[derive(Debug, Default)]
+pub struct Hello {
+    /// The requested protocol version, if any.
+    protover: Option<u64>,
+}
+
+impl Hello {
+    /// Create a new `Hello` command requesting protocol version `protover`.
+    pub fn new(protover: Option<u64>) -> Hello {
+        Hello { protover }
+    }
+
+    /// Parse a `Hello` instance from a received frame.
+    ///
+    /// The `Parse` argument provides a cursor-like API to read fields from the
+    /// `Frame`. At this point, the entire frame has already been received from
+    /// the socket.
+    ///
+    /// The `HELLO` string has already been consumed.
+    ///
+    /// # Returns
+    ///
+    /// Returns the `Hello` value on success. If the frame is malformed, `Err`
+    /// is returned.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing `HELLO` and optional arguments.
+    ///
+    /// ```text
+    /// HELLO [protover [AUTH username password] [SETNAME clientname]]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Hello> {
+        use ParseError::EndOfStream;
+
+        let protover = match parse.next_int() {
+            Ok(protover) => protover,
+            Err(EndOfStream) => return Ok(Hello::default()),
+            Err(err) => return Err(err.into()),
+        };
+
+        // Consume the options. Their values are not used.
+        loop {
+            match parse.next_string() {
+                Ok(s) if s.to_uppercase() == "AUTH" => {
+                    parse.next_string()?;
+                    parse.next_string()?;
+                }
+                Ok(s) if s.to_uppercase() == "SETNAME" => {
+                    parse.next_string()?;
+                }
+                Ok(s) => return Err(format!("ERR syntax error in HELLO option '{}'", s).into()),
+                Err(EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(Hello::new(Some(protover)))
+    }
+
+    /// Apply the `Hello` command, switching the protocol used by `dst`.
+    ///
+    /// The response is written to `dst` using the **new** protocol version.
+    #[instrument(skip(self, dst))]
+    pub(crate) async fn apply(self, dst: &mut Connection) -> crate::Result<()> {
+        let protocol = match self.protover {
+            None => dst.protocol(),
+            Some(2) => Protocol::Resp2,
+            Some(3) => Protocol::Resp3,
+            Some(_) => {
+                let response = Frame::Error("NOPROTO unsupported protocol version".to_string());
+                debug!(?response);
+                dst.write_frame(&response).await?;
+                return Ok(());
+            }
+        };
+
+        dst.set_protocol(protocol);
+
+        let proto = match protocol {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        };
+
+        let response = Frame::Map(vec![
+            (bulk("server"), bulk("mini-redis")),
+            (bulk("version"), bulk(env!("CARGO_PKG_VERSION"))),
+            (bulk("proto"), Frame::Integer(proto)),
+            (bulk("mode"), bulk("standalone")),
+            (bulk("role"), bulk("master")),
+            (bulk("modules"), Frame::array()),
+        ]);
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Hello` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hello".as_bytes()));
+        if let Some(protover) = self.protover {
+            frame.push_int(protover as i64);
+        }
+        frame
+    }
+}
+
+fn bulk(value: &'static str) -> Frame {
+    Frame::Bulk(Bytes::from_static(value.as_bytes()))
+}
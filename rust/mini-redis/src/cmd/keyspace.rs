@@ -0,0 +1,796 @@
+use crate::cmd::{Parse, ParseError};
+use crate::db::State;
+use crate::Frame;
+
+use bytes::Bytes;
+use std::convert::TryInto;
+use tracing::instrument;
+
+/// Removes the specified keys. A key is ignored if it does not exist.
+#[derive(Debug, Clone)]
+pub struct Del {
+    /// Keys to remove
+    keys: Vec<String>,
+}
+
+/// Returns the number of the specified keys that exist.
+///
+/// A key mentioned several times is counted several times.
+#[derive(Debug)]
+pub struct Exists {
+    /// Keys to check
+    keys: Vec<String>,
+}
+
+/// Returns all keys matching pattern.
+///
+/// The pattern is glob-style, as used by `PSUBSCRIBE`. `KEYS` visits every
+/// key of the database at once, `SCAN` should be preferred to inspect large
+/// databases.
+#[derive(Debug)]
+pub struct Keys {
+    /// Glob-style pattern the keys must match
+    pattern: String,
+}
+
+/// Incrementally iterates over the keys of the database.
+///
+/// Each call returns a cursor to pass to the next call, and some keys. The
+/// iteration is complete once the returned cursor is `0`. Every key that
+/// exists during the whole iteration is returned exactly once, even if other
+/// keys are added or removed in between calls.
+///
+/// # Options
+///
+/// * MATCH `pattern` -- Only return keys matching the glob-style pattern.
+/// * COUNT `count` -- Number of keys to visit per call, `10` by default.
+///   Fewer keys are returned when they are filtered by `MATCH` or `TYPE`.
+/// * TYPE `type` -- Only return keys holding a value of the given type.
+#[derive(Debug)]
+pub struct Scan {
+    /// Position to continue the iteration at, `0` to start a new iteration
+    cursor: u64,
+
+    /// Glob-style pattern the keys must match
+    pattern: Option<String>,
+
+    /// Number of keys to visit
+    count: Option<u64>,
+
+    /// Type of value the keys must hold
+    kind: Option<String>,
+}
+
+/// Returns the type of the value stored at key: `string`, `list`, `hash`,
+/// `zset` or `none` if the key does not exist.
+#[derive(Debug)]
+pub struct Type {
+    /// the lookup key
+    key: String,
+}
+
+/// Renames key to newkey.
+///
+/// An error is returned if key does not exist. If newkey already exists, it is
+/// overwritten. The time to live of key is retained.
+#[derive(Debug, Clone)]
+pub struct Rename {
+    /// Key to rename
+    key: String,
+
+    /// New name of the key
+    newkey: String,
+}
+
+/// Moves key from the selected database to the database numbered db.
+///
+/// Nothing is moved if key does not exist, or if it already exists in the
+/// destination database. The time to live of key is retained.
+#[derive(Debug, Clone)]
+pub struct Move {
+    /// Key to move
+    key: String,
+
+    /// Number of the destination database
+    db: usize,
+}
+
+/// Swaps the data of two databases.
+///
+/// Connections keep the database they selected, and see the data of the other
+/// database from then on.
+#[derive(Debug, Clone)]
+pub struct SwapDb {
+    /// Number of the first database
+    index1: usize,
+
+    /// Number of the second database
+    index2: usize,
+}
+
+/// Returns the number of keys in the selected database.
+#[derive(Debug, Default)]
+pub struct DbSize;
+
+/// Removes all keys of the selected database.
+///
+/// The `ASYNC` and `SYNC` options are accepted for compatibility. The keys are
+/// always removed before responding.
+#[derive(Debug, Clone, Default)]
+pub struct FlushDb;
+
+/// Removes all keys of all databases.
+///
+/// The `ASYNC` and `SYNC` options are accepted for compatibility. The keys are
+/// always removed before responding.
+#[derive(Debug, Clone, Default)]
+pub struct FlushAll;
+
+/// Default number of keys visited by `SCAN`.
+const DEFAULT_SCAN_COUNT: u64 = 10;
+
+impl Del {
+    /// Create a new `Del` command which removes `keys`.
+    pub fn new(keys: &[String]) -> Del {
+        Del {
+            keys: keys.to_vec(),
+        }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `Del` instance from a received frame.
+    ///
+    /// The `DEL` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least two entries.
+    ///
+    /// ```text
+    /// DEL key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Del> {
+        Ok(Del {
+            keys: parse_keys(parse)?,
+        })
+    }
+
+    /// Execute the `Del` command against the locked database state.
+    ///
+    /// Responds with the number of keys that were removed.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        Frame::Integer(state.del(&self.keys) as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Del` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("del".as_bytes()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+        }
+        frame
+    }
+}
+
+impl Exists {
+    /// Create a new `Exists` command which counts the existing `keys`.
+    pub fn new(keys: &[String]) -> Exists {
+        Exists {
+            keys: keys.to_vec(),
+        }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse an `Exists` instance from a received frame.
+    ///
+    /// The `EXISTS` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least two entries.
+    ///
+    /// ```text
+    /// EXISTS key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Exists> {
+        Ok(Exists {
+            keys: parse_keys(parse)?,
+        })
+    }
+
+    /// Execute the `Exists` command against the locked database state.
+    ///
+    /// Responds with the number of keys that exist.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        Frame::Integer(state.exists(&self.keys) as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding an `Exists` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("exists".as_bytes()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+        }
+        frame
+    }
+}
+
+impl Keys {
+    /// Create a new `Keys` command which lists the keys matching `pattern`.
+    pub fn new(pattern: impl ToString) -> Keys {
+        Keys {
+            pattern: pattern.to_string(),
+        }
+    }
+
+    /// Parse a `Keys` instance from a received frame.
+    ///
+    /// The `KEYS` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing two entries.
+    ///
+    /// ```text
+    /// KEYS pattern
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Keys> {
+        let pattern = parse.next_string()?;
+
+        Ok(Keys { pattern })
+    }
+
+    /// Execute the `Keys` command against the locked database state.
+    ///
+    /// Responds with an array of the matching keys.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let keys = state.keys(self.pattern.as_bytes());
+
+        Frame::Array(keys_frames(keys))
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Keys` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("keys".as_bytes()));
+        frame.push_bulk(Bytes::from(self.pattern.into_bytes()));
+        frame
+    }
+}
+
+impl Scan {
+    /// Create a new `Scan` command which continues the iteration at `cursor`.
+    pub fn new(cursor: u64) -> Scan {
+        Scan {
+            cursor,
+            pattern: None,
+            count: None,
+            kind: None,
+        }
+    }
+
+    /// Only return keys matching the glob-style `pattern`.
+    pub fn pattern(mut self, pattern: impl ToString) -> Scan {
+        self.pattern = Some(pattern.to_string());
+        self
+    }
+
+    /// Visit about `count` keys.
+    pub fn count(mut self, count: u64) -> Scan {
+        self.count = Some(count);
+        self
+    }
+
+    /// Only return keys holding a value of type `kind`, as returned by
+    /// `TYPE`.
+    pub fn kind(mut self, kind: impl ToString) -> Scan {
+        self.kind = Some(kind.to_string());
+        self
+    }
+
+    /// Parse a `Scan` instance from a received frame.
+    ///
+    /// The `SCAN` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least two entries.
+    ///
+    /// ```text
+    /// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Scan> {
+        let mut scan = Scan::new(parse.next_int()?);
+
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option.to_uppercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            };
+
+            match &option[..] {
+                "MATCH" => scan.pattern = Some(parse.next_string()?),
+                "COUNT" => match parse.next_int()? {
+                    0 => return Err("protocol error; `SCAN` count must be positive".into()),
+                    count => scan.count = Some(count),
+                },
+                "TYPE" => scan.kind = Some(parse.next_string()?),
+                _ => {
+                    return Err(
+                        format!("protocol error; invalid `SCAN` option '{}'", option).into(),
+                    )
+                }
+            }
+        }
+
+        Ok(scan)
+    }
+
+    /// Execute the `Scan` command against the locked database state.
+    ///
+    /// Responds with an array of two entries: the cursor to continue the
+    /// iteration with, and an array of keys.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let count = self
+            .count
+            .unwrap_or(DEFAULT_SCAN_COUNT)
+            .try_into()
+            .unwrap_or(usize::MAX);
+
+        let (cursor, keys) = state.scan(
+            self.cursor,
+            count,
+            self.pattern.as_ref().map(|pattern| pattern.as_bytes()),
+            self.kind.as_deref(),
+        );
+
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from(cursor.to_string())),
+            Frame::Array(keys_frames(keys)),
+        ])
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Scan` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("scan".as_bytes()));
+        frame.push_bulk(Bytes::from(self.cursor.to_string()));
+        if let Some(pattern) = self.pattern {
+            frame.push_bulk(Bytes::from("match".as_bytes()));
+            frame.push_bulk(Bytes::from(pattern.into_bytes()));
+        }
+        if let Some(count) = self.count {
+            frame.push_bulk(Bytes::from("count".as_bytes()));
+            frame.push_bulk(Bytes::from(count.to_string()));
+        }
+        if let Some(kind) = self.kind {
+            frame.push_bulk(Bytes::from("type".as_bytes()));
+            frame.push_bulk(Bytes::from(kind.into_bytes()));
+        }
+        frame
+    }
+}
+
+impl Type {
+    /// Create a new `Type` command which fetches the type of the value stored
+    /// at `key`.
+    pub fn new(key: impl ToString) -> Type {
+        Type {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Type` instance from a received frame.
+    ///
+    /// The `TYPE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing two entries.
+    ///
+    /// ```text
+    /// TYPE key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Type> {
+        let key = parse.next_string()?;
+
+        Ok(Type { key })
+    }
+
+    /// Execute the `Type` command against the locked database state.
+    ///
+    /// Responds with the name of the type as a simple string.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        Frame::Simple(state.key_type(&self.key).to_string())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Type` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("type".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl Rename {
+    /// Create a new `Rename` command which renames `key` to `newkey`.
+    pub fn new(key: impl ToString, newkey: impl ToString) -> Rename {
+        Rename {
+            key: key.to_string(),
+            newkey: newkey.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Get the new name of the key
+    pub fn newkey(&self) -> &str {
+        &self.newkey
+    }
+
+    /// Parse a `Rename` instance from a received frame.
+    ///
+    /// The `RENAME` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing three entries.
+    ///
+    /// ```text
+    /// RENAME key newkey
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Rename> {
+        let key = parse.next_string()?;
+        let newkey = parse.next_string()?;
+
+        Ok(Rename { key, newkey })
+    }
+
+    /// Execute the `Rename` command against the locked database state.
+    ///
+    /// Responds with `OK`, or an error if the key does not exist.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.rename(&self.key, self.newkey) {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Rename` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("rename".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.newkey.into_bytes()));
+        frame
+    }
+}
+
+impl Move {
+    /// Create a new `Move` command which moves `key` to the database numbered
+    /// `db`.
+    pub fn new(key: impl ToString, db: usize) -> Move {
+        Move {
+            key: key.to_string(),
+            db,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Move` instance from a received frame.
+    ///
+    /// The `MOVE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing three entries.
+    ///
+    /// ```text
+    /// MOVE key db
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Move> {
+        let key = parse.next_string()?;
+        let db = parse_index(parse)?;
+
+        Ok(Move { key, db })
+    }
+
+    /// Execute the `Move` command against the locked database state.
+    ///
+    /// Responds with `1` if the key was moved, and `0` otherwise.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        if state.is_cluster_enabled() {
+            return Frame::Error("ERR MOVE is not allowed in cluster mode".to_string());
+        }
+
+        match state.move_key(&self.key, self.db) {
+            Ok(moved) => Frame::Integer(moved as i64),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Move` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("move".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.db.to_string()));
+        frame
+    }
+}
+
+impl SwapDb {
+    /// Create a new `SwapDb` command which swaps the databases numbered
+    /// `index1` and `index2`.
+    pub fn new(index1: usize, index2: usize) -> SwapDb {
+        SwapDb { index1, index2 }
+    }
+
+    /// Parse a `SwapDb` instance from a received frame.
+    ///
+    /// The `SWAPDB` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing three entries.
+    ///
+    /// ```text
+    /// SWAPDB index1 index2
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<SwapDb> {
+        let index1 = parse_index(parse)?;
+        let index2 = parse_index(parse)?;
+
+        Ok(SwapDb { index1, index2 })
+    }
+
+    /// Execute the `SwapDb` command against the locked database state.
+    ///
+    /// Responds with `OK`, or an error if either database does not exist.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        if state.is_cluster_enabled() {
+            return Frame::Error("ERR SWAPDB is not allowed in cluster mode".to_string());
+        }
+
+        match state.swapdb(self.index1, self.index2) {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `SwapDb` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("swapdb".as_bytes()));
+        frame.push_bulk(Bytes::from(self.index1.to_string()));
+        frame.push_bulk(Bytes::from(self.index2.to_string()));
+        frame
+    }
+}
+
+impl DbSize {
+    /// Create a new `DbSize` command.
+    pub fn new() -> DbSize {
+        DbSize
+    }
+
+    /// Parse a `DbSize` instance from a received frame.
+    ///
+    /// The `DBSIZE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing a single entry.
+    ///
+    /// ```text
+    /// DBSIZE
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<DbSize> {
+        Ok(DbSize)
+    }
+
+    /// Execute the `DbSize` command against the locked database state.
+    ///
+    /// Responds with the number of keys.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        Frame::Integer(state.dbsize() as i64)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `DbSize` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("dbsize".as_bytes()));
+        frame
+    }
+}
+
+impl FlushDb {
+    /// Create a new `FlushDb` command.
+    pub fn new() -> FlushDb {
+        FlushDb
+    }
+
+    /// Parse a `FlushDb` instance from a received frame.
+    ///
+    /// The `FLUSHDB` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing one or two entries.
+    ///
+    /// ```text
+    /// FLUSHDB [ASYNC|SYNC]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<FlushDb> {
+        parse_flush_mode(parse, "FLUSHDB")?;
+
+        Ok(FlushDb)
+    }
+
+    /// Execute the `FlushDb` command against the locked database state.
+    ///
+    /// Responds with `OK`.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        state.flushdb();
+
+        Frame::Simple("OK".to_string())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `FlushDb` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("flushdb".as_bytes()));
+        frame
+    }
+}
+
+impl FlushAll {
+    /// Create a new `FlushAll` command.
+    pub fn new() -> FlushAll {
+        FlushAll
+    }
+
+    /// Parse a `FlushAll` instance from a received frame.
+    ///
+    /// The `FLUSHALL` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing one or two entries.
+    ///
+    /// ```text
+    /// FLUSHALL [ASYNC|SYNC]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<FlushAll> {
+        parse_flush_mode(parse, "FLUSHALL")?;
+
+        Ok(FlushAll)
+    }
+
+    /// Execute the `FlushAll` command against the locked database state.
+    ///
+    /// Responds with `OK`.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        state.flushall();
+
+        Frame::Simple("OK".to_string())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `FlushAll` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("flushall".as_bytes()));
+        frame
+    }
+}
+
+/// Parse one or more keys, until the end of the frame.
+fn parse_keys(parse: &mut Parse) -> crate::Result<Vec<String>> {
+    // At least one key is required
+    let mut keys = vec![parse.next_string()?];
+
+    loop {
+        match parse.next_string() {
+            Ok(key) => keys.push(key),
+            Err(ParseError::EndOfStream) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(keys)
+}
+
+/// Parse the optional `ASYNC` or `SYNC` option of `command`.
+fn parse_flush_mode(parse: &mut Parse, command: &str) -> crate::Result<()> {
+    match parse.next_string() {
+        Ok(option) if ["ASYNC", "SYNC"].contains(&&option.to_uppercase()[..]) => Ok(()),
+        Ok(option) => {
+            Err(format!("protocol error; invalid `{}` option '{}'", command, option).into())
+        }
+        Err(ParseError::EndOfStream) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Parse the number of a database.
+fn parse_index(parse: &mut Parse) -> crate::Result<usize> {
+    parse
+        .next_int()?
+        .try_into()
+        .map_err(|_| "protocol error; invalid database number".into())
+}
+
+/// Convert keys to an array of bulk frames.
+fn keys_frames(keys: Vec<String>) -> Vec<Frame> {
+    keys.into_iter()
+        .map(|key| Frame::Bulk(Bytes::from(key.into_bytes())))
+        .collect()
+}
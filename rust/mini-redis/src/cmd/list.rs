@@ -0,0 +1,708 @@
+use crate::cmd::{Parse, ParseError};
+use crate::db::{Side, State};
+use crate::{Connection, Db, Frame, Shutdown};
+
+use bytes::Bytes;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::{self, Instant};
+use tracing::{debug, instrument};
+
+/// Insert all the specified values at the head (`LPUSH`) or the tail
+/// (`RPUSH`) of the list stored at key.
+///
+/// If key does not exist, it is created as an empty list before performing the
+/// push operations. When key holds a value that is not a list, an error is
+/// returned.
+#[derive(Debug, Clone)]
+pub struct Push {
+    /// Name of the list
+    key: String,
+
+    /// Values to push, in order
+    values: Vec<Bytes>,
+
+    /// The end of the list to push onto
+    side: Side,
+}
+
+/// Remove and return the first (`LPOP`) or last (`RPOP`) elements of the list
+/// stored at key.
+///
+/// By default, the command pops a single element from the list. When provided
+/// with the optional `count` argument, the reply will consist of up to `count`
+/// elements, depending on the list's length.
+#[derive(Debug, Clone)]
+pub struct Pop {
+    /// Name of the list
+    key: String,
+
+    /// Number of elements to pop. `None` pops a single element and replies
+    /// with a bulk string instead of an array.
+    count: Option<u64>,
+
+    /// The end of the list to pop from
+    side: Side,
+}
+
+/// Returns the specified elements of the list stored at key.
+///
+/// The offsets `start` and `stop` are zero-based indexes, with `0` being the
+/// first element of the list. They can also be negative numbers indicating
+/// offsets starting at the end of the list.
+#[derive(Debug)]
+pub struct LRange {
+    /// Name of the list
+    key: String,
+
+    /// Index of the first element to return
+    start: i64,
+
+    /// Index of the last element to return, inclusive
+    stop: i64,
+}
+
+/// Returns the length of the list stored at key.
+///
+/// If key does not exist, it is interpreted as an empty list and `0` is
+/// returned.
+#[derive(Debug)]
+pub struct LLen {
+    /// Name of the list
+    key: String,
+}
+
+/// Blocking variant of `LPOP` (`BLPOP`) and `RPOP` (`BRPOP`).
+///
+/// Pops a single element from the first non-empty list among the given keys.
+/// If all lists are empty, the connection is blocked until another client
+/// pushes to one of the keys or the timeout elapses.
+///
+/// When several connections are blocked on the same key, each push wakes all
+/// of them and only the first one to retry receives the value. The others go
+/// back to waiting.
+#[derive(Debug)]
+pub struct BPop {
+    /// Names of the lists, checked in order
+    keys: Vec<String>,
+
+    /// Maximum duration to block for. A zero duration blocks indefinitely.
+    timeout: Duration,
+
+    /// The end of the list to pop from
+    side: Side,
+}
+
+/// Atomically moves an element from one list to another, blocking until the
+/// source list contains an element or the timeout elapses.
+///
+/// Pops an element from the `from` side of the list stored at `source` and
+/// pushes it onto the `to` side of the list stored at `destination`.
+#[derive(Debug)]
+pub struct BLMove {
+    /// Name of the list to pop from
+    source: String,
+
+    /// Name of the list to push onto
+    destination: String,
+
+    /// The end of the source list to pop from
+    from: Side,
+
+    /// The end of the destination list to push onto
+    to: Side,
+
+    /// Maximum duration to block for. A zero duration blocks indefinitely.
+    timeout: Duration,
+}
+
+impl Push {
+    /// Create a new `Push` command which pushes `values` onto `side` of the
+    /// list stored at `key`.
+    pub(crate) fn new(key: impl ToString, values: Vec<Bytes>, side: Side) -> Push {
+        Push {
+            key: key.to_string(),
+            values,
+            side,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Push` instance from a received frame.
+    ///
+    /// The `LPUSH` or `RPUSH` string has already been consumed. `side` is
+    /// determined by which of the two was received.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least three entries.
+    ///
+    /// ```text
+    /// LPUSH key element [element ...]
+    /// RPUSH key element [element ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, side: Side) -> crate::Result<Push> {
+        let key = parse.next_string()?;
+
+        // At least one value is required
+        let mut values = vec![parse.next_bytes()?];
+
+        loop {
+            match parse.next_bytes() {
+                Ok(value) => values.push(value),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(Push { key, values, side })
+    }
+
+    /// Execute the `Push` command against the locked database state.
+    ///
+    /// Responds with the length of the list after the push.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.push(self.key, self.values, self.side) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Returns the command name
+    pub(crate) fn get_name(&self) -> &'static str {
+        match self.side {
+            Side::Left => "lpush",
+            Side::Right => "rpush",
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Push` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from(self.get_name().as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        for value in self.values {
+            frame.push_bulk(value);
+        }
+        frame
+    }
+}
+
+impl Pop {
+    /// Create a new `Pop` command which pops a single value from `side` of the
+    /// list stored at `key`.
+    pub(crate) fn new(key: impl ToString, side: Side) -> Pop {
+        Pop {
+            key: key.to_string(),
+            count: None,
+            side,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Pop` instance from a received frame.
+    ///
+    /// The `LPOP` or `RPOP` string has already been consumed. `side` is
+    /// determined by which of the two was received.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing two or three entries.
+    ///
+    /// ```text
+    /// LPOP key [count]
+    /// RPOP key [count]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, side: Side) -> crate::Result<Pop> {
+        let key = parse.next_string()?;
+
+        let count = match parse.next_int() {
+            Ok(count) => Some(count),
+            Err(ParseError::EndOfStream) => None,
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Pop { key, count, side })
+    }
+
+    /// Execute the `Pop` command against the locked database state.
+    ///
+    /// Responds with the popped value, or with an array of values if a count
+    /// was given. If the key does not exist, `Null` is returned.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let count = self.count.unwrap_or(1) as usize;
+
+        match state.pop(&self.key, self.side, count) {
+            Ok(Some(values)) if self.count.is_some() => {
+                Frame::Array(values.into_iter().map(Frame::Bulk).collect())
+            }
+            Ok(Some(mut values)) => match values.pop() {
+                Some(value) => Frame::Bulk(value),
+                None => Frame::Null,
+            },
+            Ok(None) => Frame::Null,
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Returns the command name
+    pub(crate) fn get_name(&self) -> &'static str {
+        match self.side {
+            Side::Left => "lpop",
+            Side::Right => "rpop",
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Pop` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from(self.get_name().as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        if let Some(count) = self.count {
+            frame.push_int(count as i64);
+        }
+        frame
+    }
+}
+
+impl LRange {
+    /// Create a new `LRange` command which returns the elements between
+    /// `start` and `stop` of the list stored at `key`.
+    pub fn new(key: impl ToString, start: i64, stop: i64) -> LRange {
+        LRange {
+            key: key.to_string(),
+            start,
+            stop,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `LRange` instance from a received frame.
+    ///
+    /// The `LRANGE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing four entries.
+    ///
+    /// ```text
+    /// LRANGE key start stop
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<LRange> {
+        let key = parse.next_string()?;
+        let start = parse.next_signed_int()?;
+        let stop = parse.next_signed_int()?;
+
+        Ok(LRange { key, start, stop })
+    }
+
+    /// Execute the `LRange` command against the locked database state.
+    ///
+    /// Responds with an array of the requested elements.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.lrange(&self.key, self.start, self.stop) {
+            Ok(values) => Frame::Array(values.into_iter().map(Frame::Bulk).collect()),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `LRange` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("lrange".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_int(self.start);
+        frame.push_int(self.stop);
+        frame
+    }
+}
+
+impl LLen {
+    /// Create a new `LLen` command which returns the length of the list stored
+    /// at `key`.
+    pub fn new(key: impl ToString) -> LLen {
+        LLen {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `LLen` instance from a received frame.
+    ///
+    /// The `LLEN` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing two entries.
+    ///
+    /// ```text
+    /// LLEN key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<LLen> {
+        let key = parse.next_string()?;
+
+        Ok(LLen { key })
+    }
+
+    /// Execute the `LLen` command against the locked database state.
+    ///
+    /// Responds with the length of the list.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.llen(&self.key) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `LLen` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("llen".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl BPop {
+    /// Create a new `BPop` command which pops a single value from `side` of
+    /// the first non-empty list among `keys`, waiting at most `timeout`.
+    pub(crate) fn new(keys: &[String], timeout: Duration, side: Side) -> BPop {
+        BPop {
+            keys: keys.to_vec(),
+            timeout,
+            side,
+        }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `BPop` instance from a received frame.
+    ///
+    /// The `BLPOP` or `BRPOP` string has already been consumed. `side` is
+    /// determined by which of the two was received.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least three entries. The timeout
+    /// is given in seconds and may be fractional.
+    ///
+    /// ```text
+    /// BLPOP key [key ...] timeout
+    /// BRPOP key [key ...] timeout
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, side: Side) -> crate::Result<BPop> {
+        // The timeout is the last argument, so all arguments are read first.
+        let mut args = vec![parse.next_string()?, parse.next_string()?];
+
+        loop {
+            match parse.next_string() {
+                Ok(arg) => args.push(arg),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let timeout = args
+            .pop()
+            .and_then(|timeout| timeout.parse().ok())
+            .ok_or("ERR timeout is not a float or out of range")?;
+        let timeout = to_duration(timeout)?;
+
+        Ok(BPop {
+            keys: args,
+            timeout,
+            side,
+        })
+    }
+
+    /// Apply the `BPop` command to the specified `Db` instance.
+    ///
+    /// Responds with a two element array holding the name of the key the
+    /// value was popped from and the value itself. If the timeout elapses
+    /// first, `Null` is returned.
+    ///
+    /// If the server shuts down while the connection is blocked, no response
+    /// is written.
+    #[instrument(skip(self, db, dst, shutdown))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        shutdown: &mut Shutdown,
+    ) -> crate::Result<()> {
+        let waiter = Arc::new(Notify::new());
+        let deadline = deadline(self.timeout);
+
+        let response = loop {
+            let res = db.with_keys(&self.keys, |state| {
+                let res = state.pop_or_block(&self.keys, self.side, &waiter);
+
+                // Blocking is irrelevant when replaying the append-only file,
+                // so the equivalent non-blocking pop is appended instead.
+                if let Ok(Some((key, _))) = &res {
+                    state.propagate(&Pop::new(key, self.side).into_frame());
+                }
+
+                res
+            });
+
+            match res {
+                Ok(Some((key, value))) => {
+                    break Frame::Array(vec![Frame::Bulk(Bytes::from(key)), Frame::Bulk(value)]);
+                }
+                Ok(None) => {}
+                Err(err) => break err.into(),
+            }
+
+            match wait(&waiter, deadline, shutdown).await {
+                Wake::Notified => {}
+                Wake::TimedOut | Wake::Shutdown => break Frame::Null,
+            }
+        };
+
+        db.with_keys(&self.keys, |state| state.unblock(&self.keys, &waiter));
+
+        if shutdown.is_shutdown() {
+            return Ok(());
+        }
+
+        db.wait_for_aof().await;
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Returns the command name
+    pub(crate) fn get_name(&self) -> &'static str {
+        match self.side {
+            Side::Left => "blpop",
+            Side::Right => "brpop",
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `BPop` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from(self.get_name().as_bytes()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+        }
+        frame.push_bulk(Bytes::from(self.timeout.as_secs_f64().to_string()));
+        frame
+    }
+}
+
+impl BLMove {
+    /// Parse a `BLMove` instance from a received frame.
+    ///
+    /// The `BLMOVE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing six entries. The timeout is given in
+    /// seconds and may be fractional.
+    ///
+    /// ```text
+    /// BLMOVE source destination LEFT|RIGHT LEFT|RIGHT timeout
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<BLMove> {
+        let source = parse.next_string()?;
+        let destination = parse.next_string()?;
+        let from = parse_side(&parse.next_string()?)?;
+        let to = parse_side(&parse.next_string()?)?;
+        let timeout = to_duration(parse.next_float()?)?;
+
+        Ok(BLMove {
+            source,
+            destination,
+            from,
+            to,
+            timeout,
+        })
+    }
+
+    /// Get the key of the list elements are moved from
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Get the key of the list elements are moved to
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    /// Apply the `BLMove` command to the specified `Db` instance.
+    ///
+    /// Responds with the element being moved. If the timeout elapses first,
+    /// `Null` is returned.
+    ///
+    /// If the server shuts down while the connection is blocked, no response
+    /// is written.
+    #[instrument(skip(self, db, dst, shutdown))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        shutdown: &mut Shutdown,
+    ) -> crate::Result<()> {
+        let waiter = Arc::new(Notify::new());
+        let deadline = deadline(self.timeout);
+
+        let response = loop {
+            let res = db.with_keys(&[&self.source, &self.destination], |state| {
+                let res = state.move_or_block(
+                    &self.source,
+                    &self.destination,
+                    self.from,
+                    self.to,
+                    &waiter,
+                );
+
+                // There is no non-blocking variant of `BLMOVE`. The move is
+                // appended to the append-only file as a pop and a push.
+                if let Ok(Some(value)) = &res {
+                    state.propagate(&Pop::new(&self.source, self.from).into_frame());
+                    state.propagate(
+                        &Push::new(&self.destination, vec![value.clone()], self.to).into_frame(),
+                    );
+                }
+
+                res
+            });
+
+            match res {
+                Ok(Some(value)) => break Frame::Bulk(value),
+                Ok(None) => {}
+                Err(err) => break err.into(),
+            }
+
+            match wait(&waiter, deadline, shutdown).await {
+                Wake::Notified => {}
+                Wake::TimedOut | Wake::Shutdown => break Frame::Null,
+            }
+        };
+
+        let source = [self.source];
+        db.with_keys(&source, |state| state.unblock(&source, &waiter));
+
+        if shutdown.is_shutdown() {
+            return Ok(());
+        }
+
+        db.wait_for_aof().await;
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+/// Why a blocked command stopped waiting.
+pub(super) enum Wake {
+    /// A value was pushed to one of the keys. The command should retry.
+    Notified,
+
+    /// The timeout elapsed.
+    TimedOut,
+
+    /// The server is shutting down.
+    Shutdown,
+}
+
+/// Wait until `waiter` is notified, `deadline` is reached or the server starts
+/// shutting down, whichever happens first.
+pub(super) async fn wait(
+    waiter: &Notify,
+    deadline: Option<Instant>,
+    shutdown: &mut Shutdown,
+) -> Wake {
+    let timeout = async {
+        match deadline {
+            Some(deadline) => time::sleep_until(deadline).await,
+            // Block indefinitely
+            None => std::future::pending().await,
+        }
+    };
+
+    tokio::select! {
+        _ = waiter.notified() => Wake::Notified,
+        _ = timeout => Wake::TimedOut,
+        _ = shutdown.recv() => Wake::Shutdown,
+    }
+}
+
+/// Returns the instant at which a command blocking for `timeout` gives up, or
+/// `None` if it blocks indefinitely.
+pub(super) fn deadline(timeout: Duration) -> Option<Instant> {
+    if timeout == Duration::from_secs(0) {
+        None
+    } else {
+        Some(Instant::now() + timeout)
+    }
+}
+
+/// Convert a blocking timeout, given in seconds, to a `Duration`.
+fn to_duration(secs: f64) -> crate::Result<Duration> {
+    if secs < 0.0 {
+        return Err("ERR timeout is negative".into());
+    }
+
+    if !secs.is_finite() {
+        return Err("ERR timeout is out of range".into());
+    }
+
+    Ok(Duration::from_secs_f64(secs))
+}
+
+/// Parse a `LEFT` or `RIGHT` argument.
+fn parse_side(side: &str) -> crate::Result<Side> {
+    match &side.to_uppercase()[..] {
+        "LEFT" => Ok(Side::Left),
+        "RIGHT" => Ok(Side::Right),
+        _ => Err("ERR syntax error".into()),
+    }
+}
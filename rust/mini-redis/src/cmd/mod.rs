@@ -1,33 +1,159 @@
+mod bgrewriteaof;
+pub use bgrewriteaof::BgRewriteAof;
+
+mod cluster;
+pub use cluster::{Asking, Cluster};
+
+mod config;
+pub use config::Config;
+
+mod expire;
+pub(crate) use expire::{Expiration, TtlUnit};
+pub use expire::{Expire, ExpireCondition, Persist, Ttl};
+
 mod get;
-pub use get::Get;
+pub use get::{Get, GetDel, GetEx};
+
+mod hash;
+pub use hash::{HDel, HGet, HGetAll, HIncrBy, HSet};
+
+mod hello;
+pub use hello::Hello;
+
+mod keyspace;
+pub use keyspace::{
+    DbSize, Del, Exists, FlushAll, FlushDb, Keys, Move, Rename, Scan, SwapDb, Type,
+};
+
+mod list;
+pub use list::{BLMove, BPop, LLen, LRange, Pop, Push};
 
 mod publish;
 pub use publish::Publish;
 
+mod replication;
+pub use replication::{Info, PSync, ReplicaOf};
+
+mod save;
+pub use save::{BgSave, Save};
+
+mod script;
+pub use script::{Eval, EvalSha, Script};
+
+mod select;
+pub use select::Select;
+
 mod set;
-pub use set::Set;
+pub use set::{Set, SetCondition};
+
+mod sorted_set;
+pub use sorted_set::{ZAdd, ZRange, ZRangeByScore, ZRank, ZRem};
+
+mod stream;
+pub(crate) use stream::{
+    parse_group_id, parse_group_read_id, parse_new_id, parse_range_end, parse_range_start,
+    parse_read_id,
+};
+pub use stream::{XAck, XAdd, XClaim, XGroup, XLen, XPending, XRange, XRead, XReadGroup, XTrim};
+
+mod string;
+pub use string::{Append, IncrBy, IncrByFloat, StrLen};
 
 mod subscribe;
-pub use subscribe::{Subscribe, Unsubscribe};
+pub use subscribe::{PSubscribe, PUnsubscribe, Subscribe, Unsubscribe};
 
 mod ping;
 pub use ping::Ping;
 
+mod transaction;
+pub(crate) use transaction::Transaction;
+pub use transaction::{Discard, Exec, Multi, Unwatch, Watch};
+
 mod unknown;
 pub use unknown::Unknown;
 
+use crate::db::{Side, State};
 use crate::{Connection, Db, Frame, Parse, ParseError, Shutdown};
 
+use tracing::debug;
+
 /// Enumeration of supported Redis commands.
 ///
 /// Methods called on `Command` are delegated to the command implementation.
 #[derive(Debug)]
 pub enum Command {
     Get(Get),
+    GetDel(GetDel),
+    GetEx(GetEx),
+    IncrBy(IncrBy),
+    IncrByFloat(IncrByFloat),
+    Append(Append),
+    StrLen(StrLen),
+    Del(Del),
+    Exists(Exists),
+    Keys(Keys),
+    Scan(Scan),
+    Type(Type),
+    Rename(Rename),
+    Move(Move),
+    SwapDb(SwapDb),
+    DbSize(DbSize),
+    FlushDb(FlushDb),
+    FlushAll(FlushAll),
+    Hello(Hello),
     Publish(Publish),
     Set(Set),
+    Expire(Expire),
+    Ttl(Ttl),
+    Persist(Persist),
+    Push(Push),
+    Pop(Pop),
+    LRange(LRange),
+    LLen(LLen),
+    BPop(BPop),
+    BLMove(BLMove),
+    HSet(HSet),
+    HGet(HGet),
+    HDel(HDel),
+    HGetAll(HGetAll),
+    HIncrBy(HIncrBy),
+    ZAdd(ZAdd),
+    ZRange(ZRange),
+    ZRangeByScore(ZRangeByScore),
+    ZRank(ZRank),
+    ZRem(ZRem),
+    XAdd(XAdd),
+    XRange(XRange),
+    XLen(XLen),
+    XTrim(XTrim),
+    XRead(XRead),
+    XGroup(XGroup),
+    XReadGroup(XReadGroup),
+    XAck(XAck),
+    XPending(XPending),
+    XClaim(XClaim),
+    BgRewriteAof(BgRewriteAof),
+    Save(Save),
+    BgSave(BgSave),
+    Eval(Eval),
+    EvalSha(EvalSha),
+    Script(Script),
+    ReplicaOf(ReplicaOf),
+    PSync(PSync),
+    Info(Info),
+    Config(Config),
+    Cluster(Cluster),
+    Asking(Asking),
+    Select(Select),
     Subscribe(Subscribe),
     Unsubscribe(Unsubscribe),
+    PSubscribe(PSubscribe),
+    PUnsubscribe(PUnsubscribe),
+    Multi(Multi),
+    Exec(Exec),
+    Discard(Discard),
+    Watch(Watch),
+    Unwatch(Unwatch),
     Ping(Ping),
     Unknown(Unknown),
 }
@@ -58,10 +184,100 @@ impl Command {
         // specific command.
         let command = match &command_name[..] {
             "get" => Command::Get(Get::parse_frames(&mut parse)?),
+            "hello" => Command::Hello(Hello::parse_frames(&mut parse)?),
             "publish" => Command::Publish(Publish::parse_frames(&mut parse)?),
+            "getdel" => Command::GetDel(GetDel::parse_frames(&mut parse)?),
+            "getex" => Command::GetEx(GetEx::parse_frames(&mut parse)?),
+            "incr" => Command::IncrBy(IncrBy::parse_unit_frames(&mut parse, false)?),
+            "decr" => Command::IncrBy(IncrBy::parse_unit_frames(&mut parse, true)?),
+            "incrby" => Command::IncrBy(IncrBy::parse_frames(&mut parse, false)?),
+            "decrby" => Command::IncrBy(IncrBy::parse_frames(&mut parse, true)?),
+            "incrbyfloat" => Command::IncrByFloat(IncrByFloat::parse_frames(&mut parse)?),
+            "append" => Command::Append(Append::parse_frames(&mut parse)?),
+            "strlen" => Command::StrLen(StrLen::parse_frames(&mut parse)?),
+            "del" => Command::Del(Del::parse_frames(&mut parse)?),
+            "exists" => Command::Exists(Exists::parse_frames(&mut parse)?),
+            "keys" => Command::Keys(Keys::parse_frames(&mut parse)?),
+            "scan" => Command::Scan(Scan::parse_frames(&mut parse)?),
+            "type" => Command::Type(Type::parse_frames(&mut parse)?),
+            "rename" => Command::Rename(Rename::parse_frames(&mut parse)?),
+            "move" => Command::Move(Move::parse_frames(&mut parse)?),
+            "swapdb" => Command::SwapDb(SwapDb::parse_frames(&mut parse)?),
+            "dbsize" => Command::DbSize(DbSize::parse_frames(&mut parse)?),
+            "flushdb" => Command::FlushDb(FlushDb::parse_frames(&mut parse)?),
+            "flushall" => Command::FlushAll(FlushAll::parse_frames(&mut parse)?),
             "set" => Command::Set(Set::parse_frames(&mut parse)?),
+            "getset" => Command::Set(Set::parse_getset_frames(&mut parse)?),
+            "expire" => Command::Expire(Expire::parse_frames(&mut parse, Expiration::Seconds)?),
+            "pexpire" => {
+                Command::Expire(Expire::parse_frames(&mut parse, Expiration::Milliseconds)?)
+            }
+            "expireat" => {
+                Command::Expire(Expire::parse_frames(&mut parse, Expiration::UnixSeconds)?)
+            }
+            "pexpireat" => Command::Expire(Expire::parse_frames(
+                &mut parse,
+                Expiration::UnixMilliseconds,
+            )?),
+            "ttl" => Command::Ttl(Ttl::parse_frames(&mut parse, TtlUnit::Seconds)?),
+            "pttl" => Command::Ttl(Ttl::parse_frames(&mut parse, TtlUnit::Milliseconds)?),
+            "expiretime" => Command::Ttl(Ttl::parse_frames(&mut parse, TtlUnit::UnixSeconds)?),
+            "pexpiretime" => {
+                Command::Ttl(Ttl::parse_frames(&mut parse, TtlUnit::UnixMilliseconds)?)
+            }
+            "persist" => Command::Persist(Persist::parse_frames(&mut parse)?),
+            "lpush" => Command::Push(Push::parse_frames(&mut parse, Side::Left)?),
+            "rpush" => Command::Push(Push::parse_frames(&mut parse, Side::Right)?),
+            "lpop" => Command::Pop(Pop::parse_frames(&mut parse, Side::Left)?),
+            "rpop" => Command::Pop(Pop::parse_frames(&mut parse, Side::Right)?),
+            "lrange" => Command::LRange(LRange::parse_frames(&mut parse)?),
+            "llen" => Command::LLen(LLen::parse_frames(&mut parse)?),
+            "blpop" => Command::BPop(BPop::parse_frames(&mut parse, Side::Left)?),
+            "brpop" => Command::BPop(BPop::parse_frames(&mut parse, Side::Right)?),
+            "blmove" => Command::BLMove(BLMove::parse_frames(&mut parse)?),
+            "hset" => Command::HSet(HSet::parse_frames(&mut parse)?),
+            "hget" => Command::HGet(HGet::parse_frames(&mut parse)?),
+            "hdel" => Command::HDel(HDel::parse_frames(&mut parse)?),
+            "hgetall" => Command::HGetAll(HGetAll::parse_frames(&mut parse)?),
+            "hincrby" => Command::HIncrBy(HIncrBy::parse_frames(&mut parse)?),
+            "zadd" => Command::ZAdd(ZAdd::parse_frames(&mut parse)?),
+            "zrange" => Command::ZRange(ZRange::parse_frames(&mut parse)?),
+            "zrangebyscore" => Command::ZRangeByScore(ZRangeByScore::parse_frames(&mut parse)?),
+            "zrank" => Command::ZRank(ZRank::parse_frames(&mut parse)?),
+            "zrem" => Command::ZRem(ZRem::parse_frames(&mut parse)?),
+            "xadd" => Command::XAdd(XAdd::parse_frames(&mut parse)?),
+            "xrange" => Command::XRange(XRange::parse_frames(&mut parse)?),
+            "xlen" => Command::XLen(XLen::parse_frames(&mut parse)?),
+            "xtrim" => Command::XTrim(XTrim::parse_frames(&mut parse)?),
+            "xread" => Command::XRead(XRead::parse_frames(&mut parse)?),
+            "xgroup" => Command::XGroup(XGroup::parse_frames(&mut parse)?),
+            "xreadgroup" => Command::XReadGroup(XReadGroup::parse_frames(&mut parse)?),
+            "xack" => Command::XAck(XAck::parse_frames(&mut parse)?),
+            "xpending" => Command::XPending(XPending::parse_frames(&mut parse)?),
+            "xclaim" => Command::XClaim(XClaim::parse_frames(&mut parse)?),
+            "bgrewriteaof" => Command::BgRewriteAof(BgRewriteAof::parse_frames(&mut parse)?),
+            "save" => Command::Save(Save::parse_frames(&mut parse)?),
+            "bgsave" => Command::BgSave(BgSave::parse_frames(&mut parse)?),
+            "eval" => Command::Eval(Eval::parse_frames(&mut parse)?),
+            "evalsha" => Command::EvalSha(EvalSha::parse_frames(&mut parse)?),
+            "script" => Command::Script(Script::parse_frames(&mut parse)?),
+            "replicaof" | "slaveof" => Command::ReplicaOf(ReplicaOf::parse_frames(&mut parse)?),
+            "psync" => Command::PSync(PSync::parse_frames(&mut parse)?),
+            "sync" => Command::PSync(PSync::new()),
+            "info" => Command::Info(Info::parse_frames(&mut parse)?),
+            "config" => Command::Config(Config::parse_frames(&mut parse)?),
+            "cluster" => Command::Cluster(Cluster::parse_frames(&mut parse)?),
+            "asking" => Command::Asking(Asking::parse_frames(&mut parse)?),
+            "select" => Command::Select(Select::parse_frames(&mut parse)?),
             "subscribe" => Command::Subscribe(Subscribe::parse_frames(&mut parse)?),
             "unsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(&mut parse)?),
+            "psubscribe" => Command::PSubscribe(PSubscribe::parse_frames(&mut parse)?),
+            "punsubscribe" => Command::PUnsubscribe(PUnsubscribe::parse_frames(&mut parse)?),
+            "multi" => Command::Multi(Multi::parse_frames(&mut parse)?),
+            "exec" => Command::Exec(Exec::parse_frames(&mut parse)?),
+            "discard" => Command::Discard(Discard::parse_frames(&mut parse)?),
+            "watch" => Command::Watch(Watch::parse_frames(&mut parse)?),
+            "unwatch" => Command::Unwatch(Unwatch::parse_frames(&mut parse)?),
             "ping" => Command::Ping(Ping::parse_frames(&mut parse)?),
             _ => {
                 // The command is not recognized and an Unknown command is
@@ -87,24 +303,426 @@ impl Command {
     ///
     /// The response is written to `dst`. This is called by the server in order
     /// to execute a received command.
+    ///
+    /// While a transaction is started on the connection, commands are queued
+    /// in `transaction` instead of being applied. `asking` is set by `ASKING`,
+    /// and only applies to the next command. `SELECT` replaces `db` with a
+    /// handle to the selected database.
     pub(crate) async fn apply(
         self,
-        db: &Db,
+        db: &mut Db,
         dst: &mut Connection,
         shutdown: &mut Shutdown,
+        transaction: &mut Transaction,
+        asking: &mut bool,
     ) -> crate::Result<()> {
         use Command::*;
 
+        // In cluster mode, commands accessing keys served by another node are
+        // redirected to it.
+        let keys = self.keys();
+        let asking_once = std::mem::take(asking);
+        if !keys.is_empty() {
+            if let Some(response) = db.cluster_redirect(&keys, asking_once) {
+                transaction.abort();
+
+                debug!(?response);
+                dst.write_frame(&response).await?;
+
+                return Ok(());
+            }
+        }
+
+        // Replicas only receive writes from their master, otherwise their key
+        // space would diverge.
+        if self.is_write() && db.is_read_only() {
+            transaction.abort();
+
+            let response =
+                Frame::Error("READONLY You can't write against a read only replica.".to_string());
+            debug!(?response);
+            dst.write_frame(&response).await?;
+
+            return Ok(());
+        }
+
+        // Keys are evicted to bring the memory used back under `maxmemory`
+        // before commands which may use more memory. They are rejected if not
+        // enough memory could be freed.
+        if self.uses_memory() {
+            if let Err(err) = db.evict() {
+                transaction.abort();
+
+                let response = Frame::from(err);
+                debug!(?response);
+                dst.write_frame(&response).await?;
+
+                return Ok(());
+            }
+        }
+
         match self {
-            Get(cmd) => cmd.apply(db, dst).await,
+            Multi(cmd) => cmd.apply(transaction, dst).await,
+            Exec(cmd) => cmd.apply(db, transaction, dst).await,
+            Discard(cmd) => cmd.apply(db, transaction, dst).await,
+            Watch(cmd) => cmd.apply(db, transaction, dst).await,
+            cmd if transaction.is_queuing() => transaction.queue(cmd, dst).await,
+            Unwatch(cmd) => cmd.apply(db, transaction, dst).await,
+            Hello(cmd) => cmd.apply(dst).await,
             Publish(cmd) => cmd.apply(db, dst).await,
-            Set(cmd) => cmd.apply(db, dst).await,
             Subscribe(cmd) => cmd.apply(db, dst, shutdown).await,
+            PSubscribe(cmd) => cmd.apply(db, dst, shutdown).await,
+            BPop(cmd) => cmd.apply(db, dst, shutdown).await,
+            BLMove(cmd) => cmd.apply(db, dst, shutdown).await,
+            XRead(cmd) if cmd.is_blocking() => cmd.apply(db, dst, shutdown).await,
+            XReadGroup(cmd) if cmd.is_blocking() => cmd.apply(db, dst, shutdown).await,
+            ReplicaOf(cmd) => cmd.apply(db, dst).await,
+            Asking(cmd) => cmd.apply(asking, dst).await,
+            Select(cmd) => cmd.apply(db, dst).await,
+            PSync(cmd) => cmd.apply(db, dst, shutdown).await,
             Ping(cmd) => cmd.apply(dst).await,
             Unknown(cmd) => cmd.apply(dst).await,
-            // `Unsubscribe` cannot be applied. It may only be received from the
-            // context of a `Subscribe` command.
+            // `Unsubscribe` and `PUnsubscribe` cannot be applied. They may only
+            // be received from the context of a `Subscribe` command.
             Unsubscribe(_) => Err("`Unsubscribe` is unsupported in this context".into()),
+            PUnsubscribe(_) => Err("`PUnsubscribe` is unsupported in this context".into()),
+            // All other commands operate on the key space. They are executed
+            // while holding the database locks, and the response is written
+            // once the locks have been released. Commands accessing known keys
+            // only lock the shards holding them.
+            cmd => {
+                let is_write = cmd.is_write();
+
+                let response = if cmd.is_keyed() {
+                    let shards = db.shards_of(&cmd.keys());
+                    db.with_shards(&shards, |state| cmd.execute(state))
+                } else {
+                    db.with_state(|state| cmd.execute(state))
+                };
+
+                // With the `always` fsync policy, a command modifying the key
+                // space must be on disk before the client is told it
+                // succeeded.
+                if is_write {
+                    db.wait_for_aof().await;
+                }
+
+                debug!(?response);
+
+                dst.write_frame(&response).await?;
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Execute a key space command against the locked database state.
+    ///
+    /// Returns the response to send back to the client. Commands that need
+    /// access to the connection, such as `Subscribe`, cannot be executed this
+    /// way and result in an error response.
+    ///
+    /// Commands modifying the key space are appended to the append-only file
+    /// and streamed to replicas once they succeed.
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        use Command::*;
+
+        // Executing the command consumes it, so the frame to propagate is
+        // built beforehand.
+        let aof_frame = if self.is_write() && state.is_propagating() {
+            self.to_aof_frame()
+        } else {
+            None
+        };
+
+        // Accessing a key, whether to read or to modify it, keeps it from
+        // being evicted by the LRU and LFU policies.
+        for key in self.keys() {
+            state.record_access(key);
+        }
+
+        let response = match self {
+            Get(cmd) => cmd.execute(state),
+            GetDel(cmd) => cmd.execute(state),
+            GetEx(cmd) => cmd.execute(state),
+            IncrBy(cmd) => cmd.execute(state),
+            IncrByFloat(cmd) => cmd.execute(state),
+            Append(cmd) => cmd.execute(state),
+            StrLen(cmd) => cmd.execute(state),
+            Del(cmd) => cmd.execute(state),
+            Exists(cmd) => cmd.execute(state),
+            Keys(cmd) => cmd.execute(state),
+            Scan(cmd) => cmd.execute(state),
+            Type(cmd) => cmd.execute(state),
+            Rename(cmd) => cmd.execute(state),
+            Move(cmd) => cmd.execute(state),
+            SwapDb(cmd) => cmd.execute(state),
+            DbSize(cmd) => cmd.execute(state),
+            FlushDb(cmd) => cmd.execute(state),
+            FlushAll(cmd) => cmd.execute(state),
+            Set(cmd) => cmd.execute(state),
+            Expire(cmd) => cmd.execute(state),
+            Ttl(cmd) => cmd.execute(state),
+            Persist(cmd) => cmd.execute(state),
+            Push(cmd) => cmd.execute(state),
+            Pop(cmd) => cmd.execute(state),
+            LRange(cmd) => cmd.execute(state),
+            LLen(cmd) => cmd.execute(state),
+            HSet(cmd) => cmd.execute(state),
+            HGet(cmd) => cmd.execute(state),
+            HDel(cmd) => cmd.execute(state),
+            HGetAll(cmd) => cmd.execute(state),
+            HIncrBy(cmd) => cmd.execute(state),
+            ZAdd(cmd) => cmd.execute(state),
+            ZRange(cmd) => cmd.execute(state),
+            ZRangeByScore(cmd) => cmd.execute(state),
+            ZRank(cmd) => cmd.execute(state),
+            ZRem(cmd) => cmd.execute(state),
+            XAdd(cmd) => cmd.execute(state),
+            XRange(cmd) => cmd.execute(state),
+            XLen(cmd) => cmd.execute(state),
+            XTrim(cmd) => cmd.execute(state),
+            XRead(cmd) => cmd.execute(state),
+            XGroup(cmd) => cmd.execute(state),
+            XReadGroup(cmd) => cmd.execute(state),
+            XAck(cmd) => cmd.execute(state),
+            XPending(cmd) => cmd.execute(state),
+            XClaim(cmd) => cmd.execute(state),
+            BgRewriteAof(cmd) => cmd.execute(state),
+            Save(cmd) => cmd.execute(state),
+            BgSave(cmd) => cmd.execute(state),
+            Eval(cmd) => cmd.execute(state),
+            EvalSha(cmd) => cmd.execute(state),
+            Script(cmd) => cmd.execute(state),
+            Info(cmd) => cmd.execute(state),
+            Config(cmd) => cmd.execute(state),
+            Cluster(cmd) => cmd.execute(state),
+            // Queued in a transaction, `UNWATCH` has no effect as `EXEC`
+            // unwatches all keys.
+            Unwatch(_) => Frame::Simple("OK".to_string()),
+            cmd => Frame::Error(format!(
+                "ERR '{}' cannot be executed in this context",
+                cmd.get_name()
+            )),
+        };
+
+        if let Some(frame) = aof_frame {
+            if !matches!(response, Frame::Error(_)) {
+                state.propagate(&frame);
+            }
+        }
+
+        response
+    }
+
+    /// Returns `true` if the command may modify the key space.
+    pub(crate) fn is_write(&self) -> bool {
+        use Command::*;
+
+        matches!(
+            self,
+            Set(_)
+                | GetDel(_)
+                | GetEx(_)
+                | IncrBy(_)
+                | IncrByFloat(_)
+                | Append(_)
+                | Del(_)
+                | Rename(_)
+                | Move(_)
+                | SwapDb(_)
+                | FlushDb(_)
+                | FlushAll(_)
+                | Expire(_)
+                | Persist(_)
+                | Push(_)
+                | Pop(_)
+                | BPop(_)
+                | BLMove(_)
+                | HSet(_)
+                | HDel(_)
+                | HIncrBy(_)
+                | ZAdd(_)
+                | ZRem(_)
+                | XAdd(_)
+                | XTrim(_)
+                | XGroup(_)
+                | XReadGroup(_)
+                | XAck(_)
+                | XClaim(_)
+                | Eval(_)
+                | EvalSha(_)
+        )
+    }
+
+    /// Returns `true` if the command may use more memory, and must be rejected
+    /// once `maxmemory` is reached if no key can be evicted.
+    ///
+    /// Commands which only remove data are always accepted, as they are the
+    /// way to free memory under the `noeviction` policy.
+    pub(crate) fn uses_memory(&self) -> bool {
+        use Command::*;
+
+        matches!(
+            self,
+            Set(_)
+                | IncrBy(_)
+                | IncrByFloat(_)
+                | Append(_)
+                | Push(_)
+                | BLMove(_)
+                | HSet(_)
+                | HIncrBy(_)
+                | ZAdd(_)
+                | XAdd(_)
+                | XGroup(_)
+                | Eval(_)
+                | EvalSha(_)
+        )
+    }
+
+    /// Returns the keys accessed by the command.
+    ///
+    /// Used in cluster mode to find the node serving the keys, and to record
+    /// accesses to the keys for eviction.
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        use Command::*;
+
+        match self {
+            Get(cmd) => vec![cmd.key()],
+            GetDel(cmd) => vec![cmd.key()],
+            GetEx(cmd) => vec![cmd.key()],
+            IncrBy(cmd) => vec![cmd.key()],
+            IncrByFloat(cmd) => vec![cmd.key()],
+            Append(cmd) => vec![cmd.key()],
+            StrLen(cmd) => vec![cmd.key()],
+            Del(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Exists(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Type(cmd) => vec![cmd.key()],
+            Rename(cmd) => vec![cmd.key(), cmd.newkey()],
+            Move(cmd) => vec![cmd.key()],
+            Set(cmd) => vec![cmd.key()],
+            Expire(cmd) => vec![cmd.key()],
+            Ttl(cmd) => vec![cmd.key()],
+            Persist(cmd) => vec![cmd.key()],
+            Push(cmd) => vec![cmd.key()],
+            Pop(cmd) => vec![cmd.key()],
+            LRange(cmd) => vec![cmd.key()],
+            LLen(cmd) => vec![cmd.key()],
+            BPop(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            BLMove(cmd) => vec![cmd.source(), cmd.destination()],
+            HSet(cmd) => vec![cmd.key()],
+            HGet(cmd) => vec![cmd.key()],
+            HDel(cmd) => vec![cmd.key()],
+            HGetAll(cmd) => vec![cmd.key()],
+            HIncrBy(cmd) => vec![cmd.key()],
+            ZAdd(cmd) => vec![cmd.key()],
+            ZRange(cmd) => vec![cmd.key()],
+            ZRangeByScore(cmd) => vec![cmd.key()],
+            ZRank(cmd) => vec![cmd.key()],
+            ZRem(cmd) => vec![cmd.key()],
+            XAdd(cmd) => vec![cmd.key()],
+            XRange(cmd) => vec![cmd.key()],
+            XLen(cmd) => vec![cmd.key()],
+            XTrim(cmd) => vec![cmd.key()],
+            XRead(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            XGroup(cmd) => vec![cmd.key()],
+            XReadGroup(cmd) => cmd.keys(),
+            XAck(cmd) => vec![cmd.key()],
+            XPending(cmd) => vec![cmd.key()],
+            XClaim(cmd) => vec![cmd.key()],
+            Eval(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            EvalSha(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Watch(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Returns `true` if the command only accesses the keys returned by
+    /// `keys`, so it can be executed with only the shards holding them
+    /// locked.
+    ///
+    /// Scripts are given their keys, but may access any other key.
+    pub(crate) fn is_keyed(&self) -> bool {
+        !self.keys().is_empty() && !matches!(self, Command::Eval(_) | Command::EvalSha(_))
+    }
+
+    /// Returns `true` if the command can be queued in a transaction.
+    ///
+    /// Only commands executed against the locked database state can be part
+    /// of a transaction.
+    pub(crate) fn is_transactional(&self) -> bool {
+        use Command::*;
+
+        !matches!(
+            self,
+            Hello(_)
+                | Publish(_)
+                | BPop(_)
+                | BLMove(_)
+                | Subscribe(_)
+                | Unsubscribe(_)
+                | PSubscribe(_)
+                | PUnsubscribe(_)
+                | ReplicaOf(_)
+                | PSync(_)
+                | Asking(_)
+                | Select(_)
+                | Multi(_)
+                | Exec(_)
+                | Discard(_)
+                | Watch(_)
+                | Ping(_)
+                | Unknown(_)
+        )
+    }
+
+    /// Returns `true` if the command can be called from a Lua script.
+    ///
+    /// The commands allowed in a transaction are allowed in scripts, except
+    /// for the scripting commands themselves and `CONFIG`.
+    pub(crate) fn is_scriptable(&self) -> bool {
+        use Command::*;
+
+        self.is_transactional() && !matches!(self, Eval(_) | EvalSha(_) | Script(_) | Config(_))
+    }
+
+    /// Returns the frame to append to the append-only file when the command
+    /// is executed, or `None` if the command does not modify the key space.
+    ///
+    /// Timeouts are given as a Unix time, so that replaying the command later
+    /// does not restart them.
+    ///
+    /// Blocking commands, scripts, `INCRBYFLOAT` and most stream commands are
+    /// not included. They append the commands equivalent to what they did
+    /// themselves, such as `XADD` with the ID it generated.
+    fn to_aof_frame(&self) -> Option<Frame> {
+        use Command::*;
+
+        match self {
+            Set(cmd) => Some(cmd.to_absolute().into_frame()),
+            GetDel(cmd) => Some(cmd.clone().into_frame()),
+            GetEx(cmd) => Some(cmd.to_absolute().into_frame()),
+            IncrBy(cmd) => Some(cmd.clone().into_frame()),
+            Append(cmd) => Some(cmd.clone().into_frame()),
+            Del(cmd) => Some(cmd.clone().into_frame()),
+            Rename(cmd) => Some(cmd.clone().into_frame()),
+            Move(cmd) => Some(cmd.clone().into_frame()),
+            SwapDb(cmd) => Some(cmd.clone().into_frame()),
+            FlushDb(cmd) => Some(cmd.clone().into_frame()),
+            FlushAll(cmd) => Some(cmd.clone().into_frame()),
+            Expire(cmd) => Some(cmd.to_absolute().into_frame()),
+            Persist(cmd) => Some(cmd.clone().into_frame()),
+            Push(cmd) => Some(cmd.clone().into_frame()),
+            Pop(cmd) => Some(cmd.clone().into_frame()),
+            HSet(cmd) => Some(cmd.clone().into_frame()),
+            HDel(cmd) => Some(cmd.clone().into_frame()),
+            HIncrBy(cmd) => Some(cmd.clone().into_frame()),
+            ZAdd(cmd) => Some(cmd.clone().into_frame()),
+            ZRem(cmd) => Some(cmd.clone().into_frame()),
+            XTrim(cmd) => Some(cmd.clone().into_frame()),
+            XAck(cmd) => Some(cmd.clone().into_frame()),
+            _ => None,
         }
     }
 
@@ -112,10 +730,77 @@ impl Command {
     pub(crate) fn get_name(&self) -> &str {
         match self {
             Command::Get(_) => "get",
+            Command::GetDel(_) => "getdel",
+            Command::GetEx(_) => "getex",
+            Command::IncrBy(cmd) => cmd.get_name(),
+            Command::IncrByFloat(_) => "incrbyfloat",
+            Command::Append(_) => "append",
+            Command::StrLen(_) => "strlen",
+            Command::Del(_) => "del",
+            Command::Exists(_) => "exists",
+            Command::Keys(_) => "keys",
+            Command::Scan(_) => "scan",
+            Command::Type(_) => "type",
+            Command::Rename(_) => "rename",
+            Command::Move(_) => "move",
+            Command::SwapDb(_) => "swapdb",
+            Command::DbSize(_) => "dbsize",
+            Command::FlushDb(_) => "flushdb",
+            Command::FlushAll(_) => "flushall",
+            Command::Hello(_) => "hello",
             Command::Publish(_) => "pub",
             Command::Set(_) => "set",
+            Command::Expire(cmd) => cmd.get_name(),
+            Command::Ttl(cmd) => cmd.get_name(),
+            Command::Persist(_) => "persist",
+            Command::Push(cmd) => cmd.get_name(),
+            Command::Pop(cmd) => cmd.get_name(),
+            Command::LRange(_) => "lrange",
+            Command::LLen(_) => "llen",
+            Command::BPop(cmd) => cmd.get_name(),
+            Command::BLMove(_) => "blmove",
+            Command::HSet(_) => "hset",
+            Command::HGet(_) => "hget",
+            Command::HDel(_) => "hdel",
+            Command::HGetAll(_) => "hgetall",
+            Command::HIncrBy(_) => "hincrby",
+            Command::ZAdd(_) => "zadd",
+            Command::ZRange(_) => "zrange",
+            Command::ZRangeByScore(_) => "zrangebyscore",
+            Command::ZRank(_) => "zrank",
+            Command::ZRem(_) => "zrem",
+            Command::XAdd(_) => "xadd",
+            Command::XRange(_) => "xrange",
+            Command::XLen(_) => "xlen",
+            Command::XTrim(_) => "xtrim",
+            Command::XRead(_) => "xread",
+            Command::XGroup(_) => "xgroup",
+            Command::XReadGroup(_) => "xreadgroup",
+            Command::XAck(_) => "xack",
+            Command::XPending(_) => "xpending",
+            Command::XClaim(_) => "xclaim",
+            Command::BgRewriteAof(_) => "bgrewriteaof",
+            Command::Save(_) => "save",
+            Command::BgSave(_) => "bgsave",
+            Command::Eval(_) => "eval",
+            Command::EvalSha(_) => "evalsha",
+            Command::Script(_) => "script",
+            Command::ReplicaOf(_) => "replicaof",
+            Command::PSync(_) => "psync",
+            Command::Info(_) => "info",
+            Command::Config(_) => "config",
+            Command::Cluster(_) => "cluster",
+            Command::Asking(_) => "asking",
+            Command::Select(_) => "select",
             Command::Subscribe(_) => "subscribe",
             Command::Unsubscribe(_) => "unsubscribe",
+            Command::PSubscribe(_) => "psubscribe",
+            Command::PUnsubscribe(_) => "punsubscribe",
+            Command::Multi(_) => "multi",
+            Command::Exec(_) => "exec",
+            Command::Discard(_) => "discard",
+            Command::Watch(_) => "watch",
+            Command::Unwatch(_) => "unwatch",
             Command::Ping(_) => "ping",
             Command::Unknown(cmd) => cmd.get_name(),
         }
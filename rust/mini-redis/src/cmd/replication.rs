@@ -0,0 +1,279 @@
+use crate::db::State;
+use crate::{Connection, Db, Frame, Parse, ParseError, Shutdown};
+
+use bytes::Bytes;
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info, instrument};
+
+/// Follow another server as a replica, or stop following any server.
+///
+/// The replica replaces its key space with the one of the master, then applies
+/// every command modifying the key space executed by the master. By default,
+/// a replica rejects commands modifying the key space sent by clients.
+#[derive(Debug)]
+pub struct ReplicaOf {
+    /// Host and port of the master, or `None` to become a master.
+    master: Option<(String, u16)>,
+}
+
+/// Synchronize a replica with this server.
+///
+/// Sent by a replica when it connects to its master. The connection is then
+/// used to stream commands to the replica, and cannot be used for anything
+/// else.
+#[derive(Debug)]
+pub struct PSync {
+    /// Replication ID the replica last synchronized with, or `?`.
+    replid: String,
+
+    /// Offset the replica reached, or `-1`.
+    offset: i64,
+}
+
+/// Returns information about the server.
+///
+/// Only the `replication` section is supported.
+#[derive(Debug, Default)]
+pub struct Info {
+    /// Requested section, or `None` for all sections.
+    section: Option<String>,
+}
+
+impl ReplicaOf {
+    /// Create a new `ReplicaOf` command which follows the master listening on
+    /// `host` and `port`.
+    pub fn new(host: impl ToString, port: u16) -> ReplicaOf {
+        ReplicaOf {
+            master: Some((host.to_string(), port)),
+        }
+    }
+
+    /// Create a new `ReplicaOf` command which promotes a replica to master.
+    pub fn no_one() -> ReplicaOf {
+        ReplicaOf { master: None }
+    }
+
+    /// Parse a `ReplicaOf` instance from a received frame.
+    ///
+    /// The `REPLICAOF` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing three entries.
+    ///
+    /// ```text
+    /// REPLICAOF host port
+    /// REPLICAOF NO ONE
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ReplicaOf> {
+        let host = parse.next_string()?;
+        let port = parse.next_string()?;
+
+        if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+            return Ok(ReplicaOf::no_one());
+        }
+
+        let port = port.parse().map_err(|_| "protocol error; invalid port")?;
+
+        Ok(ReplicaOf::new(host, port))
+    }
+
+    /// Apply the `ReplicaOf` command to the specified `Db` instance.
+    ///
+    /// Responds with `OK` once the replication task is started. The
+    /// synchronization with the master happens in the background.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        match &self.master {
+            Some((host, port)) => info!("following master {}:{}", host, port),
+            None => info!("promoted to master"),
+        }
+
+        db.replicaof(self.master);
+
+        let response = Frame::Simple("OK".to_string());
+        debug!(?response);
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `ReplicaOf` command to
+    /// send to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("replicaof".as_bytes()));
+
+        match self.master {
+            Some((host, port)) => {
+                frame.push_bulk(Bytes::from(host.into_bytes()));
+                frame.push_bulk(Bytes::from(port.to_string()));
+            }
+            None => {
+                frame.push_bulk(Bytes::from("no".as_bytes()));
+                frame.push_bulk(Bytes::from("one".as_bytes()));
+            }
+        }
+
+        frame
+    }
+}
+
+impl PSync {
+    /// Create a new `PSync` command requesting a full synchronization.
+    pub fn new() -> PSync {
+        PSync {
+            replid: "?".to_string(),
+            offset: -1,
+        }
+    }
+
+    /// Parse a `PSync` instance from a received frame.
+    ///
+    /// The `PSYNC` string has already been consumed. `SYNC` is the older form
+    /// of the command, and takes no arguments.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing three entries.
+    ///
+    /// ```text
+    /// PSYNC replid offset
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<PSync> {
+        let replid = parse.next_string()?;
+        let offset = parse.next_signed_int()?;
+
+        Ok(PSync { replid, offset })
+    }
+
+    /// Apply the `PSync` command to the specified `Db` instance.
+    ///
+    /// Partial resynchronization is not supported: the replica always
+    /// receives a full snapshot, regardless of the offset it requested. The
+    /// commands executed afterwards are then streamed until the replica
+    /// disconnects or the server shuts down.
+    #[instrument(skip(self, db, dst, shutdown))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        shutdown: &mut Shutdown,
+    ) -> crate::Result<()> {
+        let sync = db.with_state(|state| state.add_replica());
+
+        info!(offset = sync.offset, "replica connected, full resync");
+
+        let response = Frame::Simple(format!("FULLRESYNC {} {}", sync.replid, sync.offset));
+        debug!(?response);
+        dst.write_frame(&response).await?;
+        dst.write_frame(&Frame::Bulk(sync.snapshot)).await?;
+
+        let mut updates = sync.updates;
+
+        loop {
+            select! {
+                res = updates.recv() => match res {
+                    Ok(frame) => dst.write_frame(&frame).await?,
+                    // The commands the replica missed are gone. Dropping the
+                    // connection makes it reconnect and synchronize again.
+                    Err(RecvError::Lagged(_)) => return Err("replica fell too far behind".into()),
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+                res = dst.read_frame() => {
+                    // Whatever the replica sends, such as acknowledgements, is
+                    // ignored.
+                    if res?.is_none() {
+                        return Ok(());
+                    }
+                }
+                _ = shutdown.recv() => {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by a replica when connecting to its master.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("psync".as_bytes()));
+        frame.push_bulk(Bytes::from(self.replid.into_bytes()));
+        frame.push_bulk(Bytes::from(self.offset.to_string()));
+        frame
+    }
+}
+
+impl Default for PSync {
+    fn default() -> PSync {
+        PSync::new()
+    }
+}
+
+impl Info {
+    /// Create a new `Info` command returning `section`, or all sections.
+    pub fn new(section: Option<String>) -> Info {
+        Info { section }
+    }
+
+    /// Parse an `Info` instance from a received frame.
+    ///
+    /// The `INFO` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing one or two entries.
+    ///
+    /// ```text
+    /// INFO [section]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Info> {
+        match parse.next_string() {
+            Ok(section) => Ok(Info::new(Some(section))),
+            Err(ParseError::EndOfStream) => Ok(Info::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Execute the `Info` command against the locked database state.
+    ///
+    /// Responds with the requested sections as a bulk string, one
+    /// `field:value` line per field. Unknown sections are empty.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let info = match self.section.as_deref().map(str::to_lowercase).as_deref() {
+            None | Some("all") | Some("default") | Some("everything") => [
+                state.memory_info(),
+                state.stats_info(),
+                state.replication_info(),
+            ]
+            .join("\r\n"),
+            Some("memory") => state.memory_info(),
+            Some("stats") => state.stats_info(),
+            Some("replication") => state.replication_info(),
+            Some(_) => String::new(),
+        };
+
+        Frame::Bulk(Bytes::from(info))
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding an `Info` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("info".as_bytes()));
+
+        if let Some(section) = self.section {
+            frame.push_bulk(Bytes::from(section.into_bytes()));
+        }
+
+        frame
+    }
+}
@@ -0,0 +1,107 @@
+use crate::db::State;
+use crate::{Frame, Parse};
+
+use bytes::Bytes;
+use tracing::instrument;
+
+/// Write a snapshot of the key space to disk.
+///
+/// The snapshot is written before responding, and no other command is
+/// processed in the meantime. `BgSave` writes the snapshot without blocking
+/// other connections.
+#[derive(Debug, Default)]
+pub struct Save;
+
+/// Write a snapshot of the key space to disk in the background.
+///
+/// The snapshot captures the key space at the time the command is executed.
+/// Only one background save may be in progress at a time.
+#[derive(Debug, Default)]
+pub struct BgSave;
+
+impl Save {
+    /// Create a new `Save` command.
+    pub fn new() -> Save {
+        Save
+    }
+
+    /// Parse a `Save` instance from a received frame.
+    ///
+    /// The `SAVE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing a single entry.
+    ///
+    /// ```text
+    /// SAVE
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Save> {
+        Ok(Save)
+    }
+
+    /// Execute the `Save` command against the locked database state.
+    ///
+    /// Responds with `OK` once the snapshot is on disk.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.save() {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Save` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("save".as_bytes()));
+        frame
+    }
+}
+
+impl BgSave {
+    /// Create a new `BgSave` command.
+    pub fn new() -> BgSave {
+        BgSave
+    }
+
+    /// Parse a `BgSave` instance from a received frame.
+    ///
+    /// The `BGSAVE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing a single entry.
+    ///
+    /// ```text
+    /// BGSAVE
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<BgSave> {
+        Ok(BgSave)
+    }
+
+    /// Execute the `BgSave` command against the locked database state.
+    ///
+    /// Responds once the snapshot has been taken. It is written to disk
+    /// afterwards.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.bgsave() {
+            Ok(()) => Frame::Simple("Background saving started".to_string()),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `BgSave` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("bgsave".as_bytes()));
+        frame
+    }
+}
@@ -0,0 +1,316 @@
+use crate::cmd::{Parse, ParseError};
+use crate::db::State;
+use crate::{scripting, Frame};
+
+use bytes::Bytes;
+use tracing::instrument;
+
+/// Run a Lua script on the server.
+///
+/// The script accesses the key space through `redis.call`, and runs
+/// atomically: no other command is processed until it returns. The script is
+/// cached, so it can be run again with `EVALSHA`.
+#[derive(Debug)]
+pub struct Eval {
+    /// Source of the script
+    script: Bytes,
+
+    /// Names of the keys accessed by the script, exposed as `KEYS`
+    keys: Vec<String>,
+
+    /// Additional arguments, exposed as `ARGV`
+    args: Vec<Bytes>,
+}
+
+/// Run a Lua script cached on the server, identified by its SHA1 digest.
+#[derive(Debug)]
+pub struct EvalSha {
+    /// SHA1 digest of the script, as a hexadecimal string
+    sha1: String,
+
+    /// Names of the keys accessed by the script, exposed as `KEYS`
+    keys: Vec<String>,
+
+    /// Additional arguments, exposed as `ARGV`
+    args: Vec<Bytes>,
+}
+
+/// Manage the script cache.
+#[derive(Debug)]
+pub struct Script {
+    subcommand: ScriptSubcommand,
+}
+
+#[derive(Debug)]
+enum ScriptSubcommand {
+    /// Cache a script without running it
+    Load(Bytes),
+
+    /// Check which scripts are cached
+    Exists(Vec<String>),
+
+    /// Remove all scripts from the cache
+    Flush,
+}
+
+impl Eval {
+    /// Create a new `Eval` command which runs `script`.
+    pub fn new(script: Bytes, keys: Vec<String>, args: Vec<Bytes>) -> Eval {
+        Eval { script, keys, args }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse an `Eval` instance from a received frame.
+    ///
+    /// The `EVAL` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least three entries.
+    ///
+    /// ```text
+    /// EVAL script numkeys [key ...] [arg ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Eval> {
+        let script = parse.next_bytes()?;
+        let (keys, args) = parse_keys_and_args(parse)?;
+
+        Ok(Eval { script, keys, args })
+    }
+
+    /// Execute the `Eval` command against the locked database state.
+    ///
+    /// Responds with the value returned by the script.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        state.load_script(self.script.clone());
+
+        let time_limit = state.lua_time_limit();
+        scripting::eval(state, &self.script, self.keys, self.args, time_limit)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding an `Eval` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("eval".as_bytes()));
+        frame.push_bulk(self.script);
+        push_keys_and_args(&mut frame, self.keys, self.args);
+        frame
+    }
+}
+
+impl EvalSha {
+    /// Create a new `EvalSha` command which runs the script with the given
+    /// SHA1 digest.
+    pub fn new(sha1: impl ToString, keys: Vec<String>, args: Vec<Bytes>) -> EvalSha {
+        EvalSha {
+            sha1: sha1.to_string(),
+            keys,
+            args,
+        }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse an `EvalSha` instance from a received frame.
+    ///
+    /// The `EVALSHA` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least three entries.
+    ///
+    /// ```text
+    /// EVALSHA sha1 numkeys [key ...] [arg ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<EvalSha> {
+        let sha1 = parse.next_string()?;
+        let (keys, args) = parse_keys_and_args(parse)?;
+
+        Ok(EvalSha { sha1, keys, args })
+    }
+
+    /// Execute the `EvalSha` command against the locked database state.
+    ///
+    /// Responds with the value returned by the script, or with a `NOSCRIPT`
+    /// error if the script is not cached.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let script = match state.script(&self.sha1) {
+            Some(script) => script,
+            None => {
+                return Frame::Error("NOSCRIPT No matching script. Please use EVAL.".to_string())
+            }
+        };
+
+        let time_limit = state.lua_time_limit();
+        scripting::eval(state, &script, self.keys, self.args, time_limit)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding an `EvalSha` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("evalsha".as_bytes()));
+        frame.push_bulk(Bytes::from(self.sha1.into_bytes()));
+        push_keys_and_args(&mut frame, self.keys, self.args);
+        frame
+    }
+}
+
+impl Script {
+    /// Create a new `Script` command which caches `script`.
+    pub fn load(script: Bytes) -> Script {
+        Script {
+            subcommand: ScriptSubcommand::Load(script),
+        }
+    }
+
+    /// Create a new `Script` command which checks whether the scripts with
+    /// the given SHA1 digests are cached.
+    pub fn exists(sha1s: Vec<String>) -> Script {
+        Script {
+            subcommand: ScriptSubcommand::Exists(sha1s),
+        }
+    }
+
+    /// Create a new `Script` command which empties the script cache.
+    pub fn flush() -> Script {
+        Script {
+            subcommand: ScriptSubcommand::Flush,
+        }
+    }
+
+    /// Parse a `Script` instance from a received frame.
+    ///
+    /// The `SCRIPT` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing a subcommand and its arguments.
+    ///
+    /// ```text
+    /// SCRIPT LOAD script
+    /// SCRIPT EXISTS sha1 [sha1 ...]
+    /// SCRIPT FLUSH
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Script> {
+        let subcommand = match &parse.next_string()?.to_lowercase()[..] {
+            "load" => ScriptSubcommand::Load(parse.next_bytes()?),
+            "exists" => {
+                let mut sha1s = vec![parse.next_string()?];
+
+                loop {
+                    match parse.next_string() {
+                        Ok(sha1) => sha1s.push(sha1),
+                        Err(ParseError::EndOfStream) => break,
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+
+                ScriptSubcommand::Exists(sha1s)
+            }
+            "flush" => ScriptSubcommand::Flush,
+            subcommand => {
+                return Err(
+                    format!("protocol error; unknown SCRIPT subcommand '{}'", subcommand).into(),
+                )
+            }
+        };
+
+        Ok(Script { subcommand })
+    }
+
+    /// Execute the `Script` command against the locked database state.
+    ///
+    /// `LOAD` responds with the SHA1 digest of the script, `EXISTS` with `1`
+    /// or `0` for each digest, and `FLUSH` with `OK`.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match self.subcommand {
+            ScriptSubcommand::Load(script) => Frame::Bulk(Bytes::from(state.load_script(script))),
+            ScriptSubcommand::Exists(sha1s) => Frame::Array(
+                sha1s
+                    .iter()
+                    .map(|sha1| Frame::Integer(state.script(sha1).is_some() as i64))
+                    .collect(),
+            ),
+            ScriptSubcommand::Flush => {
+                state.flush_scripts();
+                Frame::Simple("OK".to_string())
+            }
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Script` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("script".as_bytes()));
+
+        match self.subcommand {
+            ScriptSubcommand::Load(script) => {
+                frame.push_bulk(Bytes::from("load".as_bytes()));
+                frame.push_bulk(script);
+            }
+            ScriptSubcommand::Exists(sha1s) => {
+                frame.push_bulk(Bytes::from("exists".as_bytes()));
+                for sha1 in sha1s {
+                    frame.push_bulk(Bytes::from(sha1.into_bytes()));
+                }
+            }
+            ScriptSubcommand::Flush => {
+                frame.push_bulk(Bytes::from("flush".as_bytes()));
+            }
+        }
+
+        frame
+    }
+}
+
+/// Parse the `numkeys [key ...] [arg ...]` arguments shared by `EVAL` and
+/// `EVALSHA`.
+fn parse_keys_and_args(parse: &mut Parse) -> crate::Result<(Vec<String>, Vec<Bytes>)> {
+    let numkeys = parse.next_int()?;
+
+    let mut keys = vec![];
+    for _ in 0..numkeys {
+        keys.push(parse.next_string()?);
+    }
+
+    let mut args = vec![];
+    loop {
+        match parse.next_bytes() {
+            Ok(arg) => args.push(arg),
+            Err(ParseError::EndOfStream) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok((keys, args))
+}
+
+/// Encode the arguments shared by `EVAL` and `EVALSHA`.
+fn push_keys_and_args(frame: &mut Frame, keys: Vec<String>, args: Vec<Bytes>) {
+    frame.push_int(keys.len() as i64);
+    for key in keys {
+        frame.push_bulk(Bytes::from(key.into_bytes()));
+    }
+    for arg in args {
+        frame.push_bulk(arg);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{Connection, Db, Frame, Parse};
+
+use bytes::Bytes;
+use std::convert::TryInto;
+use tracing::{debug, instrument};
+
+/// Select the logical database the connection operates on.
+///
+/// Databases are numbered from `0`, which is selected by new connections. Each
+/// database has its own key space, while pub/sub channels are shared by all
+/// of them.
+#[derive(Debug)]
+pub struct Select {
+    /// Number of the database to select
+    index: usize,
+}
+
+impl Select {
+    /// Create a new `Select` command which selects the database numbered
+    /// `index`.
+    pub fn new(index: usize) -> Select {
+        Select { index }
+    }
+
+    /// Get the number of the database
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Parse a `Select` instance from a received frame.
+    ///
+    /// The `SELECT` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing two entries.
+    ///
+    /// ```text
+    /// SELECT index
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Select> {
+        let index = parse
+            .next_int()?
+            .try_into()
+            .map_err(|_| "protocol error; invalid database number")?;
+
+        Ok(Select { index })
+    }
+
+    /// Apply the `Select` command, replacing `db` with a handle to the
+    /// selected database.
+    ///
+    /// Responds with `OK`, or an error if there is no such database.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &mut Db, dst: &mut Connection) -> crate::Result<()> {
+        let cluster_enabled = db.is_cluster_enabled();
+
+        let response = if cluster_enabled && self.index != 0 {
+            Frame::Error("ERR SELECT is not allowed in cluster mode".to_string())
+        } else {
+            match db.select(self.index) {
+                Ok(selected) => {
+                    *db = selected;
+                    Frame::Simple("OK".to_string())
+                }
+                Err(err) => err.into(),
+            }
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Select` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("select".as_bytes()));
+        frame.push_bulk(Bytes::from(self.index.to_string()));
+        frame
+    }
+}
@@ -1,9 +1,10 @@
-use crate::cmd::{Parse, ParseError};
-use crate::{Connection, Db, Frame};
+use crate::cmd::{Expiration, Parse, ParseError};
+use crate::db::State;
+use crate::Frame;
 
 use bytes::Bytes;
 use std::time::Duration;
-use tracing::{debug, instrument};
+use tracing::instrument;
 
 /// Set `key` to hold the string `value`.
 ///
@@ -17,7 +18,17 @@ use tracing::{debug, instrument};
 ///
 /// * EX `seconds` -- Set the specified expire time, in seconds.
 /// * PX `milliseconds` -- Set the specified expire time, in milliseconds.
-#[derive(Debug)]
+/// * EXAT `timestamp` -- Set the specified Unix time at which the key will
+///   expire, in seconds.
+/// * PXAT `timestamp` -- Set the specified Unix time at which the key will
+///   expire, in milliseconds.
+/// * KEEPTTL -- Retain the time to live associated with the key.
+/// * NX -- Only set the key if it does not already exist.
+/// * XX -- Only set the key if it already exists.
+/// * GET -- Return the old string stored at key, or nil if key did not exist.
+///   An error is returned and SET aborted if the value stored at key is not a
+///   string.
+#[derive(Debug, Clone)]
 pub struct Set {
     /// the lookup key
     key: String,
@@ -26,7 +37,26 @@ pub struct Set {
     value: Bytes,
 
     /// When to expire the key
-    expire: Option<Duration>,
+    expire: Option<Expiration>,
+
+    /// Keep the time to live of the key, instead of discarding it
+    keep_ttl: bool,
+
+    /// Condition on the existence of the key
+    condition: Option<SetCondition>,
+
+    /// Respond with the previous value of the key
+    get: bool,
+}
+
+/// Condition on the existence of the key, checked by `SET` before setting it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetCondition {
+    /// Only set the key if it does not already exist.
+    Nx,
+
+    /// Only set the key if it already exists.
+    Xx,
 }
 
 impl Set {
@@ -38,10 +68,31 @@ impl Set {
         Set {
             key: key.to_string(),
             value,
-            expire,
+            expire: expire.map(Expiration::from),
+            keep_ttl: false,
+            condition: None,
+            get: false,
         }
     }
 
+    /// Only set the key if `condition` holds.
+    pub(crate) fn condition(mut self, condition: SetCondition) -> Set {
+        self.condition = Some(condition);
+        self
+    }
+
+    /// Keep the time to live of the key.
+    pub(crate) fn keep_ttl(mut self) -> Set {
+        self.keep_ttl = true;
+        self
+    }
+
+    /// Respond with the previous value of the key.
+    pub(crate) fn get(mut self) -> Set {
+        self.get = true;
+        self
+    }
+
     /// Get the key
     pub fn key(&self) -> &str {
         &self.key
@@ -52,9 +103,13 @@ impl Set {
         &self.value
     }
 
-    /// Get the expire
+    /// Get the expire, when set relative to now with `EX` or `PX`
     pub fn expire(&self) -> Option<Duration> {
-        self.expire
+        match self.expire {
+            Some(Expiration::Seconds(secs)) => Some(Duration::from_secs(secs.max(0) as u64)),
+            Some(Expiration::Milliseconds(ms)) => Some(Duration::from_millis(ms.max(0) as u64)),
+            _ => None,
+        }
     }
 
     /// Parse a `Set` instance from a received frame.
@@ -75,7 +130,8 @@ impl Set {
     /// Expects an array frame containing at least 3 entries.
     ///
     /// ```text
-    /// SET key value [EX seconds|PX milliseconds]
+    /// SET key value [NX|XX] [GET] [EX seconds|PX milliseconds|
+    ///     EXAT unix-time-seconds|PXAT unix-time-milliseconds|KEEPTTL]
     /// ```
     pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Set> {
         use ParseError::EndOfStream;
@@ -86,55 +142,127 @@ impl Set {
         // Read the value to set. This is a required field.
         let value = parse.next_bytes()?;
 
-        // The expiration is optional. If nothing else follows, then it is
-        // `None`.
-        let mut expire = None;
-
-        // Attempt to parse another string.
-        match parse.next_string() {
-            Ok(s) if s.to_uppercase() == "EX" => {
-                // An expiration is specified in seconds. The next value is an
-                // integer.
-                let secs = parse.next_int()?;
-                expire = Some(Duration::from_secs(secs));
-            }
-            Ok(s) if s.to_uppercase() == "PX" => {
-                // An expiration is specified in milliseconds. The next value is
-                // an integer.
-                let ms = parse.next_int()?;
-                expire = Some(Duration::from_millis(ms));
+        let mut set = Set::new(key, value, None);
+
+        // All options are optional, and may be given in any order. Parse
+        // strings until the end of the frame.
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option.to_uppercase(),
+                // The `EndOfStream` error indicates there is no further data
+                // to parse. In this case, it is a normal run time situation
+                // and indicates there are no more `SET` options.
+                Err(EndOfStream) => break,
+                // All other errors are bubbled up, resulting in the connection
+                // being terminated.
+                Err(err) => return Err(err.into()),
+            };
+
+            // An expiration takes a number as its next value.
+            let expiration: Option<fn(i64) -> Expiration> = match &option[..] {
+                "EX" => Some(Expiration::Seconds),
+                "PX" => Some(Expiration::Milliseconds),
+                "EXAT" => Some(Expiration::UnixSeconds),
+                "PXAT" => Some(Expiration::UnixMilliseconds),
+                _ => None,
+            };
+
+            match (&option[..], expiration) {
+                (_, Some(expiration)) if set.expire.is_none() && !set.keep_ttl => {
+                    set.expire = Some(expiration(parse.next_signed_int()?));
+                }
+                ("KEEPTTL", _) if set.expire.is_none() => set.keep_ttl = true,
+                ("NX", _) if set.condition.is_none() => set.condition = Some(SetCondition::Nx),
+                ("XX", _) if set.condition.is_none() => set.condition = Some(SetCondition::Xx),
+                ("GET", _) => set.get = true,
+                // An unknown option, or options which cannot be combined,
+                // such as `NX` and `XX`. An error here results in the
+                // connection being terminated. Other connections will continue
+                // to operate normally.
+                _ => {
+                    return Err(format!("protocol error; invalid `SET` option '{}'", option).into())
+                }
             }
-            // Currently, mini-redis does not support any of the other SET
-            // options. An error here results in the connection being
-            // terminated. Other connections will continue to operate normally.
-            Ok(_) => return Err("currently `SET` only supports the expiration option".into()),
-            // The `EndOfStream` error indicates there is no further data to
-            // parse. In this case, it is a normal run time situation and
-            // indicates there are no specified `SET` options.
-            Err(EndOfStream) => {}
-            // All other errors are bubbled up, resulting in the connection
-            // being terminated.
-            Err(err) => return Err(err.into()),
         }
 
-        Ok(Set { key, value, expire })
+        Ok(set)
+    }
+
+    /// Parse a `Set` instance from a received `GETSET` frame.
+    ///
+    /// `GETSET` is the same as `SET key value GET`.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing 3 entries.
+    ///
+    /// ```text
+    /// GETSET key value
+    /// ```
+    pub(crate) fn parse_getset_frames(parse: &mut Parse) -> crate::Result<Set> {
+        let key = parse.next_string()?;
+        let value = parse.next_bytes()?;
+
+        Ok(Set::new(key, value, None).get())
     }
 
-    /// Apply the `Set` command to the specified `Db` instance.
+    /// Execute the `Set` command against the locked database state.
     ///
-    /// The response is written to `dst`. This is called by the server in order
-    /// to execute a received command.
-    #[instrument(skip(self, db, dst))]
-    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+    /// Returns the response to send back to the client: `OK`, or the previous
+    /// value if `GET` was given. `Null` is returned if the key was not set
+    /// because of `NX` or `XX`.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        // With `GET`, the previous value must be a string. The key is left
+        // untouched otherwise.
+        let previous = if self.get {
+            match state.get(&self.key) {
+                Ok(previous) => previous,
+                Err(err) => return err.into(),
+            }
+        } else {
+            None
+        };
+
+        let response = |set: bool| match previous {
+            _ if !self.get && set => Frame::Simple("OK".to_string()),
+            Some(ref previous) if self.get => Frame::Bulk(previous.clone()),
+            _ => Frame::Null,
+        };
+
+        let exists = state.contains_key(&self.key);
+        match self.condition {
+            Some(SetCondition::Nx) if exists => return response(false),
+            Some(SetCondition::Xx) if !exists => return response(false),
+            _ => {}
+        }
+
+        let expires_at = if self.keep_ttl {
+            state.expires_at(&self.key)
+        } else {
+            match self.expire {
+                Some(expire) if expire.value() > 0 => match expire.instant() {
+                    Some(when) => Some(when),
+                    None => return invalid_expire_time(),
+                },
+                Some(_) => return invalid_expire_time(),
+                None => None,
+            }
+        };
+
         // Set the value in the shared database state.
-        db.set(self.key, self.value, self.expire);
+        state.set_at(self.key.clone(), self.value.clone(), expires_at);
 
-        // Create a success response and write it to `dst`.
-        let response = Frame::Simple("OK".to_string());
-        debug!(?response);
-        dst.write_frame(&response).await?;
+        response(true)
+    }
 
-        Ok(())
+    /// Returns the command with its expiration given as a Unix time, sent as
+    /// `PXAT`, so that replaying it later does not restart the timeout.
+    pub(crate) fn to_absolute(&self) -> Set {
+        Set {
+            expire: self.expire.map(Expiration::to_absolute),
+            ..self.clone()
+        }
     }
 
     /// Converts the command into an equivalent `Frame`.
@@ -146,16 +274,30 @@ impl Set {
         frame.push_bulk(Bytes::from("set".as_bytes()));
         frame.push_bulk(Bytes::from(self.key.into_bytes()));
         frame.push_bulk(self.value);
-        if let Some(ms) = self.expire {
-            // Expirations in Redis protocol can be specified in two ways
-            // 1. SET key value EX seconds
-            // 2. SET key value PX milliseconds
-            // We the second option because it allows greater precision and
-            // src/bin/cli.rs parses the expiration argument as milliseconds
-            // in duration_from_ms_str()
-            frame.push_bulk(Bytes::from("px".as_bytes()));
-            frame.push_int(ms.as_millis() as u64);
+        if let Some(expire) = self.expire {
+            // Expirations in Redis protocol can be specified in several ways.
+            // A relative expiration created by the client is always sent as
+            // `PX`, because it allows greater precision and src/bin/cli.rs
+            // parses the expiration argument as milliseconds in
+            // duration_from_ms_str()
+            frame.push_bulk(Bytes::from(expire.option().as_bytes()));
+            frame.push_int(expire.value());
+        }
+        if self.keep_ttl {
+            frame.push_bulk(Bytes::from("keepttl".as_bytes()));
+        }
+        match self.condition {
+            Some(SetCondition::Nx) => frame.push_bulk(Bytes::from("nx".as_bytes())),
+            Some(SetCondition::Xx) => frame.push_bulk(Bytes::from("xx".as_bytes())),
+            None => {}
+        }
+        if self.get {
+            frame.push_bulk(Bytes::from("get".as_bytes()));
         }
         frame
     }
 }
+
+fn invalid_expire_time() -> Frame {
+    Frame::Error("ERR invalid expire time in 'set' command".to_string())
+}
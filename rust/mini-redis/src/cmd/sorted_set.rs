@@ -0,0 +1,589 @@
+use crate::cmd::{Parse, ParseError};
+use crate::db::{ScoreBound, State};
+use crate::frame::{self, Frame};
+
+use bytes::Bytes;
+use std::convert::TryFrom;
+use tracing::instrument;
+
+/// Adds all the specified members with the specified scores to the sorted set
+/// stored at key.
+///
+/// If a specified member is already a member of the sorted set, the score is
+/// updated and the element reinserted at the right position to ensure the
+/// correct ordering. If key does not exist, a new sorted set with the
+/// specified members as sole members is created.
+///
+/// # Options
+///
+/// * NX -- Only add new members. Don't update already existing members.
+/// * XX -- Only update members that already exist. Never add members.
+/// * CH -- Reply with the number of changed members, that is members added
+///   plus members whose score was updated, instead of only the added ones.
+#[derive(Debug, Clone)]
+pub struct ZAdd {
+    /// Name of the sorted set
+    key: String,
+
+    /// Members to add, along with their scores
+    members: Vec<(f64, Bytes)>,
+
+    /// Only add new members
+    nx: bool,
+
+    /// Only update existing members
+    xx: bool,
+
+    /// Count updated members in the reply
+    ch: bool,
+}
+
+/// Returns the specified range of members in the sorted set stored at key.
+///
+/// Members are ordered from the lowest to the highest score. The offsets
+/// `start` and `stop` are zero-based indexes and can be negative numbers
+/// indicating offsets starting at the end of the sorted set.
+#[derive(Debug)]
+pub struct ZRange {
+    /// Name of the sorted set
+    key: String,
+
+    /// Index of the first member to return
+    start: i64,
+
+    /// Index of the last member to return, inclusive
+    stop: i64,
+
+    /// Include the score of each member in the reply
+    withscores: bool,
+}
+
+/// Returns all the members in the sorted set stored at key with a score
+/// between `min` and `max`.
+///
+/// Members are ordered from the lowest to the highest score. By default, both
+/// bounds are inclusive. A bound prefixed with `(` is exclusive. `-inf` and
+/// `+inf` may be used to leave a side unbounded.
+#[derive(Debug)]
+pub struct ZRangeByScore {
+    /// Name of the sorted set
+    key: String,
+
+    /// Lowest score to return
+    min: ScoreBound,
+
+    /// Highest score to return
+    max: ScoreBound,
+
+    /// Include the score of each member in the reply
+    withscores: bool,
+
+    /// Number of matching members to skip, and the maximum number of members
+    /// to return, as set by `LIMIT`
+    limit: Option<(usize, usize)>,
+}
+
+/// Returns the rank of member in the sorted set stored at key, with the scores
+/// ordered from low to high.
+///
+/// The rank is zero-based, which means that the member with the lowest score
+/// has rank `0`.
+#[derive(Debug)]
+pub struct ZRank {
+    /// Name of the sorted set
+    key: String,
+
+    /// Member to get the rank of
+    member: Bytes,
+
+    /// Include the score of the member in the reply
+    withscore: bool,
+}
+
+/// Removes the specified members from the sorted set stored at key.
+///
+/// Non-existing members are ignored. Removing all members of a sorted set
+/// removes the key.
+#[derive(Debug, Clone)]
+pub struct ZRem {
+    /// Name of the sorted set
+    key: String,
+
+    /// Members to remove
+    members: Vec<Bytes>,
+}
+
+impl ZAdd {
+    /// Create a new `ZAdd` command which adds `members` to the sorted set
+    /// stored at `key`.
+    pub fn new(key: impl ToString, members: Vec<(f64, Bytes)>) -> ZAdd {
+        ZAdd {
+            key: key.to_string(),
+            members,
+            nx: false,
+            xx: false,
+            ch: false,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZAdd` instance from a received frame.
+    ///
+    /// The `ZADD` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least four entries.
+    ///
+    /// ```text
+    /// ZADD key [NX|XX] [CH] score member [score member ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ZAdd> {
+        let mut zadd = ZAdd::new(parse.next_string()?, vec![]);
+
+        // Options come first. The first argument that is not an option is the
+        // score of the first member.
+        let score = loop {
+            let arg = parse.next_string()?;
+
+            match &arg.to_uppercase()[..] {
+                "NX" => zadd.nx = true,
+                "XX" => zadd.xx = true,
+                "CH" => zadd.ch = true,
+                _ => break parse_score(&arg)?,
+            }
+        };
+
+        if zadd.nx && zadd.xx {
+            return Err("ERR XX and NX options at the same time are not compatible".into());
+        }
+
+        zadd.members.push((score, parse.next_bytes()?));
+
+        loop {
+            match parse.next_string() {
+                Ok(score) => {
+                    let score = parse_score(&score)?;
+                    zadd.members.push((score, parse.next_bytes()?));
+                }
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(zadd)
+    }
+
+    /// Execute the `ZAdd` command against the locked database state.
+    ///
+    /// Responds with the number of members that were added, or that were
+    /// changed when `CH` is given.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.zadd(self.key, self.members, self.nx, self.xx) {
+            Ok((added, changed)) if self.ch => Frame::Integer((added + changed) as i64),
+            Ok((added, _)) => Frame::Integer(added as i64),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `ZAdd` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zadd".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        if self.nx {
+            frame.push_bulk(Bytes::from("nx".as_bytes()));
+        }
+        if self.xx {
+            frame.push_bulk(Bytes::from("xx".as_bytes()));
+        }
+        if self.ch {
+            frame.push_bulk(Bytes::from("ch".as_bytes()));
+        }
+        for (score, member) in self.members {
+            frame.push_bulk(Bytes::from(frame::format_double(score)));
+            frame.push_bulk(member);
+        }
+        frame
+    }
+}
+
+impl ZRange {
+    /// Create a new `ZRange` command which returns the members between `start`
+    /// and `stop` of the sorted set stored at `key`.
+    pub fn new(key: impl ToString, start: i64, stop: i64, withscores: bool) -> ZRange {
+        ZRange {
+            key: key.to_string(),
+            start,
+            stop,
+            withscores,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZRange` instance from a received frame.
+    ///
+    /// The `ZRANGE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing four or five entries.
+    ///
+    /// ```text
+    /// ZRANGE key start stop [WITHSCORES]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ZRange> {
+        let key = parse.next_string()?;
+        let start = parse.next_signed_int()?;
+        let stop = parse.next_signed_int()?;
+
+        let withscores = match parse.next_string() {
+            Ok(s) if s.to_uppercase() == "WITHSCORES" => true,
+            Ok(_) => return Err("ERR syntax error".into()),
+            Err(ParseError::EndOfStream) => false,
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(ZRange::new(key, start, stop, withscores))
+    }
+
+    /// Execute the `ZRange` command against the locked database state.
+    ///
+    /// Responds with an array of members. With `WITHSCORES`, each member is
+    /// followed by its score.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.zrange(&self.key, self.start, self.stop) {
+            Ok(members) => members_frame(members, self.withscores),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `ZRange` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zrange".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_int(self.start);
+        frame.push_int(self.stop);
+        if self.withscores {
+            frame.push_bulk(Bytes::from("withscores".as_bytes()));
+        }
+        frame
+    }
+}
+
+impl ZRangeByScore {
+    /// Create a new `ZRangeByScore` command which returns the members of the
+    /// sorted set stored at `key` with a score between `min` and `max`, both
+    /// inclusive.
+    pub fn new(key: impl ToString, min: f64, max: f64, withscores: bool) -> ZRangeByScore {
+        ZRangeByScore {
+            key: key.to_string(),
+            min: ScoreBound::Inclusive(min),
+            max: ScoreBound::Inclusive(max),
+            withscores,
+            limit: None,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZRangeByScore` instance from a received frame.
+    ///
+    /// The `ZRANGEBYSCORE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least four entries.
+    ///
+    /// ```text
+    /// ZRANGEBYSCORE key min max [WITHSCORES] [LIMIT offset count]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ZRangeByScore> {
+        let key = parse.next_string()?;
+        let min = parse_bound(&parse.next_string()?)?;
+        let max = parse_bound(&parse.next_string()?)?;
+
+        let mut withscores = false;
+        let mut limit = None;
+
+        loop {
+            match parse.next_string() {
+                Ok(s) if s.to_uppercase() == "WITHSCORES" => withscores = true,
+                Ok(s) if s.to_uppercase() == "LIMIT" => {
+                    let offset = parse.next_int()? as usize;
+                    // A negative count returns all members after the offset
+                    let count = parse.next_signed_int()?;
+                    limit = Some((offset, usize::try_from(count).unwrap_or(usize::MAX)));
+                }
+                Ok(_) => return Err("ERR syntax error".into()),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(ZRangeByScore {
+            key,
+            min,
+            max,
+            withscores,
+            limit,
+        })
+    }
+
+    /// Execute the `ZRangeByScore` command against the locked database state.
+    ///
+    /// Responds with an array of members. With `WITHSCORES`, each member is
+    /// followed by its score.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let (offset, count) = match self.limit {
+            Some((offset, count)) => (offset, Some(count)),
+            None => (0, None),
+        };
+
+        match state.zrangebyscore(&self.key, self.min, self.max, offset, count) {
+            Ok(members) => members_frame(members, self.withscores),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `ZRangeByScore` command to
+    /// send to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zrangebyscore".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(format_bound(self.min)));
+        frame.push_bulk(Bytes::from(format_bound(self.max)));
+        if self.withscores {
+            frame.push_bulk(Bytes::from("withscores".as_bytes()));
+        }
+        if let Some((offset, count)) = self.limit {
+            frame.push_bulk(Bytes::from("limit".as_bytes()));
+            frame.push_int(offset as i64);
+            frame.push_int(count as i64);
+        }
+        frame
+    }
+}
+
+impl ZRank {
+    /// Create a new `ZRank` command which returns the rank of `member` in the
+    /// sorted set stored at `key`.
+    pub fn new(key: impl ToString, member: Bytes) -> ZRank {
+        ZRank {
+            key: key.to_string(),
+            member,
+            withscore: false,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZRank` instance from a received frame.
+    ///
+    /// The `ZRANK` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing three or four entries.
+    ///
+    /// ```text
+    /// ZRANK key member [WITHSCORE]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ZRank> {
+        let key = parse.next_string()?;
+        let member = parse.next_bytes()?;
+
+        let withscore = match parse.next_string() {
+            Ok(s) if s.to_uppercase() == "WITHSCORE" => true,
+            Ok(_) => return Err("ERR syntax error".into()),
+            Err(ParseError::EndOfStream) => false,
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(ZRank {
+            key,
+            member,
+            withscore,
+        })
+    }
+
+    /// Execute the `ZRank` command against the locked database state.
+    ///
+    /// Responds with the rank of the member, or `Null` if either the member
+    /// or the key does not exist. With `WITHSCORE`, an array holding the rank
+    /// and the score is returned instead.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.zrank(&self.key, &self.member) {
+            Ok(Some((rank, score))) if self.withscore => {
+                Frame::Array(vec![Frame::Integer(rank as i64), Frame::Double(score)])
+            }
+            Ok(Some((rank, _))) => Frame::Integer(rank as i64),
+            Ok(None) => Frame::Null,
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `ZRank` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zrank".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.member);
+        if self.withscore {
+            frame.push_bulk(Bytes::from("withscore".as_bytes()));
+        }
+        frame
+    }
+}
+
+impl ZRem {
+    /// Create a new `ZRem` command which removes `members` from the sorted set
+    /// stored at `key`.
+    pub fn new(key: impl ToString, members: Vec<Bytes>) -> ZRem {
+        ZRem {
+            key: key.to_string(),
+            members,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `ZRem` instance from a received frame.
+    ///
+    /// The `ZREM` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least three entries.
+    ///
+    /// ```text
+    /// ZREM key member [member ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ZRem> {
+        let key = parse.next_string()?;
+
+        // At least one member is required
+        let mut members = vec![parse.next_bytes()?];
+
+        loop {
+            match parse.next_bytes() {
+                Ok(member) => members.push(member),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(ZRem { key, members })
+    }
+
+    /// Execute the `ZRem` command against the locked database state.
+    ///
+    /// Responds with the number of members that were removed.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.zrem(&self.key, &self.members) {
+            Ok(removed) => Frame::Integer(removed as i64),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `ZRem` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("zrem".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        for member in self.members {
+            frame.push_bulk(member);
+        }
+        frame
+    }
+}
+
+/// Build the reply to a range query.
+///
+/// With `withscores`, each member is followed by its score. Scores are
+/// `Double` frames, which RESP2 connections receive as bulk strings.
+fn members_frame(members: Vec<(Bytes, f64)>, withscores: bool) -> Frame {
+    let mut frames = vec![];
+
+    for (member, score) in members {
+        frames.push(Frame::Bulk(member));
+
+        if withscores {
+            frames.push(Frame::Double(score));
+        }
+    }
+
+    Frame::Array(frames)
+}
+
+/// Parse the score of a sorted set member.
+fn parse_score(score: &str) -> crate::Result<f64> {
+    match score.parse::<f64>() {
+        Ok(score) if !score.is_nan() => Ok(score),
+        _ => Err("ERR value is not a valid float".into()),
+    }
+}
+
+/// Parse one end of a score range. A leading `(` makes the bound exclusive.
+fn parse_bound(bound: &str) -> crate::Result<ScoreBound> {
+    const MSG: &str = "ERR min or max is not a float";
+
+    let (exclusive, value) = match bound.strip_prefix('(') {
+        Some(value) => (true, value),
+        None => (false, bound),
+    };
+
+    let value = parse_score(value).map_err(|_| MSG)?;
+
+    if exclusive {
+        Ok(ScoreBound::Exclusive(value))
+    } else {
+        Ok(ScoreBound::Inclusive(value))
+    }
+}
+
+/// Format one end of a score range, the inverse of `parse_bound`.
+fn format_bound(bound: ScoreBound) -> String {
+    match bound {
+        ScoreBound::Inclusive(value) => frame::format_double(value),
+        ScoreBound::Exclusive(value) => format!("({}", frame::format_double(value)),
+    }
+}
@@ -0,0 +1,1869 @@
+use crate::cmd::list::{deadline, wait, Wake};
+use crate::cmd::{Parse, ParseError};
+use crate::db::{
+    unix_time_ms, ClaimOptions, Group, GroupReadId, NewId, State, StreamEntry, StreamId, StreamRead,
+};
+use crate::{Connection, Db, Frame, Shutdown};
+
+use bytes::Bytes;
+use std::convert::TryInto;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{debug, instrument};
+
+/// Append an entry to the stream stored at key.
+///
+/// If key does not exist, the stream is created, unless the `NOMKSTREAM`
+/// option is given. The ID of the entry is generated from the current time
+/// when `*` is given, and must otherwise be greater than the ID of the last
+/// entry of the stream. With the `MAXLEN` option, the oldest entries are
+/// removed so the stream holds at most that many entries.
+#[derive(Debug, Clone)]
+pub struct XAdd {
+    /// Name of the stream
+    key: String,
+
+    /// ID of the new entry
+    id: NewId,
+
+    /// Field-value pairs of the new entry
+    fields: Vec<(Bytes, Bytes)>,
+
+    /// Maximum number of entries kept in the stream
+    maxlen: Option<usize>,
+
+    /// When `true`, nothing is added if the stream does not exist
+    nomkstream: bool,
+}
+
+/// Returns the entries of the stream stored at key with an ID in a range.
+///
+/// The special IDs `-` and `+` are the smallest and greatest possible IDs. An
+/// ID without a sequence number includes all the entries of that millisecond,
+/// and an ID prefixed with `(` is excluded from the range.
+#[derive(Debug)]
+pub struct XRange {
+    /// Name of the stream
+    key: String,
+
+    /// Smallest ID of the entries to return
+    start: StreamId,
+
+    /// Greatest ID of the entries to return
+    end: StreamId,
+
+    /// Maximum number of entries to return
+    count: Option<usize>,
+}
+
+/// Returns the number of entries in the stream stored at key.
+#[derive(Debug)]
+pub struct XLen {
+    /// Name of the stream
+    key: String,
+}
+
+/// Remove the oldest entries of the stream stored at key, so it holds at most
+/// the given number of entries.
+///
+/// The `~` modifier, which allows Redis to trim less than requested, is
+/// accepted for compatibility. The stream is always trimmed exactly.
+#[derive(Debug, Clone)]
+pub struct XTrim {
+    /// Name of the stream
+    key: String,
+
+    /// Maximum number of entries kept in the stream
+    maxlen: usize,
+}
+
+/// Read the entries added to one or more streams after the given IDs.
+///
+/// The special ID `$` is the ID of the last entry of the stream, so only
+/// entries added after the command was received are returned. With the
+/// `BLOCK` option, the connection waits until an entry is added if no stream
+/// has any, or the timeout elapses.
+#[derive(Debug)]
+pub struct XRead {
+    /// Names of the streams
+    keys: Vec<String>,
+
+    /// For each stream, the ID after which entries are returned
+    ids: Vec<ReadId>,
+
+    /// Maximum number of entries to return per stream
+    count: Option<usize>,
+
+    /// How long to wait for an entry, `0` waiting indefinitely. `None` does
+    /// not wait.
+    block: Option<Duration>,
+}
+
+/// ID after which `XREAD` returns entries.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ReadId {
+    /// `$`: the ID of the last entry when the command is received.
+    Last,
+
+    /// Entries with a greater ID are returned.
+    After(StreamId),
+}
+
+/// Manage the consumer groups of the stream stored at key.
+///
+/// A consumer group delivers each entry of the stream to a single one of its
+/// consumers, and tracks the entries delivered but not acknowledged yet.
+#[derive(Debug, Clone)]
+pub struct XGroup {
+    /// Name of the stream
+    key: String,
+
+    /// Name of the consumer group
+    group: String,
+
+    subcommand: XGroupSubcommand,
+}
+
+#[derive(Debug, Clone)]
+enum XGroupSubcommand {
+    /// Create the group, delivering the entries after the given ID, or after
+    /// the last entry with `None`. With `mkstream`, an empty stream is
+    /// created if the key does not exist.
+    Create {
+        id: Option<StreamId>,
+        mkstream: bool,
+    },
+
+    /// Set the ID of the last entry delivered to the group
+    SetId(Option<StreamId>),
+
+    /// Remove the group
+    Destroy,
+
+    /// Create a consumer in the group
+    CreateConsumer(String),
+
+    /// Remove a consumer from the group, along with its pending entries
+    DelConsumer(String),
+}
+
+/// Read entries from one or more streams as a consumer of a group.
+///
+/// The special ID `>` reads the entries never delivered to the group, which
+/// are then pending for the consumer until acknowledged with `XACK`. Any
+/// other ID reads the entries pending for the consumer with a greater ID.
+/// With the `BLOCK` option, the connection waits until an entry is added if
+/// there is none to read.
+#[derive(Debug)]
+pub struct XReadGroup {
+    /// Name of the consumer group
+    group: String,
+
+    /// Name of the consumer
+    consumer: String,
+
+    /// Names of the streams, each with where to read it from
+    streams: Vec<(String, GroupReadId)>,
+
+    /// Maximum number of entries to return per stream
+    count: Option<usize>,
+
+    /// How long to wait for an entry, `0` waiting indefinitely. `None` does
+    /// not wait.
+    block: Option<Duration>,
+
+    /// When `true`, the entries read are not added to the pending entries
+    noack: bool,
+}
+
+/// Acknowledge entries pending in a consumer group of the stream stored at
+/// key.
+#[derive(Debug, Clone)]
+pub struct XAck {
+    /// Name of the stream
+    key: String,
+
+    /// Name of the consumer group
+    group: String,
+
+    /// IDs of the entries to acknowledge
+    ids: Vec<StreamId>,
+}
+
+/// Inspect the entries pending in a consumer group of the stream stored at
+/// key.
+///
+/// Without a range, a summary of the pending entries is returned. Otherwise
+/// the pending entries in the range are listed, optionally only those of a
+/// consumer and not delivered for some time.
+#[derive(Debug)]
+pub struct XPending {
+    /// Name of the stream
+    key: String,
+
+    /// Name of the consumer group
+    group: String,
+
+    /// Pending entries to list. `None` returns the summary.
+    range: Option<PendingRange>,
+}
+
+/// Pending entries listed by `XPENDING`.
+#[derive(Debug)]
+struct PendingRange {
+    /// Only list the entries not delivered for at least this many
+    /// milliseconds
+    min_idle_ms: Option<u64>,
+
+    /// Smallest ID of the entries to list
+    start: StreamId,
+
+    /// Greatest ID of the entries to list
+    end: StreamId,
+
+    /// Maximum number of entries to list
+    count: usize,
+
+    /// Only list the entries pending for this consumer
+    consumer: Option<String>,
+}
+
+/// Transfer entries pending in a consumer group of the stream stored at key
+/// to another consumer.
+///
+/// Only the entries not delivered for at least the given time are claimed,
+/// so a consumer which failed can be taken over without two consumers
+/// claiming the same entries.
+#[derive(Debug, Clone)]
+pub struct XClaim {
+    /// Name of the stream
+    key: String,
+
+    /// Name of the consumer group
+    group: String,
+
+    /// Name of the consumer claiming the entries
+    consumer: String,
+
+    /// Minimum time since the entries were last delivered, in milliseconds
+    min_idle_ms: u64,
+
+    /// IDs of the entries to claim
+    ids: Vec<StreamId>,
+
+    /// Time since the entries are considered delivered, in milliseconds,
+    /// rather than now
+    idle_ms: Option<u64>,
+
+    /// How the claimed entries are updated
+    options: ClaimOptions,
+}
+
+impl XAdd {
+    /// Create a new `XAdd` command which appends an entry made of `fields` to
+    /// the stream stored at `key`, trimming it to `maxlen` entries if set.
+    pub(crate) fn new(
+        key: impl ToString,
+        id: NewId,
+        fields: Vec<(Bytes, Bytes)>,
+        maxlen: Option<usize>,
+    ) -> XAdd {
+        XAdd {
+            key: key.to_string(),
+            id,
+            fields,
+            maxlen,
+            nomkstream: false,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XAdd` instance from a received frame.
+    ///
+    /// The `XADD` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least five entries.
+    ///
+    /// ```text
+    /// XADD key [NOMKSTREAM] [MAXLEN [=|~] threshold] *|id field value [field value ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XAdd> {
+        let key = parse.next_string()?;
+
+        let mut maxlen = None;
+        let mut nomkstream = false;
+
+        // Options precede the ID
+        let id = loop {
+            let arg = parse.next_string()?;
+
+            match &arg.to_uppercase()[..] {
+                "NOMKSTREAM" => nomkstream = true,
+                "MAXLEN" => maxlen = Some(parse_maxlen(parse)?),
+                _ => break parse_new_id(&arg)?,
+            }
+        };
+
+        // At least one field is required
+        let mut fields = vec![(parse.next_bytes()?, parse.next_bytes()?)];
+
+        loop {
+            match parse.next_bytes() {
+                Ok(field) => fields.push((field, parse.next_bytes()?)),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(XAdd {
+            key,
+            id,
+            fields,
+            maxlen,
+            nomkstream,
+        })
+    }
+
+    /// Execute the `XAdd` command against the locked database state.
+    ///
+    /// Responds with the ID of the new entry, or `Null` if the stream does not
+    /// exist and `NOMKSTREAM` was given.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let propagated = if state.is_propagating() {
+            Some(self.clone())
+        } else {
+            None
+        };
+
+        match state.xadd(self.key, self.id, self.fields, self.maxlen, self.nomkstream) {
+            Ok(Some(id)) => {
+                // The generated ID is propagated, so the entry gets the same
+                // ID when the append-only file is replayed or on replicas.
+                if let Some(cmd) = propagated {
+                    let cmd = XAdd {
+                        id: NewId::Explicit(id),
+                        ..cmd
+                    };
+                    state.propagate(&cmd.into_frame());
+                }
+
+                Frame::Bulk(Bytes::from(id.to_string()))
+            }
+            Ok(None) => Frame::Null,
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `XAdd` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xadd".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        if self.nomkstream {
+            frame.push_bulk(Bytes::from("nomkstream".as_bytes()));
+        }
+        if let Some(maxlen) = self.maxlen {
+            frame.push_bulk(Bytes::from("maxlen".as_bytes()));
+            frame.push_bulk(Bytes::from(maxlen.to_string()));
+        }
+        frame.push_bulk(Bytes::from(format_new_id(self.id)));
+        for (field, value) in self.fields {
+            frame.push_bulk(field);
+            frame.push_bulk(value);
+        }
+        frame
+    }
+}
+
+impl XRange {
+    /// Create a new `XRange` command which returns the entries of the stream
+    /// stored at `key` with an ID between `start` and `end`, both inclusive,
+    /// up to `count` entries.
+    pub(crate) fn new(
+        key: impl ToString,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+    ) -> XRange {
+        XRange {
+            key: key.to_string(),
+            start,
+            end,
+            count,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XRange` instance from a received frame.
+    ///
+    /// The `XRANGE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing four or six entries.
+    ///
+    /// ```text
+    /// XRANGE key start end [COUNT count]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XRange> {
+        let key = parse.next_string()?;
+        let start = parse_range_start(&parse.next_string()?)?;
+        let end = parse_range_end(&parse.next_string()?)?;
+
+        let count = match parse.next_string() {
+            Ok(s) if s.to_uppercase() == "COUNT" => Some(parse_count(parse)?),
+            Ok(_) => return Err("ERR syntax error".into()),
+            Err(ParseError::EndOfStream) => None,
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(XRange {
+            key,
+            start,
+            end,
+            count,
+        })
+    }
+
+    /// Execute the `XRange` command against the locked database state.
+    ///
+    /// Responds with an array of entries, each an array holding the ID of the
+    /// entry and an array of its fields and values.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.xrange(&self.key, self.start, self.end, self.count) {
+            Ok(entries) => entries_frame(entries),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `XRange` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xrange".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.start.to_string()));
+        frame.push_bulk(Bytes::from(self.end.to_string()));
+        if let Some(count) = self.count {
+            frame.push_bulk(Bytes::from("count".as_bytes()));
+            frame.push_int(count as i64);
+        }
+        frame
+    }
+}
+
+impl XLen {
+    /// Create a new `XLen` command which returns the number of entries in the
+    /// stream stored at `key`.
+    pub fn new(key: impl ToString) -> XLen {
+        XLen {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XLen` instance from a received frame.
+    ///
+    /// The `XLEN` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing two entries.
+    ///
+    /// ```text
+    /// XLEN key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XLen> {
+        let key = parse.next_string()?;
+
+        Ok(XLen { key })
+    }
+
+    /// Execute the `XLen` command against the locked database state.
+    ///
+    /// Responds with the number of entries, `0` if the key does not exist.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.xlen(&self.key) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `XLen` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xlen".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
+
+impl XTrim {
+    /// Create a new `XTrim` command which trims the stream stored at `key` to
+    /// at most `maxlen` entries.
+    pub fn new(key: impl ToString, maxlen: usize) -> XTrim {
+        XTrim {
+            key: key.to_string(),
+            maxlen,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XTrim` instance from a received frame.
+    ///
+    /// The `XTRIM` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing four or five entries.
+    ///
+    /// ```text
+    /// XTRIM key MAXLEN [=|~] threshold
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XTrim> {
+        let key = parse.next_string()?;
+
+        if parse.next_string()?.to_uppercase() != "MAXLEN" {
+            return Err("ERR syntax error".into());
+        }
+
+        let maxlen = parse_maxlen(parse)?;
+
+        Ok(XTrim { key, maxlen })
+    }
+
+    /// Execute the `XTrim` command against the locked database state.
+    ///
+    /// Responds with the number of entries removed.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.xtrim(&self.key, self.maxlen) {
+            Ok(removed) => Frame::Integer(removed as i64),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `XTrim` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xtrim".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from("maxlen".as_bytes()));
+        frame.push_bulk(Bytes::from(self.maxlen.to_string()));
+        frame
+    }
+}
+
+impl XRead {
+    /// Create a new `XRead` command which reads the entries of the streams
+    /// stored at `keys` added after the matching ID of `ids`, up to `count`
+    /// entries per stream.
+    ///
+    /// With `block`, the connection waits for an entry for at most that long,
+    /// or indefinitely if it is zero.
+    pub(crate) fn new(
+        keys: Vec<String>,
+        ids: Vec<ReadId>,
+        count: Option<usize>,
+        block: Option<Duration>,
+    ) -> XRead {
+        XRead {
+            keys,
+            ids,
+            count,
+            block,
+        }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Returns `true` if the command waits for entries when there are none.
+    pub(crate) fn is_blocking(&self) -> bool {
+        self.block.is_some()
+    }
+
+    /// Parse a `XRead` instance from a received frame.
+    ///
+    /// The `XREAD` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least four entries. The timeout
+    /// is given in milliseconds.
+    ///
+    /// ```text
+    /// XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XRead> {
+        let mut count = None;
+        let mut block = None;
+
+        loop {
+            match &parse.next_string()?.to_uppercase()[..] {
+                "COUNT" => count = Some(parse_count(parse)?),
+                "BLOCK" => block = Some(Duration::from_millis(parse.next_int()?)),
+                "STREAMS" => break,
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+
+        // The keys are followed by as many IDs
+        let mut args = vec![];
+
+        loop {
+            match parse.next_string() {
+                Ok(arg) => args.push(arg),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        if args.is_empty() || args.len() % 2 != 0 {
+            return Err("ERR Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified.".into());
+        }
+
+        let ids = args
+            .split_off(args.len() / 2)
+            .iter()
+            .map(|id| parse_read_id(id))
+            .collect::<crate::Result<_>>()?;
+
+        Ok(XRead {
+            keys: args,
+            ids,
+            count,
+            block,
+        })
+    }
+
+    /// Execute the `XRead` command against the locked database state, without
+    /// blocking.
+    ///
+    /// This is how `XREAD` is executed in a transaction or a script, even
+    /// with the `BLOCK` option.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let res = self
+            .resolve_ids(state)
+            .and_then(|ids| state.xread(&self.keys, &ids, self.count));
+
+        match res {
+            Ok(streams) if streams.is_empty() => Frame::Null,
+            Ok(streams) => streams_frame(streams),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Apply the `XRead` command to the specified `Db` instance, waiting for
+    /// entries if there are none.
+    ///
+    /// Responds with an array holding, for each stream with new entries, an
+    /// array of the name of the stream and its entries. If the timeout elapses
+    /// first, `Null` is returned.
+    ///
+    /// If the server shuts down while the connection is blocked, no response
+    /// is written.
+    #[instrument(skip(self, db, dst, shutdown))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        shutdown: &mut Shutdown,
+    ) -> crate::Result<()> {
+        let waiter = Arc::new(Notify::new());
+        let deadline = deadline(self.block.unwrap_or_default());
+
+        // `$` is resolved once, so entries added while waiting are returned.
+        let response = match db.with_keys(&self.keys, |state| self.resolve_ids(state)) {
+            Ok(ids) => loop {
+                let res = db.with_keys(&self.keys, |state| {
+                    state.xread_or_block(&self.keys, &ids, self.count, &waiter)
+                });
+
+                match res {
+                    Ok(Some(streams)) => break streams_frame(streams),
+                    Ok(None) => {}
+                    Err(err) => break err.into(),
+                }
+
+                match wait(&waiter, deadline, shutdown).await {
+                    Wake::Notified => {}
+                    Wake::TimedOut | Wake::Shutdown => break Frame::Null,
+                }
+            },
+            Err(err) => err.into(),
+        };
+
+        db.with_keys(&self.keys, |state| state.unblock(&self.keys, &waiter));
+
+        if shutdown.is_shutdown() {
+            return Ok(());
+        }
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Returns the ID after which entries are returned, for each stream.
+    fn resolve_ids(&self, state: &State) -> Result<Vec<StreamId>, crate::db::Error> {
+        self.keys
+            .iter()
+            .zip(&self.ids)
+            .map(|(key, id)| match id {
+                ReadId::Last => state.stream_last_id(key),
+                ReadId::After(id) => Ok(*id),
+            })
+            .collect()
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `XRead` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xread".as_bytes()));
+        if let Some(count) = self.count {
+            frame.push_bulk(Bytes::from("count".as_bytes()));
+            frame.push_int(count as i64);
+        }
+        if let Some(block) = self.block {
+            frame.push_bulk(Bytes::from("block".as_bytes()));
+            frame.push_int(block.as_millis() as i64);
+        }
+        frame.push_bulk(Bytes::from("streams".as_bytes()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+        }
+        for id in self.ids {
+            let id = match id {
+                ReadId::Last => "$".to_string(),
+                ReadId::After(id) => id.to_string(),
+            };
+            frame.push_bulk(Bytes::from(id));
+        }
+        frame
+    }
+}
+
+impl XGroup {
+    /// Create a new `XGroup` command which creates the consumer group `group`
+    /// of the stream stored at `key`, delivering the entries with an ID
+    /// greater than `id`, or only new entries with `None`.
+    pub(crate) fn create(
+        key: impl ToString,
+        group: impl ToString,
+        id: Option<StreamId>,
+        mkstream: bool,
+    ) -> XGroup {
+        XGroup {
+            key: key.to_string(),
+            group: group.to_string(),
+            subcommand: XGroupSubcommand::Create { id, mkstream },
+        }
+    }
+
+    /// Create a new `XGroup` command which sets the ID of the last entry
+    /// delivered to the consumer group `group` of the stream stored at `key`.
+    pub(crate) fn setid(key: impl ToString, group: impl ToString, id: Option<StreamId>) -> XGroup {
+        XGroup {
+            key: key.to_string(),
+            group: group.to_string(),
+            subcommand: XGroupSubcommand::SetId(id),
+        }
+    }
+
+    /// Create a new `XGroup` command which removes the consumer group `group`
+    /// of the stream stored at `key`.
+    pub fn destroy(key: impl ToString, group: impl ToString) -> XGroup {
+        XGroup {
+            key: key.to_string(),
+            group: group.to_string(),
+            subcommand: XGroupSubcommand::Destroy,
+        }
+    }
+
+    /// Create a new `XGroup` command which creates the consumer `consumer` in
+    /// the consumer group `group` of the stream stored at `key`.
+    pub fn create_consumer(
+        key: impl ToString,
+        group: impl ToString,
+        consumer: impl ToString,
+    ) -> XGroup {
+        XGroup {
+            key: key.to_string(),
+            group: group.to_string(),
+            subcommand: XGroupSubcommand::CreateConsumer(consumer.to_string()),
+        }
+    }
+
+    /// Create a new `XGroup` command which removes the consumer `consumer`
+    /// from the consumer group `group` of the stream stored at `key`.
+    pub fn del_consumer(
+        key: impl ToString,
+        group: impl ToString,
+        consumer: impl ToString,
+    ) -> XGroup {
+        XGroup {
+            key: key.to_string(),
+            group: group.to_string(),
+            subcommand: XGroupSubcommand::DelConsumer(consumer.to_string()),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XGroup` instance from a received frame.
+    ///
+    /// The `XGROUP` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing a subcommand and its arguments.
+    ///
+    /// ```text
+    /// XGROUP CREATE key group id|$ [MKSTREAM]
+    /// XGROUP SETID key group id|$
+    /// XGROUP DESTROY key group
+    /// XGROUP CREATECONSUMER key group consumer
+    /// XGROUP DELCONSUMER key group consumer
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XGroup> {
+        let subcommand = parse.next_string()?.to_lowercase();
+        let key = parse.next_string()?;
+        let group = parse.next_string()?;
+
+        let subcommand = match &subcommand[..] {
+            "create" => {
+                let id = parse_group_id(&parse.next_string()?)?;
+
+                let mkstream = match parse.next_string() {
+                    Ok(s) if s.to_uppercase() == "MKSTREAM" => true,
+                    Ok(_) => return Err("ERR syntax error".into()),
+                    Err(ParseError::EndOfStream) => false,
+                    Err(err) => return Err(err.into()),
+                };
+
+                XGroupSubcommand::Create { id, mkstream }
+            }
+            "setid" => XGroupSubcommand::SetId(parse_group_id(&parse.next_string()?)?),
+            "destroy" => XGroupSubcommand::Destroy,
+            "createconsumer" => XGroupSubcommand::CreateConsumer(parse.next_string()?),
+            "delconsumer" => XGroupSubcommand::DelConsumer(parse.next_string()?),
+            subcommand => {
+                return Err(
+                    format!("protocol error; unknown XGROUP subcommand '{}'", subcommand).into(),
+                )
+            }
+        };
+
+        Ok(XGroup {
+            key,
+            group,
+            subcommand,
+        })
+    }
+
+    /// Execute the `XGroup` command against the locked database state.
+    ///
+    /// `CREATE` and `SETID` respond with `OK`, `DESTROY` and `CREATECONSUMER`
+    /// with `1` if the group or consumer was removed or created and `0`
+    /// otherwise, and `DELCONSUMER` with the number of entries which were
+    /// pending for the consumer.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let (key, group) = (&self.key, &self.group);
+
+        // `$` is propagated as the ID it stood for, so the group is the same
+        // when the append-only file is replayed or on replicas.
+        let res = match &self.subcommand {
+            XGroupSubcommand::Create { id, mkstream } => {
+                state.xgroup_create(key, group, *id, *mkstream).map(|id| {
+                    let cmd = XGroup::create(key, group, Some(id), *mkstream);
+                    state.propagate(&cmd.into_frame());
+                    Frame::Simple("OK".to_string())
+                })
+            }
+            XGroupSubcommand::SetId(id) => state.xgroup_setid(key, group, *id).map(|id| {
+                state.propagate(&XGroup::setid(key, group, Some(id)).into_frame());
+                Frame::Simple("OK".to_string())
+            }),
+            XGroupSubcommand::Destroy => state.xgroup_destroy(key, group).map(|destroyed| {
+                if destroyed {
+                    state.propagate(&self.clone().into_frame());
+                }
+                Frame::Integer(destroyed as i64)
+            }),
+            XGroupSubcommand::CreateConsumer(consumer) => state
+                .xgroup_create_consumer(key, group, consumer)
+                .map(|created| {
+                    if created {
+                        state.propagate(&self.clone().into_frame());
+                    }
+                    Frame::Integer(created as i64)
+                }),
+            XGroupSubcommand::DelConsumer(consumer) => state
+                .xgroup_del_consumer(key, group, consumer)
+                .map(|removed| {
+                    state.propagate(&self.clone().into_frame());
+                    Frame::Integer(removed as i64)
+                }),
+        };
+
+        match res {
+            Ok(response) => response,
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `XGroup` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xgroup".as_bytes()));
+
+        let subcommand = match &self.subcommand {
+            XGroupSubcommand::Create { .. } => "create",
+            XGroupSubcommand::SetId(_) => "setid",
+            XGroupSubcommand::Destroy => "destroy",
+            XGroupSubcommand::CreateConsumer(_) => "createconsumer",
+            XGroupSubcommand::DelConsumer(_) => "delconsumer",
+        };
+        frame.push_bulk(Bytes::from(subcommand.as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.group.into_bytes()));
+
+        match self.subcommand {
+            XGroupSubcommand::Create { id, mkstream } => {
+                frame.push_bulk(Bytes::from(format_group_id(id)));
+                if mkstream {
+                    frame.push_bulk(Bytes::from("mkstream".as_bytes()));
+                }
+            }
+            XGroupSubcommand::SetId(id) => frame.push_bulk(Bytes::from(format_group_id(id))),
+            XGroupSubcommand::Destroy => {}
+            XGroupSubcommand::CreateConsumer(consumer)
+            | XGroupSubcommand::DelConsumer(consumer) => {
+                frame.push_bulk(Bytes::from(consumer.into_bytes()))
+            }
+        }
+
+        frame
+    }
+}
+
+impl XReadGroup {
+    /// Create a new `XReadGroup` command which reads the streams as
+    /// `consumer` of the group `group`, up to `count` entries per stream.
+    ///
+    /// With `block`, the connection waits for an entry for at most that long,
+    /// or indefinitely if it is zero. With `noack`, the entries read are not
+    /// added to the pending entries.
+    pub(crate) fn new(
+        group: impl ToString,
+        consumer: impl ToString,
+        streams: Vec<(String, GroupReadId)>,
+        count: Option<usize>,
+        block: Option<Duration>,
+        noack: bool,
+    ) -> XReadGroup {
+        XReadGroup {
+            group: group.to_string(),
+            consumer: consumer.to_string(),
+            streams,
+            count,
+            block,
+            noack,
+        }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> Vec<&str> {
+        self.streams.iter().map(|(key, _)| key.as_str()).collect()
+    }
+
+    /// Returns `true` if the command waits for entries when there are none.
+    pub(crate) fn is_blocking(&self) -> bool {
+        self.block.is_some()
+    }
+
+    /// Parse a `XReadGroup` instance from a received frame.
+    ///
+    /// The `XREADGROUP` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least seven entries. The timeout
+    /// is given in milliseconds.
+    ///
+    /// ```text
+    /// XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key [key ...] id [id ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XReadGroup> {
+        if parse.next_string()?.to_uppercase() != "GROUP" {
+            return Err("ERR syntax error".into());
+        }
+
+        let group = parse.next_string()?;
+        let consumer = parse.next_string()?;
+
+        let mut count = None;
+        let mut block = None;
+        let mut noack = false;
+
+        loop {
+            match &parse.next_string()?.to_uppercase()[..] {
+                "COUNT" => count = Some(parse_count(parse)?),
+                "BLOCK" => block = Some(Duration::from_millis(parse.next_int()?)),
+                "NOACK" => noack = true,
+                "STREAMS" => break,
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+
+        // The keys are followed by as many IDs
+        let mut args = vec![];
+
+        loop {
+            match parse.next_string() {
+                Ok(arg) => args.push(arg),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        if args.is_empty() || args.len() % 2 != 0 {
+            return Err("ERR Unbalanced 'xreadgroup' list of streams: for each stream key an ID or '>' must be specified.".into());
+        }
+
+        let ids = args.split_off(args.len() / 2);
+        let streams = args
+            .into_iter()
+            .zip(ids)
+            .map(|(key, id)| Ok((key, parse_group_read_id(&id)?)))
+            .collect::<crate::Result<_>>()?;
+
+        Ok(XReadGroup {
+            group,
+            consumer,
+            streams,
+            count,
+            block,
+            noack,
+        })
+    }
+
+    /// Execute the `XReadGroup` command against the locked database state,
+    /// without blocking.
+    ///
+    /// This is how `XREADGROUP` is executed in a transaction or a script, even
+    /// with the `BLOCK` option.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match self.read(state, None) {
+            Ok(Some(streams)) if !streams.is_empty() => streams_frame(streams),
+            Ok(_) => Frame::Null,
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Apply the `XReadGroup` command to the specified `Db` instance, waiting
+    /// for entries if there are none.
+    ///
+    /// Responds with an array holding, for each stream read, an array of the
+    /// name of the stream and its entries. If the timeout elapses first,
+    /// `Null` is returned.
+    ///
+    /// If the server shuts down while the connection is blocked, no response
+    /// is written.
+    #[instrument(skip(self, db, dst, shutdown))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        shutdown: &mut Shutdown,
+    ) -> crate::Result<()> {
+        let waiter = Arc::new(Notify::new());
+        let deadline = deadline(self.block.unwrap_or_default());
+        let keys: Vec<String> = self.streams.iter().map(|(key, _)| key.clone()).collect();
+
+        let response = loop {
+            match db.with_keys(&keys, |state| self.read(state, Some(&waiter))) {
+                Ok(Some(streams)) => break streams_frame(streams),
+                Ok(None) => {}
+                Err(err) => break err.into(),
+            }
+
+            match wait(&waiter, deadline, shutdown).await {
+                Wake::Notified => {}
+                Wake::TimedOut | Wake::Shutdown => break Frame::Null,
+            }
+        };
+
+        db.with_keys(&keys, |state| state.unblock(&keys, &waiter));
+
+        if shutdown.is_shutdown() {
+            return Ok(());
+        }
+
+        db.wait_for_aof().await;
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Read the streams. If nothing is read and `waiter` is set, it is
+    /// registered to be notified once an entry is added.
+    ///
+    /// Reading modifies the group, which is propagated as the equivalent
+    /// `XGROUP CREATECONSUMER`, `XCLAIM` and `XGROUP SETID` commands. Replaying
+    /// the read itself would deliver the entries at another time.
+    fn read(
+        &self,
+        state: &mut State,
+        waiter: Option<&Arc<Notify>>,
+    ) -> Result<Option<StreamRead>, crate::db::Error> {
+        let (group, consumer) = (&self.group, &self.consumer);
+
+        // Reading creates the consumer, even if nothing is read.
+        let created: Vec<&str> = if state.is_propagating() {
+            self.streams
+                .iter()
+                .filter(|(key, _)| match state.xgroup(key, group) {
+                    Ok(group) => !group.has_consumer(consumer),
+                    Err(_) => false,
+                })
+                .map(|(key, _)| key.as_str())
+                .collect()
+        } else {
+            vec![]
+        };
+
+        let read = match waiter {
+            Some(waiter) => state.xreadgroup_or_block(
+                group,
+                consumer,
+                &self.streams,
+                self.count,
+                self.noack,
+                waiter,
+            )?,
+            None => {
+                Some(state.xreadgroup(group, consumer, &self.streams, self.count, self.noack)?)
+            }
+        };
+
+        for key in created {
+            state.propagate(&XGroup::create_consumer(key, group, consumer).into_frame());
+        }
+
+        if let (true, Some(read)) = (state.is_propagating(), &read) {
+            for (key, entries) in read {
+                let new = self
+                    .streams
+                    .iter()
+                    .any(|(k, id)| k == key && matches!(id, GroupReadId::New));
+
+                if !(new && self.noack) {
+                    for (id, _) in entries {
+                        propagate_delivery(state, key, group, *id);
+                    }
+                }
+
+                if let (true, Some((last, _))) = (new, entries.last()) {
+                    state.propagate(&XGroup::setid(key, group, Some(*last)).into_frame());
+                }
+            }
+        }
+
+        Ok(read)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `XReadGroup` command to
+    /// send to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xreadgroup".as_bytes()));
+        frame.push_bulk(Bytes::from("group".as_bytes()));
+        frame.push_bulk(Bytes::from(self.group.into_bytes()));
+        frame.push_bulk(Bytes::from(self.consumer.into_bytes()));
+        if let Some(count) = self.count {
+            frame.push_bulk(Bytes::from("count".as_bytes()));
+            frame.push_int(count as i64);
+        }
+        if let Some(block) = self.block {
+            frame.push_bulk(Bytes::from("block".as_bytes()));
+            frame.push_int(block.as_millis() as i64);
+        }
+        if self.noack {
+            frame.push_bulk(Bytes::from("noack".as_bytes()));
+        }
+        frame.push_bulk(Bytes::from("streams".as_bytes()));
+        let (keys, ids): (Vec<_>, Vec<_>) = self.streams.into_iter().unzip();
+        for key in keys {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+        }
+        for id in ids {
+            let id = match id {
+                GroupReadId::New => ">".to_string(),
+                GroupReadId::Pending(id) => id.to_string(),
+            };
+            frame.push_bulk(Bytes::from(id));
+        }
+        frame
+    }
+}
+
+impl XAck {
+    /// Create a new `XAck` command which acknowledges the entries `ids`
+    /// pending in the consumer group `group` of the stream stored at `key`.
+    pub(crate) fn new(key: impl ToString, group: impl ToString, ids: Vec<StreamId>) -> XAck {
+        XAck {
+            key: key.to_string(),
+            group: group.to_string(),
+            ids,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XAck` instance from a received frame.
+    ///
+    /// The `XACK` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least four entries.
+    ///
+    /// ```text
+    /// XACK key group id [id ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XAck> {
+        let key = parse.next_string()?;
+        let group = parse.next_string()?;
+
+        let mut ids = vec![parse_id(&parse.next_string()?, 0)?];
+
+        loop {
+            match parse.next_string() {
+                Ok(id) => ids.push(parse_id(&id, 0)?),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(XAck { key, group, ids })
+    }
+
+    /// Execute the `XAck` command against the locked database state.
+    ///
+    /// Responds with the number of entries which were pending.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.xack(&self.key, &self.group, &self.ids) {
+            Ok(acked) => Frame::Integer(acked as i64),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `XAck` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xack".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.group.into_bytes()));
+        for id in self.ids {
+            frame.push_bulk(Bytes::from(id.to_string()));
+        }
+        frame
+    }
+}
+
+impl XPending {
+    /// Create a new `XPending` command which returns a summary of the entries
+    /// pending in the consumer group `group` of the stream stored at `key`.
+    pub fn summary(key: impl ToString, group: impl ToString) -> XPending {
+        XPending {
+            key: key.to_string(),
+            group: group.to_string(),
+            range: None,
+        }
+    }
+
+    /// Create a new `XPending` command which lists the entries pending in the
+    /// consumer group `group` of the stream stored at `key`, with an ID
+    /// between `start` and `end`, up to `count` entries. With `consumer`,
+    /// only the entries pending for that consumer are listed.
+    pub(crate) fn range(
+        key: impl ToString,
+        group: impl ToString,
+        start: StreamId,
+        end: StreamId,
+        count: usize,
+        consumer: Option<String>,
+    ) -> XPending {
+        XPending {
+            key: key.to_string(),
+            group: group.to_string(),
+            range: Some(PendingRange {
+                min_idle_ms: None,
+                start,
+                end,
+                count,
+                consumer,
+            }),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XPending` instance from a received frame.
+    ///
+    /// The `XPENDING` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing three entries, or at least six with
+    /// a range. The minimum idle time is given in milliseconds.
+    ///
+    /// ```text
+    /// XPENDING key group [[IDLE min-idle-time] start end count [consumer]]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XPending> {
+        let key = parse.next_string()?;
+        let group = parse.next_string()?;
+
+        let mut arg = match parse.next_string() {
+            Ok(arg) => arg,
+            Err(ParseError::EndOfStream) => {
+                return Ok(XPending {
+                    key,
+                    group,
+                    range: None,
+                })
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let min_idle_ms = if arg.to_uppercase() == "IDLE" {
+            let min_idle_ms = parse.next_int()?;
+            arg = parse.next_string()?;
+            Some(min_idle_ms)
+        } else {
+            None
+        };
+
+        let start = parse_range_start(&arg)?;
+        let end = parse_range_end(&parse.next_string()?)?;
+        let count = parse_count(parse)?;
+
+        let consumer = match parse.next_string() {
+            Ok(consumer) => Some(consumer),
+            Err(ParseError::EndOfStream) => None,
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(XPending {
+            key,
+            group,
+            range: Some(PendingRange {
+                min_idle_ms,
+                start,
+                end,
+                count,
+                consumer,
+            }),
+        })
+    }
+
+    /// Execute the `XPending` command against the locked database state.
+    ///
+    /// The summary is an array of the number of pending entries, the
+    /// smallest and greatest pending IDs, and an array of the number of
+    /// entries pending for each consumer. A range is listed as an array of
+    /// entries, each an array of its ID, its consumer, the milliseconds since
+    /// it was last delivered and the number of times it was delivered.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let group = match state.xgroup(&self.key, &self.group) {
+            Ok(group) => group,
+            Err(err) => return err.into(),
+        };
+
+        let range = match self.range {
+            Some(range) => range,
+            None => return pending_summary_frame(group),
+        };
+
+        let now = unix_time_ms();
+
+        let entries = group
+            .pending(range.start, range.end)
+            .filter(|(_, pending)| match &range.consumer {
+                Some(consumer) => pending.consumer() == consumer,
+                None => true,
+            })
+            .map(|(id, pending)| (id, pending, now.saturating_sub(pending.delivered_ms())))
+            .filter(|(_, _, idle)| *idle >= range.min_idle_ms.unwrap_or(0))
+            .take(range.count)
+            .map(|(id, pending, idle)| {
+                Frame::Array(vec![
+                    Frame::Bulk(Bytes::from(id.to_string())),
+                    Frame::Bulk(Bytes::from(pending.consumer().to_string())),
+                    Frame::Integer(idle as i64),
+                    Frame::Integer(pending.delivery_count() as i64),
+                ])
+            })
+            .collect();
+
+        Frame::Array(entries)
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `XPending` command to
+    /// send to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xpending".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.group.into_bytes()));
+        if let Some(range) = self.range {
+            if let Some(min_idle_ms) = range.min_idle_ms {
+                frame.push_bulk(Bytes::from("idle".as_bytes()));
+                frame.push_int(min_idle_ms as i64);
+            }
+            frame.push_bulk(Bytes::from(range.start.to_string()));
+            frame.push_bulk(Bytes::from(range.end.to_string()));
+            frame.push_int(range.count as i64);
+            if let Some(consumer) = range.consumer {
+                frame.push_bulk(Bytes::from(consumer.into_bytes()));
+            }
+        }
+        frame
+    }
+}
+
+impl XClaim {
+    /// Create a new `XClaim` command which transfers the entries `ids`
+    /// pending in the consumer group `group` of the stream stored at `key` to
+    /// `consumer`, if they were not delivered for at least `min_idle_ms`.
+    pub(crate) fn new(
+        key: impl ToString,
+        group: impl ToString,
+        consumer: impl ToString,
+        min_idle_ms: u64,
+        ids: Vec<StreamId>,
+        options: ClaimOptions,
+    ) -> XClaim {
+        XClaim {
+            key: key.to_string(),
+            group: group.to_string(),
+            consumer: consumer.to_string(),
+            min_idle_ms,
+            ids,
+            idle_ms: None,
+            options,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `XClaim` instance from a received frame.
+    ///
+    /// The `XCLAIM` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least six entries. Times are
+    /// given in milliseconds.
+    ///
+    /// ```text
+    /// XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-milliseconds] [RETRYCOUNT count] [FORCE] [JUSTID]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<XClaim> {
+        let key = parse.next_string()?;
+        let group = parse.next_string()?;
+        let consumer = parse.next_string()?;
+        let min_idle_ms = parse.next_int()?;
+
+        let mut ids = vec![parse_id(&parse.next_string()?, 0)?];
+        let mut idle_ms = None;
+        let mut options = ClaimOptions::default();
+
+        loop {
+            let arg = match parse.next_string() {
+                Ok(arg) => arg,
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            };
+
+            match &arg.to_uppercase()[..] {
+                "IDLE" => idle_ms = Some(parse.next_int()?),
+                "TIME" => options.delivered_ms = Some(parse.next_int()?),
+                "RETRYCOUNT" => options.retry_count = Some(parse.next_int()?),
+                "FORCE" => options.force = true,
+                "JUSTID" => options.justid = true,
+                _ => ids.push(parse_id(&arg, 0)?),
+            }
+        }
+
+        Ok(XClaim {
+            key,
+            group,
+            consumer,
+            min_idle_ms,
+            ids,
+            idle_ms,
+            options,
+        })
+    }
+
+    /// Execute the `XClaim` command against the locked database state.
+    ///
+    /// Responds with the claimed entries, or only their IDs with `JUSTID`.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let mut options = self.options;
+        if let Some(idle_ms) = self.idle_ms {
+            options.delivered_ms = Some(unix_time_ms().saturating_sub(idle_ms));
+        }
+
+        let res = state.xclaim(
+            &self.key,
+            &self.group,
+            &self.consumer,
+            self.min_idle_ms,
+            &self.ids,
+            options,
+        );
+
+        match res {
+            Ok(claimed) => {
+                if state.is_propagating() {
+                    for (id, _) in &claimed {
+                        propagate_delivery(state, &self.key, &self.group, *id);
+                    }
+                }
+
+                if options.justid {
+                    Frame::Array(
+                        claimed
+                            .into_iter()
+                            .map(|(id, _)| Frame::Bulk(Bytes::from(id.to_string())))
+                            .collect(),
+                    )
+                } else {
+                    entries_frame(claimed)
+                }
+            }
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `XClaim` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("xclaim".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.group.into_bytes()));
+        frame.push_bulk(Bytes::from(self.consumer.into_bytes()));
+        frame.push_bulk(Bytes::from(self.min_idle_ms.to_string()));
+        for id in self.ids {
+            frame.push_bulk(Bytes::from(id.to_string()));
+        }
+        if let Some(idle_ms) = self.idle_ms {
+            frame.push_bulk(Bytes::from("idle".as_bytes()));
+            frame.push_bulk(Bytes::from(idle_ms.to_string()));
+        }
+        if let Some(delivered_ms) = self.options.delivered_ms {
+            frame.push_bulk(Bytes::from("time".as_bytes()));
+            frame.push_bulk(Bytes::from(delivered_ms.to_string()));
+        }
+        if let Some(retry_count) = self.options.retry_count {
+            frame.push_bulk(Bytes::from("retrycount".as_bytes()));
+            frame.push_bulk(Bytes::from(retry_count.to_string()));
+        }
+        if self.options.force {
+            frame.push_bulk(Bytes::from("force".as_bytes()));
+        }
+        if self.options.justid {
+            frame.push_bulk(Bytes::from("justid".as_bytes()));
+        }
+        frame
+    }
+}
+
+/// Parse the ID given to `XADD`: `*`, `<ms>-*` or an explicit ID.
+pub(crate) fn parse_new_id(s: &str) -> crate::Result<NewId> {
+    if s == "*" {
+        return Ok(NewId::Auto);
+    }
+
+    if let Some(ms) = s.strip_suffix("-*") {
+        let ms = ms.parse().map_err(|_| invalid_id())?;
+        return Ok(NewId::AutoSeq(ms));
+    }
+
+    Ok(NewId::Explicit(parse_id(s, 0)?))
+}
+
+/// Parse the start of an `XRANGE` interval.
+pub(crate) fn parse_range_start(s: &str) -> crate::Result<StreamId> {
+    match s {
+        "-" => Ok(StreamId::MIN),
+        "+" => Ok(StreamId::MAX),
+        _ => match s.strip_prefix('(') {
+            Some(id) => parse_id(id, 0)?
+                .next()
+                .ok_or_else(|| "ERR invalid start ID for the interval".into()),
+            None => parse_id(s, 0),
+        },
+    }
+}
+
+/// Parse the end of an `XRANGE` interval.
+pub(crate) fn parse_range_end(s: &str) -> crate::Result<StreamId> {
+    match s {
+        "-" => Ok(StreamId::MIN),
+        "+" => Ok(StreamId::MAX),
+        _ => match s.strip_prefix('(') {
+            Some(id) => parse_id(id, u64::MAX)?
+                .prev()
+                .ok_or_else(|| "ERR invalid end ID for the interval".into()),
+            None => parse_id(s, u64::MAX),
+        },
+    }
+}
+
+/// Parse an ID given to `XREAD`: `$` or an ID.
+pub(crate) fn parse_read_id(s: &str) -> crate::Result<ReadId> {
+    match s {
+        "$" => Ok(ReadId::Last),
+        _ => Ok(ReadId::After(parse_id(s, 0)?)),
+    }
+}
+
+/// Parse an ID, which may omit the sequence number. `seq` is used then.
+fn parse_id(s: &str, seq: u64) -> crate::Result<StreamId> {
+    if s.contains('-') {
+        s.parse()
+    } else {
+        let ms = s.parse().map_err(|_| invalid_id())?;
+        Ok(StreamId::new(ms, seq))
+    }
+}
+
+fn invalid_id() -> crate::Error {
+    "ERR Invalid stream ID specified as stream command argument".into()
+}
+
+/// Parse the ID given to `XGROUP CREATE` and `XGROUP SETID`: `$`, standing
+/// for the ID of the last entry, or an ID.
+pub(crate) fn parse_group_id(s: &str) -> crate::Result<Option<StreamId>> {
+    match s {
+        "$" => Ok(None),
+        _ => Ok(Some(parse_id(s, 0)?)),
+    }
+}
+
+/// Parse an ID given to `XREADGROUP`: `>` or an ID.
+pub(crate) fn parse_group_read_id(s: &str) -> crate::Result<GroupReadId> {
+    match s {
+        ">" => Ok(GroupReadId::New),
+        _ => Ok(GroupReadId::Pending(parse_id(s, 0)?)),
+    }
+}
+
+/// Format the ID given to `XADD`.
+fn format_new_id(id: NewId) -> String {
+    match id {
+        NewId::Auto => "*".to_string(),
+        NewId::AutoSeq(ms) => format!("{}-*", ms),
+        NewId::Explicit(id) => id.to_string(),
+    }
+}
+
+/// Parse the threshold following `MAXLEN`, optionally preceded by `=` or `~`.
+fn parse_maxlen(parse: &mut Parse) -> crate::Result<usize> {
+    let arg = parse.next_string()?;
+
+    let threshold = match &arg[..] {
+        "=" | "~" => parse.next_string()?,
+        _ => arg,
+    };
+
+    threshold
+        .parse()
+        .map_err(|_| "ERR value is not an integer or out of range".into())
+}
+
+/// Parse the argument of a `COUNT` option.
+fn parse_count(parse: &mut Parse) -> crate::Result<usize> {
+    parse
+        .next_int()?
+        .try_into()
+        .map_err(|_| "ERR value is not an integer or out of range".into())
+}
+
+/// Convert stream entries to an array frame.
+fn entries_frame(entries: Vec<StreamEntry>) -> Frame {
+    Frame::Array(
+        entries
+            .into_iter()
+            .map(|(id, fields)| {
+                let fields = fields
+                    .into_iter()
+                    .flat_map(|(field, value)| vec![Frame::Bulk(field), Frame::Bulk(value)])
+                    .collect();
+
+                Frame::Array(vec![
+                    Frame::Bulk(Bytes::from(id.to_string())),
+                    Frame::Array(fields),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// Convert the entries read from each stream to an array frame.
+fn streams_frame(streams: StreamRead) -> Frame {
+    Frame::Array(
+        streams
+            .into_iter()
+            .map(|(key, entries)| {
+                Frame::Array(vec![Frame::Bulk(Bytes::from(key)), entries_frame(entries)])
+            })
+            .collect(),
+    )
+}
+
+/// Format the ID given to `XGROUP CREATE` and `XGROUP SETID`.
+fn format_group_id(id: Option<StreamId>) -> String {
+    match id {
+        Some(id) => id.to_string(),
+        None => "$".to_string(),
+    }
+}
+
+/// Propagate the delivery of the pending entry `id` of the group `group` as
+/// the equivalent `XCLAIM`, so the entry is pending for the same consumer,
+/// with the same delivery time and count, when the append-only file is
+/// replayed or on replicas.
+fn propagate_delivery(state: &mut State, key: &str, group: &str, id: StreamId) {
+    let pending = match state.xgroup(key, group) {
+        Ok(group) => group.pending_entry(id).cloned(),
+        Err(_) => None,
+    };
+
+    if let Some(pending) = pending {
+        let options = ClaimOptions {
+            delivered_ms: Some(pending.delivered_ms()),
+            retry_count: Some(pending.delivery_count()),
+            force: true,
+            justid: true,
+        };
+        let cmd = XClaim::new(key, group, pending.consumer(), 0, vec![id], options);
+        state.propagate(&cmd.into_frame());
+    }
+}
+
+/// Convert the summary of the entries pending in `group` to an array frame.
+fn pending_summary_frame(group: &Group) -> Frame {
+    let mut pending = group.pending(StreamId::MIN, StreamId::MAX);
+
+    let (first, last) = match (pending.next(), pending.last()) {
+        (Some((first, _)), Some((last, _))) => (*first, *last),
+        (Some((first, _)), None) => (*first, *first),
+        _ => {
+            return Frame::Array(vec![
+                Frame::Integer(0),
+                Frame::Null,
+                Frame::Null,
+                Frame::Null,
+            ])
+        }
+    };
+
+    let consumers = group
+        .pending_by_consumer()
+        .into_iter()
+        .map(|(consumer, count)| {
+            Frame::Array(vec![
+                Frame::Bulk(Bytes::from(consumer)),
+                Frame::Bulk(Bytes::from(count.to_string())),
+            ])
+        })
+        .collect();
+
+    Frame::Array(vec![
+        Frame::Integer(group.pending_len() as i64),
+        Frame::Bulk(Bytes::from(first.to_string())),
+        Frame::Bulk(Bytes::from(last.to_string())),
+        Frame::Array(consumers),
+    ])
+}
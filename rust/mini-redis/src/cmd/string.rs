@@ -0,0 +1,352 @@
+use crate::cmd::{Parse, Set};
+use crate::db::{Error, State};
+use crate::Frame;
+
+use bytes::Bytes;
+use tracing::instrument;
+
+/// Increments or decrements the number stored at key by increment.
+///
+/// If the key does not exist, it is set to `0` before performing the
+/// operation. An error is returned if the key holds a value that cannot be
+/// represented as a 64 bit signed integer. The time to live of the key is
+/// retained.
+///
+/// `INCR` and `DECR` are the same as `INCRBY` and `DECRBY` with an increment of
+/// `1`.
+#[derive(Debug, Clone)]
+pub struct IncrBy {
+    /// the lookup key
+    key: String,
+
+    /// Amount to add to, or subtract from, the value
+    increment: i64,
+
+    /// Subtract the increment instead of adding it
+    decrement: bool,
+}
+
+/// Increments the floating point number stored at key by increment.
+///
+/// If the key does not exist, it is set to `0` before performing the
+/// operation. The increment may be negative.
+#[derive(Debug, Clone)]
+pub struct IncrByFloat {
+    /// the lookup key
+    key: String,
+
+    /// Amount to add to the value
+    increment: f64,
+}
+
+/// Appends value at the end of the string stored at key.
+///
+/// If the key does not exist, it is created holding an empty string before
+/// performing the operation.
+#[derive(Debug, Clone)]
+pub struct Append {
+    /// the lookup key
+    key: String,
+
+    /// Data to append
+    value: Bytes,
+}
+
+/// Returns the length of the string stored at key.
+#[derive(Debug)]
+pub struct StrLen {
+    /// the lookup key
+    key: String,
+}
+
+impl IncrBy {
+    /// Create a new `IncrBy` command which adds `increment` to the number
+    /// stored at `key`.
+    pub fn new(key: impl ToString, increment: i64) -> IncrBy {
+        IncrBy {
+            key: key.to_string(),
+            increment,
+            decrement: false,
+        }
+    }
+
+    /// Create a new `IncrBy` command which subtracts `decrement` from the
+    /// number stored at `key`.
+    pub fn decr(key: impl ToString, decrement: i64) -> IncrBy {
+        IncrBy {
+            key: key.to_string(),
+            increment: decrement,
+            decrement: true,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `IncrBy` instance from a received frame.
+    ///
+    /// The `INCRBY` or `DECRBY` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing three entries.
+    ///
+    /// ```text
+    /// INCRBY key increment
+    /// DECRBY key decrement
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, decrement: bool) -> crate::Result<IncrBy> {
+        let key = parse.next_string()?;
+        let increment = parse.next_signed_int()?;
+
+        Ok(IncrBy {
+            key,
+            increment,
+            decrement,
+        })
+    }
+
+    /// Parse an `IncrBy` instance from a received `INCR` or `DECR` frame.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing two entries.
+    ///
+    /// ```text
+    /// INCR key
+    /// DECR key
+    /// ```
+    pub(crate) fn parse_unit_frames(parse: &mut Parse, decrement: bool) -> crate::Result<IncrBy> {
+        let key = parse.next_string()?;
+
+        Ok(IncrBy {
+            key,
+            increment: 1,
+            decrement,
+        })
+    }
+
+    /// Execute the `IncrBy` command against the locked database state.
+    ///
+    /// Responds with the value of the key after the increment.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        let delta = if self.decrement {
+            match self.increment.checked_neg() {
+                Some(delta) => delta,
+                None => return Error::Overflow.into(),
+            }
+        } else {
+            self.increment
+        };
+
+        match state.incr_by(&self.key, delta) {
+            Ok(value) => Frame::Integer(value),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Returns the command name
+    pub(crate) fn get_name(&self) -> &'static str {
+        if self.decrement {
+            "decrby"
+        } else {
+            "incrby"
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding an `IncrBy` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from(self.get_name().as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_int(self.increment);
+        frame
+    }
+}
+
+impl IncrByFloat {
+    /// Create a new `IncrByFloat` command which adds `increment` to the number
+    /// stored at `key`.
+    pub fn new(key: impl ToString, increment: f64) -> IncrByFloat {
+        IncrByFloat {
+            key: key.to_string(),
+            increment,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `IncrByFloat` instance from a received frame.
+    ///
+    /// The `INCRBYFLOAT` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing three entries.
+    ///
+    /// ```text
+    /// INCRBYFLOAT key increment
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<IncrByFloat> {
+        let key = parse.next_string()?;
+        let increment = parse.next_float()?;
+
+        Ok(IncrByFloat { key, increment })
+    }
+
+    /// Execute the `IncrByFloat` command against the locked database state.
+    ///
+    /// Responds with the value of the key after the increment, as a string.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.incr_by_float(&self.key, self.increment) {
+            Ok(value) => {
+                let value = Bytes::from(value.to_string());
+
+                // The resulting value is propagated rather than the
+                // increment, so floating point rounding cannot make the
+                // append-only file or replicas diverge.
+                if state.is_propagating() {
+                    let cmd = Set::new(&self.key, value.clone(), None).keep_ttl();
+                    state.propagate(&cmd.into_frame());
+                }
+
+                Frame::Bulk(value)
+            }
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding an `IncrByFloat` command to
+    /// send to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("incrbyfloat".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.increment.to_string()));
+        frame
+    }
+}
+
+impl Append {
+    /// Create a new `Append` command which appends `value` to the string
+    /// stored at `key`.
+    pub fn new(key: impl ToString, value: Bytes) -> Append {
+        Append {
+            key: key.to_string(),
+            value,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse an `Append` instance from a received frame.
+    ///
+    /// The `APPEND` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing three entries.
+    ///
+    /// ```text
+    /// APPEND key value
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Append> {
+        let key = parse.next_string()?;
+        let value = parse.next_bytes()?;
+
+        Ok(Append { key, value })
+    }
+
+    /// Execute the `Append` command against the locked database state.
+    ///
+    /// Responds with the length of the string after the append.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.append(&self.key, &self.value) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding an `Append` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("append".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(self.value);
+        frame
+    }
+}
+
+impl StrLen {
+    /// Create a new `StrLen` command which fetches the length of the string
+    /// stored at `key`.
+    pub fn new(key: impl ToString) -> StrLen {
+        StrLen {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `StrLen` instance from a received frame.
+    ///
+    /// The `STRLEN` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing two entries.
+    ///
+    /// ```text
+    /// STRLEN key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<StrLen> {
+        let key = parse.next_string()?;
+
+        Ok(StrLen { key })
+    }
+
+    /// Execute the `StrLen` command against the locked database state.
+    ///
+    /// Responds with the length of the string, or `0` if the key does not
+    /// exist.
+    #[instrument(skip(self, state))]
+    pub(crate) fn execute(self, state: &mut State) -> Frame {
+        match state.strlen(&self.key) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `StrLen` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("strlen".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
@@ -26,12 +26,48 @@ pub struct Unsubscribe {
     channels: Vec<String>,
 }
 
+/// Subscribes the client to one or more glob-style patterns.
+///
+/// The client receives messages published on every channel matching one of
+/// the patterns, along with the pattern and the name of the channel. Pattern
+/// subscriptions count towards the same total as channel subscriptions.
+#[derive(Debug)]
+pub struct PSubscribe {
+    patterns: Vec<String>,
+}
+
+/// Unsubscribes the client from one or more patterns.
+///
+/// When no patterns are specified, the client is unsubscribed from all the
+/// previously subscribed patterns.
+#[derive(Clone, Debug)]
+pub struct PUnsubscribe {
+    patterns: Vec<String>,
+}
+
 /// Stream of messages. The stream receives messages from the
 /// `broadcast::Receiver`. We use `stream!` to create a `Stream` that consumes
 /// messages. Because `stream!` values cannot be named, we box the stream using
 /// a trait object.
 type Messages = Pin<Box<dyn Stream<Item = Bytes> + Send>>;
 
+/// Stream of messages published on channels matching a pattern, along with
+/// the name of the channel.
+type PatternMessages = Pin<Box<dyn Stream<Item = (String, Bytes)> + Send>>;
+
+/// The subscriptions of a client in the subscribed state.
+///
+/// Redis reports the total number of channels and patterns a client is
+/// subscribed to when it subscribes or unsubscribes.
+#[derive(Default)]
+struct Subscriptions {
+    /// Subscriptions to channels, keyed by channel name.
+    channels: StreamMap<String, Messages>,
+
+    /// Subscriptions to patterns, keyed by pattern.
+    patterns: StreamMap<String, PatternMessages>,
+}
+
 impl Subscribe {
     /// Creates a new `Subscribe` command to listen on the specified channels.
     pub(crate) fn new(channels: Vec<String>) -> Subscribe {
@@ -98,59 +134,12 @@ impl Subscribe {
     ///
     /// [here]: https://redis.io/topics/pubsub
     pub(crate) async fn apply(
-        mut self,
+        self,
         db: &Db,
         dst: &mut Connection,
         shutdown: &mut Shutdown,
     ) -> crate::Result<()> {
-        // Each individual channel subscription is handled using a
-        // `sync::broadcast` channel. Messages are then fanned out to all
-        // clients currently subscribed to the channels.
-        //
-        // An individual client may subscribe to multiple channels and may
-        // dynamically add and remove channels from its subscription set. To
-        // handle this, a `StreamMap` is used to track active subscriptions. The
-        // `StreamMap` merges messages from individual broadcast channels as
-        // they are received.
-        let mut subscriptions = StreamMap::new();
-
-        loop {
-            // `self.channels` is used to track additional channels to subscribe
-            // to. When new `SUBSCRIBE` commands are received during the
-            // execution of `apply`, the new channels are pushed onto this vec.
-            for channel_name in self.channels.drain(..) {
-                subscribe_to_channel(channel_name, &mut subscriptions, db, dst).await?;
-            }
-
-            // Wait for one of the following to happen:
-            //
-            // - Receive a message from one of the subscribed channels.
-            // - Receive a subscribe or unsubscribe command from the client.
-            // - A server shutdown signal.
-            select! {
-                // Receive messages from subscribed channels
-                Some((channel_name, msg)) = subscriptions.next() => {
-                    dst.write_frame(&make_message_frame(channel_name, msg)).await?;
-                }
-                res = dst.read_frame() => {
-                    let frame = match res? {
-                        Some(frame) => frame,
-                        // This happens if the remote client has disconnected.
-                        None => return Ok(())
-                    };
-
-                    handle_command(
-                        frame,
-                        &mut self.channels,
-                        &mut subscriptions,
-                        dst,
-                    ).await?;
-                }
-                _ = shutdown.recv() => {
-                    return Ok(());
-                }
-            };
-        }
+        subscribed(self.channels, vec![], db, dst, shutdown).await
     }
 
     /// Converts the command into an equivalent `Frame`.
@@ -167,9 +156,83 @@ impl Subscribe {
     }
 }
 
+/// Process messages and pub/sub commands until the client disconnects or the
+/// server shuts down.
+///
+/// `channels` and `patterns` are the initial channels and patterns to
+/// subscribe to, from the `SUBSCRIBE` or `PSUBSCRIBE` command that put the
+/// client in the subscribed state.
+async fn subscribed(
+    mut channels: Vec<String>,
+    mut patterns: Vec<String>,
+    db: &Db,
+    dst: &mut Connection,
+    shutdown: &mut Shutdown,
+) -> crate::Result<()> {
+    // Each individual channel subscription is handled using a
+    // `sync::broadcast` channel. Messages are then fanned out to all clients
+    // currently subscribed to the channels.
+    //
+    // An individual client may subscribe to multiple channels and may
+    // dynamically add and remove channels from its subscription set. To handle
+    // this, a `StreamMap` is used to track active subscriptions. The
+    // `StreamMap` merges messages from individual broadcast channels as they
+    // are received. Pattern subscriptions are tracked the same way, in a
+    // separate `StreamMap`.
+    let mut subscriptions = Subscriptions::default();
+
+    loop {
+        // `channels` and `patterns` are used to track additional channels and
+        // patterns to subscribe to. When new `SUBSCRIBE` or `PSUBSCRIBE`
+        // commands are received, they are pushed onto these vecs.
+        for channel_name in channels.drain(..) {
+            subscribe_to_channel(channel_name, &mut subscriptions, db, dst).await?;
+        }
+
+        for pattern in patterns.drain(..) {
+            subscribe_to_pattern(pattern, &mut subscriptions, db, dst).await?;
+        }
+
+        // Wait for one of the following to happen:
+        //
+        // - Receive a message from one of the subscribed channels.
+        // - Receive a message from a channel matching a subscribed pattern.
+        // - Receive a subscribe or unsubscribe command from the client.
+        // - A server shutdown signal.
+        select! {
+            // Receive messages from subscribed channels
+            Some((channel_name, msg)) = subscriptions.channels.next() => {
+                dst.write_frame(&make_message_frame(channel_name, msg)).await?;
+            }
+            // Receive messages from channels matching subscribed patterns
+            Some((pattern, (channel_name, msg))) = subscriptions.patterns.next() => {
+                dst.write_frame(&make_pmessage_frame(pattern, channel_name, msg)).await?;
+            }
+            res = dst.read_frame() => {
+                let frame = match res? {
+                    Some(frame) => frame,
+                    // This happens if the remote client has disconnected.
+                    None => return Ok(())
+                };
+
+                handle_command(
+                    frame,
+                    &mut channels,
+                    &mut patterns,
+                    &mut subscriptions,
+                    dst,
+                ).await?;
+            }
+            _ = shutdown.recv() => {
+                return Ok(());
+            }
+        };
+    }
+}
+
 async fn subscribe_to_channel(
     channel_name: String,
-    subscriptions: &mut StreamMap<String, Messages>,
+    subscriptions: &mut Subscriptions,
     db: &Db,
     dst: &mut Connection,
 ) -> crate::Result<()> {
@@ -188,35 +251,66 @@ async fn subscribe_to_channel(
     });
 
     // Track subscription in this client's subscription set.
-    subscriptions.insert(channel_name.clone(), rx);
+    subscriptions.channels.insert(channel_name.clone(), rx);
 
     // Respond with the successful subscription
-    let response = make_subscribe_frame(channel_name, subscriptions.len());
+    let response = make_subscribe_frame("subscribe", channel_name, subscriptions.len());
     dst.write_frame(&response).await?;
 
     Ok(())
 }
 
-/// Handle a command received while inside `Subscribe::apply`. Only subscribe
-/// and unsubscribe commands are permitted in this context.
+async fn subscribe_to_pattern(
+    pattern: String,
+    subscriptions: &mut Subscriptions,
+    db: &Db,
+    dst: &mut Connection,
+) -> crate::Result<()> {
+    let mut rx = db.psubscribe(pattern.clone());
+
+    let rx = Box::pin(async_stream::stream! {
+        loop {
+            match rx.recv().await {
+                Ok(msg) => yield msg,
+                // If we lagged in consuming messages, just resume.
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(_) => break,
+            }
+        }
+    });
+
+    subscriptions.patterns.insert(pattern.clone(), rx);
+
+    let response = make_subscribe_frame("psubscribe", pattern, subscriptions.len());
+    dst.write_frame(&response).await?;
+
+    Ok(())
+}
+
+/// Handle a command received while inside `Subscribe::apply`. Only pub/sub
+/// commands are permitted in this context.
 ///
-/// Any new subscriptions are appended to `subscribe_to` instead of modifying
-/// `subscriptions`.
+/// Any new subscriptions are appended to `subscribe_to` or `psubscribe_to`
+/// instead of modifying `subscriptions`.
 async fn handle_command(
     frame: Frame,
     subscribe_to: &mut Vec<String>,
-    subscriptions: &mut StreamMap<String, Messages>,
+    psubscribe_to: &mut Vec<String>,
+    subscriptions: &mut Subscriptions,
     dst: &mut Connection,
 ) -> crate::Result<()> {
     // A command has been received from the client.
     //
-    // Only `SUBSCRIBE` and `UNSUBSCRIBE` commands are permitted
-    // in this context.
+    // Only `SUBSCRIBE`, `UNSUBSCRIBE`, `PSUBSCRIBE` and `PUNSUBSCRIBE`
+    // commands are permitted in this context.
     match Command::from_frame(frame)? {
         Command::Subscribe(subscribe) => {
             // The `apply` method will subscribe to the channels we add to this
             // vector.
-            subscribe_to.extend(subscribe.channels.into_iter());
+            subscribe_to.extend(subscribe.channels);
+        }
+        Command::PSubscribe(psubscribe) => {
+            psubscribe_to.extend(psubscribe.patterns);
         }
         Command::Unsubscribe(mut unsubscribe) => {
             // If no channels are specified, this requests unsubscribing from
@@ -225,15 +319,34 @@ async fn handle_command(
             // to.
             if unsubscribe.channels.is_empty() {
                 unsubscribe.channels = subscriptions
+                    .channels
                     .keys()
                     .map(|channel_name| channel_name.to_string())
                     .collect();
             }
 
             for channel_name in unsubscribe.channels {
-                subscriptions.remove(&channel_name);
+                subscriptions.channels.remove(&channel_name);
+
+                let response =
+                    make_unsubscribe_frame("unsubscribe", channel_name, subscriptions.len());
+                dst.write_frame(&response).await?;
+            }
+        }
+        Command::PUnsubscribe(mut punsubscribe) => {
+            // Same as `Unsubscribe`, no patterns means all patterns.
+            if punsubscribe.patterns.is_empty() {
+                punsubscribe.patterns = subscriptions
+                    .patterns
+                    .keys()
+                    .map(|pattern| pattern.to_string())
+                    .collect();
+            }
 
-                let response = make_unsubscribe_frame(channel_name, subscriptions.len());
+            for pattern in punsubscribe.patterns {
+                subscriptions.patterns.remove(&pattern);
+
+                let response = make_unsubscribe_frame("punsubscribe", pattern, subscriptions.len());
                 dst.write_frame(&response).await?;
             }
         }
@@ -251,33 +364,52 @@ async fn handle_command(
 /// a `&str` since `Bytes::from` can reuse the allocation in the `String`, and
 /// taking a `&str` would require copying the data. This allows the caller to
 /// decide whether to clone the channel name or not.
-fn make_subscribe_frame(channel_name: String, num_subs: usize) -> Frame {
-    let mut response = Frame::array();
-    response.push_bulk(Bytes::from_static(b"subscribe"));
+///
+/// Pub/sub frames are out-of-band data, so they are sent as push frames. On
+/// RESP2 connections these are written as plain arrays.
+///
+/// `kind` is either `subscribe` or `psubscribe`, and `num_subs` counts both
+/// channel and pattern subscriptions.
+fn make_subscribe_frame(kind: &'static str, channel_name: String, num_subs: usize) -> Frame {
+    let mut response = Frame::push();
+    response.push_bulk(Bytes::from_static(kind.as_bytes()));
     response.push_bulk(Bytes::from(channel_name));
-    response.push_int(num_subs as u64);
+    response.push_int(num_subs as i64);
     response
 }
 
 /// Creates the response to an unsubcribe request.
-fn make_unsubscribe_frame(channel_name: String, num_subs: usize) -> Frame {
-    let mut response = Frame::array();
-    response.push_bulk(Bytes::from_static(b"unsubscribe"));
+///
+/// `kind` is either `unsubscribe` or `punsubscribe`.
+fn make_unsubscribe_frame(kind: &'static str, channel_name: String, num_subs: usize) -> Frame {
+    let mut response = Frame::push();
+    response.push_bulk(Bytes::from_static(kind.as_bytes()));
     response.push_bulk(Bytes::from(channel_name));
-    response.push_int(num_subs as u64);
+    response.push_int(num_subs as i64);
     response
 }
 
 /// Creates a message informing the client about a new message on a channel that
 /// the client subscribes to.
 fn make_message_frame(channel_name: String, msg: Bytes) -> Frame {
-    let mut response = Frame::array();
+    let mut response = Frame::push();
     response.push_bulk(Bytes::from_static(b"message"));
     response.push_bulk(Bytes::from(channel_name));
     response.push_bulk(msg);
     response
 }
 
+/// Creates a message informing the client about a new message on a channel
+/// matching a pattern that the client subscribes to.
+fn make_pmessage_frame(pattern: String, channel_name: String, msg: Bytes) -> Frame {
+    let mut response = Frame::push();
+    response.push_bulk(Bytes::from_static(b"pmessage"));
+    response.push_bulk(Bytes::from(pattern));
+    response.push_bulk(Bytes::from(channel_name));
+    response.push_bulk(msg);
+    response
+}
+
 impl Unsubscribe {
     /// Create a new `Unsubscribe` command with the given `channels`.
     pub(crate) fn new(channels: &[String]) -> Unsubscribe {
@@ -347,3 +479,119 @@ impl Unsubscribe {
         frame
     }
 }
+
+impl PSubscribe {
+    /// Creates a new `PSubscribe` command to listen on channels matching the
+    /// specified patterns.
+    pub(crate) fn new(patterns: Vec<String>) -> PSubscribe {
+        PSubscribe { patterns }
+    }
+
+    /// Parse a `PSubscribe` instance from a received frame.
+    ///
+    /// The `PSUBSCRIBE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing two or more entries.
+    ///
+    /// ```text
+    /// PSUBSCRIBE pattern [pattern ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<PSubscribe> {
+        // At least one pattern is required
+        let mut patterns = vec![parse.next_string()?];
+
+        loop {
+            match parse.next_string() {
+                Ok(s) => patterns.push(s),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(PSubscribe { patterns })
+    }
+
+    /// Apply the `PSubscribe` command to the specified `Db` instance.
+    ///
+    /// The client enters the subscribed state, the same as with `Subscribe`,
+    /// starting with subscriptions to the given patterns.
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        shutdown: &mut Shutdown,
+    ) -> crate::Result<()> {
+        subscribed(vec![], self.patterns, db, dst, shutdown).await
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `PSubscribe` command to
+    /// send to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("psubscribe".as_bytes()));
+        for pattern in self.patterns {
+            frame.push_bulk(Bytes::from(pattern.into_bytes()));
+        }
+        frame
+    }
+}
+
+impl PUnsubscribe {
+    /// Create a new `PUnsubscribe` command with the given `patterns`.
+    pub(crate) fn new(patterns: &[String]) -> PUnsubscribe {
+        PUnsubscribe {
+            patterns: patterns.to_vec(),
+        }
+    }
+
+    /// Parse a `PUnsubscribe` instance from a received frame.
+    ///
+    /// The `PUNSUBSCRIBE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least one entry.
+    ///
+    /// ```text
+    /// PUNSUBSCRIBE [pattern [pattern ...]]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<PUnsubscribe, ParseError> {
+        let mut patterns = vec![];
+
+        loop {
+            match parse.next_string() {
+                Ok(s) => patterns.push(s),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(PUnsubscribe { patterns })
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `PUnsubscribe` command to
+    /// send to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("punsubscribe".as_bytes()));
+
+        for pattern in self.patterns {
+            frame.push_bulk(Bytes::from(pattern.into_bytes()));
+        }
+
+        frame
+    }
+}
+
+impl Subscriptions {
+    /// Returns the number of channels and patterns subscribed to.
+    fn len(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+}
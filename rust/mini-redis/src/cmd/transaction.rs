@@ -0,0 +1,426 @@
+use crate::cmd::Command;
+use crate::{Connection, Db, Frame, Parse, ParseError};
+
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{debug, instrument};
+
+/// Start a transaction.
+///
+/// Subsequent commands are queued instead of being executed, until `EXEC`
+/// executes them all atomically or `DISCARD` drops them.
+#[derive(Debug, Default)]
+pub struct Multi;
+
+/// Execute the commands queued since `MULTI`.
+///
+/// The commands are executed while holding the database lock, so no other
+/// connection observes the key space in the middle of the transaction. If a
+/// key watched with `WATCH` was modified, nothing is executed and `EXEC`
+/// responds with a null reply.
+#[derive(Debug, Default)]
+pub struct Exec;
+
+/// Drop the commands queued since `MULTI` and end the transaction.
+#[derive(Debug, Default)]
+pub struct Discard;
+
+/// Watch keys for modifications.
+///
+/// The next `EXEC` on the connection fails if any of the watched keys was
+/// modified by another command in the meantime. All keys are unwatched once
+/// `EXEC` or `DISCARD` is called.
+#[derive(Debug)]
+pub struct Watch {
+    keys: Vec<String>,
+}
+
+/// Stop watching all keys watched on the connection.
+#[derive(Debug, Default)]
+pub struct Unwatch;
+
+/// Transaction state of a connection.
+///
+/// Tracks the commands queued since `MULTI` and the keys watched with `WATCH`.
+#[derive(Debug, Default)]
+pub(crate) struct Transaction {
+    /// Commands queued since `MULTI`, or `None` if no transaction is started.
+    queued: Option<Vec<Command>>,
+
+    /// Set when a command could not be queued. `EXEC` then discards the
+    /// transaction instead of executing it.
+    failed: bool,
+
+    /// Keys watched by the connection, by database number.
+    watched: HashMap<usize, Vec<String>>,
+
+    /// Set by the database when one of the watched keys is modified.
+    dirty: Arc<AtomicBool>,
+}
+
+impl Transaction {
+    /// Returns `true` if commands are being queued, between `MULTI` and `EXEC`.
+    pub(crate) fn is_queuing(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    /// Queue `cmd` to be executed by `EXEC`.
+    ///
+    /// Commands which cannot be executed as part of a transaction are
+    /// rejected, and cause `EXEC` to discard the transaction.
+    pub(crate) async fn queue(&mut self, cmd: Command, dst: &mut Connection) -> crate::Result<()> {
+        let response = if let Command::Unknown(cmd) = &cmd {
+            self.failed = true;
+            Frame::Error(format!("ERR unknown command '{}'", cmd.get_name()))
+        } else if !cmd.is_transactional() {
+            self.failed = true;
+            Frame::Error(format!(
+                "ERR '{}' is not allowed in a transaction",
+                cmd.get_name()
+            ))
+        } else {
+            // `is_queuing` was checked by the caller
+            if let Some(queued) = &mut self.queued {
+                queued.push(cmd);
+            }
+
+            Frame::Simple("QUEUED".to_string())
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+
+    /// Make `EXEC` discard the transaction, if one is started. Called when a
+    /// command is rejected instead of being queued.
+    pub(crate) fn abort(&mut self) {
+        if self.is_queuing() {
+            self.failed = true;
+        }
+    }
+
+    /// Stop watching keys and end the transaction, if any.
+    pub(crate) fn reset(&mut self, db: &Db) {
+        let dirty = &self.dirty;
+        for (index, keys) in self.watched.drain() {
+            if let Ok(db) = db.select(index) {
+                db.with_keys(&keys, |state| state.unwatch(&keys, dirty));
+            }
+        }
+
+        self.queued = None;
+        self.failed = false;
+        self.dirty.store(false, Ordering::Release);
+    }
+}
+
+impl Multi {
+    /// Create a new `Multi` command.
+    pub fn new() -> Multi {
+        Multi
+    }
+
+    /// Parse a `Multi` instance from a received frame.
+    ///
+    /// The `MULTI` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing a single entry.
+    ///
+    /// ```text
+    /// MULTI
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Multi> {
+        Ok(Multi)
+    }
+
+    /// Start queuing commands on the connection.
+    #[instrument(skip(self, transaction, dst))]
+    pub(crate) async fn apply(
+        self,
+        transaction: &mut Transaction,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        let response = if transaction.is_queuing() {
+            Frame::Error("ERR MULTI calls can not be nested".to_string())
+        } else {
+            transaction.queued = Some(vec![]);
+            Frame::Simple("OK".to_string())
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Multi` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("multi".as_bytes()));
+        frame
+    }
+}
+
+impl Exec {
+    /// Create a new `Exec` command.
+    pub fn new() -> Exec {
+        Exec
+    }
+
+    /// Parse an `Exec` instance from a received frame.
+    ///
+    /// The `EXEC` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing a single entry.
+    ///
+    /// ```text
+    /// EXEC
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Exec> {
+        Ok(Exec)
+    }
+
+    /// Execute the queued commands.
+    ///
+    /// Responds with an array holding the response of each command, or with a
+    /// null reply if a watched key was modified.
+    #[instrument(skip(self, db, transaction, dst))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        transaction: &mut Transaction,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        let response = match transaction.queued.take() {
+            None => Frame::Error("ERR EXEC without MULTI".to_string()),
+            Some(_) if transaction.failed => Frame::Error(
+                "EXECABORT Transaction discarded because of previous errors.".to_string(),
+            ),
+            Some(queued) => {
+                let is_write = queued.iter().any(Command::is_write);
+                let dirty = &transaction.dirty;
+
+                // The watched keys are checked and the commands executed
+                // without releasing the lock in between.
+                let response = db.with_state(|state| {
+                    if dirty.load(Ordering::Acquire) {
+                        return Frame::Null;
+                    }
+
+                    Frame::Array(queued.into_iter().map(|cmd| cmd.execute(state)).collect())
+                });
+
+                if is_write && matches!(response, Frame::Array(_)) {
+                    db.wait_for_aof().await;
+                }
+
+                response
+            }
+        };
+
+        transaction.reset(db);
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding an `Exec` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("exec".as_bytes()));
+        frame
+    }
+}
+
+impl Discard {
+    /// Create a new `Discard` command.
+    pub fn new() -> Discard {
+        Discard
+    }
+
+    /// Parse a `Discard` instance from a received frame.
+    ///
+    /// The `DISCARD` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing a single entry.
+    ///
+    /// ```text
+    /// DISCARD
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Discard> {
+        Ok(Discard)
+    }
+
+    /// Drop the queued commands and unwatch all keys.
+    #[instrument(skip(self, db, transaction, dst))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        transaction: &mut Transaction,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        let response = if transaction.is_queuing() {
+            transaction.reset(db);
+            Frame::Simple("OK".to_string())
+        } else {
+            Frame::Error("ERR DISCARD without MULTI".to_string())
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+}
+
+impl Watch {
+    /// Create a new `Watch` command which watches `keys`.
+    pub fn new(keys: Vec<String>) -> Watch {
+        Watch { keys }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parse a `Watch` instance from a received frame.
+    ///
+    /// The `WATCH` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least two entries.
+    ///
+    /// ```text
+    /// WATCH key [key ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Watch> {
+        let mut keys = vec![parse.next_string()?];
+
+        loop {
+            match parse.next_string() {
+                Ok(key) => keys.push(key),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(Watch { keys })
+    }
+
+    /// Register the keys with the database, so the transaction is aborted
+    /// when they are modified.
+    #[instrument(skip(self, db, transaction, dst))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        transaction: &mut Transaction,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        let response = if transaction.is_queuing() {
+            Frame::Error("ERR WATCH inside MULTI is not allowed".to_string())
+        } else {
+            db.with_keys(&self.keys, |state| {
+                state.watch(&self.keys, &transaction.dirty)
+            });
+            transaction
+                .watched
+                .entry(db.index())
+                .or_default()
+                .extend(self.keys);
+            Frame::Simple("OK".to_string())
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Watch` command to send to
+    /// the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("watch".as_bytes()));
+
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+        }
+
+        frame
+    }
+}
+
+impl Unwatch {
+    /// Create a new `Unwatch` command.
+    pub fn new() -> Unwatch {
+        Unwatch
+    }
+
+    /// Parse an `Unwatch` instance from a received frame.
+    ///
+    /// The `UNWATCH` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing a single entry.
+    ///
+    /// ```text
+    /// UNWATCH
+    /// ```
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Unwatch> {
+        Ok(Unwatch)
+    }
+
+    /// Stop watching all keys.
+    ///
+    /// When queued in a transaction, this does nothing as `EXEC` unwatches all
+    /// keys anyway.
+    #[instrument(skip(self, db, transaction, dst))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        transaction: &mut Transaction,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        transaction.reset(db);
+
+        let response = Frame::Simple("OK".to_string());
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding an `Unwatch` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("unwatch".as_bytes()));
+        frame
+    }
+}
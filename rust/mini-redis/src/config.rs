@@ -0,0 +1,820 @@
+//! Server configuration.
+//!
+//! `Config` holds the settings that control how `mini-redis` behaves. The
+//! server binary loads a `Config` from a configuration file, applies its
+//! command line arguments on top, and passes it to
+//! [`server::run_with_config`](crate::server::run_with_config).
+//!
+//! # Configuration file
+//!
+//! The file uses the format of `redis.conf`. Each line holds a directive
+//! followed by its arguments, separated by spaces. Arguments containing
+//! spaces are quoted with `"` or `'`. Empty lines and lines starting with `#`
+//! are ignored.
+//!
+//! ```text
+//! # Listen on all interfaces
+//! bind 0.0.0.0
+//! port 7000
+//! unixsocket /run/mini-redis.sock
+//! unixsocketperm 770
+//! maxclients 1000
+//! maxmemory 100mb
+//! maxmemory-policy allkeys-lru
+//! appendonly yes
+//! dbfilename "my dump.rdb"
+//! ```
+//!
+//! The directives are named after the fields of `Config`, with dashes instead
+//! of underscores. Every node of a cluster is listed with its own
+//! `cluster-node` directive. Memory sizes, such as `maxmemory`, are given in
+//! bytes or with a unit: `k`, `m` and `g` are powers of 1000, while `kb`, `mb`
+//! and `gb` are powers of 1024.
+
+use crate::cluster::ClusterNode;
+use crate::{glob, DEFAULT_PORT};
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::level_filters::LevelFilter;
+
+/// Settings for a `mini-redis` server.
+///
+/// The field names match the equivalent Redis configuration directives.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Address of the interface the server binary listens on.
+    pub bind: String,
+
+    /// Port the server listens on. The server binary only listens on
+    /// `unixsocket` when set to 0.
+    pub port: u16,
+
+    /// Path of the Unix socket the server listens on, in addition to the TCP
+    /// port, if any.
+    pub unixsocket: Option<PathBuf>,
+
+    /// Permissions of the Unix socket, such as `0o700`, or the permissions
+    /// given by the umask of the process if `None`.
+    pub unixsocketperm: Option<u32>,
+
+    /// Maximum number of clients connected at the same time. Once reached,
+    /// new connections wait until a client disconnects.
+    pub maxclients: usize,
+
+    /// Close the connection of a client idle for this long, or never if
+    /// `None`.
+    pub timeout: Option<Duration>,
+
+    /// Maximum number of bytes used by the key space, or no limit if `None`.
+    /// Once reached, keys are evicted according to `maxmemory_policy` before
+    /// executing commands that may use more memory.
+    pub maxmemory: Option<usize>,
+
+    /// How keys are chosen to be evicted once `maxmemory` is reached.
+    pub maxmemory_policy: EvictionPolicy,
+
+    /// Number of keys sampled in each database to find the best key to evict.
+    /// More samples make the eviction more accurate, but slower.
+    pub maxmemory_samples: usize,
+
+    /// Minimum level of the messages logged by the server binary.
+    pub loglevel: LogLevel,
+
+    /// When `true`, every command that modifies the key space is appended to
+    /// the append-only file. The file is replayed when the server starts.
+    pub appendonly: bool,
+
+    /// Number of logical databases, selected with `SELECT`. Must be at least
+    /// one.
+    pub databases: usize,
+
+    /// Number of shards the key space is split into. Each shard is locked
+    /// independently, so commands on keys of different shards run in
+    /// parallel. Must be at least one.
+    pub shards: usize,
+
+    /// Path of the append-only file.
+    pub appendfilename: PathBuf,
+
+    /// How often the append-only file is flushed to disk.
+    pub appendfsync: FsyncPolicy,
+
+    /// Path of the snapshot file written by `SAVE` and `BGSAVE`. When the
+    /// append-only file is disabled, the snapshot is loaded on startup if it
+    /// exists.
+    pub dbfilename: PathBuf,
+
+    /// Maximum time a Lua script run by `EVAL` or `EVALSHA` may take. Scripts
+    /// running for longer are aborted with an error.
+    pub lua_time_limit: Duration,
+
+    /// Host and port of the master to follow on startup. When set, the server
+    /// starts as a replica.
+    pub replicaof: Option<(String, u16)>,
+
+    /// When `true`, a replica rejects commands modifying the key space, which
+    /// it only receives from its master.
+    pub replica_read_only: bool,
+
+    /// When `true`, the server runs as a node of a cluster. Commands
+    /// accessing keys in hash slots served by another node are rejected with
+    /// a redirect to that node.
+    pub cluster_enabled: bool,
+
+    /// Nodes of the cluster and the hash slots they serve. The node whose
+    /// address is the one the server listens on is this server.
+    pub cluster_nodes: Vec<ClusterNode>,
+
+    /// Path of the PEM file holding the certificate chain of the server. When
+    /// set along with `tls_key_file`, the server only accepts TLS
+    /// connections.
+    pub tls_cert_file: Option<PathBuf>,
+
+    /// Path of the PEM file holding the private key of `tls_cert_file`.
+    pub tls_key_file: Option<PathBuf>,
+
+    /// Path of the PEM file holding the certificate authorities trusted to
+    /// issue the certificates of clients, and of the master when
+    /// `tls_replication` is set.
+    pub tls_ca_cert_file: Option<PathBuf>,
+
+    /// When `true`, clients must present a certificate issued by one of the
+    /// authorities of `tls_ca_cert_file` to connect.
+    pub tls_auth_clients: bool,
+
+    /// When `true`, a replica connects to its master using TLS, presenting
+    /// `tls_cert_file` as its client certificate.
+    pub tls_replication: bool,
+
+    /// Path of the configuration file the settings were loaded from, which
+    /// `CONFIG REWRITE` updates.
+    pub config_file: Option<PathBuf>,
+}
+
+/// Controls how often the append-only file is flushed to disk using `fsync`.
+///
+/// Writes are always handed to the operating system as soon as possible.
+/// `fsync` ensures the operating system has written them to the disk, which is
+/// what protects the data against power loss.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FsyncPolicy {
+    /// `fsync` after every write. A client receives the response to a command
+    /// only once the command is on disk. This is the slowest, but safest
+    /// option.
+    Always,
+
+    /// `fsync` once per second. Up to one second of writes may be lost.
+    #[default]
+    EverySec,
+
+    /// Never `fsync`, and let the operating system decide when to write the
+    /// data to disk.
+    No,
+}
+
+/// Chooses the keys to evict once the memory limit is reached.
+///
+/// Like Redis, the policies are approximate: a few keys are sampled at random
+/// and the best candidate among them is evicted, instead of keeping all keys
+/// ordered by access time or frequency. The `volatile` policies only evict
+/// keys with an expiration, the `allkeys` policies evict any key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// Never evict keys. Commands that may use more memory fail with an `OOM`
+    /// error instead.
+    #[default]
+    NoEviction,
+
+    /// Evict the least recently used key.
+    AllKeysLru,
+
+    /// Evict the least recently used key with an expiration.
+    VolatileLru,
+
+    /// Evict the least frequently used key.
+    AllKeysLfu,
+
+    /// Evict the least frequently used key with an expiration.
+    VolatileLfu,
+
+    /// Evict a random key.
+    AllKeysRandom,
+
+    /// Evict a random key with an expiration.
+    VolatileRandom,
+
+    /// Evict the key with an expiration which expires first.
+    VolatileTtl,
+}
+
+/// Minimum level of the messages logged by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogLevel {
+    /// Everything.
+    Debug,
+
+    /// Details useful when investigating an issue, such as every command
+    /// received.
+    Verbose,
+
+    /// Significant events, such as loading a file or a replica connecting.
+    #[default]
+    Notice,
+
+    /// Only warnings and errors.
+    Warning,
+}
+
+/// Directives supported in the configuration file and by `CONFIG GET`, in the
+/// order `CONFIG REWRITE` appends them.
+const PARAMETERS: &[&str] = &[
+    "bind",
+    "port",
+    "unixsocket",
+    "unixsocketperm",
+    "maxclients",
+    "timeout",
+    "maxmemory",
+    "maxmemory-policy",
+    "maxmemory-samples",
+    "loglevel",
+    "databases",
+    "shards",
+    "appendonly",
+    "appendfilename",
+    "appendfsync",
+    "dbfilename",
+    "lua-time-limit",
+    "replicaof",
+    "replica-read-only",
+    "cluster-enabled",
+    "cluster-node",
+    "tls-cert-file",
+    "tls-key-file",
+    "tls-ca-cert-file",
+    "tls-auth-clients",
+    "tls-replication",
+];
+
+/// Directives `CONFIG SET` can change while the server is running. The others
+/// are only read on startup.
+const MUTABLE: &[&str] = &[
+    "maxclients",
+    "timeout",
+    "maxmemory",
+    "maxmemory-policy",
+    "maxmemory-samples",
+    "dbfilename",
+    "lua-time-limit",
+    "replica-read-only",
+];
+
+impl Config {
+    /// Load the configuration file at `path`. Directives missing from the
+    /// file keep their default value.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the file cannot be read, or if it contains an unknown
+    /// directive or an invalid value.
+    pub fn load(path: impl AsRef<Path>) -> crate::Result<Config> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read config file {:?}: {}", path, err))?;
+
+        let mut config = Config {
+            config_file: Some(path.to_path_buf()),
+            ..Config::default()
+        };
+        let mut cluster_nodes = vec![];
+
+        for (i, line) in contents.lines().enumerate() {
+            let invalid = |err| format!("invalid config file {:?}, line {}: {}", path, i + 1, err);
+
+            let mut args = split_line(line).map_err(invalid)?.into_iter();
+            let name = match args.next() {
+                Some(name) => name.to_lowercase(),
+                None => continue,
+            };
+            let value = args.collect::<Vec<_>>().join(" ");
+
+            // Each node of the cluster is usually listed on its own line
+            if name == "cluster-node" {
+                cluster_nodes.push(value);
+                continue;
+            }
+
+            config
+                .set(&name, &value)
+                .map_err(|err| invalid(err.to_string()))?;
+        }
+
+        if !cluster_nodes.is_empty() {
+            config.set("cluster-node", &cluster_nodes.join(" "))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Returns the value of the directive `name` as it appears in the
+    /// configuration file, or `None` if there is no such directive.
+    pub fn get(&self, name: &str) -> Option<String> {
+        let value = match &name.to_lowercase()[..] {
+            "bind" => self.bind.clone(),
+            "port" => self.port.to_string(),
+            "unixsocket" => display_path(&self.unixsocket),
+            "unixsocketperm" => format!("{:o}", self.unixsocketperm.unwrap_or(0)),
+            "maxclients" => self.maxclients.to_string(),
+            "timeout" => self
+                .timeout
+                .map_or(0, |timeout| timeout.as_secs())
+                .to_string(),
+            "maxmemory" => self.maxmemory.unwrap_or(0).to_string(),
+            "maxmemory-policy" => self.maxmemory_policy.to_string(),
+            "maxmemory-samples" => self.maxmemory_samples.to_string(),
+            "loglevel" => self.loglevel.to_string(),
+            "databases" => self.databases.to_string(),
+            "shards" => self.shards.to_string(),
+            "appendonly" => yes_no(self.appendonly),
+            "appendfilename" => self.appendfilename.display().to_string(),
+            "appendfsync" => self.appendfsync.to_string(),
+            "dbfilename" => self.dbfilename.display().to_string(),
+            "lua-time-limit" => self.lua_time_limit.as_millis().to_string(),
+            "replicaof" => match &self.replicaof {
+                Some((host, port)) => format!("{} {}", host, port),
+                None => String::new(),
+            },
+            "replica-read-only" => yes_no(self.replica_read_only),
+            "cluster-enabled" => yes_no(self.cluster_enabled),
+            "cluster-node" => self
+                .cluster_nodes
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" "),
+            "tls-cert-file" => display_path(&self.tls_cert_file),
+            "tls-key-file" => display_path(&self.tls_key_file),
+            "tls-ca-cert-file" => display_path(&self.tls_ca_cert_file),
+            "tls-auth-clients" => yes_no(self.tls_auth_clients),
+            "tls-replication" => yes_no(self.tls_replication),
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    /// Returns the name and value of every directive matching the glob-style
+    /// `pattern`, as `CONFIG GET` does.
+    pub fn matching(&self, pattern: &str) -> Vec<(String, String)> {
+        let pattern = pattern.to_lowercase();
+
+        PARAMETERS
+            .iter()
+            .filter(|name| glob::matches(pattern.as_bytes(), name.as_bytes()))
+            .map(|name| (name.to_string(), self.get(name).unwrap()))
+            .collect()
+    }
+
+    /// Set the directive `name` from its value in the configuration file.
+    /// Directives taking several arguments, such as `replicaof`, take them
+    /// separated by spaces.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if there is no such directive or the value is invalid.
+    /// The configuration is left unchanged in that case.
+    pub fn set(&mut self, name: &str, value: &str) -> crate::Result<()> {
+        match &name.to_lowercase()[..] {
+            "bind" => self.bind = value.to_string(),
+            "port" => self.port = parse_number(value)?,
+            "unixsocket" => self.unixsocket = parse_path(value),
+            "unixsocketperm" => match u32::from_str_radix(value, 8) {
+                Ok(0) => self.unixsocketperm = None,
+                Ok(perm) if perm <= 0o777 => self.unixsocketperm = Some(perm),
+                _ => return Err(format!("invalid permissions '{}'", value).into()),
+            },
+            "maxclients" => match parse_number(value)? {
+                0 => return Err("maxclients must be at least 1".into()),
+                maxclients => self.maxclients = maxclients,
+            },
+            "timeout" => match parse_number(value)? {
+                0 => self.timeout = None,
+                secs => self.timeout = Some(Duration::from_secs(secs)),
+            },
+            "maxmemory" => match parse_memory(value)? {
+                0 => self.maxmemory = None,
+                bytes => self.maxmemory = Some(bytes),
+            },
+            "maxmemory-policy" => self.maxmemory_policy = value.parse()?,
+            "maxmemory-samples" => match parse_number(value)? {
+                0 => return Err("maxmemory-samples must be at least 1".into()),
+                samples => self.maxmemory_samples = samples,
+            },
+            "loglevel" => self.loglevel = value.parse()?,
+            "databases" => match parse_number(value)? {
+                0 => return Err("databases must be at least 1".into()),
+                databases => self.databases = databases,
+            },
+            "shards" => match parse_number(value)? {
+                0 => return Err("shards must be at least 1".into()),
+                shards => self.shards = shards,
+            },
+            "appendonly" => self.appendonly = parse_yes_no(value)?,
+            "appendfilename" => self.appendfilename = PathBuf::from(value),
+            "appendfsync" => self.appendfsync = value.parse()?,
+            "dbfilename" => self.dbfilename = PathBuf::from(value),
+            "lua-time-limit" => self.lua_time_limit = Duration::from_millis(parse_number(value)?),
+            "replicaof" => {
+                let args = value.split_whitespace().collect::<Vec<_>>();
+                self.replicaof = match &args[..] {
+                    [] => None,
+                    [host, port]
+                        if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") =>
+                    {
+                        None
+                    }
+                    [host, port] => Some((host.to_string(), parse_number(port)?)),
+                    _ => return Err("replicaof expects a host and a port".into()),
+                };
+            }
+            "replica-read-only" => self.replica_read_only = parse_yes_no(value)?,
+            "cluster-enabled" => self.cluster_enabled = parse_yes_no(value)?,
+            "cluster-node" => {
+                self.cluster_nodes = value
+                    .split_whitespace()
+                    .map(str::parse)
+                    .collect::<crate::Result<_>>()?;
+            }
+            "tls-cert-file" => self.tls_cert_file = parse_path(value),
+            "tls-key-file" => self.tls_key_file = parse_path(value),
+            "tls-ca-cert-file" => self.tls_ca_cert_file = parse_path(value),
+            "tls-auth-clients" => self.tls_auth_clients = parse_yes_no(value)?,
+            "tls-replication" => self.tls_replication = parse_yes_no(value)?,
+            _ => return Err(format!("unknown directive '{}'", name).into()),
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if `CONFIG SET` can change the directive `name` while
+    /// the server is running.
+    pub fn is_mutable(name: &str) -> bool {
+        MUTABLE.contains(&&name.to_lowercase()[..])
+    }
+
+    /// Write the current settings to the configuration file the settings were
+    /// loaded from, as `CONFIG REWRITE` does.
+    ///
+    /// Comments and the order of the directives are kept. Each directive
+    /// present in the file is updated in place, and the directives missing
+    /// from the file are appended if they differ from their default value.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the settings were not loaded from a file, or if the
+    /// file cannot be written.
+    pub fn rewrite(&self) -> crate::Result<()> {
+        let path = match &self.config_file {
+            Some(path) => path,
+            None => return Err("The server is running without a config file".into()),
+        };
+
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut lines = vec![];
+        let mut written = vec![];
+
+        for line in contents.lines() {
+            let name = match split_line(line) {
+                Ok(args) if !args.is_empty() => args[0].to_lowercase(),
+                _ => {
+                    lines.push(line.to_string());
+                    continue;
+                }
+            };
+
+            if !PARAMETERS.contains(&&name[..]) {
+                lines.push(line.to_string());
+                continue;
+            }
+
+            // The first occurrence of a directive holds the value, any other
+            // one is removed
+            if !written.contains(&name) {
+                lines.extend(self.directive(&name));
+                written.push(name);
+            }
+        }
+
+        let default = Config::default();
+
+        for name in PARAMETERS {
+            if !written.iter().any(|written| written == name) && self.get(name) != default.get(name)
+            {
+                lines.extend(self.directive(name));
+            }
+        }
+
+        let tmp = path.with_extension("conf.tmp");
+        fs::write(&tmp, lines.join("\n") + "\n")?;
+        fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+
+    /// Returns the line setting the directive `name` in the configuration
+    /// file, or `None` if the directive has no value, such as `replicaof` on
+    /// a master.
+    fn directive(&self, name: &str) -> Option<String> {
+        let value = self.get(name)?;
+
+        match name {
+            // The arguments are written as separate words
+            "replicaof" | "cluster-node" if value.is_empty() => None,
+            "replicaof" | "cluster-node" => Some(format!("{} {}", name, value)),
+            _ => Some(format!("{} {}", name, quote(&value))),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            bind: "127.0.0.1".to_string(),
+            port: DEFAULT_PORT,
+            unixsocket: None,
+            unixsocketperm: None,
+            maxclients: 250,
+            timeout: None,
+            maxmemory: None,
+            maxmemory_policy: EvictionPolicy::default(),
+            maxmemory_samples: 5,
+            loglevel: LogLevel::default(),
+            appendonly: false,
+            databases: 16,
+            shards: 16,
+            appendfilename: PathBuf::from("appendonly.aof"),
+            appendfsync: FsyncPolicy::default(),
+            dbfilename: PathBuf::from("dump.rdb"),
+            lua_time_limit: Duration::from_secs(5),
+            replicaof: None,
+            replica_read_only: true,
+            cluster_enabled: false,
+            cluster_nodes: vec![],
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_ca_cert_file: None,
+            tls_auth_clients: false,
+            tls_replication: false,
+            config_file: None,
+        }
+    }
+}
+
+impl FromStr for FsyncPolicy {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<FsyncPolicy> {
+        match &s.to_lowercase()[..] {
+            "always" => Ok(FsyncPolicy::Always),
+            "everysec" => Ok(FsyncPolicy::EverySec),
+            "no" => Ok(FsyncPolicy::No),
+            _ => Err(format!("invalid fsync policy '{}'", s).into()),
+        }
+    }
+}
+
+impl fmt::Display for FsyncPolicy {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FsyncPolicy::Always => "always".fmt(fmt),
+            FsyncPolicy::EverySec => "everysec".fmt(fmt),
+            FsyncPolicy::No => "no".fmt(fmt),
+        }
+    }
+}
+
+impl EvictionPolicy {
+    /// Returns `true` if the policy only evicts keys with an expiration.
+    pub fn is_volatile(self) -> bool {
+        matches!(
+            self,
+            EvictionPolicy::VolatileLru
+                | EvictionPolicy::VolatileLfu
+                | EvictionPolicy::VolatileRandom
+                | EvictionPolicy::VolatileTtl
+        )
+    }
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<EvictionPolicy> {
+        match &s.to_lowercase()[..] {
+            "noeviction" => Ok(EvictionPolicy::NoEviction),
+            "allkeys-lru" => Ok(EvictionPolicy::AllKeysLru),
+            "volatile-lru" => Ok(EvictionPolicy::VolatileLru),
+            "allkeys-lfu" => Ok(EvictionPolicy::AllKeysLfu),
+            "volatile-lfu" => Ok(EvictionPolicy::VolatileLfu),
+            "allkeys-random" => Ok(EvictionPolicy::AllKeysRandom),
+            "volatile-random" => Ok(EvictionPolicy::VolatileRandom),
+            "volatile-ttl" => Ok(EvictionPolicy::VolatileTtl),
+            _ => Err(format!("invalid maxmemory policy '{}'", s).into()),
+        }
+    }
+}
+
+impl fmt::Display for EvictionPolicy {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvictionPolicy::NoEviction => "noeviction".fmt(fmt),
+            EvictionPolicy::AllKeysLru => "allkeys-lru".fmt(fmt),
+            EvictionPolicy::VolatileLru => "volatile-lru".fmt(fmt),
+            EvictionPolicy::AllKeysLfu => "allkeys-lfu".fmt(fmt),
+            EvictionPolicy::VolatileLfu => "volatile-lfu".fmt(fmt),
+            EvictionPolicy::AllKeysRandom => "allkeys-random".fmt(fmt),
+            EvictionPolicy::VolatileRandom => "volatile-random".fmt(fmt),
+            EvictionPolicy::VolatileTtl => "volatile-ttl".fmt(fmt),
+        }
+    }
+}
+
+impl LogLevel {
+    /// Returns the most verbose `tracing` level logged at this log level.
+    pub fn level_filter(self) -> LevelFilter {
+        match self {
+            LogLevel::Debug => LevelFilter::TRACE,
+            LogLevel::Verbose => LevelFilter::DEBUG,
+            LogLevel::Notice => LevelFilter::INFO,
+            LogLevel::Warning => LevelFilter::WARN,
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<LogLevel> {
+        match &s.to_lowercase()[..] {
+            "debug" => Ok(LogLevel::Debug),
+            "verbose" => Ok(LogLevel::Verbose),
+            "notice" => Ok(LogLevel::Notice),
+            "warning" => Ok(LogLevel::Warning),
+            _ => Err(format!("invalid log level '{}'", s).into()),
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogLevel::Debug => "debug".fmt(fmt),
+            LogLevel::Verbose => "verbose".fmt(fmt),
+            LogLevel::Notice => "notice".fmt(fmt),
+            LogLevel::Warning => "warning".fmt(fmt),
+        }
+    }
+}
+
+/// Split a line of the configuration file into its arguments.
+///
+/// Arguments are separated by spaces, unless quoted with `"` or `'`. Within
+/// double quotes, `\` escapes the next character. The rest of the line is
+/// ignored after a `#` starting an argument.
+fn split_line(line: &str) -> Result<Vec<String>, String> {
+    let mut args = vec![];
+    let mut chars = line.trim().chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let quote = match chars.peek() {
+            None | Some('#') => return Ok(args),
+            Some(&c) if c == '"' || c == '\'' => chars.next(),
+            Some(_) => None,
+        };
+
+        let mut arg = String::new();
+
+        loop {
+            match (chars.next(), quote) {
+                (None, None) => break,
+                (None, Some(_)) => return Err("unbalanced quotes".to_string()),
+                (Some(c), Some(quote)) if c == quote => {
+                    // The closing quote must end the argument
+                    if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                        return Err("closing quote must be followed by a space".to_string());
+                    }
+                    break;
+                }
+                (Some('\\'), Some('"')) => match chars.next() {
+                    Some('n') => arg.push('\n'),
+                    Some('t') => arg.push('\t'),
+                    Some(c) => arg.push(c),
+                    None => return Err("unbalanced quotes".to_string()),
+                },
+                (Some(c), None) if c.is_whitespace() => break,
+                (Some(c), _) => arg.push(c),
+            }
+        }
+
+        args.push(arg);
+    }
+}
+
+/// Quote `value` if needed for it to be read back as a single argument by
+/// `split_line`.
+fn quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && !value.starts_with('#')
+        && !value
+            .chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == '\'' || c == '\\');
+
+    if plain {
+        return value.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parse a numeric directive.
+fn parse_number<T: FromStr>(value: &str) -> crate::Result<T> {
+    value
+        .parse()
+        .map_err(|_| format!("argument couldn't be parsed into an integer: '{}'", value).into())
+}
+
+/// Parse a memory size, in bytes or with a unit such as `100mb`.
+fn parse_memory(value: &str) -> crate::Result<usize> {
+    let lower = value.to_lowercase();
+    let split = lower
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(lower.len());
+    let (number, unit) = lower.split_at(split);
+
+    let multiplier: usize = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return Err(format!("invalid memory size '{}'", value).into()),
+    };
+
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid memory size '{}'", value).into())
+}
+
+/// Parse a boolean directive, written `yes` or `no`.
+fn parse_yes_no(value: &str) -> crate::Result<bool> {
+    match &value.to_lowercase()[..] {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err(format!("argument must be 'yes' or 'no': '{}'", value).into()),
+    }
+}
+
+/// Parse an optional path, which is unset when empty.
+fn parse_path(value: &str) -> Option<PathBuf> {
+    Some(PathBuf::from(value)).filter(|_| !value.is_empty())
+}
+
+/// Format an optional path, written empty when unset.
+fn display_path(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map_or_else(String::new, |path| path.display().to_string())
+}
+
+/// Format a boolean directive.
+fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_string()
+}
@@ -1,15 +1,16 @@
-use crate::frame::{self, Frame};
+use crate::frame::{self, Frame, Protocol};
 
 use bytes::{Buf, BytesMut};
+use std::fmt;
 use std::io::{self, Cursor};
-use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
 
 /// Send and receive `Frame` values from a remote peer.
 ///
 /// When implementing networking protocols, a message on that protocol is
 /// often composed of several smaller messages known as frames. The purpose of
-/// `Connection` is to read and write frames on the underlying `TcpStream`.
+/// `Connection` is to read and write frames on the underlying stream, such as a
+/// `TcpStream` or a TLS stream wrapping one.
 ///
 /// To read frames, the `Connection` uses an internal buffer, which is filled
 /// up until there are enough bytes to create a full frame. Once this happens,
@@ -17,31 +18,59 @@ use tokio::net::TcpStream;
 ///
 /// When sending frames, the frame is first encoded into the write buffer.
 /// The contents of the write buffer are then written to the socket.
+///
+/// The server and the clients accept both plaintext and TLS connections, so
+/// they use the default stream type, which boxes any [`AsyncStream`].
 #[derive(Debug)]
-pub struct Connection {
-    // The `TcpStream`. It is decorated with a `BufWriter`, which provides write
-    // level buffering. The `BufWriter` implementation provided by Tokio is
-    // sufficient for our needs.
-    stream: BufWriter<TcpStream>,
+pub struct Connection<S = Box<dyn AsyncStream>> {
+    // The underlying stream. It is decorated with a `BufWriter`, which provides
+    // write level buffering. The `BufWriter` implementation provided by Tokio
+    // is sufficient for our needs.
+    stream: BufWriter<S>,
 
     // The buffer for reading frames.
     buffer: BytesMut,
+
+    // The protocol version negotiated with the peer. This only affects how
+    // frames are written. Frames of either version are always accepted when
+    // reading.
+    protocol: Protocol,
 }
 
-impl Connection {
-    /// Create a new `Connection`, backed by `socket`. Read and write buffers
+/// A stream frames can be read from and written to by a [`Connection`].
+///
+/// Implemented for all the streams that can be sent across tasks, such as
+/// `TcpStream` and the TLS streams of `tokio-rustls`.
+pub trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send + fmt::Debug {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send + fmt::Debug> AsyncStream for S {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    /// Create a new `Connection`, backed by `stream`. Read and write buffers
     /// are initialized.
-    pub fn new(socket: TcpStream) -> Connection {
+    pub fn new(stream: S) -> Connection<S> {
         Connection {
-            stream: BufWriter::new(socket),
+            stream: BufWriter::new(stream),
             // Default to a 4KB read buffer. For the use case of mini redis,
             // this is fine. However, real applications will want to tune this
             // value to their specific use case. There is a high likelihood that
             // a larger read buffer will work better.
             buffer: BytesMut::with_capacity(4 * 1024),
+            protocol: Protocol::Resp2,
         }
     }
 
+    /// Returns the protocol version used when writing frames.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Switch the protocol version used when writing frames. This is done
+    /// once the peer has agreed on a new version using `HELLO`.
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
     /// Read a single `Frame` value from the underlying stream.
     ///
     /// The function waits until it has retrieved enough data to parse a frame.
@@ -50,9 +79,9 @@ impl Connection {
     ///
     /// # Returns
     ///
-    /// On success, the received frame is returned. If the `TcpStream`
-    /// is closed in a way that doesn't break a frame in half, it returns
-    /// `None`. Otherwise, an error is returned.
+    /// On success, the received frame is returned. If the stream is closed in
+    /// a way that doesn't break a frame in half, it returns `None`. Otherwise,
+    /// an error is returned.
     pub async fn read_frame(&mut self) -> crate::Result<Option<Frame>> {
         loop {
             // Attempt to parse a frame from the buffered data. If enough data
@@ -153,26 +182,13 @@ impl Connection {
     /// syscalls. However, it is fine to call these functions on a *buffered*
     /// write stream. The data will be written to the buffer. Once the buffer is
     /// full, it is flushed to the underlying socket.
+    ///
+    /// RESP3 frames written to a connection speaking RESP2 are downgraded to
+    /// the closest RESP2 representation, the same way Redis does it. For
+    /// example, a map is written as a flat array of alternating keys and
+    /// values.
     pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
-        // Arrays are encoded by encoding each entry. All other frame types are
-        // considered literals. For now, mini-redis is not able to encode
-        // recursive frame structures. See below for more details.
-        match frame {
-            Frame::Array(val) => {
-                // Encode the frame type prefix. For an array, it is `*`.
-                self.stream.write_u8(b'*').await?;
-
-                // Encode the length of the array.
-                self.write_decimal(val.len() as u64).await?;
-
-                // Iterate and encode each entry in the array.
-                for entry in &**val {
-                    self.write_value(entry).await?;
-                }
-            }
-            // The frame type is a literal. Encode the value directly.
-            _ => self.write_value(frame).await?,
-        }
+        self.write_value(frame).await?;
 
         // Ensure the encoded frame is written to the socket. The calls above
         // are to the buffered stream and writes. Calling `flush` writes the
@@ -180,8 +196,22 @@ impl Connection {
         self.stream.flush().await
     }
 
-    /// Write a frame literal to the stream
+    /// Write several frames to the underlying stream.
+    ///
+    /// The frames are buffered and flushed together, so a batch of small
+    /// frames is sent with as few writes to the socket as possible.
+    pub async fn write_frames(&mut self, frames: &[Frame]) -> io::Result<()> {
+        for frame in frames {
+            self.write_value(frame).await?;
+        }
+
+        self.stream.flush().await
+    }
+
+    /// Write a frame to the stream, without flushing.
     async fn write_value(&mut self, frame: &Frame) -> io::Result<()> {
+        let resp3 = self.protocol == Protocol::Resp3;
+
         match frame {
             Frame::Simple(val) => {
                 self.stream.write_u8(b'+').await?;
@@ -197,29 +227,117 @@ impl Connection {
                 self.stream.write_u8(b':').await?;
                 self.write_decimal(*val).await?;
             }
+            Frame::Null if resp3 => {
+                self.stream.write_all(b"_\r\n").await?;
+            }
             Frame::Null => {
                 self.stream.write_all(b"$-1\r\n").await?;
             }
             Frame::Bulk(val) => {
-                let len = val.len();
-
-                self.stream.write_u8(b'$').await?;
-                self.write_decimal(len as u64).await?;
-                self.stream.write_all(val).await?;
+                self.write_bulk(b'$', val).await?;
+            }
+            Frame::Array(val) => {
+                self.write_aggregate(b'*', val).await?;
+            }
+            Frame::Boolean(val) if resp3 => {
+                let encoded: &[u8] = if *val { b"#t\r\n" } else { b"#f\r\n" };
+                self.stream.write_all(encoded).await?;
+            }
+            Frame::Boolean(val) => {
+                // RESP2 represents booleans as the integers 0 and 1.
+                self.stream.write_u8(b':').await?;
+                self.write_decimal(*val as i64).await?;
+            }
+            Frame::Double(val) if resp3 => {
+                self.stream.write_u8(b',').await?;
+                self.stream
+                    .write_all(frame::format_double(*val).as_bytes())
+                    .await?;
+                self.stream.write_all(b"\r\n").await?;
+            }
+            Frame::Double(val) => {
+                let formatted = frame::format_double(*val);
+                self.write_bulk(b'$', formatted.as_bytes()).await?;
+            }
+            Frame::BigNumber(val) if resp3 => {
+                self.stream.write_u8(b'(').await?;
+                self.stream.write_all(val.as_bytes()).await?;
                 self.stream.write_all(b"\r\n").await?;
             }
-            // Encoding an `Array` from within a value cannot be done using a
-            // recursive strategy. In general, async fns do not support
-            // recursion. Mini-redis has not needed to encode nested arrays yet,
-            // so for now it is skipped.
-            Frame::Array(_val) => unreachable!(),
+            Frame::BigNumber(val) => {
+                self.write_bulk(b'$', val.as_bytes()).await?;
+            }
+            Frame::Verbatim(format, val) if resp3 => {
+                let mut data = Vec::with_capacity(format.len() + 1 + val.len());
+                data.extend_from_slice(format.as_bytes());
+                data.push(b':');
+                data.extend_from_slice(val);
+
+                self.write_bulk(b'=', &data).await?;
+            }
+            Frame::Verbatim(_, val) => {
+                self.write_bulk(b'$', val).await?;
+            }
+            Frame::Map(entries) => {
+                // In RESP3, the length of a map is the number of entries. In
+                // RESP2 the map is flattened into an array, so the length is
+                // the number of keys plus the number of values.
+                if resp3 {
+                    self.stream.write_u8(b'%').await?;
+                    self.write_decimal(entries.len() as i64).await?;
+                } else {
+                    self.stream.write_u8(b'*').await?;
+                    self.write_decimal(2 * entries.len() as i64).await?;
+                }
+
+                for (key, value) in entries {
+                    Box::pin(self.write_value(key)).await?;
+                    Box::pin(self.write_value(value)).await?;
+                }
+            }
+            Frame::Set(val) => {
+                self.write_aggregate(if resp3 { b'~' } else { b'*' }, val)
+                    .await?;
+            }
+            Frame::Push(val) => {
+                self.write_aggregate(if resp3 { b'>' } else { b'*' }, val)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write an aggregate frame, such as an array, to the stream.
+    ///
+    /// Aggregates are encoded as a type prefix and a length, followed by each
+    /// entry. Entries may be aggregates themselves. In general, async fns do
+    /// not support recursion, so the recursive call is boxed.
+    async fn write_aggregate(&mut self, prefix: u8, entries: &[Frame]) -> io::Result<()> {
+        // Encode the frame type prefix and the number of entries.
+        self.stream.write_u8(prefix).await?;
+        self.write_decimal(entries.len() as i64).await?;
+
+        // Iterate and encode each entry.
+        for entry in entries {
+            Box::pin(self.write_value(entry)).await?;
         }
 
         Ok(())
     }
 
+    /// Write a length prefixed blob to the stream
+    async fn write_bulk(&mut self, prefix: u8, val: &[u8]) -> io::Result<()> {
+        self.stream.write_u8(prefix).await?;
+        self.write_decimal(val.len() as i64).await?;
+        self.stream.write_all(val).await?;
+        self.stream.write_all(b"\r\n").await?;
+
+        Ok(())
+    }
+
     /// Write a decimal frame to the stream
-    async fn write_decimal(&mut self, val: u64) -> io::Result<()> {
+    async fn write_decimal(&mut self, val: i64) -> io::Result<()> {
         use std::io::Write;
 
         // Convert the value to a string
@@ -1,10 +1,47 @@
-use tokio::sync::{broadcast, Notify};
+use tokio::sync::{broadcast, Notify, Semaphore};
+use tokio::task::JoinHandle;
 use tokio::time::{self, Duration, Instant};
+use tokio_rustls::rustls::ClientConfig;
 
 use bytes::Bytes;
-use std::collections::{BTreeSet, HashMap};
-use std::sync::{Arc, Mutex};
-use tracing::debug;
+use indexmap::IndexMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info};
+
+use crate::cluster::{self, Topology};
+use crate::cmd::{Del, ExpireCondition, Select};
+use crate::config::{EvictionPolicy, FsyncPolicy};
+use crate::{glob, replication, scripting, tls, Config, Frame};
+
+pub(crate) mod aof;
+use aof::Aof;
+
+mod eviction;
+use eviction::Access;
+
+mod rdb;
+
+mod sorted_set;
+pub(crate) use sorted_set::ScoreBound;
+use sorted_set::SortedSet;
+
+mod stream;
+use stream::Stream;
+pub(crate) use stream::{
+    ClaimOptions, Group, GroupReadId, NewId, StreamEntry, StreamId, StreamRead,
+};
+
+/// Number of commands buffered for replicas. A replica falling further behind
+/// is disconnected, and performs a full synchronization when it reconnects.
+const REPLICATION_BACKLOG: usize = 16 * 1024;
 
 /// A wrapper around a `Db` instance. This exists to allow orderly cleanup
 /// of the `Db` by signalling the background purge task to shut down when
@@ -18,12 +55,16 @@ pub(crate) struct DbDropGuard {
 
 /// Server state shared across all connections.
 ///
-/// `Db` contains a `HashMap` storing the key/value data and all
-/// `broadcast::Sender` values for active pub/sub channels.
+/// `Db` contains the key/value data, split into independently locked shards,
+/// and all `broadcast::Sender` values for active pub/sub channels.
 ///
 /// A `Db` instance is a handle to shared state. Cloning `Db` is shallow and
 /// only incurs an atomic ref count increment.
 ///
+/// The key space is split into numbered logical databases. Each handle
+/// operates on one of them, as selected by `SELECT`. The pub/sub channels are
+/// shared by all databases.
+///
 /// When a `Db` value is created, a background task is spawned. This task is
 /// used to expire values after the requested duration has elapsed. The task
 /// runs until all instances of `Db` are dropped, at which point the task
@@ -33,14 +74,20 @@ pub(crate) struct Db {
     /// Handle to shared state. The background task will also have an
     /// `Arc<Shared>`.
     shared: Arc<Shared>,
+
+    /// Number of the database this handle operates on.
+    index: usize,
 }
 
 #[derive(Debug)]
 struct Shared {
-    /// The shared state is guarded by a mutex. This is a `std::sync::Mutex` and
-    /// not a Tokio mutex. This is because there are no asynchronous operations
-    /// being performed while holding the mutex. Additionally, the critical
-    /// sections are very small.
+    /// The key space, split into shards by the hash of the keys. Each shard is
+    /// guarded by its own mutex, so commands operating on keys of different
+    /// shards do not wait for each other.
+    ///
+    /// These are `std::sync::Mutex` and not Tokio mutexes. This is because
+    /// there are no asynchronous operations being performed while holding a
+    /// mutex. Additionally, the critical sections are very small.
     ///
     /// A Tokio mutex is mostly intended to be used when locks need to be held
     /// across `.await` yield points. All other cases are **usually** best
@@ -49,7 +96,30 @@ struct Shared {
     /// operations), then the entire operation, including waiting for the mutex,
     /// is considered a "blocking" operation and `tokio::task::spawn_blocking`
     /// should be used.
-    state: Mutex<State>,
+    shards: Vec<Mutex<Shard>>,
+
+    /// State of the server which is not part of the key space. It is only
+    /// locked for short periods, after the shards, and no shard is ever locked
+    /// while holding it.
+    global: RwLock<Global>,
+
+    /// The pub/sub key-space. Redis uses a **separate** key space for key-value
+    /// and pub/sub. `mini-redis` handles this by using separate `HashMap`s,
+    /// split by the hash of the channel name the same way as the key space.
+    pub_sub: Vec<Mutex<HashMap<String, broadcast::Sender<Bytes>>>>,
+
+    /// Pattern subscriptions, as created by `PSUBSCRIBE`. Messages published
+    /// on any channel matching the pattern are sent along with the name of
+    /// the channel.
+    pattern_subs: Mutex<HashMap<String, broadcast::Sender<(String, Bytes)>>>,
+
+    /// Number of logical databases.
+    databases: usize,
+
+    /// Estimated number of bytes used by the entries of all shards, as
+    /// compared to `maxmemory`. It is updated as shards are unlocked, so the
+    /// limit can be checked without locking them.
+    used_memory: AtomicUsize,
 
     /// Notifies the background task handling entry expiration. The background
     /// task waits on this to be notified, then checks for expired values or the
@@ -57,15 +127,14 @@ struct Shared {
     background_task: Notify,
 }
 
+/// A shard of the key space, holding the keys whose hash maps to it, in every
+/// logical database.
 #[derive(Debug)]
-struct State {
-    /// The key-value data. We are not trying to do anything fancy so a
-    /// `std::collections::HashMap` works fine.
-    entries: HashMap<String, Entry>,
-
-    /// The pub/sub key-space. Redis uses a **separate** key space for key-value
-    /// and pub/sub. `mini-redis` handles this by using a separate `HashMap`.
-    pub_sub: HashMap<String, broadcast::Sender<Bytes>>,
+struct Shard {
+    /// The key-value data. An `IndexMap` is used instead of a `HashMap` so
+    /// keys can be picked at random, in constant time, when looking for keys
+    /// to evict.
+    entries: IndexMap<String, Entry>,
 
     /// Tracks key TTLs.
     ///
@@ -79,28 +148,327 @@ struct State {
     /// break these ties.
     expirations: BTreeSet<(Instant, String)>,
 
+    /// Set when an operation changed which key of the shard expires next. The
+    /// background task is notified once the lock is released. See
+    /// `Db::with_shards`.
+    notify_background_task: bool,
+
+    /// Connections blocked waiting for data to be pushed to a key, as done by
+    /// `BLPOP`.
+    ///
+    /// Each blocked connection owns a `Notify` which is registered under every
+    /// key it waits on. When a value is pushed to a key, all connections
+    /// waiting on that key are notified and retry their operation. A `Notify`
+    /// stores a permit if the connection is not waiting yet, so a push that
+    /// happens between registering and starting to wait is not missed.
+    waiters: HashMap<String, Vec<Arc<Notify>>>,
+
+    /// Keys watched by connections using `WATCH`.
+    ///
+    /// Each connection owns a flag which is registered under every key it
+    /// watches. When a key is modified, the flags registered under it are set
+    /// and the registrations are removed. `EXEC` checks the flag of the
+    /// connection while holding the lock, and aborts the transaction if it is
+    /// set.
+    watched: HashMap<String, Vec<Arc<AtomicBool>>>,
+
+    /// Key spaces of the logical databases, by number.
+    ///
+    /// The key space of the selected database is moved out to the `entries`,
+    /// `expirations`, `waiters` and `watched` fields, so commands operate on
+    /// it directly. Its slot in this vector is left empty until another
+    /// database is selected. See `Shard::select`.
+    databases: Vec<Database>,
+
+    /// Number of the selected database.
+    selected: usize,
+
+    /// Estimated number of bytes used by the entries of the shard, in all
+    /// databases. This is the sum of `Entry::size`.
+    used_memory: usize,
+}
+
+/// State of the server shared by all shards.
+#[derive(Debug)]
+struct Global {
     /// True when the Db instance is shutting down. This happens when all `Db`
     /// values drop. Setting this to `true` signals to the background task to
     /// exit.
     shutdown: bool,
+
+    /// The append-only file, if persistence is enabled. Commands modifying the
+    /// key space are appended while the locks of their shards are held, which
+    /// guarantees commands on the same keys are written in the order they
+    /// were applied.
+    aof: Option<Aof>,
+
+    /// Settings of the server, as reported by `CONFIG GET`. The settings
+    /// which can be changed while running are read from here when needed.
+    config: Config,
+
+    /// Limits the number of connections to `maxclients`. The listener
+    /// acquires a permit before accepting a connection, which the connection
+    /// returns once closed.
+    connections: Arc<Semaphore>,
+
+    /// Set while `BGSAVE` writes a snapshot in the background. The flag is
+    /// shared with the thread writing the snapshot, which clears it once done.
+    bgsave_in_progress: Arc<AtomicBool>,
+
+    /// Lua scripts cached by `EVAL` and `SCRIPT LOAD`, by SHA1 digest.
+    scripts: HashMap<String, Bytes>,
+
+    /// Streams commands modifying the key space to the connected replicas.
+    /// Each replica connection holds a receiver.
+    replicas: broadcast::Sender<Frame>,
+
+    /// Replication ID of the data set. Together with `repl_offset`, it
+    /// identifies a point in the history of the data set.
+    replid: String,
+
+    /// Number of bytes of commands streamed to replicas so far or, on a
+    /// replica, received from the master.
+    repl_offset: u64,
+
+    /// The master followed by this server, or `None` if it is a master.
+    master: Option<MasterLink>,
+
+    /// Assignment of the hash slots to the nodes of the cluster, or `None` if
+    /// cluster mode is disabled.
+    cluster: Option<Topology>,
+
+    /// Database the last propagated command was applied to, or `None` if the
+    /// next command must be preceded by a `SELECT` in any case.
+    propagated_db: Option<usize>,
+
+    /// Number of keys evicted to stay under `maxmemory`, as reported by
+    /// `INFO`.
+    evicted_keys: u64,
+}
+
+/// The locked database state.
+///
+/// Commands that operate on the key space are executed against a `&mut State`
+/// obtained through `Db::with_state` or `Db::with_keys`. It holds the locks of
+/// the shards the command accesses, and forwards operations on a key to the
+/// shard holding it. Each operation checks the type of the value stored at a
+/// key before operating on it.
+///
+/// Operations on the global state of the server lock it for their own
+/// duration only.
+#[derive(Debug)]
+pub(crate) struct State<'a> {
+    shared: &'a Shared,
+
+    /// The locked shards, by number. The shards the command does not access
+    /// are not locked, and are `None`.
+    shards: Vec<Option<MutexGuard<'a, Shard>>>,
+
+    /// Number of the selected database.
+    selected: usize,
+}
+
+/// Link to the master followed by a replica, as set by `REPLICAOF`.
+#[derive(Debug)]
+struct MasterLink {
+    host: String,
+    port: u16,
+
+    /// `true` once the initial synchronization succeeded, until the
+    /// connection is lost.
+    link_up: bool,
+
+    /// Task receiving the commands streamed by the master.
+    task: JoinHandle<()>,
+}
+
+/// Initial state sent to a replica when it connects.
+#[derive(Debug)]
+pub(crate) struct FullResync {
+    /// Replication ID of the data set.
+    pub(crate) replid: String,
+
+    /// Replication offset the snapshot corresponds to.
+    pub(crate) offset: u64,
+
+    /// Snapshot of the key space, in the format of the snapshot file.
+    pub(crate) snapshot: Bytes,
+
+    /// Receives the commands executed after the snapshot was taken.
+    pub(crate) updates: broadcast::Receiver<Frame>,
 }
 
 /// Entry in the key-value store
 #[derive(Debug)]
 struct Entry {
     /// Stored data
-    data: Bytes,
+    value: Value,
 
     /// Instant at which the entry expires and should be removed from the
     /// database.
     expires_at: Option<Instant>,
+
+    /// Estimated number of bytes used by the entry, as accounted in
+    /// `Shard::used_memory`. Updated by `Shard::touch` whenever the entry is
+    /// modified.
+    size: usize,
+
+    /// When and how often the key was accessed, used to pick the keys to
+    /// evict.
+    access: Access,
+}
+
+/// Key space of a logical database which is not selected. The fields match
+/// those of `Shard` holding the key space of the selected database.
+#[derive(Debug, Default)]
+struct Database {
+    entries: IndexMap<String, Entry>,
+    expirations: BTreeSet<(Instant, String)>,
+    waiters: HashMap<String, Vec<Arc<Notify>>>,
+    watched: HashMap<String, Vec<Arc<AtomicBool>>>,
+}
+
+/// A value stored in the key-value store.
+///
+/// Every key holds exactly one type of value. Commands operating on one type
+/// fail with `Error::WrongType` when used on a key holding another type.
+#[derive(Debug)]
+enum Value {
+    /// A binary safe string, as set by `SET`.
+    String(Bytes),
+
+    /// A list of strings, ordered by insertion. Elements may be pushed and
+    /// popped at both ends, so a `VecDeque` is used.
+    List(VecDeque<Bytes>),
+
+    /// A map of fields to values, as set by `HSET`.
+    Hash(HashMap<Bytes, Bytes>),
+
+    /// A set of unique members ordered by score, as set by `ZADD`.
+    SortedSet(SortedSet),
+
+    /// An append-only log of entries, as added by `XADD`.
+    Stream(Stream),
+}
+
+/// One of the two ends of a list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Side {
+    /// The head of the list, as used by `LPUSH` and `LPOP`.
+    Left,
+
+    /// The tail of the list, as used by `RPUSH` and `RPOP`.
+    Right,
+}
+
+/// Error returned when a command cannot be applied to the value stored at a
+/// key.
+///
+/// These errors are expected at runtime and are reported back to the client
+/// as an error frame. They do not terminate the connection.
+#[derive(Debug)]
+pub(crate) enum Error {
+    /// The key holds a value of another type than the command operates on.
+    WrongType,
+
+    /// The value cannot be interpreted as a 64 bit signed integer.
+    NotInteger,
+
+    /// Incrementing or decrementing the value would overflow a 64 bit signed
+    /// integer.
+    Overflow,
+
+    /// The key does not exist.
+    NoSuchKey,
+
+    /// There is no database with this number.
+    DbIndexOutOfRange,
+
+    /// The source and destination of the command are the same.
+    SameObject,
+
+    /// The ID given to `XADD` is not greater than the last ID of the stream.
+    StreamIdTooSmall,
+
+    /// The ID given to `XADD` is `0-0`, which is never a valid entry ID.
+    StreamIdZero,
+
+    /// The stream or its consumer group does not exist.
+    NoGroup,
+
+    /// A consumer group with the same name already exists.
+    BusyGroup,
+
+    /// `XGROUP` was called on a key which does not exist.
+    XGroupNoKey,
+
+    /// The value cannot be interpreted as a floating point number.
+    NotFloat,
+
+    /// Incrementing the value would result in NaN or an infinite number.
+    NanOrInfinity,
+
+    /// The command requires the append-only file, but persistence is
+    /// disabled.
+    AofDisabled,
+
+    /// A snapshot is already being written in the background.
+    SaveInProgress,
+
+    /// Writing the snapshot failed. The cause is logged by the server.
+    SaveFailed,
+
+    /// There is no setting with this name.
+    UnknownConfig(String),
+
+    /// The setting cannot be changed to the value, for the given reason.
+    InvalidConfig(String, String),
+
+    /// `CONFIG REWRITE` was called, but the server was started without a
+    /// configuration file.
+    NoConfigFile,
+
+    /// Writing the configuration file failed, for the given reason.
+    ConfigRewriteFailed(String),
+
+    /// The memory used is over `maxmemory`, and no key can be evicted.
+    OutOfMemory,
+}
+
+impl Entry {
+    /// Returns a new entry holding `value`. Its size is accounted once
+    /// inserted, when the key is touched.
+    fn new(value: Value, expires_at: Option<Instant>) -> Entry {
+        Entry {
+            value,
+            expires_at,
+            size: 0,
+            access: Access::new(),
+        }
+    }
+}
+
+impl Value {
+    /// Returns the name of the type, as reported by `TYPE`.
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::SortedSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
 }
 
 impl DbDropGuard {
-    /// Create a new `DbDropGuard`, wrapping a `Db` instance. When this is dropped
-    /// the `Db`'s purge task will be shut down.
-    pub(crate) fn new() -> DbDropGuard {
-        DbDropGuard { db: Db::new() }
+    /// Create a new `DbDropGuard`, wrapping a `Db` instance with the settings
+    /// of `config`. When this is dropped the `Db`'s purge task will be shut
+    /// down.
+    pub(crate) fn new(config: Config) -> DbDropGuard {
+        DbDropGuard {
+            db: Db::new(config),
+        }
     }
 
     /// Get the shared database. Internally, this is an
@@ -118,97 +486,147 @@ impl Drop for DbDropGuard {
 }
 
 impl Db {
-    /// Create a new, empty, `Db` instance. Allocates shared state and spawns a
-    /// background task to manage key expiration.
-    pub(crate) fn new() -> Db {
+    /// Create a new, empty, `Db` instance with the settings of `config`.
+    /// Allocates shared state and spawns a background task to manage key
+    /// expiration.
+    ///
+    /// The returned handle operates on database `0`.
+    pub(crate) fn new(config: Config) -> Db {
+        let databases = config.databases.max(1);
+        let shards = config.shards.max(1);
+
         let shared = Arc::new(Shared {
-            state: Mutex::new(State {
-                entries: HashMap::new(),
-                pub_sub: HashMap::new(),
-                expirations: BTreeSet::new(),
+            shards: (0..shards)
+                .map(|_| {
+                    Mutex::new(Shard {
+                        entries: IndexMap::new(),
+                        expirations: BTreeSet::new(),
+                        notify_background_task: false,
+                        waiters: HashMap::new(),
+                        watched: HashMap::new(),
+                        databases: (0..databases).map(|_| Database::default()).collect(),
+                        selected: 0,
+                        used_memory: 0,
+                    })
+                })
+                .collect(),
+            global: RwLock::new(Global {
                 shutdown: false,
+                aof: None,
+                connections: Arc::new(Semaphore::new(config.maxclients)),
+                config,
+                bgsave_in_progress: Arc::new(AtomicBool::new(false)),
+                scripts: HashMap::new(),
+                replicas: broadcast::channel(REPLICATION_BACKLOG).0,
+                replid: replication::new_replid(),
+                repl_offset: 0,
+                master: None,
+                cluster: None,
+                propagated_db: None,
+                evicted_keys: 0,
             }),
+            pub_sub: (0..shards).map(|_| Mutex::new(HashMap::new())).collect(),
+            pattern_subs: Mutex::new(HashMap::new()),
+            databases,
+            used_memory: AtomicUsize::new(0),
             background_task: Notify::new(),
         });
 
         // Start the background task.
         tokio::spawn(purge_expired_tasks(shared.clone()));
 
-        Db { shared }
+        Db { shared, index: 0 }
+    }
+
+    /// Returns a handle to the database numbered `index`.
+    pub(crate) fn select(&self, index: usize) -> Result<Db, Error> {
+        if index >= self.shared.databases {
+            return Err(Error::DbIndexOutOfRange);
+        }
+
+        Ok(Db {
+            shared: self.shared.clone(),
+            index,
+        })
+    }
+
+    /// Returns the number of the database this handle operates on.
+    pub(crate) fn index(&self) -> usize {
+        self.index
     }
 
-    /// Get the value associated with a key.
+    /// Run `f` with exclusive access to the whole key space.
     ///
-    /// Returns `None` if there is no value associated with the key. This may be
-    /// due to never having assigned a value to the key or a previously assigned
-    /// value expired.
-    pub(crate) fn get(&self, key: &str) -> Option<Bytes> {
-        // Acquire the lock, get the entry and clone the value.
-        //
-        // Because data is stored using `Bytes`, a clone here is a shallow
-        // clone. Data is not copied.
-        let state = self.shared.state.lock().unwrap();
-        state.entries.get(key).map(|entry| entry.data.clone())
+    /// All shards are locked for the duration of `f`, which means everything
+    /// `f` does is atomic with respect to all other connections. It also means
+    /// that `f` must be quick and must not block. Commands only accessing
+    /// known keys should use `with_keys` instead.
+    ///
+    /// The database of this handle is selected before calling `f`.
+    pub(crate) fn with_state<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        let shards: Vec<usize> = (0..self.shared.shards.len()).collect();
+        self.with_shards(&shards, f)
     }
 
-    /// Set the value associated with a key along with an optional expiration
-    /// Duration.
+    /// Run `f` with exclusive access to `keys`.
     ///
-    /// If a value is already associated with the key, it is removed.
-    pub(crate) fn set(&self, key: String, value: Bytes, expire: Option<Duration>) {
-        let mut state = self.shared.state.lock().unwrap();
+    /// Only the shards holding `keys` are locked, so connections accessing
+    /// keys of other shards are not blocked meanwhile. `f` must not access
+    /// any other key. Everything `f` does with `keys` is atomic, even when
+    /// they are held by different shards.
+    pub(crate) fn with_keys<K, R>(&self, keys: &[K], f: impl FnOnce(&mut State) -> R) -> R
+    where
+        K: AsRef<str>,
+    {
+        self.with_shards(&self.shards_of(keys), f)
+    }
 
-        // If this `set` becomes the key that expires **next**, the background
-        // task needs to be notified so it can update its state.
-        //
-        // Whether or not the task needs to be notified is computed during the
-        // `set` routine.
-        let mut notify = false;
+    /// Returns the numbers of the shards holding `keys`, sorted and without
+    /// duplicates, as expected by `with_shards`.
+    pub(crate) fn shards_of<K: AsRef<str>>(&self, keys: &[K]) -> Vec<usize> {
+        let mut shards: Vec<usize> = keys
+            .iter()
+            .map(|key| self.shared.shard_index(key.as_ref()))
+            .collect();
+        shards.sort_unstable();
+        shards.dedup();
+        shards
+    }
 
-        let expires_at = expire.map(|duration| {
-            // `Instant` at which the key expires.
-            let when = Instant::now() + duration;
+    /// Run `f` with the shards numbered `shards` locked, which must be sorted.
+    /// See `with_keys`.
+    pub(crate) fn with_shards<R>(&self, shards: &[usize], f: impl FnOnce(&mut State) -> R) -> R {
+        let mut state = State {
+            shared: &self.shared,
+            shards: self.shared.shards.iter().map(|_| None).collect(),
+            selected: self.index,
+        };
 
-            // Only notify the worker task if the newly inserted expiration is the
-            // **next** key to evict. In this case, the worker needs to be woken up
-            // to update its state.
-            notify = state
-                .next_expiration()
-                .map(|expiration| expiration > when)
-                .unwrap_or(true);
+        // Shards are always locked in ascending order. Two connections locking
+        // overlapping sets of shards therefore cannot deadlock.
+        for &index in shards {
+            let mut shard = self.shared.shards[index].lock().unwrap();
+            shard.select(self.index);
+            state.shards[index] = Some(shard);
+        }
 
-            when
-        });
+        let used_memory = state.used_memory();
 
-        // Insert the entry into the `HashMap`.
-        let prev = state.entries.insert(
-            key.clone(),
-            Entry {
-                data: value,
-                expires_at,
-            },
-        );
+        let ret = f(&mut state);
 
-        // If there was a value previously associated with the key **and** it
-        // had an expiration time. The associated entry in the `expirations` map
-        // must also be removed. This avoids leaking data.
-        if let Some(prev) = prev {
-            if let Some(when) = prev.expires_at {
-                // clear expiration
-                state.expirations.remove(&(when, key.clone()));
-            }
-        }
+        self.shared.account(used_memory, state.used_memory());
 
-        // Track the expiration. If we insert before remove that will cause bug
-        // when current `(when, key)` equals prev `(when, key)`. Remove then insert
-        // can avoid this.
-        if let Some(when) = expires_at {
-            state.expirations.insert((when, key));
+        // If one of the operations performed by `f` changed the key that
+        // expires **next**, the background task needs to be notified so it
+        // can update its state.
+        let mut notify = false;
+        for shard in state.shards.iter_mut().flatten() {
+            notify |= std::mem::take(&mut shard.notify_background_task);
         }
 
-        // Release the mutex before notifying the background task. This helps
+        // Release the mutexes before notifying the background task. This helps
         // reduce contention by avoiding the background task waking up only to
-        // be unable to acquire the mutex due to this function still holding it.
+        // be unable to acquire a mutex due to this function still holding it.
         drop(state);
 
         if notify {
@@ -216,6 +634,149 @@ impl Db {
             // its state to reflect a new expiration.
             self.shared.background_task.notify_one();
         }
+
+        ret
+    }
+
+    /// Evict keys until the memory used is back under `maxmemory`. See
+    /// `State::evict`.
+    ///
+    /// The whole key space is only locked if the limit is exceeded.
+    pub(crate) fn evict(&self) -> Result<(), Error> {
+        let maxmemory = self.shared.global.read().unwrap().config.maxmemory;
+
+        match maxmemory {
+            Some(maxmemory) if self.shared.used_memory.load(Ordering::Relaxed) > maxmemory => {
+                self.with_state(|state| state.evict())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check whether a command accessing `keys` may be executed by this node.
+    /// See `State::cluster_redirect`.
+    ///
+    /// The shards holding `keys` are only locked if cluster mode is enabled.
+    pub(crate) fn cluster_redirect(&self, keys: &[&str], asking: bool) -> Option<Frame> {
+        if !self.is_cluster_enabled() {
+            return None;
+        }
+
+        self.with_keys(keys, |state| state.cluster_redirect(keys, asking))
+    }
+
+    /// Returns `true` if cluster mode is enabled.
+    ///
+    /// Only the global state is read, without locking any shard.
+    pub(crate) fn is_cluster_enabled(&self) -> bool {
+        self.shared.global.read().unwrap().cluster.is_some()
+    }
+
+    /// Start appending commands that modify the key space to `aof`.
+    ///
+    /// This is called once the existing file has been replayed, so the
+    /// replayed commands are not appended a second time.
+    pub(crate) fn attach_aof(&self, aof: Aof) {
+        self.shared.global.write().unwrap().aof = Some(aof);
+    }
+
+    /// Returns the semaphore limiting the number of connections.
+    pub(crate) fn connection_limit(&self) -> Arc<Semaphore> {
+        self.shared.global.read().unwrap().connections.clone()
+    }
+
+    /// Returns how long a connection may stay idle before it is closed, or
+    /// `None` if idle connections are kept open.
+    pub(crate) fn client_timeout(&self) -> Option<Duration> {
+        self.shared.global.read().unwrap().config.timeout
+    }
+
+    /// Enable cluster mode. Commands accessing keys in slots not served by
+    /// this node are redirected from then on.
+    pub(crate) fn enable_cluster(&self, topology: Topology) {
+        self.shared.global.write().unwrap().cluster = Some(topology);
+    }
+
+    /// Returns `true` if commands modifying the key space must be rejected,
+    /// because the server is a read-only replica.
+    pub(crate) fn is_read_only(&self) -> bool {
+        let global = self.shared.global.read().unwrap();
+        global.master.is_some() && global.config.replica_read_only
+    }
+
+    /// Returns the configuration of the TLS connection to the master, or
+    /// `None` if replication uses plaintext.
+    pub(crate) fn replication_tls(&self) -> crate::Result<Option<Arc<ClientConfig>>> {
+        let config = self.shared.global.read().unwrap().config.clone();
+        tls::replication_config(&config)
+    }
+
+    /// Start following the master listening on `host` and `port`, or stop
+    /// following any master if `None`.
+    ///
+    /// When following a new master, the key space is replaced once the
+    /// initial synchronization completes. When promoted to master, the key
+    /// space is kept as is and a new replication ID is generated, as the data
+    /// set may now diverge from the former master's.
+    pub(crate) fn replicaof(&self, master: Option<(String, u16)>) {
+        let mut global = self.shared.global.write().unwrap();
+
+        if let Some(link) = global.master.take() {
+            link.task.abort();
+        }
+
+        match master {
+            Some((host, port)) => {
+                // The master selects the database of each command it streams.
+                let db = Db {
+                    shared: self.shared.clone(),
+                    index: 0,
+                };
+                let task = tokio::spawn(replication::follow(db, host.clone(), port));
+
+                global.master = Some(MasterLink {
+                    host,
+                    port,
+                    link_up: false,
+                    task,
+                });
+            }
+            None => global.replid = replication::new_replid(),
+        }
+    }
+
+    /// Wait until all commands appended to the append-only file so far are on
+    /// disk, if the `always` fsync policy is used.
+    ///
+    /// This is called after executing a command that modifies the key space,
+    /// before responding to the client.
+    pub(crate) async fn wait_for_aof(&self) {
+        let synced = match &self.shared.global.read().unwrap().aof {
+            Some(aof) if aof.fsync() == FsyncPolicy::Always => Some(aof.sync()),
+            _ => None,
+        };
+
+        if let Some(synced) = synced {
+            let _ = synced.await;
+        }
+    }
+
+    /// Wait until all commands appended to the append-only file so far are on
+    /// disk, regardless of the fsync policy. Called when the server shuts
+    /// down.
+    pub(crate) async fn sync_aof(&self) {
+        let synced = self
+            .shared
+            .global
+            .read()
+            .unwrap()
+            .aof
+            .as_ref()
+            .map(Aof::sync);
+
+        if let Some(synced) = synced {
+            let _ = synced.await;
+        }
     }
 
     /// Returns a `Receiver` for the requested channel.
@@ -225,13 +786,15 @@ impl Db {
     pub(crate) fn subscribe(&self, key: String) -> broadcast::Receiver<Bytes> {
         use std::collections::hash_map::Entry;
 
-        // Acquire the mutex
-        let mut state = self.shared.state.lock().unwrap();
+        // Acquire the mutex of the channel's shard
+        let mut pub_sub = self.shared.pub_sub[self.shared.shard_index(&key)]
+            .lock()
+            .unwrap();
 
         // If there is no entry for the requested channel, then create a new
         // broadcast channel and associate it with the key. If one already
         // exists, return an associated receiver.
-        match state.pub_sub.entry(key) {
+        match pub_sub.entry(key) {
             Entry::Occupied(e) => e.get().subscribe(),
             Entry::Vacant(e) => {
                 // No broadcast channel exists yet, so create one.
@@ -251,74 +814,131 @@ impl Db {
         }
     }
 
+    /// Returns a `Receiver` for messages published on channels matching
+    /// `pattern`.
+    ///
+    /// Each message is received along with the name of the channel it was
+    /// published on.
+    pub(crate) fn psubscribe(&self, pattern: String) -> broadcast::Receiver<(String, Bytes)> {
+        let mut pattern_subs = self.shared.pattern_subs.lock().unwrap();
+
+        // Same as `subscribe`, one broadcast channel is shared by all
+        // connections subscribed to the same pattern.
+        pattern_subs
+            .entry(pattern)
+            .or_insert_with(|| broadcast::channel(1024).0)
+            .subscribe()
+    }
+
     /// Publish a message to the channel. Returns the number of subscribers
-    /// listening on the channel.
+    /// listening on the channel, including subscribers to patterns matching
+    /// the channel.
     pub(crate) fn publish(&self, key: &str, value: Bytes) -> usize {
-        let state = self.shared.state.lock().unwrap();
-
-        state
-            .pub_sub
+        let num_subscribers = self.shared.pub_sub[self.shared.shard_index(key)]
+            .lock()
+            .unwrap()
             .get(key)
             // On a successful message send on the broadcast channel, the number
             // of subscribers is returned. An error indicates there are no
             // receivers, in which case, `0` should be returned.
-            .map(|tx| tx.send(value).unwrap_or(0))
+            .map(|tx| tx.send(value.clone()).unwrap_or(0))
             // If there is no entry for the channel key, then there are no
             // subscribers. In this case, return `0`.
-            .unwrap_or(0)
+            .unwrap_or(0);
+
+        // Patterns cannot be looked up by channel name, so every pattern is
+        // checked.
+        let num_pattern_subscribers: usize = self
+            .shared
+            .pattern_subs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(pattern, _)| glob::matches(pattern.as_bytes(), key.as_bytes()))
+            .map(|(_, tx)| tx.send((key.to_string(), value.clone())).unwrap_or(0))
+            .sum();
+
+        num_subscribers + num_pattern_subscribers
     }
 
     /// Signals the purge background task to shut down. This is called by the
     /// `DbShutdown`s `Drop` implementation.
+    ///
+    /// The task following the master, if any, is stopped as well. It holds a
+    /// `Db` handle, so the state would otherwise never be dropped.
     fn shutdown_purge_task(&self) {
         // The background task must be signaled to shut down. This is done by
-        // setting `State::shutdown` to `true` and signalling the task.
-        let mut state = self.shared.state.lock().unwrap();
-        state.shutdown = true;
+        // setting `Global::shutdown` to `true` and signalling the task.
+        let mut global = self.shared.global.write().unwrap();
+        global.shutdown = true;
+
+        if let Some(link) = global.master.take() {
+            link.task.abort();
+        }
 
         // Drop the lock before signalling the background task. This helps
         // reduce lock contention by ensuring the background task doesn't
-        // wake up only to be unable to acquire the mutex.
-        drop(state);
+        // wake up only to be unable to acquire the lock.
+        drop(global);
         self.shared.background_task.notify_one();
     }
 }
 
 impl Shared {
+    /// Returns the number of the shard holding `key`. Pub/sub channels are
+    /// split the same way.
+    fn shard_index(&self, key: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    /// Account for the memory used by locked shards going from `before` to
+    /// `after` bytes.
+    fn account(&self, before: usize, after: usize) {
+        if after > before {
+            self.used_memory
+                .fetch_add(after - before, Ordering::Relaxed);
+        } else {
+            self.used_memory
+                .fetch_sub(before - after, Ordering::Relaxed);
+        }
+    }
+
     /// Purge all expired keys and return the `Instant` at which the **next**
     /// key will expire. The background task will sleep until this instant.
     fn purge_expired_keys(&self) -> Option<Instant> {
-        let mut state = self.state.lock().unwrap();
-
-        if state.shutdown {
+        if self.is_shutdown() {
             // The database is shutting down. All handles to the shared state
             // have dropped. The background task should exit.
             return None;
         }
 
-        // This is needed to make the borrow checker happy. In short, `lock()`
-        // returns a `MutexGuard` and not a `&mut State`. The borrow checker is
-        // not able to see "through" the mutex guard and determine that it is
-        // safe to access both `state.expirations` and `state.entries` mutably,
-        // so we get a "real" mutable reference to `State` outside of the loop.
-        let state = &mut *state;
-
-        // Find all keys scheduled to expire **before** now.
         let now = Instant::now();
+        let mut next: Option<Instant> = None;
 
-        while let Some(&(when, ref key)) = state.expirations.iter().next() {
-            if when > now {
-                // Done purging, `when` is the instant at which the next key
-                // expires. The worker task will wait until this instant.
-                return Some(when);
+        // Shards are purged one at a time, so connections are only blocked
+        // while the shard holding their keys is purged.
+        for shard in &self.shards {
+            let mut shard = shard.lock().unwrap();
+            let used_memory = shard.used_memory;
+            let selected = shard.selected;
+
+            // Each database tracks its own expirations. The worker task waits
+            // until the earliest of them.
+            for index in 0..shard.databases.len() {
+                shard.select(index);
+
+                if let Some(when) = shard.purge_expired(now) {
+                    next = Some(next.map_or(when, |next| next.min(when)));
+                }
             }
 
-            // The key expired, remove it
-            state.entries.remove(key);
-            state.expirations.remove(&(when, key.clone()));
+            shard.select(selected);
+            self.account(used_memory, shard.used_memory);
         }
 
-        None
+        next
     }
 
     /// Returns `true` if the database is shutting down
@@ -326,17 +946,2768 @@ impl Shared {
     /// The `shutdown` flag is set when all `Db` values have dropped, indicating
     /// that the shared state can no longer be accessed.
     fn is_shutdown(&self) -> bool {
-        self.state.lock().unwrap().shutdown
+        self.global.read().unwrap().shutdown
     }
 }
 
-impl State {
-    fn next_expiration(&self) -> Option<Instant> {
-        self.expirations
-            .iter()
-            .next()
-            .map(|expiration| expiration.0)
+impl Shard {
+    /// Select the database numbered `index`, which must exist.
+    ///
+    /// The key space of the previously selected database is moved back to
+    /// `databases`, and the one of `index` is moved out of it. Only the maps
+    /// are moved, not their content, so this is cheap.
+    fn select(&mut self, index: usize) {
+        if index != self.selected {
+            self.swap_selected();
+            self.selected = index;
+            self.swap_selected();
+        }
+    }
+
+    /// Swap the key space fields with the slot of the selected database in
+    /// `databases`.
+    fn swap_selected(&mut self) {
+        let database = &mut self.databases[self.selected];
+
+        std::mem::swap(&mut self.entries, &mut database.entries);
+        std::mem::swap(&mut self.expirations, &mut database.expirations);
+        std::mem::swap(&mut self.waiters, &mut database.waiters);
+        std::mem::swap(&mut self.watched, &mut database.watched);
+    }
+
+    /// Returns the entries and expirations of the database numbered `index`,
+    /// whether it is selected or not.
+    fn database(&self, index: usize) -> (&IndexMap<String, Entry>, &BTreeSet<(Instant, String)>) {
+        if index == self.selected {
+            (&self.entries, &self.expirations)
+        } else {
+            let database = &self.databases[index];
+            (&database.entries, &database.expirations)
+        }
+    }
+
+    /// Remove the keys of the selected database that expired at `now`.
+    ///
+    /// Returns the `Instant` at which the next key of the database expires.
+    fn purge_expired(&mut self, now: Instant) -> Option<Instant> {
+        while let Some(&(when, ref key)) = self.expirations.iter().next() {
+            if when > now {
+                // Done purging, `when` is the instant at which the next key
+                // expires.
+                return Some(when);
+            }
+
+            // The key expired, remove it. Expiring a key modifies it as far
+            // as `WATCH` is concerned.
+            let key = key.clone();
+            self.remove(&key);
+            self.touch(&key);
+        }
+
+        None
+    }
+
+    /// Get the string value associated with a key.
+    ///
+    /// Returns `None` if there is no value associated with the key. This may be
+    /// due to never having assigned a value to the key or a previously assigned
+    /// value expired.
+    fn get(&self, key: &str) -> Result<Option<Bytes>, Error> {
+        // Because data is stored using `Bytes`, a clone here is a shallow
+        // clone. Data is not copied.
+        match self.entries.get(key).map(|entry| &entry.value) {
+            Some(Value::String(data)) => Ok(Some(data.clone())),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
     }
+
+    /// Set the string value associated with a key, expiring at `expires_at`
+    /// if set.
+    ///
+    /// An instant in the past deletes the key instead.
+    fn set_at(&mut self, key: String, value: Bytes, expires_at: Option<Instant>) {
+        match expires_at {
+            Some(when) if when <= Instant::now() => {
+                self.remove(&key);
+                self.touch(&key);
+            }
+            _ => self.insert_at(key, Value::String(value), expires_at),
+        }
+    }
+
+    /// Remove `key` and return its value, which must be a string.
+    fn getdel(&mut self, key: &str) -> Result<Option<Bytes>, Error> {
+        let value = self.get(key)?;
+
+        if value.is_some() {
+            self.remove(key);
+            self.touch(key);
+        }
+
+        Ok(value)
+    }
+
+    /// Increment the integer stored at `key` by `delta`.
+    ///
+    /// A missing key is treated as `0`. The time to live of the key is
+    /// retained. Returns the value after the increment.
+    fn incr_by(&mut self, key: &str, delta: i64) -> Result<i64, Error> {
+        let current = match self.get(key)? {
+            Some(value) => parse_i64(&value)?,
+            None => 0,
+        };
+
+        let value = current.checked_add(delta).ok_or(Error::Overflow)?;
+
+        self.update_string(key, Bytes::from(value.to_string()));
+
+        Ok(value)
+    }
+
+    /// Increment the floating point number stored at `key` by `delta`.
+    ///
+    /// A missing key is treated as `0`. The time to live of the key is
+    /// retained. Returns the value after the increment.
+    fn incr_by_float(&mut self, key: &str, delta: f64) -> Result<f64, Error> {
+        let current = match self.get(key)? {
+            Some(value) => parse_f64(&value)?,
+            None => 0.0,
+        };
+
+        let value = current + delta;
+
+        if !value.is_finite() {
+            return Err(Error::NanOrInfinity);
+        }
+
+        self.update_string(key, Bytes::from(value.to_string()));
+
+        Ok(value)
+    }
+
+    /// Append `value` to the string stored at `key`, creating the key if it
+    /// does not exist.
+    ///
+    /// Returns the length of the string after the append.
+    fn append(&mut self, key: &str, value: &[u8]) -> Result<usize, Error> {
+        let mut data = match self.get(key)? {
+            Some(data) => data.to_vec(),
+            None => vec![],
+        };
+
+        data.extend_from_slice(value);
+        let len = data.len();
+
+        self.update_string(key, Bytes::from(data));
+
+        Ok(len)
+    }
+
+    /// Returns the length of the string stored at `key`, or `0` if the key
+    /// does not exist.
+    fn strlen(&self, key: &str) -> Result<usize, Error> {
+        Ok(self.get(key)?.map(|value| value.len()).unwrap_or(0))
+    }
+
+    /// Replace the string stored at `key`, retaining its time to live, or
+    /// create the key without expiration.
+    ///
+    /// The caller must have checked that the key does not hold another type.
+    fn update_string(&mut self, key: &str, value: Bytes) {
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.value = Value::String(value);
+                self.touch(key);
+            }
+            None => self.insert_at(key.to_string(), Value::String(value), None),
+        }
+    }
+
+    /// Returns `true` if `key` holds a value, of any type.
+    fn contains_key(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Returns the `Instant` at which `key` expires, if it exists and has an
+    /// expiration.
+    fn expires_at(&self, key: &str) -> Option<Instant> {
+        self.entries.get(key)?.expires_at
+    }
+
+    /// Set the `Instant` at which `key` expires, if `condition` holds.
+    ///
+    /// An instant in the past deletes the key. Returns `false` if the key
+    /// does not exist or `condition` does not hold.
+    fn expire(&mut self, key: &str, when: Instant, condition: Option<ExpireCondition>) -> bool {
+        let current = match self.entries.get(key) {
+            Some(entry) => entry.expires_at,
+            None => return false,
+        };
+
+        // A key without expiration has an infinite time to live, so it is
+        // never earlier than `when`.
+        let holds = match condition {
+            None => true,
+            Some(ExpireCondition::Nx) => current.is_none(),
+            Some(ExpireCondition::Xx) => current.is_some(),
+            Some(ExpireCondition::Gt) => current.map(|current| when > current).unwrap_or(false),
+            Some(ExpireCondition::Lt) => current.map(|current| when < current).unwrap_or(true),
+        };
+
+        if !holds {
+            return false;
+        }
+
+        if when <= Instant::now() {
+            self.remove(key);
+        } else {
+            self.set_expiration(key, Some(when));
+        }
+
+        self.touch(key);
+
+        true
+    }
+
+    /// Remove the expiration of `key`.
+    ///
+    /// Returns `false` if the key does not exist or has no expiration.
+    fn persist(&mut self, key: &str) -> bool {
+        match self.entries.get(key) {
+            Some(entry) if entry.expires_at.is_some() => {}
+            _ => return false,
+        }
+
+        self.set_expiration(key, None);
+        self.touch(key);
+
+        true
+    }
+
+    /// Returns the remaining time to live of `key`.
+    ///
+    /// Returns `None` if the key does not exist, and `Some(None)` if it has no
+    /// expiration.
+    fn ttl(&self, key: &str) -> Option<Option<Duration>> {
+        let entry = self.entries.get(key)?;
+
+        Some(
+            entry
+                .expires_at
+                .map(|when| when.saturating_duration_since(Instant::now())),
+        )
+    }
+
+    /// Replace the expiration of the entry at `key`, which must exist.
+    fn set_expiration(&mut self, key: &str, expires_at: Option<Instant>) {
+        let entry = match self.entries.get_mut(key) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        if let Some(when) = std::mem::replace(&mut entry.expires_at, expires_at) {
+            self.expirations.remove(&(when, key.to_string()));
+        }
+
+        if let Some(when) = expires_at {
+            // Same as in `insert`, the worker task is only notified if the key
+            // is now the next one to expire.
+            if self
+                .next_expiration()
+                .map(|expiration| expiration > when)
+                .unwrap_or(true)
+            {
+                self.notify_background_task = true;
+            }
+
+            self.expirations.insert((when, key.to_string()));
+        }
+    }
+
+    /// Returns the name of the type of the value stored at `key`, or `none`
+    /// if the key does not exist.
+    fn key_type(&self, key: &str) -> &'static str {
+        match self.entries.get(key) {
+            Some(entry) => entry.value.type_name(),
+            None => "none",
+        }
+    }
+
+    /// Returns the keys of the selected database matching the glob-style
+    /// `pattern`.
+    fn keys(&self, pattern: &[u8]) -> Vec<String> {
+        self.entries
+            .keys()
+            .filter(|key| glob::matches(pattern, key.as_bytes()))
+            .cloned()
+            .collect()
+    }
+
+    /// Remove all keys of the selected database.
+    fn flushdb(&mut self) {
+        let mut keys = vec![];
+        for (key, entry) in self.entries.drain(..) {
+            self.used_memory -= entry.size;
+            keys.push(key);
+        }
+        self.expirations.clear();
+
+        for key in keys {
+            self.touch(&key);
+        }
+    }
+
+    /// Remove all keys of all databases.
+    fn flushall(&mut self) {
+        let selected = self.selected;
+
+        for index in 0..self.databases.len() {
+            self.select(index);
+            self.flushdb();
+        }
+
+        self.select(selected);
+    }
+
+    /// Move `key` from the selected database to the database numbered
+    /// `index`.
+    ///
+    /// Returns `false` if the key does not exist, or if it already exists in
+    /// the destination database. The time to live of the key is retained.
+    fn move_key(&mut self, key: &str, index: usize) -> Result<bool, Error> {
+        if index >= self.databases.len() {
+            return Err(Error::DbIndexOutOfRange);
+        }
+
+        if index == self.selected {
+            return Err(Error::SameObject);
+        }
+
+        // The destination is not selected, so its key space is in
+        // `databases`.
+        if self.databases[index].entries.contains_key(key) {
+            return Ok(false);
+        }
+
+        let entry = match self.remove(key) {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+        self.touch(key);
+
+        let selected = self.selected;
+        self.select(index);
+
+        let wake = matches!(entry.value, Value::List(_));
+        self.insert_at(key.to_string(), entry.value, entry.expires_at);
+        if wake {
+            self.wake(key);
+        }
+
+        self.select(selected);
+
+        Ok(true)
+    }
+
+    /// Swap the data of the databases numbered `a` and `b`.
+    ///
+    /// Connections keep the database they selected, and see the data of the
+    /// other one from then on. The transactions of connections watching keys
+    /// of either database are aborted, and connections blocked on either
+    /// database retry their operation.
+    fn swapdb(&mut self, a: usize, b: usize) -> Result<(), Error> {
+        if a >= self.databases.len() || b >= self.databases.len() {
+            return Err(Error::DbIndexOutOfRange);
+        }
+
+        if a == b {
+            return Ok(());
+        }
+
+        // Move the key space of the selected database back to `databases`,
+        // so both databases are found there.
+        self.swap_selected();
+
+        let (low, high) = (a.min(b), a.max(b));
+        let (head, tail) = self.databases.split_at_mut(high);
+        std::mem::swap(&mut head[low].entries, &mut tail[0].entries);
+        std::mem::swap(&mut head[low].expirations, &mut tail[0].expirations);
+
+        self.swap_selected();
+
+        let selected = self.selected;
+
+        for &index in &[a, b] {
+            self.select(index);
+
+            for (_, flags) in self.watched.drain() {
+                for flag in flags {
+                    flag.store(true, Ordering::Release);
+                }
+            }
+
+            for (_, waiters) in self.waiters.drain() {
+                for waiter in waiters {
+                    waiter.notify_one();
+                }
+            }
+        }
+
+        self.select(selected);
+
+        Ok(())
+    }
+
+    /// Insert `value` at `key`, replacing any existing entry, and expire it
+    /// after `expire` if set.
+    fn insert(&mut self, key: String, value: Value, expire: Option<Duration>) {
+        // `Instant` at which the key expires.
+        let expires_at = expire.map(|duration| Instant::now() + duration);
+
+        self.insert_at(key, value, expires_at);
+    }
+
+    /// Insert `value` at `key`, replacing any existing entry, and expire it at
+    /// `expires_at` if set.
+    fn insert_at(&mut self, key: String, value: Value, expires_at: Option<Instant>) {
+        if let Some(when) = expires_at {
+            // Only notify the worker task if the newly inserted expiration is the
+            // **next** key to evict. In this case, the worker needs to be woken up
+            // to update its state.
+            if self
+                .next_expiration()
+                .map(|expiration| expiration > when)
+                .unwrap_or(true)
+            {
+                self.notify_background_task = true;
+            }
+        }
+
+        // Insert the entry into the `IndexMap`.
+        let prev = self
+            .entries
+            .insert(key.clone(), Entry::new(value, expires_at));
+
+        // If there was a value previously associated with the key **and** it
+        // had an expiration time. The associated entry in the `expirations` map
+        // must also be removed. This avoids leaking data.
+        if let Some(prev) = prev {
+            self.used_memory -= prev.size;
+
+            if let Some(when) = prev.expires_at {
+                // clear expiration
+                self.expirations.remove(&(when, key.clone()));
+            }
+        }
+
+        // Track the expiration. If we insert before remove that will cause bug
+        // when current `(when, key)` equals prev `(when, key)`. Remove then insert
+        // can avoid this.
+        if let Some(when) = expires_at {
+            self.expirations.insert((when, key.clone()));
+        }
+
+        self.touch(&key);
+    }
+
+    /// Push `values` onto one end of the list stored at `key`, creating the
+    /// list if the key does not exist.
+    ///
+    /// Values are pushed one after the other, so pushing `a b c` on the left
+    /// results in the list `c b a`. Returns the length of the list after the
+    /// push.
+    fn push(&mut self, key: String, values: Vec<Bytes>, side: Side) -> Result<usize, Error> {
+        let entry = self
+            .entries
+            .entry(key.clone())
+            .or_insert_with(|| Entry::new(Value::List(VecDeque::new()), None));
+
+        let list = match &mut entry.value {
+            Value::List(list) => list,
+            _ => return Err(Error::WrongType),
+        };
+
+        for value in values {
+            match side {
+                Side::Left => list.push_front(value),
+                Side::Right => list.push_back(value),
+            }
+        }
+
+        let len = list.len();
+
+        self.touch(&key);
+        self.wake(&key);
+
+        Ok(len)
+    }
+
+    /// Pop up to `count` values from one end of the list stored at `key`.
+    ///
+    /// Returns `None` if the key does not exist. Lists are never empty; once
+    /// the last value is popped, the key is removed.
+    fn pop(&mut self, key: &str, side: Side, count: usize) -> Result<Option<Vec<Bytes>>, Error> {
+        let list = match self.entries.get_mut(key).map(|entry| &mut entry.value) {
+            Some(Value::List(list)) => list,
+            Some(_) => return Err(Error::WrongType),
+            None => return Ok(None),
+        };
+
+        let n = count.min(list.len());
+
+        let popped = match side {
+            Side::Left => list.drain(..n).collect(),
+            Side::Right => list.drain(list.len() - n..).rev().collect(),
+        };
+
+        if list.is_empty() {
+            self.remove(key);
+        }
+
+        if n > 0 {
+            self.touch(key);
+        }
+
+        Ok(Some(popped))
+    }
+
+    /// Returns the values of the list stored at `key` between the `start` and
+    /// `stop` indices, both inclusive.
+    ///
+    /// Negative indices count from the end of the list, with `-1` being the
+    /// last element. Out of range indices are clamped to the list bounds.
+    fn lrange(&self, key: &str, start: i64, stop: i64) -> Result<Vec<Bytes>, Error> {
+        let list = match self.entries.get(key).map(|entry| &entry.value) {
+            Some(Value::List(list)) => list,
+            Some(_) => return Err(Error::WrongType),
+            None => return Ok(vec![]),
+        };
+
+        let len = list.len() as i64;
+
+        let start = if start < 0 {
+            (len + start).max(0)
+        } else {
+            start
+        };
+        let stop = if stop < 0 {
+            len + stop
+        } else {
+            stop.min(len - 1)
+        };
+
+        if start > stop {
+            return Ok(vec![]);
+        }
+
+        Ok(list
+            .range(start as usize..=stop as usize)
+            .cloned()
+            .collect())
+    }
+
+    /// Returns the length of the list stored at `key`, or `0` if the key does
+    /// not exist.
+    fn llen(&self, key: &str) -> Result<usize, Error> {
+        match self.entries.get(key).map(|entry| &entry.value) {
+            Some(Value::List(list)) => Ok(list.len()),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(0),
+        }
+    }
+
+    /// Set `fields` of the hash stored at `key`, creating the hash if the key
+    /// does not exist.
+    ///
+    /// Existing fields are overwritten. Returns the number of fields that were
+    /// added, not counting the ones that were updated.
+    fn hset(&mut self, key: String, fields: Vec<(Bytes, Bytes)>) -> Result<usize, Error> {
+        let hash = self.hash_mut(&key)?;
+
+        let mut added = 0;
+
+        for (field, value) in fields {
+            if hash.insert(field, value).is_none() {
+                added += 1;
+            }
+        }
+
+        self.touch(&key);
+
+        Ok(added)
+    }
+
+    /// Returns the value of `field` in the hash stored at `key`.
+    fn hget(&self, key: &str, field: &[u8]) -> Result<Option<Bytes>, Error> {
+        match self.entries.get(key).map(|entry| &entry.value) {
+            Some(Value::Hash(hash)) => Ok(hash.get(field).cloned()),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Remove `fields` from the hash stored at `key`.
+    ///
+    /// Returns the number of fields that were removed. Hashes are never empty;
+    /// once the last field is removed, the key is removed.
+    fn hdel(&mut self, key: &str, fields: &[Bytes]) -> Result<usize, Error> {
+        let hash = match self.entries.get_mut(key).map(|entry| &mut entry.value) {
+            Some(Value::Hash(hash)) => hash,
+            Some(_) => return Err(Error::WrongType),
+            None => return Ok(0),
+        };
+
+        let removed = fields
+            .iter()
+            .filter(|field| hash.remove(&field[..]).is_some())
+            .count();
+
+        if hash.is_empty() {
+            self.remove(key);
+        }
+
+        if removed > 0 {
+            self.touch(key);
+        }
+
+        Ok(removed)
+    }
+
+    /// Returns all fields and values of the hash stored at `key`, in no
+    /// particular order.
+    fn hgetall(&self, key: &str) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        match self.entries.get(key).map(|entry| &entry.value) {
+            Some(Value::Hash(hash)) => Ok(hash
+                .iter()
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect()),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Increment the integer stored in `field` of the hash stored at `key` by
+    /// `delta`.
+    ///
+    /// A missing field is treated as `0`. Returns the value after the
+    /// increment.
+    fn hincrby(&mut self, key: String, field: Bytes, delta: i64) -> Result<i64, Error> {
+        let hash = self.hash_mut(&key)?;
+
+        let current = match hash.get(&field) {
+            Some(value) => parse_i64(value)?,
+            None => 0,
+        };
+
+        let value = current.checked_add(delta).ok_or(Error::Overflow)?;
+
+        hash.insert(field, Bytes::from(value.to_string()));
+
+        self.touch(&key);
+
+        Ok(value)
+    }
+
+    /// Returns the hash stored at `key`, creating an empty one if the key does
+    /// not exist.
+    fn hash_mut(&mut self, key: &str) -> Result<&mut HashMap<Bytes, Bytes>, Error> {
+        let entry = self
+            .entries
+            .entry(key.to_string())
+            .or_insert_with(|| Entry::new(Value::Hash(HashMap::new()), None));
+
+        match &mut entry.value {
+            Value::Hash(hash) => Ok(hash),
+            _ => Err(Error::WrongType),
+        }
+    }
+
+    /// Add `members` with their scores to the sorted set stored at `key`,
+    /// creating the sorted set if the key does not exist.
+    ///
+    /// With `nx`, members already in the set are left untouched. With `xx`,
+    /// only members already in the set are updated and no member is added.
+    ///
+    /// Returns the number of members that were added and the number of
+    /// members whose score changed.
+    fn zadd(
+        &mut self,
+        key: String,
+        members: Vec<(f64, Bytes)>,
+        nx: bool,
+        xx: bool,
+    ) -> Result<(usize, usize), Error> {
+        // `XX` never adds members, so it must not create the key either.
+        if xx && !self.entries.contains_key(&key) {
+            return Ok((0, 0));
+        }
+
+        let entry = self
+            .entries
+            .entry(key.clone())
+            .or_insert_with(|| Entry::new(Value::SortedSet(SortedSet::default()), None));
+
+        let set = match &mut entry.value {
+            Value::SortedSet(set) => set,
+            _ => return Err(Error::WrongType),
+        };
+
+        let mut added = 0;
+        let mut changed = 0;
+
+        for (score, member) in members {
+            match set.score(&member) {
+                Some(_) if nx => {}
+                Some(prev) => {
+                    if prev != score {
+                        set.insert(member, score);
+                        changed += 1;
+                    }
+                }
+                None if xx => {}
+                None => {
+                    set.insert(member, score);
+                    added += 1;
+                }
+            }
+        }
+
+        if added + changed > 0 {
+            self.touch(&key);
+        }
+
+        Ok((added, changed))
+    }
+
+    /// Returns the members of the sorted set stored at `key` between the
+    /// `start` and `stop` indices, both inclusive, with their scores.
+    ///
+    /// Members are ordered from the lowest to the highest score. Negative
+    /// indices count from the end of the set.
+    fn zrange(&self, key: &str, start: i64, stop: i64) -> Result<Vec<(Bytes, f64)>, Error> {
+        match self.sorted_set(key)? {
+            Some(set) => Ok(set.range(start, stop)),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Returns the members of the sorted set stored at `key` with a score
+    /// between `min` and `max`, with their scores.
+    ///
+    /// The first `offset` matching members are skipped, and at most `count`
+    /// members are returned.
+    fn zrangebyscore(
+        &self,
+        key: &str,
+        min: ScoreBound,
+        max: ScoreBound,
+        offset: usize,
+        count: Option<usize>,
+    ) -> Result<Vec<(Bytes, f64)>, Error> {
+        match self.sorted_set(key)? {
+            Some(set) => Ok(set.range_by_score(min, max, offset, count)),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Returns the rank of `member` in the sorted set stored at `key`, along
+    /// with its score.
+    ///
+    /// The rank is the zero-based position of the member, ordered from the
+    /// lowest to the highest score.
+    fn zrank(&self, key: &str, member: &[u8]) -> Result<Option<(usize, f64)>, Error> {
+        Ok(self
+            .sorted_set(key)?
+            .and_then(|set| Some((set.rank(member)?, set.score(member)?))))
+    }
+
+    /// Remove `members` from the sorted set stored at `key`.
+    ///
+    /// Returns the number of members that were removed. Sorted sets are never
+    /// empty; once the last member is removed, the key is removed.
+    fn zrem(&mut self, key: &str, members: &[Bytes]) -> Result<usize, Error> {
+        let set = match self.entries.get_mut(key).map(|entry| &mut entry.value) {
+            Some(Value::SortedSet(set)) => set,
+            Some(_) => return Err(Error::WrongType),
+            None => return Ok(0),
+        };
+
+        let removed = members.iter().filter(|member| set.remove(member)).count();
+
+        if set.is_empty() {
+            self.remove(key);
+        }
+
+        if removed > 0 {
+            self.touch(key);
+        }
+
+        Ok(removed)
+    }
+
+    /// Returns the sorted set stored at `key`, if any.
+    fn sorted_set(&self, key: &str) -> Result<Option<&SortedSet>, Error> {
+        match self.entries.get(key).map(|entry| &entry.value) {
+            Some(Value::SortedSet(set)) => Ok(Some(set)),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Add an entry to the stream stored at `key`, creating the stream if the
+    /// key does not exist, unless `nomkstream` is set.
+    ///
+    /// With `maxlen`, the oldest entries are then removed so at most `maxlen`
+    /// entries remain. Connections blocked reading the stream are notified.
+    ///
+    /// Returns the ID of the entry, or `None` if the stream does not exist
+    /// and `nomkstream` is set.
+    fn xadd(
+        &mut self,
+        key: String,
+        id: NewId,
+        fields: Vec<(Bytes, Bytes)>,
+        maxlen: Option<usize>,
+        nomkstream: bool,
+    ) -> Result<Option<StreamId>, Error> {
+        let created = !self.entries.contains_key(&key);
+        if created && nomkstream {
+            return Ok(None);
+        }
+
+        let entry = self
+            .entries
+            .entry(key.clone())
+            .or_insert_with(|| Entry::new(Value::Stream(Stream::default()), None));
+
+        let stream = match &mut entry.value {
+            Value::Stream(stream) => stream,
+            _ => return Err(Error::WrongType),
+        };
+
+        let id = match stream.add(id, fields, unix_time_ms()) {
+            Ok(id) => id,
+            Err(err) => {
+                if created {
+                    self.remove(&key);
+                }
+                return Err(err);
+            }
+        };
+
+        if let Some(maxlen) = maxlen {
+            stream.trim(maxlen);
+        }
+
+        self.touch(&key);
+        self.wake(&key);
+
+        Ok(Some(id))
+    }
+
+    /// Returns the entries of the stream stored at `key` with an ID between
+    /// `start` and `end`, both inclusive, up to `count` entries.
+    fn xrange(
+        &self,
+        key: &str,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+    ) -> Result<Vec<StreamEntry>, Error> {
+        match self.stream(key)? {
+            Some(stream) => Ok(stream.range(start, end, count)),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Returns the number of entries in the stream stored at `key`.
+    fn xlen(&self, key: &str) -> Result<usize, Error> {
+        Ok(self.stream(key)?.map(Stream::len).unwrap_or(0))
+    }
+
+    /// Remove the oldest entries of the stream stored at `key`, so at most
+    /// `maxlen` entries remain.
+    ///
+    /// Returns the number of entries removed. The stream is kept, even once
+    /// it has no entries left.
+    fn xtrim(&mut self, key: &str, maxlen: usize) -> Result<usize, Error> {
+        let removed = match self.entries.get_mut(key).map(|entry| &mut entry.value) {
+            Some(Value::Stream(stream)) => stream.trim(maxlen),
+            Some(_) => return Err(Error::WrongType),
+            None => return Ok(0),
+        };
+
+        if removed > 0 {
+            self.touch(key);
+        }
+
+        Ok(removed)
+    }
+
+    /// Returns the ID of the last entry added to the stream stored at `key`,
+    /// or `0-0` if the key does not exist.
+    fn stream_last_id(&self, key: &str) -> Result<StreamId, Error> {
+        Ok(self
+            .stream(key)?
+            .map(Stream::last_id)
+            .unwrap_or(StreamId::MIN))
+    }
+
+    /// Create the consumer group `group` of the stream stored at `key`,
+    /// which delivers the entries with an ID greater than `id`. With `None`,
+    /// only entries added from now on are delivered.
+    ///
+    /// If the key does not exist, an empty stream is created if `mkstream`
+    /// is set, and an error is returned otherwise.
+    ///
+    /// Returns the ID of the last entry considered delivered to the group.
+    fn xgroup_create(
+        &mut self,
+        key: &str,
+        group: &str,
+        id: Option<StreamId>,
+        mkstream: bool,
+    ) -> Result<StreamId, Error> {
+        if mkstream && !self.entries.contains_key(key) {
+            let entry = Entry::new(Value::Stream(Stream::default()), None);
+            self.entries.insert(key.to_string(), entry);
+        }
+
+        let stream = self.stream_mut(key)?.ok_or(Error::XGroupNoKey)?;
+        let id = id.unwrap_or_else(|| stream.last_id());
+        stream.create_group(group, id)?;
+
+        self.touch(key);
+
+        Ok(id)
+    }
+
+    /// Set the ID of the last entry delivered to the consumer group `group`
+    /// of the stream stored at `key`. With `None`, the ID of the last entry
+    /// of the stream is used.
+    ///
+    /// Returns the ID that was set.
+    fn xgroup_setid(
+        &mut self,
+        key: &str,
+        group: &str,
+        id: Option<StreamId>,
+    ) -> Result<StreamId, Error> {
+        let stream = self.stream_mut(key)?.ok_or(Error::XGroupNoKey)?;
+        let id = id.unwrap_or_else(|| stream.last_id());
+        stream.group_mut(group)?.set_last_delivered(id);
+
+        self.touch(key);
+
+        Ok(id)
+    }
+
+    /// Remove the consumer group `group` of the stream stored at `key`.
+    ///
+    /// Connections blocked reading from the group are notified, and then
+    /// fail as the group no longer exists.
+    ///
+    /// Returns `true` if the group existed.
+    fn xgroup_destroy(&mut self, key: &str, group: &str) -> Result<bool, Error> {
+        let stream = self.stream_mut(key)?.ok_or(Error::XGroupNoKey)?;
+
+        if !stream.destroy_group(group) {
+            return Ok(false);
+        }
+
+        self.touch(key);
+        self.wake(key);
+
+        Ok(true)
+    }
+
+    /// Create the consumer `consumer` in the group `group` of the stream
+    /// stored at `key`.
+    ///
+    /// Returns `true` if the consumer was created, `false` if it already
+    /// existed.
+    fn xgroup_create_consumer(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+    ) -> Result<bool, Error> {
+        let stream = self.stream_mut(key)?.ok_or(Error::XGroupNoKey)?;
+        let created = stream
+            .group_mut(group)?
+            .create_consumer(consumer, unix_time_ms());
+
+        if created {
+            self.touch(key);
+        }
+
+        Ok(created)
+    }
+
+    /// Remove the consumer `consumer` from the group `group` of the stream
+    /// stored at `key`, along with its pending entries.
+    ///
+    /// Returns the number of entries which were pending for the consumer.
+    fn xgroup_del_consumer(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+    ) -> Result<usize, Error> {
+        let stream = self.stream_mut(key)?.ok_or(Error::XGroupNoKey)?;
+        let removed = stream.group_mut(group)?.delete_consumer(consumer);
+
+        self.touch(key);
+
+        Ok(removed)
+    }
+
+    /// Acknowledge the entries `ids` pending in the group `group` of the
+    /// stream stored at `key`.
+    ///
+    /// Returns the number of entries which were pending.
+    fn xack(&mut self, key: &str, group: &str, ids: &[StreamId]) -> Result<usize, Error> {
+        let group = match self.stream_mut(key)?.map(|stream| stream.group_mut(group)) {
+            Some(Ok(group)) => group,
+            _ => return Ok(0),
+        };
+
+        let acked = ids.iter().filter(|id| group.ack(**id)).count();
+
+        if acked > 0 {
+            self.touch(key);
+        }
+
+        Ok(acked)
+    }
+
+    /// Returns the consumer group `group` of the stream stored at `key`, to
+    /// inspect its pending entries.
+    fn xgroup(&self, key: &str, group: &str) -> Result<&Group, Error> {
+        self.stream(key)?.ok_or(Error::NoGroup)?.group(group)
+    }
+
+    /// Transfer the pending entries `ids` of the group `group` of the stream
+    /// stored at `key` to `consumer`. See `Stream::claim`.
+    ///
+    /// Returns the claimed entries.
+    fn xclaim(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle_ms: u64,
+        ids: &[StreamId],
+        options: ClaimOptions,
+    ) -> Result<Vec<StreamEntry>, Error> {
+        let stream = self.stream_mut(key)?.ok_or(Error::NoGroup)?;
+        let claimed = stream.claim(group, consumer, min_idle_ms, ids, options, unix_time_ms())?;
+
+        self.touch(key);
+
+        Ok(claimed)
+    }
+
+    /// Returns the stream stored at `key`, if any.
+    fn stream(&self, key: &str) -> Result<Option<&Stream>, Error> {
+        match self.entries.get(key).map(|entry| &entry.value) {
+            Some(Value::Stream(stream)) => Ok(Some(stream)),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the stream stored at `key`, if any, to be modified.
+    fn stream_mut(&mut self, key: &str) -> Result<Option<&mut Stream>, Error> {
+        match self.entries.get_mut(key).map(|entry| &mut entry.value) {
+            Some(Value::Stream(stream)) => Ok(Some(stream)),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Register `waiter` to be notified when a value is pushed to `key`.
+    fn block(&mut self, key: &str, waiter: &Arc<Notify>) {
+        let waiters = self.waiters.entry(key.to_string()).or_default();
+
+        if !waiters.iter().any(|w| Arc::ptr_eq(w, waiter)) {
+            waiters.push(waiter.clone());
+        }
+    }
+
+    /// Remove `waiter` from `key`.
+    fn unblock(&mut self, key: &str, waiter: &Arc<Notify>) {
+        if let Some(waiters) = self.waiters.get_mut(key) {
+            waiters.retain(|w| !Arc::ptr_eq(w, waiter));
+
+            if waiters.is_empty() {
+                self.waiters.remove(key);
+            }
+        }
+    }
+
+    /// Notify all connections waiting for data to be pushed to `key`.
+    ///
+    /// The waiters are removed. Those that do not find a value when retrying
+    /// register themselves again.
+    fn wake(&mut self, key: &str) {
+        if let Some(waiters) = self.waiters.remove(key) {
+            for waiter in waiters {
+                waiter.notify_one();
+            }
+        }
+    }
+
+    /// Watch `key` for modifications with the `dirty` flag.
+    fn watch(&mut self, key: &str, dirty: &Arc<AtomicBool>) {
+        self.watched
+            .entry(key.to_string())
+            .or_default()
+            .push(dirty.clone());
+    }
+
+    /// Stop watching `key` with the `dirty` flag.
+    fn unwatch(&mut self, key: &str, dirty: &Arc<AtomicBool>) {
+        if let Some(flags) = self.watched.get_mut(key) {
+            flags.retain(|flag| !Arc::ptr_eq(flag, dirty));
+
+            if flags.is_empty() {
+                self.watched.remove(key);
+            }
+        }
+    }
+
+    /// Record that `key` was modified, aborting the transactions of all
+    /// connections watching it, and updating the memory used by its entry.
+    fn touch(&mut self, key: &str) {
+        if let Some(flags) = self.watched.remove(key) {
+            for flag in flags {
+                flag.store(true, Ordering::Release);
+            }
+        }
+
+        if let Some(entry) = self.entries.get_mut(key) {
+            let size = eviction::entry_size(key, &entry.value);
+            self.used_memory = self.used_memory - entry.size + size;
+            entry.size = size;
+        }
+    }
+
+    /// Record an access to `key`, which makes it less likely to be evicted by
+    /// the LRU and LFU policies.
+    fn record_access(&mut self, key: &str) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.access.record(Instant::now());
+        }
+    }
+
+    /// Remove the entry associated with `key`, along with its expiration.
+    fn remove(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.entries.swap_remove(key)?;
+        self.used_memory -= entry.size;
+
+        if let Some(when) = entry.expires_at {
+            self.expirations.remove(&(when, key.to_string()));
+        }
+
+        Some(entry)
+    }
+
+    fn next_expiration(&self) -> Option<Instant> {
+        self.expirations
+            .iter()
+            .next()
+            .map(|expiration| expiration.0)
+    }
+}
+
+impl Global {
+    /// Propagate `frame`, a command that modified the database numbered `db`,
+    /// to the append-only file and to the connected replicas.
+    ///
+    /// The command is preceded by a `SELECT` if it was applied to another
+    /// database than the previous one. Does nothing if persistence is disabled
+    /// and no replica is connected.
+    fn propagate(&mut self, db: usize, frame: &Frame) {
+        if self.propagated_db != Some(db) {
+            self.propagated_db = Some(db);
+            self.propagate_frame(&Select::new(db).into_frame());
+        }
+
+        self.propagate_frame(frame);
+    }
+
+    /// Append `frame` to the append-only file, and stream it to the connected
+    /// replicas.
+    fn propagate_frame(&mut self, frame: &Frame) {
+        if let Some(aof) = &self.aof {
+            aof.append(frame);
+        }
+
+        if self.replicas.receiver_count() > 0 {
+            let frame = replication::normalize(frame);
+
+            // A replica tracks the offset of the stream received from its
+            // master, which is the same as the one it forwards.
+            if self.master.is_none() {
+                self.repl_offset += replication::command_len(&frame);
+            }
+
+            let _ = self.replicas.send(frame);
+        }
+    }
+}
+
+impl<'a> State<'a> {
+    /// Returns the shard holding `key`, which must be locked.
+    fn shard(&self, key: &str) -> &Shard {
+        self.shards[self.shared.shard_index(key)]
+            .as_deref()
+            .expect("the shard holding the key is not locked")
+    }
+
+    /// Returns the shard holding `key`, which must be locked, to be modified.
+    fn shard_mut(&mut self, key: &str) -> &mut Shard {
+        self.shards[self.shared.shard_index(key)]
+            .as_deref_mut()
+            .expect("the shard holding the key is not locked")
+    }
+
+    /// Returns the locked shards.
+    fn locked(&self) -> impl Iterator<Item = &Shard> {
+        self.shards.iter().flatten().map(|shard| &**shard)
+    }
+
+    /// Lock the global state for reading.
+    fn global(&self) -> RwLockReadGuard<'a, Global> {
+        self.shared.global.read().unwrap()
+    }
+
+    /// Lock the global state for writing.
+    fn global_mut(&self) -> RwLockWriteGuard<'a, Global> {
+        self.shared.global.write().unwrap()
+    }
+
+    /// Select the database numbered `index`, which must exist, in all locked
+    /// shards.
+    fn select(&mut self, index: usize) {
+        for shard in self.shards.iter_mut().flatten() {
+            shard.select(index);
+        }
+
+        self.selected = index;
+    }
+
+    /// Returns the estimated number of bytes used by the entries of the
+    /// locked shards.
+    fn used_memory(&self) -> usize {
+        self.locked().map(|shard| shard.used_memory).sum()
+    }
+
+    /// Get the string value associated with a key.
+    pub(crate) fn get(&self, key: &str) -> Result<Option<Bytes>, Error> {
+        self.shard(key).get(key)
+    }
+
+    /// Set the string value associated with a key, expiring at `expires_at`
+    /// if set.
+    pub(crate) fn set_at(&mut self, key: String, value: Bytes, expires_at: Option<Instant>) {
+        self.shard_mut(&key).set_at(key, value, expires_at)
+    }
+
+    /// Remove `key` and return its value, which must be a string.
+    pub(crate) fn getdel(&mut self, key: &str) -> Result<Option<Bytes>, Error> {
+        self.shard_mut(key).getdel(key)
+    }
+
+    /// Increment the integer stored at `key` by `delta`.
+    pub(crate) fn incr_by(&mut self, key: &str, delta: i64) -> Result<i64, Error> {
+        self.shard_mut(key).incr_by(key, delta)
+    }
+
+    /// Increment the floating point number stored at `key` by `delta`.
+    pub(crate) fn incr_by_float(&mut self, key: &str, delta: f64) -> Result<f64, Error> {
+        self.shard_mut(key).incr_by_float(key, delta)
+    }
+
+    /// Append `value` to the string stored at `key`, creating the key if it
+    /// does not exist.
+    pub(crate) fn append(&mut self, key: &str, value: &[u8]) -> Result<usize, Error> {
+        self.shard_mut(key).append(key, value)
+    }
+
+    /// Returns the length of the string stored at `key`, or `0` if the key
+    /// does not exist.
+    pub(crate) fn strlen(&self, key: &str) -> Result<usize, Error> {
+        self.shard(key).strlen(key)
+    }
+
+    /// Returns `true` if `key` holds a value, of any type.
+    pub(crate) fn contains_key(&self, key: &str) -> bool {
+        self.shard(key).contains_key(key)
+    }
+
+    /// Returns the `Instant` at which `key` expires, if it exists and has an
+    /// expiration.
+    pub(crate) fn expires_at(&self, key: &str) -> Option<Instant> {
+        self.shard(key).expires_at(key)
+    }
+
+    /// Set the `Instant` at which `key` expires, if `condition` holds.
+    pub(crate) fn expire(
+        &mut self,
+        key: &str,
+        when: Instant,
+        condition: Option<ExpireCondition>,
+    ) -> bool {
+        self.shard_mut(key).expire(key, when, condition)
+    }
+
+    /// Remove the expiration of `key`.
+    pub(crate) fn persist(&mut self, key: &str) -> bool {
+        self.shard_mut(key).persist(key)
+    }
+
+    /// Returns the remaining time to live of `key`.
+    pub(crate) fn ttl(&self, key: &str) -> Option<Option<Duration>> {
+        self.shard(key).ttl(key)
+    }
+
+    /// Returns the name of the type of the value stored at `key`, or `none`
+    /// if the key does not exist.
+    pub(crate) fn key_type(&self, key: &str) -> &'static str {
+        self.shard(key).key_type(key)
+    }
+
+    /// Move `key` from the selected database to the database numbered
+    /// `index`.
+    pub(crate) fn move_key(&mut self, key: &str, index: usize) -> Result<bool, Error> {
+        self.shard_mut(key).move_key(key, index)
+    }
+
+    /// Insert `value` at `key`, replacing any existing entry, and expire it
+    /// after `expire` if set.
+    fn insert(&mut self, key: String, value: Value, expire: Option<Duration>) {
+        self.shard_mut(&key).insert(key, value, expire)
+    }
+
+    /// Insert `value` at `key`, replacing any existing entry, and expire it at
+    /// `expires_at` if set.
+    fn insert_at(&mut self, key: String, value: Value, expires_at: Option<Instant>) {
+        self.shard_mut(&key).insert_at(key, value, expires_at)
+    }
+
+    /// Push `values` onto one end of the list stored at `key`, creating the
+    /// list if the key does not exist.
+    pub(crate) fn push(
+        &mut self,
+        key: String,
+        values: Vec<Bytes>,
+        side: Side,
+    ) -> Result<usize, Error> {
+        self.shard_mut(&key).push(key, values, side)
+    }
+
+    /// Pop up to `count` values from one end of the list stored at `key`.
+    pub(crate) fn pop(
+        &mut self,
+        key: &str,
+        side: Side,
+        count: usize,
+    ) -> Result<Option<Vec<Bytes>>, Error> {
+        self.shard_mut(key).pop(key, side, count)
+    }
+
+    /// Returns the values of the list stored at `key` between the `start` and
+    /// `stop` indices, both inclusive.
+    pub(crate) fn lrange(&self, key: &str, start: i64, stop: i64) -> Result<Vec<Bytes>, Error> {
+        self.shard(key).lrange(key, start, stop)
+    }
+
+    /// Returns the length of the list stored at `key`, or `0` if the key does
+    /// not exist.
+    pub(crate) fn llen(&self, key: &str) -> Result<usize, Error> {
+        self.shard(key).llen(key)
+    }
+
+    /// Set `fields` of the hash stored at `key`, creating the hash if the key
+    /// does not exist.
+    pub(crate) fn hset(
+        &mut self,
+        key: String,
+        fields: Vec<(Bytes, Bytes)>,
+    ) -> Result<usize, Error> {
+        self.shard_mut(&key).hset(key, fields)
+    }
+
+    /// Returns the value of `field` in the hash stored at `key`.
+    pub(crate) fn hget(&self, key: &str, field: &[u8]) -> Result<Option<Bytes>, Error> {
+        self.shard(key).hget(key, field)
+    }
+
+    /// Remove `fields` from the hash stored at `key`.
+    pub(crate) fn hdel(&mut self, key: &str, fields: &[Bytes]) -> Result<usize, Error> {
+        self.shard_mut(key).hdel(key, fields)
+    }
+
+    /// Returns all fields and values of the hash stored at `key`, in no
+    /// particular order.
+    pub(crate) fn hgetall(&self, key: &str) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        self.shard(key).hgetall(key)
+    }
+
+    /// Increment the integer stored in `field` of the hash stored at `key` by
+    /// `delta`.
+    pub(crate) fn hincrby(&mut self, key: String, field: Bytes, delta: i64) -> Result<i64, Error> {
+        self.shard_mut(&key).hincrby(key, field, delta)
+    }
+
+    /// Add `members` with their scores to the sorted set stored at `key`,
+    /// creating the sorted set if the key does not exist.
+    pub(crate) fn zadd(
+        &mut self,
+        key: String,
+        members: Vec<(f64, Bytes)>,
+        nx: bool,
+        xx: bool,
+    ) -> Result<(usize, usize), Error> {
+        self.shard_mut(&key).zadd(key, members, nx, xx)
+    }
+
+    /// Returns the members of the sorted set stored at `key` between the
+    /// `start` and `stop` indices, both inclusive, with their scores.
+    pub(crate) fn zrange(
+        &self,
+        key: &str,
+        start: i64,
+        stop: i64,
+    ) -> Result<Vec<(Bytes, f64)>, Error> {
+        self.shard(key).zrange(key, start, stop)
+    }
+
+    /// Returns the members of the sorted set stored at `key` with a score
+    /// between `min` and `max`, with their scores.
+    pub(crate) fn zrangebyscore(
+        &self,
+        key: &str,
+        min: ScoreBound,
+        max: ScoreBound,
+        offset: usize,
+        count: Option<usize>,
+    ) -> Result<Vec<(Bytes, f64)>, Error> {
+        self.shard(key).zrangebyscore(key, min, max, offset, count)
+    }
+
+    /// Returns the rank of `member` in the sorted set stored at `key`, along
+    /// with its score.
+    pub(crate) fn zrank(&self, key: &str, member: &[u8]) -> Result<Option<(usize, f64)>, Error> {
+        self.shard(key).zrank(key, member)
+    }
+
+    /// Remove `members` from the sorted set stored at `key`.
+    pub(crate) fn zrem(&mut self, key: &str, members: &[Bytes]) -> Result<usize, Error> {
+        self.shard_mut(key).zrem(key, members)
+    }
+
+    /// Add an entry to the stream stored at `key`, creating the stream if the
+    /// key does not exist, unless `nomkstream` is set.
+    pub(crate) fn xadd(
+        &mut self,
+        key: String,
+        id: NewId,
+        fields: Vec<(Bytes, Bytes)>,
+        maxlen: Option<usize>,
+        nomkstream: bool,
+    ) -> Result<Option<StreamId>, Error> {
+        self.shard_mut(&key)
+            .xadd(key, id, fields, maxlen, nomkstream)
+    }
+
+    /// Returns the entries of the stream stored at `key` with an ID between
+    /// `start` and `end`, both inclusive, up to `count` entries.
+    pub(crate) fn xrange(
+        &self,
+        key: &str,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+    ) -> Result<Vec<StreamEntry>, Error> {
+        self.shard(key).xrange(key, start, end, count)
+    }
+
+    /// Returns the number of entries in the stream stored at `key`.
+    pub(crate) fn xlen(&self, key: &str) -> Result<usize, Error> {
+        self.shard(key).xlen(key)
+    }
+
+    /// Remove the oldest entries of the stream stored at `key`, so at most
+    /// `maxlen` entries remain.
+    pub(crate) fn xtrim(&mut self, key: &str, maxlen: usize) -> Result<usize, Error> {
+        self.shard_mut(key).xtrim(key, maxlen)
+    }
+
+    /// Returns the ID of the last entry added to the stream stored at `key`,
+    /// or `0-0` if the key does not exist.
+    pub(crate) fn stream_last_id(&self, key: &str) -> Result<StreamId, Error> {
+        self.shard(key).stream_last_id(key)
+    }
+
+    /// Create the consumer group `group` of the stream stored at `key`,
+    /// which delivers the entries with an ID greater than `id`. With `None`,
+    /// only entries added from now on are delivered.
+    pub(crate) fn xgroup_create(
+        &mut self,
+        key: &str,
+        group: &str,
+        id: Option<StreamId>,
+        mkstream: bool,
+    ) -> Result<StreamId, Error> {
+        self.shard_mut(key).xgroup_create(key, group, id, mkstream)
+    }
+
+    /// Set the ID of the last entry delivered to the consumer group `group`
+    /// of the stream stored at `key`. With `None`, the ID of the last entry
+    /// of the stream is used.
+    pub(crate) fn xgroup_setid(
+        &mut self,
+        key: &str,
+        group: &str,
+        id: Option<StreamId>,
+    ) -> Result<StreamId, Error> {
+        self.shard_mut(key).xgroup_setid(key, group, id)
+    }
+
+    /// Remove the consumer group `group` of the stream stored at `key`.
+    pub(crate) fn xgroup_destroy(&mut self, key: &str, group: &str) -> Result<bool, Error> {
+        self.shard_mut(key).xgroup_destroy(key, group)
+    }
+
+    /// Create the consumer `consumer` in the group `group` of the stream
+    /// stored at `key`.
+    pub(crate) fn xgroup_create_consumer(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+    ) -> Result<bool, Error> {
+        self.shard_mut(key)
+            .xgroup_create_consumer(key, group, consumer)
+    }
+
+    /// Remove the consumer `consumer` from the group `group` of the stream
+    /// stored at `key`, along with its pending entries.
+    pub(crate) fn xgroup_del_consumer(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+    ) -> Result<usize, Error> {
+        self.shard_mut(key)
+            .xgroup_del_consumer(key, group, consumer)
+    }
+
+    /// Acknowledge the entries `ids` pending in the group `group` of the
+    /// stream stored at `key`.
+    pub(crate) fn xack(
+        &mut self,
+        key: &str,
+        group: &str,
+        ids: &[StreamId],
+    ) -> Result<usize, Error> {
+        self.shard_mut(key).xack(key, group, ids)
+    }
+
+    /// Returns the consumer group `group` of the stream stored at `key`, to
+    /// inspect its pending entries.
+    pub(crate) fn xgroup(&self, key: &str, group: &str) -> Result<&Group, Error> {
+        self.shard(key).xgroup(key, group)
+    }
+
+    /// Transfer the pending entries `ids` of the group `group` of the stream
+    /// stored at `key` to `consumer`. See `Stream::claim`.
+    pub(crate) fn xclaim(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle_ms: u64,
+        ids: &[StreamId],
+        options: ClaimOptions,
+    ) -> Result<Vec<StreamEntry>, Error> {
+        self.shard_mut(key)
+            .xclaim(key, group, consumer, min_idle_ms, ids, options)
+    }
+
+    /// Returns the stream stored at `key`, if any.
+    fn stream(&self, key: &str) -> Result<Option<&Stream>, Error> {
+        self.shard(key).stream(key)
+    }
+
+    /// Returns the stream stored at `key`, if any, to be modified.
+    fn stream_mut(&mut self, key: &str) -> Result<Option<&mut Stream>, Error> {
+        self.shard_mut(key).stream_mut(key)
+    }
+
+    /// Notify all connections waiting for data to be pushed to `key`.
+    fn wake(&mut self, key: &str) {
+        self.shard_mut(key).wake(key)
+    }
+
+    /// Record that `key` was modified, aborting the transactions of all
+    /// connections watching it, and updating the memory used by its entry.
+    fn touch(&mut self, key: &str) {
+        self.shard_mut(key).touch(key)
+    }
+
+    /// Record an access to `key`, which makes it less likely to be evicted by
+    /// the LRU and LFU policies.
+    pub(crate) fn record_access(&mut self, key: &str) {
+        self.shard_mut(key).record_access(key)
+    }
+
+    /// Remove the entry associated with `key`, along with its expiration.
+    fn remove(&mut self, key: &str) -> Option<Entry> {
+        self.shard_mut(key).remove(key)
+    }
+
+    /// Remove `keys`, of any type.
+    ///
+    /// Returns the number of keys that were removed.
+    pub(crate) fn del(&mut self, keys: &[String]) -> usize {
+        let mut removed = 0;
+
+        for key in keys {
+            if self.remove(key).is_some() {
+                self.touch(key);
+                removed += 1;
+            }
+        }
+
+        removed
+    }
+
+    /// Returns the number of `keys` that exist. A key given several times is
+    /// counted as many times.
+    pub(crate) fn exists(&self, keys: &[String]) -> usize {
+        keys.iter().filter(|key| self.contains_key(key)).count()
+    }
+
+    /// Returns all keys matching the glob-style `pattern`.
+    pub(crate) fn keys(&self, pattern: &[u8]) -> Vec<String> {
+        self.locked()
+            .flat_map(|shard| shard.keys(pattern))
+            .collect()
+    }
+
+    /// Iterate over the key space, starting at `cursor`.
+    ///
+    /// Keys are visited in the order of a hash of their name, and the cursor
+    /// is the position of the next key to visit in that order. Unlike the
+    /// iteration order of the `HashMap`, the position of a key does not change
+    /// when other keys are added or removed. A full iteration therefore
+    /// returns every key that exists during the whole iteration, and never
+    /// returns a key twice.
+    ///
+    /// About `count` keys are visited, then filtered by `pattern` and `kind`.
+    /// Returns the cursor to continue the iteration with, which is `0` once
+    /// all keys have been visited, along with the matching keys.
+    pub(crate) fn scan(
+        &self,
+        cursor: u64,
+        count: usize,
+        pattern: Option<&[u8]>,
+        kind: Option<&str>,
+    ) -> (u64, Vec<String>) {
+        let mut remaining: Vec<(u64, &String)> = self
+            .locked()
+            .flat_map(|shard| shard.entries.keys())
+            .map(|key| (scan_position(key), key))
+            .filter(|(position, _)| *position >= cursor)
+            .collect();
+        remaining.sort_unstable();
+
+        // Keys sharing a position are all visited at once, as the cursor
+        // cannot point in between them.
+        let mut end = count.max(1).min(remaining.len());
+        while end < remaining.len() && end > 0 && remaining[end].0 == remaining[end - 1].0 {
+            end += 1;
+        }
+
+        let next = remaining
+            .get(end)
+            .map(|(position, _)| *position)
+            .unwrap_or(0);
+
+        let keys = remaining[..end]
+            .iter()
+            .map(|(_, key)| *key)
+            .filter(|key| match pattern {
+                Some(pattern) => glob::matches(pattern, key.as_bytes()),
+                None => true,
+            })
+            .filter(|key| match kind {
+                Some(kind) => self.key_type(key).eq_ignore_ascii_case(kind),
+                None => true,
+            })
+            .cloned()
+            .collect();
+
+        (next, keys)
+    }
+
+    /// Rename `key` to `newkey`, overwriting any value stored at `newkey`.
+    ///
+    /// The time to live of the key is retained.
+    pub(crate) fn rename(&mut self, key: &str, newkey: String) -> Result<(), Error> {
+        let entry = self.remove(key).ok_or(Error::NoSuchKey)?;
+        self.touch(key);
+
+        // A list moved to `newkey` may be popped by a blocked connection.
+        let wake = matches!(entry.value, Value::List(_));
+
+        self.insert_at(newkey.clone(), entry.value, entry.expires_at);
+
+        if wake {
+            self.wake(&newkey);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of keys.
+    pub(crate) fn dbsize(&self) -> usize {
+        self.locked().map(|shard| shard.entries.len()).sum()
+    }
+
+    /// Remove all keys of the selected database.
+    pub(crate) fn flushdb(&mut self) {
+        for shard in self.shards.iter_mut().flatten() {
+            shard.flushdb();
+        }
+    }
+
+    /// Remove all keys of all databases.
+    pub(crate) fn flushall(&mut self) {
+        for shard in self.shards.iter_mut().flatten() {
+            shard.flushall();
+        }
+    }
+
+    /// Swap the data of the databases numbered `a` and `b`.
+    ///
+    /// Connections keep the database they selected, and see the data of the
+    /// other one from then on. The transactions of connections watching keys
+    /// of either database are aborted, and connections blocked on either
+    /// database retry their operation.
+    pub(crate) fn swapdb(&mut self, a: usize, b: usize) -> Result<(), Error> {
+        if a >= self.shared.databases || b >= self.shared.databases {
+            return Err(Error::DbIndexOutOfRange);
+        }
+
+        for shard in self.shards.iter_mut().flatten() {
+            shard.swapdb(a, b)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns, for each of `keys`, the entries of the stream with an ID
+    /// greater than the matching ID in `ids`, up to `count` entries per
+    /// stream.
+    ///
+    /// Streams without such entries are omitted.
+    pub(crate) fn xread(
+        &self,
+        keys: &[String],
+        ids: &[StreamId],
+        count: Option<usize>,
+    ) -> Result<StreamRead, Error> {
+        let mut streams = vec![];
+
+        for (key, id) in keys.iter().zip(ids) {
+            let entries = match (self.stream(key)?, id.next()) {
+                (Some(stream), Some(start)) => stream.range(start, StreamId::MAX, count),
+                _ => continue,
+            };
+
+            if !entries.is_empty() {
+                streams.push((key.clone(), entries));
+            }
+        }
+
+        Ok(streams)
+    }
+
+    /// Same as `xread`, but if no stream has new entries, `waiter` is
+    /// registered under each of `keys` and will be notified once an entry is
+    /// added to one of them. See `pop_or_block`.
+    pub(crate) fn xread_or_block(
+        &mut self,
+        keys: &[String],
+        ids: &[StreamId],
+        count: Option<usize>,
+        waiter: &Arc<Notify>,
+    ) -> Result<Option<StreamRead>, Error> {
+        let streams = self.xread(keys, ids, count)?;
+
+        if streams.is_empty() {
+            self.block(keys, waiter);
+            return Ok(None);
+        }
+
+        Ok(Some(streams))
+    }
+
+    /// Read the streams stored at each key of `streams` as `consumer` of the
+    /// group `group`, up to `count` entries per stream. See
+    /// `Stream::read_group`.
+    ///
+    /// Streams read for new entries are omitted if there are none. Streams
+    /// read for pending entries are always included. Nothing is read if the
+    /// group does not exist for one of the streams.
+    pub(crate) fn xreadgroup(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        streams: &[(String, GroupReadId)],
+        count: Option<usize>,
+        noack: bool,
+    ) -> Result<StreamRead, Error> {
+        for (key, _) in streams {
+            self.stream(key)?.ok_or(Error::NoGroup)?.group(group)?;
+        }
+
+        let now = unix_time_ms();
+        let mut read = vec![];
+
+        for (key, id) in streams {
+            let entries = match self.stream_mut(key)? {
+                Some(stream) => stream.read_group(group, consumer, *id, count, noack, now)?,
+                None => continue,
+            };
+
+            if !entries.is_empty() || matches!(id, GroupReadId::Pending(_)) {
+                read.push((key.clone(), entries));
+            }
+        }
+
+        Ok(read)
+    }
+
+    /// Same as `xreadgroup`, but if nothing is read, `waiter` is registered
+    /// under each of the keys and will be notified once an entry is added to
+    /// one of them. See `pop_or_block`.
+    pub(crate) fn xreadgroup_or_block(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        streams: &[(String, GroupReadId)],
+        count: Option<usize>,
+        noack: bool,
+        waiter: &Arc<Notify>,
+    ) -> Result<Option<StreamRead>, Error> {
+        let read = self.xreadgroup(group, consumer, streams, count, noack)?;
+
+        if read.is_empty() {
+            let keys: Vec<String> = streams.iter().map(|(key, _)| key.clone()).collect();
+            self.block(&keys, waiter);
+            return Ok(None);
+        }
+
+        Ok(Some(read))
+    }
+
+    /// Pop a single value from `side` of the first non-empty list in `keys`.
+    ///
+    /// If all lists are empty, `waiter` is registered under each of `keys`
+    /// and will be notified once a value is pushed to one of them. Checking
+    /// the lists and registering happens atomically, so no push can be missed
+    /// in between. The caller is responsible for calling `unblock` once it
+    /// stops waiting.
+    ///
+    /// Returns the key the value was popped from, along with the value.
+    pub(crate) fn pop_or_block(
+        &mut self,
+        keys: &[String],
+        side: Side,
+        waiter: &Arc<Notify>,
+    ) -> Result<Option<(String, Bytes)>, Error> {
+        for key in keys {
+            if let Some(value) = self.pop(key, side, 1)?.and_then(|mut values| values.pop()) {
+                return Ok(Some((key.clone(), value)));
+            }
+        }
+
+        self.block(keys, waiter);
+
+        Ok(None)
+    }
+
+    /// Atomically pop a value from the `from` side of the list stored at
+    /// `source` and push it to the `to` side of the list stored at
+    /// `destination`.
+    ///
+    /// If the source list is empty, `waiter` is registered the same way as
+    /// with `pop_or_block`.
+    ///
+    /// Returns the value that was moved.
+    pub(crate) fn move_or_block(
+        &mut self,
+        source: &str,
+        destination: &str,
+        from: Side,
+        to: Side,
+        waiter: &Arc<Notify>,
+    ) -> Result<Option<Bytes>, Error> {
+        // Check the type of the destination **before** popping. Otherwise, the
+        // value would be lost when the push fails.
+        if !matches!(self.key_type(destination), "list" | "none") {
+            return Err(Error::WrongType);
+        }
+
+        match self
+            .pop(source, from, 1)?
+            .and_then(|mut values| values.pop())
+        {
+            Some(value) => {
+                self.push(destination.to_string(), vec![value.clone()], to)?;
+                Ok(Some(value))
+            }
+            None => {
+                self.block(&[source.to_string()], waiter);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Register `waiter` to be notified when a value is pushed to any of
+    /// `keys`.
+    fn block(&mut self, keys: &[String], waiter: &Arc<Notify>) {
+        for key in keys {
+            self.shard_mut(key).block(key, waiter);
+        }
+    }
+
+    /// Remove `waiter` from all of `keys`. Called when a blocked connection
+    /// stops waiting, whether it received a value, timed out or is shutting
+    /// down.
+    pub(crate) fn unblock(&mut self, keys: &[String], waiter: &Arc<Notify>) {
+        for key in keys {
+            self.shard_mut(key).unblock(key, waiter);
+        }
+    }
+
+    /// Watch `keys` for modifications. `dirty` is set as soon as one of them
+    /// is modified.
+    pub(crate) fn watch(&mut self, keys: &[String], dirty: &Arc<AtomicBool>) {
+        for key in keys {
+            self.shard_mut(key).watch(key, dirty);
+        }
+    }
+
+    /// Stop watching `keys` with the `dirty` flag.
+    pub(crate) fn unwatch(&mut self, keys: &[String], dirty: &Arc<AtomicBool>) {
+        for key in keys {
+            self.shard_mut(key).unwatch(key, dirty);
+        }
+    }
+
+    /// Cache `script`, and return its SHA1 digest.
+    pub(crate) fn load_script(&mut self, script: Bytes) -> String {
+        let mut global = self.global_mut();
+        let sha1 = scripting::sha1(&script);
+        global.scripts.insert(sha1.clone(), script);
+        sha1
+    }
+
+    /// Returns the cached script with the given SHA1 digest.
+    ///
+    /// Digests are compared case insensitively, as clients may send them in
+    /// upper case.
+    pub(crate) fn script(&self, sha1: &str) -> Option<Bytes> {
+        let global = self.global();
+        global.scripts.get(&sha1.to_lowercase()).cloned()
+    }
+
+    /// Remove all scripts from the cache.
+    pub(crate) fn flush_scripts(&mut self) {
+        let mut global = self.global_mut();
+        global.scripts.clear();
+    }
+
+    /// Returns the maximum time a Lua script may run before it is aborted.
+    pub(crate) fn lua_time_limit(&self) -> Duration {
+        let global = self.global();
+        global.config.lua_time_limit
+    }
+
+    /// Returns the name and value of the settings matching any of
+    /// `patterns`.
+    pub(crate) fn config_get(&self, patterns: &[String]) -> Vec<(String, String)> {
+        let global = self.global();
+        let mut params: Vec<(String, String)> = vec![];
+
+        for pattern in patterns {
+            for (name, value) in global.config.matching(pattern) {
+                if !params.iter().any(|(param, _)| *param == name) {
+                    params.push((name, value));
+                }
+            }
+        }
+
+        params
+    }
+
+    /// Change the settings named in `params` to the given values.
+    ///
+    /// Either all settings are changed, or none of them is if one of the
+    /// settings cannot be changed while running or a value is invalid.
+    pub(crate) fn config_set(&mut self, params: &[(String, String)]) -> Result<(), Error> {
+        let mut global = self.global_mut();
+        let mut config = global.config.clone();
+
+        for (name, value) in params {
+            if config.get(name).is_none() {
+                return Err(Error::UnknownConfig(name.clone()));
+            }
+
+            if !Config::is_mutable(name) {
+                return Err(Error::InvalidConfig(
+                    name.clone(),
+                    "can't set immutable config".to_string(),
+                ));
+            }
+
+            config
+                .set(name, value)
+                .map_err(|err| Error::InvalidConfig(name.clone(), err.to_string()))?;
+        }
+
+        // Lowering the limit takes effect as connections close, once the
+        // permits in excess could be taken back.
+        let maxclients = global.config.maxclients;
+        if config.maxclients > maxclients {
+            global
+                .connections
+                .add_permits(config.maxclients - maxclients);
+        } else if config.maxclients < maxclients {
+            let connections = global.connections.clone();
+            let excess = (maxclients - config.maxclients) as u32;
+
+            tokio::spawn(async move {
+                if let Ok(permits) = connections.acquire_many_owned(excess).await {
+                    permits.forget();
+                }
+            });
+        }
+
+        global.config = config;
+
+        Ok(())
+    }
+
+    /// Write the current settings to the configuration file the server was
+    /// started with.
+    pub(crate) fn config_rewrite(&self) -> Result<(), Error> {
+        let global = self.global();
+        if global.config.config_file.is_none() {
+            return Err(Error::NoConfigFile);
+        }
+
+        global.config.rewrite().map_err(|err| {
+            error!(cause = %err, "failed to rewrite config file");
+            Error::ConfigRewriteFailed(err.to_string())
+        })
+    }
+
+    /// Evict keys following `maxmemory-policy` until the memory used is back
+    /// under `maxmemory`. The evicted keys are propagated as `DEL`.
+    ///
+    /// Returns `Error::OutOfMemory` if the memory used is still over the limit,
+    /// because the policy does not allow evicting keys or no key is left to
+    /// evict. All shards must be locked.
+    pub(crate) fn evict(&mut self) -> Result<(), Error> {
+        let maxmemory = match self.global().config.maxmemory {
+            Some(maxmemory) => maxmemory,
+            None => return Ok(()),
+        };
+
+        // A replica holds the same keys as its master, which evicts keys
+        // itself and propagates the deletions.
+        if self.global().master.is_some() {
+            return Ok(());
+        }
+
+        let selected = self.selected;
+        let mut res = Ok(());
+
+        while self.used_memory() > maxmemory {
+            let (index, key) = match self.eviction_candidate() {
+                Some(candidate) => candidate,
+                None => {
+                    res = Err(Error::OutOfMemory);
+                    break;
+                }
+            };
+
+            self.select(index);
+            self.remove(&key);
+            self.touch(&key);
+
+            let mut global = self.global_mut();
+            global.evicted_keys += 1;
+            global.propagate(index, &Del::new(&[key]).into_frame());
+        }
+
+        self.select(selected);
+
+        res
+    }
+
+    /// Returns the key to evict following `maxmemory-policy`, along with the
+    /// number of its database.
+    ///
+    /// `maxmemory-samples` keys are sampled in each database, spread over the
+    /// shards, and the best candidate among all of them is returned.
+    fn eviction_candidate(&self) -> Option<(usize, String)> {
+        let (policy, samples) = {
+            let global = self.global();
+            (
+                global.config.maxmemory_policy,
+                global.config.maxmemory_samples,
+            )
+        };
+        if policy == EvictionPolicy::NoEviction {
+            return None;
+        }
+
+        let now = Instant::now();
+        let samples = samples.div_ceil(self.shards.len());
+        let mut best: Option<(u64, usize, String)> = None;
+
+        for index in 0..self.shared.databases {
+            for shard in self.locked() {
+                let (entries, expirations) = shard.database(index);
+                let keys = eviction::sample(entries, expirations, policy.is_volatile(), samples);
+
+                for key in keys {
+                    let score = eviction::score(policy, &entries[key], now);
+
+                    if !matches!(&best, Some((best, ..)) if *best >= score) {
+                        best = Some((score, index, key.clone()));
+                    }
+                }
+            }
+        }
+
+        best.map(|(_, index, key)| (index, key))
+    }
+
+    /// Returns the `# Memory` section of `INFO`.
+    pub(crate) fn memory_info(&self) -> String {
+        let mut info = String::from("# Memory\r\n");
+        let global = self.global();
+
+        info.push_str(&format!(
+            "used_memory:{}\r\n",
+            self.shared.used_memory.load(Ordering::Relaxed)
+        ));
+        info.push_str(&format!(
+            "maxmemory:{}\r\n",
+            global.config.maxmemory.unwrap_or(0)
+        ));
+        info.push_str(&format!(
+            "maxmemory_policy:{}\r\n",
+            global.config.maxmemory_policy
+        ));
+
+        info
+    }
+
+    /// Returns the `# Stats` section of `INFO`.
+    pub(crate) fn stats_info(&self) -> String {
+        let global = self.global();
+        format!("# Stats\r\nevicted_keys:{}\r\n", global.evicted_keys)
+    }
+
+    /// Propagate `frame`, a command that modified the key space, to the
+    /// append-only file and to the connected replicas. See
+    /// `Global::propagate`.
+    pub(crate) fn propagate(&mut self, frame: &Frame) {
+        self.global_mut().propagate(self.selected, frame);
+    }
+
+    /// Returns `true` if commands modifying the key space are propagated,
+    /// either to the append-only file or to replicas.
+    pub(crate) fn is_propagating(&self) -> bool {
+        let global = self.global();
+        global.aof.is_some() || global.replicas.receiver_count() > 0
+    }
+
+    /// Register a new replica.
+    ///
+    /// The snapshot is taken and the replica subscribed to the command stream
+    /// while holding the locks of all shards, so no command is missed or sent
+    /// twice.
+    pub(crate) fn add_replica(&mut self) -> FullResync {
+        let snapshot = self.dump();
+        let mut global = self.global_mut();
+
+        // The replica does not know which database the stream is at.
+        global.propagated_db = None;
+
+        FullResync {
+            replid: global.replid.clone(),
+            offset: global.repl_offset,
+            snapshot,
+            updates: global.replicas.subscribe(),
+        }
+    }
+
+    /// Replace the key space with `snapshot`, received from the master during
+    /// the initial synchronization.
+    ///
+    /// Returns the number of keys loaded.
+    pub(crate) fn full_sync(
+        &mut self,
+        replid: String,
+        offset: u64,
+        snapshot: &[u8],
+    ) -> crate::Result<usize> {
+        let records = rdb::decode(snapshot)?;
+
+        self.flushall();
+        let count = self.load_records(records)?;
+
+        let mut guard = self.global_mut();
+        let global = &mut *guard;
+
+        // The previous content of the append-only file is obsolete.
+        if let Some(aof) = &global.aof {
+            aof.rewrite(self.to_commands());
+            global.propagated_db = None;
+        }
+
+        global.replid = replid;
+        global.repl_offset = offset;
+
+        if let Some(link) = &mut global.master {
+            link.link_up = true;
+        }
+
+        Ok(count)
+    }
+
+    /// Account for `len` bytes of commands received from the master.
+    pub(crate) fn advance_repl_offset(&mut self, len: u64) {
+        let mut global = self.global_mut();
+        global.repl_offset += len;
+    }
+
+    /// Record that the connection to the master was lost.
+    pub(crate) fn set_master_link_down(&mut self) {
+        let mut global = self.global_mut();
+        if let Some(link) = &mut global.master {
+            link.link_up = false;
+        }
+    }
+
+    /// Check whether a command accessing `keys` may be executed by this node,
+    /// when cluster mode is enabled.
+    ///
+    /// Returns the error to reply with if the keys are in different slots, or
+    /// if the command must be sent to another node. `asking` is set if the
+    /// client sent `ASKING` before the command.
+    pub(crate) fn cluster_redirect(&self, keys: &[&str], asking: bool) -> Option<Frame> {
+        let global = self.global();
+        let topology = global.cluster.as_ref()?;
+
+        let slot = cluster::key_hash_slot(keys.first()?.as_bytes());
+        if keys[1..]
+            .iter()
+            .any(|key| cluster::key_hash_slot(key.as_bytes()) != slot)
+        {
+            return Some(Frame::Error(
+                "CROSSSLOT Keys in request don't hash to the same slot".to_string(),
+            ));
+        }
+
+        topology
+            .route(slot, asking, || {
+                keys.iter().all(|key| self.contains_key(key))
+            })
+            .err()
+            .map(Frame::Error)
+    }
+
+    /// Returns `true` if cluster mode is enabled.
+    pub(crate) fn is_cluster_enabled(&self) -> bool {
+        let global = self.global();
+        global.cluster.is_some()
+    }
+
+    /// Run `f` with the cluster topology, for modification. Returns `None`
+    /// without calling `f` if cluster mode is disabled.
+    pub(crate) fn with_cluster<R>(&mut self, f: impl FnOnce(&mut Topology) -> R) -> Option<R> {
+        self.global_mut().cluster.as_mut().map(f)
+    }
+
+    /// Returns the `# Replication` section of `INFO`.
+    pub(crate) fn replication_info(&self) -> String {
+        let global = self.global();
+        let mut info = String::from("# Replication\r\n");
+
+        match &global.master {
+            None => info.push_str("role:master\r\n"),
+            Some(link) => {
+                info.push_str("role:slave\r\n");
+                info.push_str(&format!("master_host:{}\r\n", link.host));
+                info.push_str(&format!("master_port:{}\r\n", link.port));
+                info.push_str(&format!(
+                    "master_link_status:{}\r\n",
+                    if link.link_up { "up" } else { "down" }
+                ));
+                info.push_str(&format!("slave_repl_offset:{}\r\n", global.repl_offset));
+                info.push_str(&format!(
+                    "slave_read_only:{}\r\n",
+                    global.config.replica_read_only as u8
+                ));
+            }
+        }
+
+        info.push_str(&format!(
+            "connected_slaves:{}\r\n",
+            global.replicas.receiver_count()
+        ));
+        info.push_str(&format!("master_replid:{}\r\n", global.replid));
+        info.push_str(&format!("master_repl_offset:{}\r\n", global.repl_offset));
+
+        info
+    }
+
+    /// Compact the append-only file by replacing it with the minimal set of
+    /// commands recreating the current key space.
+    ///
+    /// The commands are generated while holding the locks, but the file is
+    /// written in the background.
+    pub(crate) fn rewrite_aof(&mut self) -> Result<(), Error> {
+        let mut guard = self.global_mut();
+        let global = &mut *guard;
+        let aof = global.aof.as_ref().ok_or(Error::AofDisabled)?;
+
+        aof.rewrite(self.to_commands());
+
+        // The rewritten file may end with another database selected.
+        global.propagated_db = None;
+
+        Ok(())
+    }
+
+    /// Returns commands that recreate the key space of all databases when
+    /// applied to empty databases.
+    fn to_commands(&self) -> Vec<Frame> {
+        let now = Instant::now();
+        let mut commands = vec![];
+
+        let mut selected = None;
+
+        for (index, entries) in self.keyspaces() {
+            if entries.is_empty() {
+                continue;
+            }
+
+            // The entries of a database are spread over the shards, which are
+            // visited one database after the other.
+            if selected != Some(index) {
+                selected = Some(index);
+                commands.push(Select::new(index).into_frame());
+            }
+
+            for (key, entry) in entries {
+                let key = Bytes::from(key.clone());
+
+                // Most values are recreated by a single command.
+                let key_commands = match &entry.value {
+                    Value::String(value) => {
+                        vec![vec![Bytes::from("set"), key.clone(), value.clone()]]
+                    }
+                    Value::List(list) => {
+                        let mut args = vec![Bytes::from("rpush"), key.clone()];
+                        args.extend(list.iter().cloned());
+                        vec![args]
+                    }
+                    Value::Hash(hash) => {
+                        let mut args = vec![Bytes::from("hset"), key.clone()];
+                        for (field, value) in hash {
+                            args.push(field.clone());
+                            args.push(value.clone());
+                        }
+                        vec![args]
+                    }
+                    Value::SortedSet(set) => {
+                        let mut args = vec![Bytes::from("zadd"), key.clone()];
+                        for (member, score) in set.range(0, -1) {
+                            args.push(Bytes::from(crate::frame::format_double(score)));
+                            args.push(member);
+                        }
+                        vec![args]
+                    }
+                    Value::Stream(stream) => stream_commands(&key, stream),
+                };
+
+                // Skip keys that expired, but were not purged yet.
+                if entry.expires_at.map(|when| when <= now).unwrap_or(false) {
+                    continue;
+                }
+
+                for args in key_commands {
+                    commands.push(Frame::Array(args.into_iter().map(Frame::Bulk).collect()));
+                }
+
+                if let Some(when) = entry.expires_at {
+                    // Expirations are stored as an `Instant`, which cannot be
+                    // persisted. The Unix time at which the key expires is
+                    // stored instead, so that the file may be replayed later.
+                    let unix_ms = unix_time_ms() as u128 + (when - now).as_millis().max(1);
+                    commands.push(Frame::Array(vec![
+                        Frame::Bulk(Bytes::from("pexpireat")),
+                        Frame::Bulk(key),
+                        Frame::Bulk(Bytes::from(unix_ms.to_string())),
+                    ]));
+                }
+            }
+        }
+
+        commands
+    }
+
+    /// Returns the entries of every database, along with its number. The
+    /// entries of a database are spread over the shards, so each database
+    /// is returned once per shard.
+    fn keyspaces(&self) -> impl Iterator<Item = (usize, &IndexMap<String, Entry>)> {
+        (0..self.shared.databases).flat_map(move |index| {
+            self.locked()
+                .map(move |shard| (index, shard.database(index).0))
+        })
+    }
+
+    /// Write a snapshot of the key space to the snapshot file.
+    ///
+    /// The file is written while holding the locks, which blocks all other
+    /// connections until it is done. `bgsave` avoids this.
+    pub(crate) fn save(&self) -> Result<(), Error> {
+        let global = self.global();
+
+        if global.bgsave_in_progress.load(Ordering::Acquire) {
+            return Err(Error::SaveInProgress);
+        }
+
+        let path = &global.config.dbfilename;
+
+        rdb::write(path, &self.dump()).map_err(|err| {
+            error!(cause = %err, path = ?path, "failed to save snapshot");
+            Error::SaveFailed
+        })
+    }
+
+    /// Write a snapshot of the key space to the snapshot file in the
+    /// background.
+    ///
+    /// The snapshot is encoded while holding the locks, so it reflects the key
+    /// space at this point in time. Writing it to disk happens on a separate
+    /// thread.
+    pub(crate) fn bgsave(&self) -> Result<(), Error> {
+        let global = self.global();
+
+        if global.bgsave_in_progress.swap(true, Ordering::AcqRel) {
+            return Err(Error::SaveInProgress);
+        }
+
+        let data = self.dump();
+        let path = global.config.dbfilename.clone();
+        let in_progress = global.bgsave_in_progress.clone();
+
+        let spawned = thread::Builder::new()
+            .name("mini-redis-bgsave".to_string())
+            .spawn(move || {
+                match rdb::write(&path, &data) {
+                    Ok(()) => info!(path = ?path, "background save done"),
+                    Err(err) => error!(cause = %err, path = ?path, "background save failed"),
+                }
+
+                in_progress.store(false, Ordering::Release);
+            });
+
+        if let Err(err) = spawned {
+            error!(cause = %err, "failed to start background save");
+            global.bgsave_in_progress.store(false, Ordering::Release);
+            return Err(Error::SaveFailed);
+        }
+
+        Ok(())
+    }
+
+    /// Replace the key space with the snapshot stored at `path`.
+    ///
+    /// Returns the number of keys loaded, or `None` if the file does not
+    /// exist. Entries that expired before the snapshot was written are not
+    /// part of it; the others expire after the time they had remaining.
+    pub(crate) fn load_rdb(&mut self, path: &Path) -> crate::Result<Option<usize>> {
+        let records = match rdb::load(path)? {
+            Some(records) => records,
+            None => return Ok(None),
+        };
+
+        self.load_records(records).map(Some)
+    }
+
+    /// Insert the entries read from a snapshot into their database.
+    ///
+    /// Returns the number of keys loaded. Nothing is loaded if the snapshot
+    /// uses more databases than configured.
+    fn load_records(&mut self, records: Vec<rdb::Record>) -> crate::Result<usize> {
+        if let Some((index, ..)) = records
+            .iter()
+            .find(|(index, ..)| *index >= self.shared.databases)
+        {
+            return Err(format!(
+                "snapshot contains database {}, but only {} databases are configured",
+                index, self.shared.databases
+            )
+            .into());
+        }
+
+        let count = records.len();
+        let selected = self.selected;
+
+        for (index, key, value, ttl) in records {
+            self.select(index);
+            self.insert(key, value, ttl);
+        }
+
+        self.select(selected);
+
+        Ok(count)
+    }
+
+    /// Encode the key space of all databases as a snapshot, skipping keys that
+    /// expired but were not purged yet.
+    fn dump(&self) -> Bytes {
+        let now = Instant::now();
+
+        let entries = self.keyspaces().flat_map(|(index, entries)| {
+            entries
+                .iter()
+                .filter(move |(_, entry)| entry.expires_at.map(|when| when > now).unwrap_or(true))
+                .map(move |(key, entry)| {
+                    let ttl = entry.expires_at.map(|when| when - now);
+                    (index, &key[..], &entry.value, ttl)
+                })
+        });
+
+        rdb::encode(entries)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::WrongType => {
+                "WRONGTYPE Operation against a key holding the wrong kind of value".fmt(fmt)
+            }
+            Error::NotInteger => "ERR value is not an integer or out of range".fmt(fmt),
+            Error::Overflow => "ERR increment or decrement would overflow".fmt(fmt),
+            Error::NoSuchKey => "ERR no such key".fmt(fmt),
+            Error::DbIndexOutOfRange => "ERR DB index is out of range".fmt(fmt),
+            Error::SameObject => "ERR source and destination objects are the same".fmt(fmt),
+            Error::StreamIdTooSmall => {
+                "ERR The ID specified in XADD is equal or smaller than the target stream top item"
+                    .fmt(fmt)
+            }
+            Error::StreamIdZero => "ERR The ID specified in XADD must be greater than 0-0".fmt(fmt),
+            Error::NoGroup => "NOGROUP No such key or consumer group".fmt(fmt),
+            Error::BusyGroup => "BUSYGROUP Consumer Group name already exists".fmt(fmt),
+            Error::XGroupNoKey => "ERR The XGROUP subcommand requires the key to exist. \
+                Note that for CREATE you may want to use the MKSTREAM option to create an \
+                empty stream automatically."
+                .fmt(fmt),
+            Error::NotFloat => "ERR value is not a valid float".fmt(fmt),
+            Error::NanOrInfinity => "ERR increment would produce NaN or Infinity".fmt(fmt),
+            Error::AofDisabled => "ERR append only file is disabled".fmt(fmt),
+            Error::SaveInProgress => "ERR Background save already in progress".fmt(fmt),
+            Error::SaveFailed => "ERR failed to save the snapshot".fmt(fmt),
+            Error::UnknownConfig(name) => write!(
+                fmt,
+                "ERR Unknown option or number of arguments for CONFIG SET - '{}'",
+                name
+            ),
+            Error::InvalidConfig(name, reason) => write!(
+                fmt,
+                "ERR CONFIG SET failed (possibly related to argument '{}') - {}",
+                name, reason
+            ),
+            Error::NoConfigFile => "ERR The server is running without a config file".fmt(fmt),
+            Error::ConfigRewriteFailed(reason) => {
+                write!(fmt, "ERR Rewriting config file: {}", reason)
+            }
+            Error::OutOfMemory => {
+                "OOM command not allowed when used memory > 'maxmemory'.".fmt(fmt)
+            }
+        }
+    }
+}
+
+impl From<Error> for Frame {
+    fn from(src: Error) -> Frame {
+        Frame::Error(src.to_string())
+    }
+}
+
+/// Position of `key` in the iteration order of `SCAN`.
+///
+/// The hasher uses fixed keys, so the position of a key does not change for
+/// the lifetime of the process. Positions start at `1`, as a cursor of `0`
+/// starts a new iteration.
+fn scan_position(key: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    (hasher.finish() >> 1) + 1
+}
+
+/// Parse a stored value as a 64 bit signed integer.
+fn parse_i64(value: &[u8]) -> Result<i64, Error> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .ok_or(Error::NotInteger)
+}
+
+/// Parse a stored value as a finite floating point number.
+fn parse_f64(value: &[u8]) -> Result<f64, Error> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse::<f64>().ok())
+        .filter(|value| value.is_finite())
+        .ok_or(Error::NotFloat)
+}
+
+/// Returns the current time, in milliseconds since the Unix epoch.
+pub(crate) fn unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Returns the commands recreating `stream` at `key`.
+///
+/// The entries are added one by one with their ID. A stream without entries
+/// is created by adding an entry that is immediately trimmed, which also sets
+/// its last ID. If no entry was ever added, the stream is created along with
+/// its first consumer group instead, as `0-0` is not a valid entry ID.
+///
+/// Consumer groups are then created, and their pending entries claimed by
+/// the consumers they were delivered to, with the same delivery time and
+/// count. Pending entries removed from the stream are dropped.
+fn stream_commands(key: &Bytes, stream: &Stream) -> Vec<Vec<Bytes>> {
+    let mut commands = stream_entries_commands(key, stream);
+    let mkstream = commands.is_empty();
+
+    if mkstream && stream.groups().next().is_none() {
+        let group = Bytes::new();
+        commands.push(vec![
+            Bytes::from("xgroup"),
+            Bytes::from("create"),
+            key.clone(),
+            group.clone(),
+            Bytes::from("0-0"),
+            Bytes::from("mkstream"),
+        ]);
+        commands.push(vec![
+            Bytes::from("xgroup"),
+            Bytes::from("destroy"),
+            key.clone(),
+            group,
+        ]);
+    }
+
+    for (name, group) in stream.groups() {
+        let name = Bytes::from(name.clone());
+
+        let mut args = vec![
+            Bytes::from("xgroup"),
+            Bytes::from("create"),
+            key.clone(),
+            name.clone(),
+            Bytes::from(group.last_delivered().to_string()),
+        ];
+        if mkstream {
+            args.push(Bytes::from("mkstream"));
+        }
+        commands.push(args);
+
+        for (consumer, _) in group.consumers() {
+            commands.push(vec![
+                Bytes::from("xgroup"),
+                Bytes::from("createconsumer"),
+                key.clone(),
+                name.clone(),
+                Bytes::from(consumer.clone()),
+            ]);
+        }
+
+        for (id, pending) in group.pending(StreamId::MIN, StreamId::MAX) {
+            commands.push(vec![
+                Bytes::from("xclaim"),
+                key.clone(),
+                name.clone(),
+                Bytes::from(pending.consumer().to_string()),
+                Bytes::from("0"),
+                Bytes::from(id.to_string()),
+                Bytes::from("time"),
+                Bytes::from(pending.delivered_ms().to_string()),
+                Bytes::from("retrycount"),
+                Bytes::from(pending.delivery_count().to_string()),
+                Bytes::from("force"),
+                Bytes::from("justid"),
+            ]);
+        }
+    }
+
+    commands
+}
+
+/// Returns the `XADD` commands recreating the entries of `stream` at `key`,
+/// or nothing if no entry was ever added. See `stream_commands`.
+fn stream_entries_commands(key: &Bytes, stream: &Stream) -> Vec<Vec<Bytes>> {
+    let xadd = |id: &StreamId, fields: &[(Bytes, Bytes)], maxlen: Option<&str>| {
+        let mut args = vec![Bytes::from("xadd"), key.clone()];
+        if let Some(maxlen) = maxlen {
+            args.push(Bytes::from("maxlen"));
+            args.push(Bytes::from(maxlen.to_string()));
+        }
+        args.push(Bytes::from(id.to_string()));
+        for (field, value) in fields {
+            args.push(field.clone());
+            args.push(value.clone());
+        }
+        args
+    };
+
+    if stream.last_id() == StreamId::MIN {
+        return vec![];
+    }
+
+    if stream.len() == 0 {
+        let placeholder = [(Bytes::new(), Bytes::new())];
+        return vec![xadd(&stream.last_id(), &placeholder, Some("0"))];
+    }
+
+    stream
+        .entries()
+        .map(|(id, fields)| xadd(id, fields, None))
+        .collect()
 }
 
 /// Routine executed by the background task.
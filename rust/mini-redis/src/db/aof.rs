@@ -0,0 +1,309 @@
+//! Append-only file persistence.
+//!
+//! Every command that modifies the key space is appended to the file, encoded
+//! the same way as it is sent over the network. When the server starts, the
+//! file is read back and the commands are applied again, which restores the
+//! key space.
+//!
+//! Writing to a file blocks the thread. To avoid blocking the Tokio runtime,
+//! or connections waiting on the locks of the key space, the file is written
+//! by a dedicated thread. Commands are sent to the thread over a channel.
+//!
+//! A command is propagated before the locks of the shards holding its keys
+//! are released, so commands touching the same key are appended in the same
+//! order as they were applied. Commands on different shards may be appended
+//! in either order, but they touch different keys, so replaying them in
+//! either order gives the same key space. Commands affecting every key, such
+//! as `FLUSHALL`, lock all shards.
+
+use crate::config::FsyncPolicy;
+use crate::frame::{self, Frame};
+
+use bytes::{Buf, Bytes, BytesMut};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tracing::{debug, error, warn};
+
+/// Handle to the thread writing the append-only file.
+///
+/// Dropping the handle closes the channel. The thread then flushes any
+/// pending writes and exits.
+#[derive(Debug)]
+pub(crate) struct Aof {
+    /// Sends requests to the writer thread
+    tx: mpsc::Sender<Message>,
+
+    /// How often the file is flushed to disk
+    fsync: FsyncPolicy,
+}
+
+/// Requests sent to the writer thread.
+#[derive(Debug)]
+enum Message {
+    /// Append an encoded command to the file
+    Append(Bytes),
+
+    /// Flush all pending writes to disk, then notify the sender
+    Sync(oneshot::Sender<()>),
+
+    /// Replace the file with a new file containing only the given commands.
+    /// Subsequent commands are appended to the new file.
+    Rewrite(Vec<Frame>),
+}
+
+/// The state owned by the writer thread.
+struct Writer {
+    /// Path of the append-only file
+    path: PathBuf,
+
+    /// The file, with writes buffered in memory until the next flush
+    file: BufWriter<File>,
+
+    /// How often the file is flushed to disk
+    fsync: FsyncPolicy,
+
+    /// When the file was last flushed to disk
+    last_fsync: Instant,
+
+    /// `true` if commands were written since the last `fsync`
+    dirty: bool,
+}
+
+impl Aof {
+    /// Open the append-only file at `path` for appending, creating it if it
+    /// does not exist, and spawn the thread writing to it.
+    pub(crate) fn open(path: &Path, fsync: FsyncPolicy) -> io::Result<Aof> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        let mut writer = Writer {
+            path: path.to_path_buf(),
+            file: BufWriter::new(file),
+            fsync,
+            last_fsync: Instant::now(),
+            dirty: false,
+        };
+
+        let (tx, rx) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("mini-redis-aof".to_string())
+            .spawn(move || writer.run(rx))?;
+
+        Ok(Aof { tx, fsync })
+    }
+
+    /// Returns how often the file is flushed to disk.
+    pub(crate) fn fsync(&self) -> FsyncPolicy {
+        self.fsync
+    }
+
+    /// Append `frame` to the file.
+    pub(crate) fn append(&self, frame: &Frame) {
+        let mut buf = BytesMut::new();
+        encode(frame, &mut buf);
+
+        // Sending only fails if the writer thread has exited, in which case
+        // it already logged the reason.
+        let _ = self.tx.send(Message::Append(buf.freeze()));
+    }
+
+    /// Request that all commands appended so far are flushed to disk.
+    ///
+    /// The returned receiver completes once they are.
+    pub(crate) fn sync(&self) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.tx.send(Message::Sync(tx));
+        rx
+    }
+
+    /// Replace the file with one containing only `frames`.
+    ///
+    /// `frames` must recreate the current key space. The rewrite happens in
+    /// the background. Commands appended after calling `rewrite` are written
+    /// to the new file.
+    pub(crate) fn rewrite(&self, frames: Vec<Frame>) {
+        let _ = self.tx.send(Message::Rewrite(frames));
+    }
+}
+
+impl Writer {
+    /// Process requests until all `Aof` handles are dropped.
+    fn run(&mut self, rx: mpsc::Receiver<Message>) {
+        loop {
+            // Wake up at least once per second in order to apply the
+            // `everysec` policy when no commands are received.
+            let message = match rx.recv_timeout(Duration::from_secs(1)) {
+                Ok(message) => Some(message),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            if let Some(message) = message {
+                if let Err(err) = self.process(message) {
+                    error!(cause = %err, path = ?self.path, "failed to write append-only file");
+                }
+
+                // Process all messages that are already queued before
+                // flushing. This groups the writes of concurrent commands.
+                while let Ok(message) = rx.try_recv() {
+                    if let Err(err) = self.process(message) {
+                        error!(cause = %err, path = ?self.path, "failed to write append-only file");
+                    }
+                }
+            }
+
+            if let Err(err) = self.flush() {
+                error!(cause = %err, path = ?self.path, "failed to flush append-only file");
+            }
+        }
+
+        if let Err(err) = self.sync() {
+            error!(cause = %err, path = ?self.path, "failed to flush append-only file");
+        }
+
+        debug!("append-only file writer shut down");
+    }
+
+    fn process(&mut self, message: Message) -> io::Result<()> {
+        match message {
+            Message::Append(data) => {
+                self.file.write_all(&data)?;
+                self.dirty = true;
+            }
+            Message::Sync(tx) => {
+                self.sync()?;
+                let _ = tx.send(());
+            }
+            Message::Rewrite(frames) => self.rewrite(frames)?,
+        }
+
+        Ok(())
+    }
+
+    /// Hand buffered writes to the operating system, and `fsync` the file as
+    /// required by the policy.
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        let fsync = match self.fsync {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::EverySec => self.last_fsync.elapsed() >= Duration::from_secs(1),
+            FsyncPolicy::No => false,
+        };
+
+        if fsync && self.dirty {
+            self.sync()?;
+        }
+
+        Ok(())
+    }
+
+    /// Flush all buffered writes to disk, regardless of the policy.
+    fn sync(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()?;
+        self.last_fsync = Instant::now();
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Write `frames` to a temporary file, then atomically replace the
+    /// append-only file with it.
+    ///
+    /// If the rewrite fails, the current file is kept and commands continue
+    /// to be appended to it.
+    fn rewrite(&mut self, frames: Vec<Frame>) -> io::Result<()> {
+        let tmp = self.path.with_extension("aof.tmp");
+
+        let mut buf = BytesMut::new();
+        for frame in &frames {
+            encode(frame, &mut buf);
+        }
+
+        let mut file = File::create(&tmp)?;
+        file.write_all(&buf)?;
+        file.sync_all()?;
+
+        // Make sure nothing written to the old file is left in the buffer
+        // before switching to the new file.
+        self.file.flush()?;
+
+        fs::rename(&tmp, &self.path)?;
+
+        let file = OpenOptions::new().append(true).open(&self.path)?;
+        self.file = BufWriter::new(file);
+
+        debug!(commands = frames.len(), "rewrote append-only file");
+
+        Ok(())
+    }
+}
+
+/// Read the commands stored in the append-only file at `path`.
+///
+/// Returns an empty list if the file does not exist. If the file ends with an
+/// incomplete command, for example because the server crashed while writing
+/// it, the file is truncated to the last complete command.
+pub(crate) fn load(path: &Path) -> crate::Result<Vec<Frame>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut buf = Cursor::new(&data[..]);
+    let mut frames = vec![];
+
+    while buf.has_remaining() {
+        let start = buf.position();
+
+        match Frame::check(&mut buf) {
+            Ok(_) => {
+                buf.set_position(start);
+                frames.push(Frame::parse(&mut buf)?);
+            }
+            Err(frame::Error::Incomplete) => {
+                warn!(
+                    path = ?path,
+                    offset = start,
+                    "append-only file ends with an incomplete command; truncating"
+                );
+
+                OpenOptions::new().write(true).open(path)?.set_len(start)?;
+                break;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(frames)
+}
+
+/// Encode a command frame into `dst`.
+///
+/// Commands are arrays of strings. Integers are encoded as strings, which is
+/// how Redis clients send them.
+fn encode(frame: &Frame, dst: &mut BytesMut) {
+    let entries = match frame {
+        Frame::Array(entries) => entries,
+        _ => unreachable!("commands are encoded as arrays"),
+    };
+
+    dst.extend_from_slice(format!("*{}\r\n", entries.len()).as_bytes());
+
+    for entry in entries {
+        let data = match entry {
+            Frame::Bulk(data) => data.clone(),
+            entry => Bytes::from(entry.to_string()),
+        };
+
+        dst.extend_from_slice(format!("${}\r\n", data.len()).as_bytes());
+        dst.extend_from_slice(&data);
+        dst.extend_from_slice(b"\r\n");
+    }
+}
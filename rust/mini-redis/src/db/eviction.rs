@@ -0,0 +1,210 @@
+//! Memory accounting and key eviction.
+//!
+//! The memory used by the key space is estimated from the size of the keys
+//! and values, plus a fixed overhead per entry. It does not match what the
+//! allocator actually uses, but grows and shrinks along with it, which is
+//! what matters to keep the server under `maxmemory`.
+//!
+//! Like Redis, eviction is approximate. Instead of keeping every key ordered
+//! by last access or by frequency, a few keys are sampled at random and the
+//! best candidate among them is evicted.
+
+use super::stream::{PendingEntry, StreamId};
+use super::{Entry, Value};
+use crate::config::EvictionPolicy;
+
+use bytes::Bytes;
+use indexmap::IndexMap;
+use rand::Rng;
+use std::collections::BTreeSet;
+use std::mem;
+use tokio::time::{Duration, Instant};
+
+/// Memory used by an entry besides its key and value: the slot in the map,
+/// holding the `String` of the key and the `Entry`, and the hash of the key.
+const ENTRY_OVERHEAD: usize = mem::size_of::<(String, Entry)>() + mem::size_of::<u64>();
+
+/// Number of elements of a collection used to estimate its size. The size
+/// of the other elements is extrapolated, as Redis does for `MEMORY USAGE`.
+const SIZE_SAMPLES: usize = 5;
+
+/// Frequency counter of a new key. Starting above zero gives new keys a
+/// chance to be accessed again before the LFU policies evict them.
+const LFU_INIT: u8 = 5;
+
+/// The higher the factor, the more accesses it takes to increment the
+/// frequency counter. With `10`, the counter saturates after about a million
+/// accesses, which is the default of Redis.
+const LFU_LOG_FACTOR: f64 = 10.0;
+
+/// The frequency counter is decremented once per period without access, so
+/// keys that used to be accessed often do not stay forever.
+const LFU_DECAY_PERIOD: Duration = Duration::from_secs(60);
+
+/// When and how often a key is accessed, as used by the LRU and LFU policies.
+#[derive(Debug)]
+pub(super) struct Access {
+    /// Instant of the last access, or of the creation of the key.
+    last: Instant,
+
+    /// Logarithmic access frequency counter. The more accesses are recorded,
+    /// the less likely the counter is to be incremented.
+    counter: u8,
+}
+
+impl Access {
+    /// Returns the access information of a key created now.
+    pub(super) fn new() -> Access {
+        Access {
+            last: Instant::now(),
+            counter: LFU_INIT,
+        }
+    }
+
+    /// Record an access at `now`.
+    pub(super) fn record(&mut self, now: Instant) {
+        let counter = self.frequency(now);
+
+        // The counter grows with the logarithm of the number of accesses, so
+        // a `u8` is enough to tell apart rarely and very often used keys.
+        let base = counter.saturating_sub(LFU_INIT) as f64;
+        let probability = 1.0 / (base * LFU_LOG_FACTOR + 1.0);
+
+        self.counter = if counter < u8::MAX && rand::thread_rng().gen::<f64>() < probability {
+            counter + 1
+        } else {
+            counter
+        };
+        self.last = now;
+    }
+
+    /// Returns the time elapsed since the last access.
+    fn idle(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last)
+    }
+
+    /// Returns the frequency counter, decayed for the time elapsed since the
+    /// last access.
+    fn frequency(&self, now: Instant) -> u8 {
+        let periods = self.idle(now).as_secs() / LFU_DECAY_PERIOD.as_secs();
+        self.counter
+            .saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+}
+
+/// Returns the estimated number of bytes used by the entry holding `value` at
+/// `key`.
+pub(super) fn entry_size(key: &str, value: &Value) -> usize {
+    const BYTES: usize = mem::size_of::<Bytes>();
+
+    let value_size = match value {
+        Value::String(data) => data.len(),
+        Value::List(list) => estimate(list.len(), list.iter().map(|value| BYTES + value.len())),
+        Value::Hash(hash) => estimate(
+            hash.len(),
+            hash.iter()
+                .map(|(field, value)| 2 * BYTES + field.len() + value.len()),
+        ),
+        // Members are stored twice, once in each of the structures of the set.
+        Value::SortedSet(set) => estimate(
+            set.len(),
+            set.range(0, SIZE_SAMPLES as i64 - 1)
+                .into_iter()
+                .map(|(member, _)| 2 * (BYTES + member.len() + mem::size_of::<f64>())),
+        ),
+        Value::Stream(stream) => {
+            let entries = estimate(
+                stream.len(),
+                stream.entries().map(|(_, fields)| {
+                    let fields: usize = fields
+                        .iter()
+                        .map(|(field, value)| 2 * BYTES + field.len() + value.len())
+                        .sum();
+                    mem::size_of::<StreamId>() + fields
+                }),
+            );
+            let pending: usize = stream
+                .groups()
+                .map(|(name, group)| {
+                    name.len() + group.pending_len() * mem::size_of::<(StreamId, PendingEntry)>()
+                })
+                .sum();
+
+            entries + pending
+        }
+    };
+
+    ENTRY_OVERHEAD + key.len() + value_size
+}
+
+/// Estimate the size of a collection of `len` elements from the `sizes` of
+/// its first elements.
+fn estimate(len: usize, sizes: impl Iterator<Item = usize>) -> usize {
+    let (count, total) = sizes
+        .take(SIZE_SAMPLES)
+        .fold((0, 0), |(count, total), size| (count + 1, total + size));
+
+    if count == 0 {
+        return 0;
+    }
+
+    total / count * len
+}
+
+/// Returns up to `count` keys picked at random among `entries`, or only among
+/// the keys with an expiration if `volatile` is set. A key may be returned
+/// more than once.
+pub(super) fn sample<'a>(
+    entries: &'a IndexMap<String, Entry>,
+    expirations: &'a BTreeSet<(Instant, String)>,
+    volatile: bool,
+    count: usize,
+) -> Vec<&'a String> {
+    let mut rng = rand::thread_rng();
+
+    if !volatile {
+        if entries.is_empty() {
+            return vec![];
+        }
+
+        return (0..count)
+            .filter_map(|_| entries.get_index(rng.gen_range(0..entries.len())))
+            .map(|(key, _)| key)
+            .collect();
+    }
+
+    // Expirations are sorted, and cannot be accessed by position. Instead, a
+    // random instant is picked between the first and the last expiration, and
+    // the key expiring next from then on is used.
+    let (first, last) = match (expirations.iter().next(), expirations.iter().next_back()) {
+        (Some(first), Some(last)) => (first.0, last.0),
+        _ => return vec![],
+    };
+
+    (0..count)
+        .filter_map(|_| {
+            let when = first + (last - first).mul_f64(rng.gen());
+            expirations.range((when, String::new())..).next()
+        })
+        .map(|(_, key)| key)
+        .collect()
+}
+
+/// Returns how good a candidate for eviction `entry` is under `policy`. The
+/// entry with the highest score is evicted.
+pub(super) fn score(policy: EvictionPolicy, entry: &Entry, now: Instant) -> u64 {
+    match policy {
+        EvictionPolicy::NoEviction => 0,
+        EvictionPolicy::AllKeysLru | EvictionPolicy::VolatileLru => {
+            entry.access.idle(now).as_nanos() as u64
+        }
+        EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu => {
+            (u8::MAX - entry.access.frequency(now)) as u64
+        }
+        EvictionPolicy::AllKeysRandom | EvictionPolicy::VolatileRandom => rand::random(),
+        EvictionPolicy::VolatileTtl => match entry.expires_at {
+            Some(when) => u64::MAX - when.saturating_duration_since(now).as_millis() as u64,
+            None => 0,
+        },
+    }
+}
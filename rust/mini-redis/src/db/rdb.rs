@@ -0,0 +1,416 @@
+//! Point-in-time snapshots of the key space.
+//!
+//! A snapshot is a compact binary encoding of every entry in the key space,
+//! written by `SAVE` and `BGSAVE` and loaded when the server starts. Unlike
+//! the append-only file, a snapshot only contains the data, not the commands
+//! that produced it.
+//!
+//! # Format
+//!
+//! All integers are big endian.
+//!
+//! ```text
+//! "MINIREDIS" version:u8
+//! (0xFE db:u32 | entry)*
+//! 0xFF checksum:u32
+//! ```
+//!
+//! The `0xFE` opcode selects the database the following entries belong to.
+//! Entries preceding the first one belong to database `0`. Each entry is
+//! encoded as:
+//!
+//! ```text
+//! [0xFC ttl_ms:u64] type:u8 key:string value
+//! ```
+//!
+//! The optional `0xFC` prefix holds the time remaining before the entry
+//! expires, in milliseconds. Strings are encoded as a `u32` length followed by
+//! the bytes. The encoding of the value depends on its type:
+//!
+//! * String: `string`
+//! * List: `len:u32 string*`
+//! * Hash: `len:u32 (field:string value:string)*`
+//! * Sorted set: `len:u32 (member:string score:f64)*`
+//! * Stream: `last_id:id len:u32 (id:id fields:u32 (field:string value:string)*)* groups`
+//!
+//! A stream entry ID is encoded as its time in milliseconds followed by its
+//! sequence number, both `u64`. The consumer groups of a stream are encoded
+//! as:
+//!
+//! ```text
+//! len:u32 (name:string last_delivered:id consumers pending)*
+//! consumers: len:u32 (name:string seen_ms:u64)*
+//! pending: len:u32 (id:id consumer:string delivered_ms:u64 delivery_count:u64)*
+//! ```
+//!
+//! The checksum is the CRC-32 of everything preceding it. A snapshot with an
+//! invalid checksum is rejected rather than partially loaded.
+
+use super::{Group, SortedSet, Stream, StreamId, Value};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Identifies a snapshot file.
+const MAGIC: &[u8] = b"MINIREDIS";
+
+/// Version of the encoding. Incremented whenever the encoding changes.
+///
+/// Version `1` snapshots, written before databases could be selected, and
+/// version `2` snapshots, written before streams had consumer groups, are
+/// still loaded.
+const VERSION: u8 = 3;
+
+/// Precedes the number of the database the following entries belong to.
+const OPCODE_SELECTDB: u8 = 0xFE;
+
+/// Precedes the TTL of an entry.
+const OPCODE_EXPIRE_MS: u8 = 0xFC;
+
+/// Marks the end of the entries.
+const OPCODE_EOF: u8 = 0xFF;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_HASH: u8 = 2;
+const TYPE_SORTED_SET: u8 = 3;
+const TYPE_STREAM: u8 = 4;
+
+/// An entry read from a snapshot: the database, the key, the value, and the
+/// time remaining before the entry expires.
+pub(super) type Record = (usize, String, Value, Option<Duration>);
+
+/// Encode entries into a snapshot. Entries of the same database must be
+/// consecutive.
+pub(super) fn encode<'a>(
+    entries: impl Iterator<Item = (usize, &'a str, &'a Value, Option<Duration>)>,
+) -> Bytes {
+    let mut buf = BytesMut::new();
+
+    buf.put_slice(MAGIC);
+    buf.put_u8(VERSION);
+
+    let mut selected = 0;
+
+    for (db, key, value, ttl) in entries {
+        if db != selected {
+            buf.put_u8(OPCODE_SELECTDB);
+            put_len(&mut buf, db);
+            selected = db;
+        }
+
+        if let Some(ttl) = ttl {
+            buf.put_u8(OPCODE_EXPIRE_MS);
+            // Round up, so an entry with less than a millisecond remaining
+            // does not become persistent.
+            buf.put_u64(u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX).max(1));
+        }
+
+        match value {
+            Value::String(value) => {
+                buf.put_u8(TYPE_STRING);
+                put_string(&mut buf, key.as_bytes());
+                put_string(&mut buf, value);
+            }
+            Value::List(list) => {
+                buf.put_u8(TYPE_LIST);
+                put_string(&mut buf, key.as_bytes());
+                put_len(&mut buf, list.len());
+                for value in list {
+                    put_string(&mut buf, value);
+                }
+            }
+            Value::Hash(hash) => {
+                buf.put_u8(TYPE_HASH);
+                put_string(&mut buf, key.as_bytes());
+                put_len(&mut buf, hash.len());
+                for (field, value) in hash {
+                    put_string(&mut buf, field);
+                    put_string(&mut buf, value);
+                }
+            }
+            Value::SortedSet(set) => {
+                buf.put_u8(TYPE_SORTED_SET);
+                put_string(&mut buf, key.as_bytes());
+                put_len(&mut buf, set.len());
+                for (member, score) in set.range(0, -1) {
+                    put_string(&mut buf, &member);
+                    buf.put_f64(score);
+                }
+            }
+            Value::Stream(stream) => {
+                buf.put_u8(TYPE_STREAM);
+                put_string(&mut buf, key.as_bytes());
+                put_id(&mut buf, stream.last_id());
+                put_len(&mut buf, stream.len());
+                for (id, fields) in stream.entries() {
+                    put_id(&mut buf, *id);
+                    put_len(&mut buf, fields.len());
+                    for (field, value) in fields {
+                        put_string(&mut buf, field);
+                        put_string(&mut buf, value);
+                    }
+                }
+                put_groups(&mut buf, stream);
+            }
+        }
+    }
+
+    buf.put_u8(OPCODE_EOF);
+
+    let checksum = crc32fast::hash(&buf);
+    buf.put_u32(checksum);
+
+    buf.freeze()
+}
+
+/// Write the snapshot `data` to `path`.
+///
+/// The data is first written to a temporary file, which then replaces the
+/// file at `path`. Renaming is atomic, so `path` always holds a complete
+/// snapshot, even if the server crashes while writing.
+pub(crate) fn write(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("rdb.tmp");
+
+    let mut file = File::create(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+
+    fs::rename(&tmp, path)
+}
+
+/// Read the snapshot stored at `path`.
+///
+/// Returns `None` if the file does not exist.
+pub(super) fn load(path: &Path) -> crate::Result<Option<Vec<Record>>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    decode(&data).map(Some)
+}
+
+/// Decode a snapshot, verifying its checksum.
+pub(super) fn decode(data: &[u8]) -> crate::Result<Vec<Record>> {
+    if data.len() < MAGIC.len() + 1 + 1 + 4 || !data.starts_with(MAGIC) {
+        return Err(invalid("not a snapshot file"));
+    }
+
+    let (data, mut checksum) = data.split_at(data.len() - 4);
+
+    if crc32fast::hash(data) != checksum.get_u32() {
+        return Err(invalid("checksum mismatch"));
+    }
+
+    let mut buf = &data[MAGIC.len()..];
+
+    let version = buf.get_u8();
+    if version == 0 || version > VERSION {
+        return Err(invalid(&format!("unsupported version {}", version)));
+    }
+
+    let mut records = vec![];
+    let mut db = 0;
+
+    loop {
+        let mut opcode = get_u8(&mut buf)?;
+
+        if opcode == OPCODE_EOF {
+            break;
+        }
+
+        if opcode == OPCODE_SELECTDB {
+            db = get_u32(&mut buf)? as usize;
+            continue;
+        }
+
+        let ttl = if opcode == OPCODE_EXPIRE_MS {
+            let ttl = Duration::from_millis(get_u64(&mut buf)?);
+            opcode = get_u8(&mut buf)?;
+            Some(ttl)
+        } else {
+            None
+        };
+
+        let key = String::from_utf8(get_string(&mut buf)?.to_vec())
+            .map_err(|_| invalid("key is not valid UTF-8"))?;
+
+        let value = match opcode {
+            TYPE_STRING => Value::String(get_string(&mut buf)?),
+            TYPE_LIST => {
+                let len = get_u32(&mut buf)?;
+                let mut list = VecDeque::new();
+                for _ in 0..len {
+                    list.push_back(get_string(&mut buf)?);
+                }
+                Value::List(list)
+            }
+            TYPE_HASH => {
+                let len = get_u32(&mut buf)?;
+                let mut hash = HashMap::new();
+                for _ in 0..len {
+                    hash.insert(get_string(&mut buf)?, get_string(&mut buf)?);
+                }
+                Value::Hash(hash)
+            }
+            TYPE_SORTED_SET => {
+                let len = get_u32(&mut buf)?;
+                let mut set = SortedSet::default();
+                for _ in 0..len {
+                    let member = get_string(&mut buf)?;
+                    set.insert(member, get_f64(&mut buf)?);
+                }
+                Value::SortedSet(set)
+            }
+            TYPE_STREAM => {
+                let last_id = get_id(&mut buf)?;
+                let len = get_u32(&mut buf)?;
+                let mut entries = vec![];
+                for _ in 0..len {
+                    let id = get_id(&mut buf)?;
+                    let fields_len = get_u32(&mut buf)?;
+                    let mut fields = vec![];
+                    for _ in 0..fields_len {
+                        fields.push((get_string(&mut buf)?, get_string(&mut buf)?));
+                    }
+                    entries.push((id, fields));
+                }
+                let groups = if version >= 3 {
+                    get_groups(&mut buf)?
+                } else {
+                    vec![]
+                };
+                Value::Stream(Stream::from_parts(entries, last_id, groups))
+            }
+            opcode => return Err(invalid(&format!("unknown value type {}", opcode))),
+        };
+
+        records.push((db, key, value, ttl));
+    }
+
+    if buf.has_remaining() {
+        return Err(invalid("unexpected data after the last entry"));
+    }
+
+    Ok(records)
+}
+
+fn put_len(buf: &mut BytesMut, len: usize) {
+    buf.put_u32(u32::try_from(len).expect("value too large for a snapshot"));
+}
+
+fn put_string(buf: &mut BytesMut, data: &[u8]) {
+    put_len(buf, data.len());
+    buf.put_slice(data);
+}
+
+fn put_id(buf: &mut BytesMut, id: StreamId) {
+    buf.put_u64(id.ms());
+    buf.put_u64(id.seq());
+}
+
+fn put_groups(buf: &mut BytesMut, stream: &Stream) {
+    put_len(buf, stream.groups().count());
+    for (name, group) in stream.groups() {
+        put_string(buf, name.as_bytes());
+        put_id(buf, group.last_delivered());
+
+        put_len(buf, group.consumers().count());
+        for (consumer, seen_ms) in group.consumers() {
+            put_string(buf, consumer.as_bytes());
+            buf.put_u64(seen_ms);
+        }
+
+        put_len(buf, group.pending_len());
+        for (id, pending) in group.pending(StreamId::MIN, StreamId::MAX) {
+            put_id(buf, *id);
+            put_string(buf, pending.consumer().as_bytes());
+            buf.put_u64(pending.delivered_ms());
+            buf.put_u64(pending.delivery_count());
+        }
+    }
+}
+
+fn get_u8(buf: &mut &[u8]) -> crate::Result<u8> {
+    check_remaining(buf, 1)?;
+    Ok(buf.get_u8())
+}
+
+fn get_u32(buf: &mut &[u8]) -> crate::Result<u32> {
+    check_remaining(buf, 4)?;
+    Ok(buf.get_u32())
+}
+
+fn get_u64(buf: &mut &[u8]) -> crate::Result<u64> {
+    check_remaining(buf, 8)?;
+    Ok(buf.get_u64())
+}
+
+fn get_f64(buf: &mut &[u8]) -> crate::Result<f64> {
+    check_remaining(buf, 8)?;
+    Ok(buf.get_f64())
+}
+
+fn get_id(buf: &mut &[u8]) -> crate::Result<StreamId> {
+    Ok(StreamId::new(get_u64(buf)?, get_u64(buf)?))
+}
+
+fn get_groups(buf: &mut &[u8]) -> crate::Result<Vec<(String, Group)>> {
+    let len = get_u32(buf)?;
+    let mut groups = vec![];
+
+    for _ in 0..len {
+        let name = get_name(buf)?;
+        let mut group = Group::default();
+        group.set_last_delivered(get_id(buf)?);
+
+        let consumers = get_u32(buf)?;
+        for _ in 0..consumers {
+            let consumer = get_name(buf)?;
+            group.create_consumer(&consumer, get_u64(buf)?);
+        }
+
+        let pending = get_u32(buf)?;
+        for _ in 0..pending {
+            let id = get_id(buf)?;
+            let consumer = get_name(buf)?;
+            let delivered_ms = get_u64(buf)?;
+            group.deliver(id, &consumer, delivered_ms, get_u64(buf)?);
+        }
+
+        groups.push((name, group));
+    }
+
+    Ok(groups)
+}
+
+/// Read a string which must be valid UTF-8, such as the name of a consumer
+/// group.
+fn get_name(buf: &mut &[u8]) -> crate::Result<String> {
+    String::from_utf8(get_string(buf)?.to_vec()).map_err(|_| invalid("name is not valid UTF-8"))
+}
+
+fn get_string(buf: &mut &[u8]) -> crate::Result<Bytes> {
+    let len = get_u32(buf)? as usize;
+    check_remaining(buf, len)?;
+    Ok(buf.copy_to_bytes(len))
+}
+
+fn check_remaining(buf: &[u8], len: usize) -> crate::Result<()> {
+    if buf.len() < len {
+        return Err(invalid("unexpected end of file"));
+    }
+
+    Ok(())
+}
+
+fn invalid(msg: &str) -> crate::Error {
+    format!("invalid snapshot file: {}", msg).into()
+}
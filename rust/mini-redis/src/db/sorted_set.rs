@@ -0,0 +1,206 @@
+use bytes::Bytes;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Bound;
+
+/// A set of unique members, ordered by their score.
+///
+/// Members are kept in two structures. A `HashMap` maps each member to its
+/// score, which allows looking up the score of a member in constant time. A
+/// `BTreeSet` holds `(score, member)` pairs, which keeps the members sorted by
+/// score and then lexicographically. This is the same approach used to track
+/// key expirations in `State`, where the member breaks ties between equal
+/// scores.
+#[derive(Debug, Default)]
+pub(crate) struct SortedSet {
+    /// Score of each member
+    scores: HashMap<Bytes, f64>,
+
+    /// Members ordered by score
+    ordered: BTreeSet<(Score, Bytes)>,
+}
+
+/// A score that can be used as a `BTreeSet` key.
+///
+/// `f64` does not implement `Ord` because of `NaN`. Sorted sets never store
+/// `NaN` scores, so a total order is obtained using `f64::total_cmp`.
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+/// One end of a score range, as used by `ZRANGEBYSCORE`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ScoreBound {
+    /// Scores equal to the value are included
+    Inclusive(f64),
+
+    /// Scores equal to the value are excluded
+    Exclusive(f64),
+}
+
+impl SortedSet {
+    /// Returns the number of members in the set.
+    pub(crate) fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    /// Returns `true` if the set has no members.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// Returns the score of `member`.
+    pub(crate) fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Insert `member` with the given `score`, replacing its previous score.
+    ///
+    /// Returns the previous score, if the member was already in the set.
+    pub(crate) fn insert(&mut self, member: Bytes, score: f64) -> Option<f64> {
+        // `-0.0` and `0.0` are equal, but `total_cmp` orders them differently.
+        // Adding `0.0` turns `-0.0` into `0.0`.
+        let score = score + 0.0;
+
+        let prev = self.scores.insert(member.clone(), score);
+
+        if let Some(prev) = prev {
+            self.ordered.remove(&(Score(prev), member.clone()));
+        }
+
+        self.ordered.insert((Score(score), member));
+
+        prev
+    }
+
+    /// Remove `member` from the set. Returns `true` if it was in the set.
+    pub(crate) fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove_entry(member) {
+            Some((member, score)) => {
+                self.ordered.remove(&(Score(score), member));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the zero-based position of `member` in the set, ordered from
+    /// the lowest to the highest score.
+    ///
+    /// `BTreeSet` does not track the size of its subtrees, so the rank is
+    /// computed by counting the members ordered before `member`.
+    pub(crate) fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        let key = (Score(score), Bytes::copy_from_slice(member));
+
+        Some(self.ordered.range(..key).count())
+    }
+
+    /// Returns the members between the `start` and `stop` indices, both
+    /// inclusive, along with their scores.
+    ///
+    /// Negative indices count from the end of the set, with `-1` being the
+    /// member with the highest score.
+    pub(crate) fn range(&self, start: i64, stop: i64) -> Vec<(Bytes, f64)> {
+        let len = self.len() as i64;
+
+        let start = if start < 0 {
+            (len + start).max(0)
+        } else {
+            start
+        };
+        let stop = if stop < 0 {
+            len + stop
+        } else {
+            stop.min(len - 1)
+        };
+
+        if start > stop {
+            return vec![];
+        }
+
+        self.ordered
+            .iter()
+            .skip(start as usize)
+            .take((stop - start + 1) as usize)
+            .map(|(score, member)| (member.clone(), score.0))
+            .collect()
+    }
+
+    /// Returns the members with a score between `min` and `max`, along with
+    /// their scores.
+    ///
+    /// The first `offset` matching members are skipped, and at most `count`
+    /// members are returned.
+    pub(crate) fn range_by_score(
+        &self,
+        min: ScoreBound,
+        max: ScoreBound,
+        offset: usize,
+        count: Option<usize>,
+    ) -> Vec<(Bytes, f64)> {
+        if min.value() > max.value() {
+            return vec![];
+        }
+
+        // Members with equal scores are ordered lexicographically, so the
+        // empty member comes first.
+        let lower = match min {
+            ScoreBound::Inclusive(score) => Bound::Included((Score(score), Bytes::new())),
+            ScoreBound::Exclusive(score) => Bound::Excluded((Score(score), Bytes::new())),
+        };
+
+        self.ordered
+            .range((lower, Bound::Unbounded))
+            .filter(|(score, _)| min.contains_above(score.0))
+            .take_while(|(score, _)| max.contains_below(score.0))
+            .skip(offset)
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(score, member)| (member.clone(), score.0))
+            .collect()
+    }
+}
+
+impl ScoreBound {
+    /// Returns the score value of the bound.
+    fn value(self) -> f64 {
+        match self {
+            ScoreBound::Inclusive(value) | ScoreBound::Exclusive(value) => value,
+        }
+    }
+
+    /// Returns `true` if `score` is above this lower bound.
+    fn contains_above(self, score: f64) -> bool {
+        match self {
+            ScoreBound::Inclusive(min) => score >= min,
+            ScoreBound::Exclusive(min) => score > min,
+        }
+    }
+
+    /// Returns `true` if `score` is below this upper bound.
+    fn contains_below(self, score: f64) -> bool {
+        match self {
+            ScoreBound::Inclusive(max) => score <= max,
+            ScoreBound::Exclusive(max) => score < max,
+        }
+    }
+}
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Score) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Score) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Score) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
@@ -0,0 +1,627 @@
+use super::Error;
+
+use bytes::Bytes;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// ID of a stream entry.
+///
+/// An ID is made of the time the entry was added, in milliseconds since the
+/// Unix epoch, and a sequence number distinguishing entries added within the
+/// same millisecond. IDs are ordered by time, then by sequence number. It is
+/// written as `<ms>-<seq>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub(crate) struct StreamId {
+    ms: u64,
+    seq: u64,
+}
+
+/// ID requested for a new stream entry, as given to `XADD`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum NewId {
+    /// `*`: generated from the current time.
+    Auto,
+
+    /// `<ms>-*`: the given time, with a generated sequence number.
+    AutoSeq(u64),
+
+    /// `<ms>-<seq>`: exactly this ID.
+    Explicit(StreamId),
+}
+
+/// A stream entry: its ID and its field-value pairs.
+pub(crate) type StreamEntry = (StreamId, Vec<(Bytes, Bytes)>);
+
+/// Entries read from each stream by `XREAD`, by key.
+pub(crate) type StreamRead = Vec<(String, Vec<StreamEntry>)>;
+
+/// Where `XREADGROUP` reads a stream from.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum GroupReadId {
+    /// `>`: entries never delivered to the group.
+    New,
+
+    /// Entries already delivered to the consumer and not acknowledged yet,
+    /// with an ID greater than this one.
+    Pending(StreamId),
+}
+
+/// How `XCLAIM` updates the entries it claims.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ClaimOptions {
+    /// Time the entries are considered delivered at, rather than now
+    pub(crate) delivered_ms: Option<u64>,
+
+    /// Delivery count of the entries, rather than incrementing it
+    pub(crate) retry_count: Option<u64>,
+
+    /// Claim entries which are not pending, as long as they are still in the
+    /// stream
+    pub(crate) force: bool,
+
+    /// Leave the delivery count unchanged
+    pub(crate) justid: bool,
+}
+
+/// An append-only log of entries, ordered by ID.
+///
+/// Entries are only ever added at the end, with an ID greater than all the
+/// previous ones, and trimmed from the beginning. The last ID is tracked
+/// separately from the entries, so IDs keep increasing after the stream was
+/// trimmed, even down to no entry at all.
+#[derive(Debug, Default)]
+pub(crate) struct Stream {
+    /// Entries by ID
+    entries: BTreeMap<StreamId, Vec<(Bytes, Bytes)>>,
+
+    /// ID of the last entry ever added
+    last_id: StreamId,
+
+    /// Consumer groups by name
+    groups: BTreeMap<String, Group>,
+}
+
+/// A consumer group of a stream.
+///
+/// The group tracks the last entry delivered to any of its consumers, so
+/// each entry is delivered to a single consumer. Delivered entries are
+/// pending until the consumer acknowledges them, and may be claimed by
+/// another consumer in the meantime.
+#[derive(Debug, Default)]
+pub(crate) struct Group {
+    /// ID of the last entry delivered to a consumer of the group
+    last_delivered: StreamId,
+
+    /// Entries delivered and not acknowledged yet, by ID
+    pending: BTreeMap<StreamId, PendingEntry>,
+
+    /// Consumers by name
+    consumers: BTreeMap<String, Consumer>,
+}
+
+/// An entry delivered to a consumer and not acknowledged yet.
+#[derive(Debug, Clone)]
+pub(crate) struct PendingEntry {
+    /// Name of the consumer the entry was delivered to
+    consumer: String,
+
+    /// Time of the last delivery, in milliseconds since the Unix epoch
+    delivered_ms: u64,
+
+    /// Number of times the entry was delivered
+    delivery_count: u64,
+}
+
+/// A consumer of a group.
+#[derive(Debug, Default)]
+pub(crate) struct Consumer {
+    /// Time the consumer last read or claimed entries, in milliseconds since
+    /// the Unix epoch
+    seen_ms: u64,
+
+    /// IDs of the entries pending for the consumer
+    pending: BTreeSet<StreamId>,
+}
+
+impl StreamId {
+    /// The smallest ID, `0-0`.
+    pub(crate) const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+
+    /// The greatest ID.
+    pub(crate) const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub(crate) fn new(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    /// Returns the time part of the ID.
+    pub(crate) fn ms(&self) -> u64 {
+        self.ms
+    }
+
+    /// Returns the sequence number part of the ID.
+    pub(crate) fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Returns the ID immediately following this one, or `None` if this is
+    /// the greatest ID.
+    pub(crate) fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_add(1)?,
+                seq: 0,
+            }),
+        }
+    }
+
+    /// Returns the ID immediately preceding this one, or `None` if this is
+    /// the smallest ID.
+    pub(crate) fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_sub(1)?,
+                seq: u64::MAX,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl FromStr for StreamId {
+    type Err = crate::Error;
+
+    /// Parse an ID written as `<ms>-<seq>`.
+    fn from_str(s: &str) -> crate::Result<StreamId> {
+        let invalid = || "ERR Invalid stream ID specified as stream command argument";
+
+        let (ms, seq) = s.split_once('-').ok_or_else(invalid)?;
+
+        Ok(StreamId {
+            ms: ms.parse().map_err(|_| invalid())?,
+            seq: seq.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Stream {
+    /// Returns the number of entries.
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns the ID of the last entry ever added, or `0-0` if none was.
+    pub(crate) fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Add an entry with the ID requested by `id`, where `now_ms` is the
+    /// current time in milliseconds.
+    ///
+    /// Returns the ID of the entry. An error is returned if the ID is not
+    /// greater than the last ID of the stream.
+    pub(crate) fn add(
+        &mut self,
+        id: NewId,
+        fields: Vec<(Bytes, Bytes)>,
+        now_ms: u64,
+    ) -> Result<StreamId, Error> {
+        let last = self.last_id;
+
+        let id = match id {
+            // The clock may go backwards, in which case the time of the last
+            // entry is reused.
+            NewId::Auto if now_ms > last.ms => Some(StreamId::new(now_ms, 0)),
+            NewId::Auto => last.next(),
+            NewId::AutoSeq(ms) if ms > last.ms => Some(StreamId::new(ms, 0)),
+            NewId::AutoSeq(ms) if ms == last.ms => {
+                last.seq.checked_add(1).map(|seq| StreamId::new(ms, seq))
+            }
+            NewId::AutoSeq(_) => None,
+            NewId::Explicit(id) if id == StreamId::MIN => return Err(Error::StreamIdZero),
+            NewId::Explicit(id) if id > last => Some(id),
+            NewId::Explicit(_) => None,
+        };
+
+        let id = id.ok_or(Error::StreamIdTooSmall)?;
+
+        self.entries.insert(id, fields);
+        self.last_id = id;
+
+        Ok(id)
+    }
+
+    /// Returns the entries with an ID between `start` and `end`, both
+    /// inclusive, up to `count` entries.
+    pub(crate) fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+    ) -> Vec<StreamEntry> {
+        if start > end {
+            return vec![];
+        }
+
+        self.entries
+            .range(start..=end)
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(id, fields)| (*id, fields.clone()))
+            .collect()
+    }
+
+    /// Remove the oldest entries, so at most `maxlen` entries remain.
+    ///
+    /// Returns the number of entries removed.
+    pub(crate) fn trim(&mut self, maxlen: usize) -> usize {
+        let removed = self.entries.len().saturating_sub(maxlen);
+
+        for _ in 0..removed {
+            self.entries.pop_first();
+        }
+
+        removed
+    }
+
+    /// Returns all entries, ordered by ID.
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (&StreamId, &Vec<(Bytes, Bytes)>)> {
+        self.entries.iter()
+    }
+
+    /// Returns the consumer groups, ordered by name.
+    pub(crate) fn groups(&self) -> impl Iterator<Item = (&String, &Group)> {
+        self.groups.iter()
+    }
+
+    /// Returns the consumer group `name`.
+    pub(crate) fn group(&self, name: &str) -> Result<&Group, Error> {
+        self.groups.get(name).ok_or(Error::NoGroup)
+    }
+
+    /// Returns the consumer group `name`, to be modified.
+    pub(crate) fn group_mut(&mut self, name: &str) -> Result<&mut Group, Error> {
+        self.groups.get_mut(name).ok_or(Error::NoGroup)
+    }
+
+    /// Create the consumer group `name`, which delivers the entries with an
+    /// ID greater than `last_delivered`.
+    pub(crate) fn create_group(
+        &mut self,
+        name: &str,
+        last_delivered: StreamId,
+    ) -> Result<(), Error> {
+        if self.groups.contains_key(name) {
+            return Err(Error::BusyGroup);
+        }
+
+        let group = Group {
+            last_delivered,
+            ..Group::default()
+        };
+        self.groups.insert(name.to_string(), group);
+
+        Ok(())
+    }
+
+    /// Remove the consumer group `name`, along with its pending entries.
+    ///
+    /// Returns `true` if the group existed.
+    pub(crate) fn destroy_group(&mut self, name: &str) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Read entries as `consumer` of the group `group`, up to `count`
+    /// entries. The consumer is created if needed.
+    ///
+    /// Reading new entries delivers them to the consumer, which makes them
+    /// pending unless `noack` is set. Reading pending entries delivers them
+    /// again. Pending entries removed from the stream since are returned
+    /// without fields.
+    pub(crate) fn read_group(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        id: GroupReadId,
+        count: Option<usize>,
+        noack: bool,
+        now_ms: u64,
+    ) -> Result<Vec<StreamEntry>, Error> {
+        let entries = &self.entries;
+        let group = self.groups.get_mut(group).ok_or(Error::NoGroup)?;
+        let count = count.unwrap_or(usize::MAX);
+
+        group.consumer(consumer, now_ms);
+
+        match id {
+            GroupReadId::New => {
+                let start = match group.last_delivered.next() {
+                    Some(start) => start,
+                    None => return Ok(vec![]),
+                };
+
+                let read: Vec<StreamEntry> = entries
+                    .range(start..)
+                    .take(count)
+                    .map(|(id, fields)| (*id, fields.clone()))
+                    .collect();
+
+                if let Some((id, _)) = read.last() {
+                    group.last_delivered = *id;
+                }
+
+                if !noack {
+                    for (id, _) in &read {
+                        group.deliver(*id, consumer, now_ms, 1);
+                    }
+                }
+
+                Ok(read)
+            }
+            GroupReadId::Pending(after) => {
+                let start = match after.next() {
+                    Some(start) => start,
+                    None => return Ok(vec![]),
+                };
+
+                let ids: Vec<StreamId> = group.consumers[consumer]
+                    .pending
+                    .range(start..)
+                    .take(count)
+                    .copied()
+                    .collect();
+
+                Ok(ids
+                    .into_iter()
+                    .map(|id| {
+                        if let Some(pending) = group.pending.get_mut(&id) {
+                            pending.delivered_ms = now_ms;
+                            pending.delivery_count += 1;
+                        }
+
+                        (id, entries.get(&id).cloned().unwrap_or_default())
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    /// Transfer the pending entries `ids` of the group `group` to
+    /// `consumer`, if they have not been delivered for at least
+    /// `min_idle_ms`. The consumer is created if needed.
+    ///
+    /// Pending entries removed from the stream since are dropped instead.
+    ///
+    /// Returns the claimed entries.
+    pub(crate) fn claim(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        min_idle_ms: u64,
+        ids: &[StreamId],
+        options: ClaimOptions,
+        now_ms: u64,
+    ) -> Result<Vec<StreamEntry>, Error> {
+        let entries = &self.entries;
+        let group = self.groups.get_mut(group).ok_or(Error::NoGroup)?;
+
+        group.consumer(consumer, now_ms);
+
+        let mut claimed = vec![];
+
+        for id in ids {
+            let fields = match entries.get(id) {
+                Some(fields) => fields,
+                None => {
+                    group.ack(*id);
+                    continue;
+                }
+            };
+
+            let delivery_count = match group.pending.get(id) {
+                Some(pending) if now_ms.saturating_sub(pending.delivered_ms) < min_idle_ms => {
+                    continue;
+                }
+                Some(pending) => pending.delivery_count,
+                None if options.force => 0,
+                None => continue,
+            };
+
+            let delivery_count = match options.retry_count {
+                Some(retry_count) => retry_count,
+                None if options.justid => delivery_count,
+                None => delivery_count + 1,
+            };
+
+            let delivered_ms = options.delivered_ms.unwrap_or(now_ms);
+            group.deliver(*id, consumer, delivered_ms, delivery_count);
+
+            claimed.push((*id, fields.clone()));
+        }
+
+        Ok(claimed)
+    }
+
+    /// Create a stream from its entries, last ID and consumer groups, as
+    /// stored in a snapshot.
+    pub(crate) fn from_parts(
+        entries: Vec<StreamEntry>,
+        last_id: StreamId,
+        groups: Vec<(String, Group)>,
+    ) -> Stream {
+        Stream {
+            entries: entries.into_iter().collect(),
+            last_id,
+            groups: groups.into_iter().collect(),
+        }
+    }
+}
+
+impl Group {
+    /// Returns the ID of the last entry delivered to a consumer.
+    pub(crate) fn last_delivered(&self) -> StreamId {
+        self.last_delivered
+    }
+
+    /// Set the ID of the last entry delivered to a consumer, so the group
+    /// delivers the entries with a greater ID next.
+    pub(crate) fn set_last_delivered(&mut self, id: StreamId) {
+        self.last_delivered = id;
+    }
+
+    /// Returns the consumers, ordered by name, along with the time each one
+    /// was last seen.
+    pub(crate) fn consumers(&self) -> impl Iterator<Item = (&String, u64)> {
+        self.consumers
+            .iter()
+            .map(|(name, consumer)| (name, consumer.seen_ms))
+    }
+
+    /// Returns `true` if the group has a consumer named `consumer`.
+    pub(crate) fn has_consumer(&self, consumer: &str) -> bool {
+        self.consumers.contains_key(consumer)
+    }
+
+    /// Returns the number of entries pending for each consumer with at least
+    /// one, ordered by name.
+    pub(crate) fn pending_by_consumer(&self) -> Vec<(String, usize)> {
+        self.consumers
+            .iter()
+            .filter(|(_, consumer)| !consumer.pending.is_empty())
+            .map(|(name, consumer)| (name.clone(), consumer.pending.len()))
+            .collect()
+    }
+
+    /// Returns the pending entries with an ID between `start` and `end`,
+    /// both inclusive.
+    pub(crate) fn pending(
+        &self,
+        start: StreamId,
+        end: StreamId,
+    ) -> impl Iterator<Item = (&StreamId, &PendingEntry)> {
+        // `range` does not accept a start after the end.
+        let range = if start <= end {
+            Some(self.pending.range(start..=end))
+        } else {
+            None
+        };
+
+        range.into_iter().flatten()
+    }
+
+    /// Returns the pending entry `id`, if any.
+    pub(crate) fn pending_entry(&self, id: StreamId) -> Option<&PendingEntry> {
+        self.pending.get(&id)
+    }
+
+    /// Returns the number of pending entries.
+    pub(crate) fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Create `consumer` if it does not exist.
+    ///
+    /// Returns `true` if the consumer was created.
+    pub(crate) fn create_consumer(&mut self, consumer: &str, now_ms: u64) -> bool {
+        if self.consumers.contains_key(consumer) {
+            return false;
+        }
+
+        self.consumer(consumer, now_ms);
+        true
+    }
+
+    /// Remove `consumer`, along with its pending entries.
+    ///
+    /// Returns the number of entries which were pending for the consumer.
+    pub(crate) fn delete_consumer(&mut self, consumer: &str) -> usize {
+        let removed = match self.consumers.remove(consumer) {
+            Some(removed) => removed,
+            None => return 0,
+        };
+
+        for id in &removed.pending {
+            self.pending.remove(id);
+        }
+
+        removed.pending.len()
+    }
+
+    /// Acknowledge the entry `id`, which stops being pending.
+    ///
+    /// Returns `true` if the entry was pending.
+    pub(crate) fn ack(&mut self, id: StreamId) -> bool {
+        let pending = match self.pending.remove(&id) {
+            Some(pending) => pending,
+            None => return false,
+        };
+
+        if let Some(consumer) = self.consumers.get_mut(&pending.consumer) {
+            consumer.pending.remove(&id);
+        }
+
+        true
+    }
+
+    /// Mark the entry `id` as delivered to `consumer` at `delivered_ms`,
+    /// taking it over from the consumer it was pending for, if any. The
+    /// consumer is created if needed.
+    pub(crate) fn deliver(
+        &mut self,
+        id: StreamId,
+        consumer: &str,
+        delivered_ms: u64,
+        delivery_count: u64,
+    ) {
+        self.ack(id);
+
+        self.consumers
+            .entry(consumer.to_string())
+            .or_default()
+            .pending
+            .insert(id);
+
+        let pending = PendingEntry {
+            consumer: consumer.to_string(),
+            delivered_ms,
+            delivery_count,
+        };
+        self.pending.insert(id, pending);
+    }
+
+    /// Returns `consumer`, creating it if needed, and marks it as seen at
+    /// `now_ms`.
+    fn consumer(&mut self, consumer: &str, now_ms: u64) -> &mut Consumer {
+        let consumer = self.consumers.entry(consumer.to_string()).or_default();
+        consumer.seen_ms = now_ms;
+        consumer
+    }
+}
+
+impl PendingEntry {
+    /// Returns the name of the consumer the entry was delivered to.
+    pub(crate) fn consumer(&self) -> &str {
+        &self.consumer
+    }
+
+    /// Returns the time of the last delivery, in milliseconds since the Unix
+    /// epoch.
+    pub(crate) fn delivered_ms(&self) -> u64 {
+        self.delivered_ms
+    }
+
+    /// Returns the number of times the entry was delivered.
+    pub(crate) fn delivery_count(&self) -> u64 {
+        self.delivery_count
+    }
+}
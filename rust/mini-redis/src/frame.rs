@@ -9,14 +9,37 @@ use std::num::TryFromIntError;
 use std::string::FromUtf8Error;
 
 /// A frame in the Redis protocol.
+///
+/// The first six variants make up RESP2. The remaining variants were added by
+/// RESP3 and are only written as-is to connections that negotiated version 3
+/// with `HELLO`. When writing to a RESP2 connection, they are downgraded to
+/// their closest RESP2 equivalent. See `Connection::write_frame`.
 #[derive(Clone, Debug)]
 pub enum Frame {
     Simple(String),
     Error(String),
-    Integer(u64),
+    Integer(i64),
     Bulk(Bytes),
     Null,
     Array(Vec<Frame>),
+    Boolean(bool),
+    Double(f64),
+    BigNumber(String),
+    Verbatim(String, Bytes),
+    Map(Vec<(Frame, Frame)>),
+    Set(Vec<Frame>),
+    Push(Vec<Frame>),
+}
+
+/// Version of the Redis protocol spoken on a connection.
+///
+/// Connections start out speaking RESP2. A client may switch to RESP3 by
+/// issuing `HELLO 3`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
 }
 
 #[derive(Debug)]
@@ -34,28 +57,35 @@ impl Frame {
         Frame::Array(vec![])
     }
 
-    /// Push a "bulk" frame into the array. `self` must be an Array frame.
+    /// Returns an empty push frame
+    pub(crate) fn push() -> Frame {
+        Frame::Push(vec![])
+    }
+
+    /// Push a "bulk" frame into the array. `self` must be an Array or Push
+    /// frame.
     ///
     /// # Panics
     ///
     /// panics if `self` is not an array
     pub(crate) fn push_bulk(&mut self, bytes: Bytes) {
         match self {
-            Frame::Array(vec) => {
+            Frame::Array(vec) | Frame::Push(vec) => {
                 vec.push(Frame::Bulk(bytes));
             }
             _ => panic!("not an array frame"),
         }
     }
 
-    /// Push an "integer" frame into the array. `self` must be an Array frame.
+    /// Push an "integer" frame into the array. `self` must be an Array or Push
+    /// frame.
     ///
     /// # Panics
     ///
     /// panics if `self` is not an array
-    pub(crate) fn push_int(&mut self, value: u64) {
+    pub(crate) fn push_int(&mut self, value: i64) {
         match self {
-            Frame::Array(vec) => {
+            Frame::Array(vec) | Frame::Push(vec) => {
                 vec.push(Frame::Integer(value));
             }
             _ => panic!("not an array frame"),
@@ -74,7 +104,7 @@ impl Frame {
                 Ok(())
             }
             b':' => {
-                let _ = get_decimal(src)?;
+                let _ = get_signed_decimal(src)?;
                 Ok(())
             }
             b'$' => {
@@ -83,21 +113,45 @@ impl Frame {
                     skip(src, 4)
                 } else {
                     // Read the bulk string
-                    let len: usize = get_decimal(src)?.try_into()?;
-
                     // skip that number of bytes + 2 (\r\n).
-                    skip(src, len + 2)
+                    let n = blob_len(src)?;
+                    skip(src, n)
                 }
             }
             b'*' => {
+                if b'-' == peek_u8(src)? {
+                    // Skip '-1\r\n', the RESP2 null array
+                    return skip(src, 4);
+                }
+
+                check_aggregate(src)
+            }
+            // Unlike arrays, sets and pushes have no null variant, so a
+            // negative length is rejected by `get_decimal`, as in `parse`.
+            b'~' | b'>' => check_aggregate(src),
+            b'%' => {
+                // A map of `len` entries is encoded as `2 * len` frames,
+                // alternating between keys and values.
                 let len = get_decimal(src)?;
 
                 for _ in 0..len {
                     Frame::check(src)?;
+                    Frame::check(src)?;
                 }
 
                 Ok(())
             }
+            b'_' => skip(src, 2),
+            b'#' | b',' | b'(' => {
+                get_line(src)?;
+                Ok(())
+            }
+            b'!' | b'=' => {
+                // Blob errors and verbatim strings are length prefixed just
+                // like bulk strings.
+                let n = blob_len(src)?;
+                skip(src, n)
+            }
             actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
         }
     }
@@ -124,8 +178,8 @@ impl Frame {
                 Ok(Frame::Error(string))
             }
             b':' => {
-                let len = get_decimal(src)?;
-                Ok(Frame::Integer(len))
+                let value = get_signed_decimal(src)?;
+                Ok(Frame::Integer(value))
             }
             b'$' => {
                 if b'-' == peek_u8(src)? {
@@ -138,32 +192,94 @@ impl Frame {
                     Ok(Frame::Null)
                 } else {
                     // Read the bulk string
-                    let len = get_decimal(src)?.try_into()?;
-                    let n = len + 2;
-
-                    if src.remaining() < n {
-                        return Err(Error::Incomplete);
-                    }
-
-                    let data = Bytes::copy_from_slice(&src.chunk()[..len]);
-
-                    // skip that number of bytes + 2 (\r\n).
-                    skip(src, n)?;
+                    let data = get_blob(src)?;
 
                     Ok(Frame::Bulk(data))
                 }
             }
             b'*' => {
+                if b'-' == peek_u8(src)? {
+                    let line = get_line(src)?;
+
+                    if line != b"-1" {
+                        return Err("protocol error; invalid frame format".into());
+                    }
+
+                    // RESP2 has a separate "null array". Both nulls are
+                    // represented by the same frame.
+                    return Ok(Frame::Null);
+                }
+
+                Ok(Frame::Array(parse_aggregate(src)?))
+            }
+            b'~' => Ok(Frame::Set(parse_aggregate(src)?)),
+            b'>' => Ok(Frame::Push(parse_aggregate(src)?)),
+            b'%' => {
                 let len = get_decimal(src)?.try_into()?;
                 let mut out = Vec::with_capacity(len);
 
                 for _ in 0..len {
-                    out.push(Frame::parse(src)?);
+                    let key = Frame::parse(src)?;
+                    let value = Frame::parse(src)?;
+                    out.push((key, value));
                 }
 
-                Ok(Frame::Array(out))
+                Ok(Frame::Map(out))
+            }
+            b'_' => {
+                let line = get_line(src)?;
+
+                if !line.is_empty() {
+                    return Err("protocol error; invalid frame format".into());
+                }
+
+                Ok(Frame::Null)
+            }
+            b'#' => match get_line(src)? {
+                b"t" => Ok(Frame::Boolean(true)),
+                b"f" => Ok(Frame::Boolean(false)),
+                _ => Err("protocol error; invalid frame format".into()),
+            },
+            b',' => {
+                let line = get_line(src)?;
+
+                let value = std::str::from_utf8(line)
+                    .ok()
+                    .and_then(|line| line.parse::<f64>().ok())
+                    .ok_or("protocol error; invalid frame format")?;
+
+                Ok(Frame::Double(value))
+            }
+            b'(' => {
+                let line = get_line(src)?.to_vec();
+
+                // Big numbers are kept in their textual representation. There
+                // is no native type wide enough to hold them.
+                let string = String::from_utf8(line)?;
+
+                Ok(Frame::BigNumber(string))
+            }
+            b'!' => {
+                // A blob error is just an error that may contain new lines.
+                let data = get_blob(src)?;
+                let string = String::from_utf8(data.to_vec())?;
+
+                Ok(Frame::Error(string))
             }
-            _ => unimplemented!(),
+            b'=' => {
+                // Verbatim strings start with a three character format,
+                // followed by `:`, followed by the data.
+                let data = get_blob(src)?;
+
+                if data.len() < 4 || data[3] != b':' {
+                    return Err("protocol error; invalid frame format".into());
+                }
+
+                let format = String::from_utf8(data[..3].to_vec())?;
+
+                Ok(Frame::Verbatim(format, data.slice(4..)))
+            }
+            actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
         }
     }
 
@@ -178,6 +294,7 @@ impl PartialEq<&str> for Frame {
         match self {
             Frame::Simple(s) => s.eq(other),
             Frame::Bulk(s) => s.eq(other),
+            Frame::Verbatim(_, s) => s.eq(other),
             _ => false,
         }
     }
@@ -196,7 +313,7 @@ impl fmt::Display for Frame {
                 Err(_) => write!(fmt, "{:?}", msg),
             },
             Frame::Null => "(nil)".fmt(fmt),
-            Frame::Array(parts) => {
+            Frame::Array(parts) | Frame::Set(parts) | Frame::Push(parts) => {
                 for (i, part) in parts.iter().enumerate() {
                     if i > 0 {
                         // use space as the array element display separator
@@ -206,12 +323,70 @@ impl fmt::Display for Frame {
                     part.fmt(fmt)?;
                 }
 
+                Ok(())
+            }
+            Frame::Boolean(value) => value.fmt(fmt),
+            Frame::Double(value) => format_double(*value).fmt(fmt),
+            Frame::BigNumber(value) => value.fmt(fmt),
+            Frame::Verbatim(_, msg) => match str::from_utf8(msg) {
+                Ok(string) => string.fmt(fmt),
+                Err(_) => write!(fmt, "{:?}", msg),
+            },
+            Frame::Map(entries) => {
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " ")?;
+                    }
+
+                    write!(fmt, "{} => {}", key, value)?;
+                }
+
                 Ok(())
             }
         }
     }
 }
 
+/// Formats a double the way Redis does on the wire.
+///
+/// Rust's `Display` for `f64` already produces the shortest representation
+/// that round trips, which is what Redis aims for as well. Only the special
+/// values need adjusting.
+pub(crate) fn format_double(value: f64) -> String {
+    if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Checks the entries of an array, set or push frame, following their
+/// length.
+fn check_aggregate(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
+    let len = get_decimal(src)?;
+
+    for _ in 0..len {
+        Frame::check(src)?;
+    }
+
+    Ok(())
+}
+
+/// Parse `len` frames, where `len` is the decimal on the current line. Used
+/// by all aggregate types that are encoded as a flat sequence of frames.
+fn parse_aggregate(src: &mut Cursor<&[u8]>) -> Result<Vec<Frame>, Error> {
+    let len = get_decimal(src)?.try_into()?;
+    let mut out = Vec::with_capacity(len);
+
+    for _ in 0..len {
+        out.push(Frame::parse(src)?);
+    }
+
+    Ok(out)
+}
+
 fn peek_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
     if !src.has_remaining() {
         return Err(Error::Incomplete);
@@ -246,6 +421,42 @@ fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
     atoi::<u64>(line).ok_or_else(|| "protocol error; invalid frame format".into())
 }
 
+/// Read a new-line terminated decimal that may be negative
+fn get_signed_decimal(src: &mut Cursor<&[u8]>) -> Result<i64, Error> {
+    use atoi::atoi;
+
+    let line = get_line(src)?;
+
+    atoi::<i64>(line).ok_or_else(|| "protocol error; invalid frame format".into())
+}
+
+/// Read the length of a length prefixed blob, returned with the 2 bytes of
+/// the trailing `\r\n`.
+fn blob_len(src: &mut Cursor<&[u8]>) -> Result<usize, Error> {
+    let len: usize = get_decimal(src)?.try_into()?;
+
+    len.checked_add(2)
+        .ok_or_else(|| "protocol error; invalid frame format".into())
+}
+
+/// Read a length prefixed blob, as used by bulk strings, blob errors and
+/// verbatim strings.
+fn get_blob(src: &mut Cursor<&[u8]>) -> Result<Bytes, Error> {
+    let n = blob_len(src)?;
+    let len = n - 2;
+
+    if src.remaining() < n {
+        return Err(Error::Incomplete);
+    }
+
+    let data = Bytes::copy_from_slice(&src.chunk()[..len]);
+
+    // skip that number of bytes + 2 (\r\n).
+    skip(src, n)?;
+
+    Ok(data)
+}
+
 /// Find a line
 fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
     // Scan the bytes directly
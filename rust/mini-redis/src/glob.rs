@@ -0,0 +1,101 @@
+//! Glob-style pattern matching, as used by `PSUBSCRIBE`, `KEYS` and `SCAN`.
+//!
+//! The supported syntax is the same as Redis:
+//!
+//! * `?` matches any single byte.
+//! * `*` matches any sequence of bytes, including an empty one.
+//! * `[abc]` matches one of the listed bytes, `[a-z]` matches a range and
+//!   `[^abc]` matches any byte except the listed ones.
+//! * `\` escapes the following byte, so `\*` only matches `*`.
+
+/// Returns `true` if `string` matches `pattern`.
+pub(crate) fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    let mut p = 0;
+    let mut s = 0;
+
+    // Position in the pattern just after the last `*` seen, and the position
+    // in the string it is currently assumed to match up to. On a mismatch,
+    // the `*` is extended by one byte and matching resumes from there.
+    let mut star: Option<(usize, usize)> = None;
+
+    while s < string.len() {
+        if p < pattern.len() {
+            if pattern[p] == b'*' {
+                p += 1;
+                star = Some((p, s));
+                continue;
+            }
+
+            if let Some(len) = match_one(&pattern[p..], string[s]) {
+                p += len;
+                s += 1;
+                continue;
+            }
+        }
+
+        match star {
+            Some((star_p, star_s)) => {
+                p = star_p;
+                s = star_s + 1;
+                star = Some((star_p, s));
+            }
+            None => return false,
+        }
+    }
+
+    // The string is consumed, so only trailing `*` may remain in the pattern.
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+/// Match the token at the start of `pattern`, which is not `*`, against the
+/// byte `c`.
+///
+/// Returns the length of the token if it matches.
+fn match_one(pattern: &[u8], c: u8) -> Option<usize> {
+    let (matched, len) = match pattern[0] {
+        b'?' => (true, 1),
+        b'[' => match_class(pattern, c),
+        b'\\' if pattern.len() > 1 => (pattern[1] == c, 2),
+        b => (b == c, 1),
+    };
+
+    if matched {
+        Some(len)
+    } else {
+        None
+    }
+}
+
+/// Match the character class at the start of `pattern` against the byte `c`.
+///
+/// Returns whether it matches, and the length of the class. A class that is
+/// not terminated extends to the end of the pattern.
+fn match_class(pattern: &[u8], c: u8) -> (bool, usize) {
+    let mut i = 1;
+
+    let negate = pattern.get(i) == Some(&b'^');
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+
+    while i < pattern.len() && pattern[i] != b']' {
+        if pattern[i] == b'\\' && i + 1 < pattern.len() {
+            matched |= pattern[i + 1] == c;
+            i += 2;
+        } else if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']' {
+            let (start, end) = (pattern[i], pattern[i + 2]);
+            matched |= start.min(end) <= c && c <= start.max(end);
+            i += 3;
+        } else {
+            matched |= pattern[i] == c;
+            i += 1;
+        }
+    }
+
+    // Include the closing `]`, if any
+    let len = (i + 1).min(pattern.len());
+
+    (matched != negate, len)
+}
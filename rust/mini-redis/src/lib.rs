@@ -23,29 +23,42 @@
 //!
 //! * `frame`: represents a single Redis protocol frame. A frame is used as an
 //!   intermediate representation between a "command" and the byte
-//!   representation.
+//!   representation. Both RESP2 and RESP3 frames are supported.
 
 pub mod clients;
-pub use clients::{BlockingClient, BufferedClient, Client};
+pub use clients::{BlockingClient, BufferedClient, Client, ClusterClient};
+
+pub mod cluster;
 
 pub mod cmd;
 pub use cmd::Command;
 
+pub mod config;
+pub use config::Config;
+
 mod connection;
-pub use connection::Connection;
+pub use connection::{AsyncStream, Connection};
 
 pub mod frame;
-pub use frame::Frame;
+pub use frame::{Frame, Protocol};
 
 mod db;
 use db::Db;
 use db::DbDropGuard;
 
+mod glob;
+
 mod parse;
 use parse::{Parse, ParseError};
 
+mod replication;
+
+mod scripting;
+
 pub mod server;
 
+pub mod tls;
+
 mod shutdown;
 use shutdown::Shutdown;
 
@@ -1,6 +1,7 @@
 use crate::Frame;
 
 use bytes::Bytes;
+use std::convert::TryInto;
 use std::{fmt, str, vec};
 
 /// Utility for parsing a command
@@ -106,7 +107,7 @@ impl Parse {
 
         match self.next()? {
             // An integer frame type is already stored as an integer.
-            Frame::Integer(v) => Ok(v),
+            Frame::Integer(v) => v.try_into().map_err(|_| MSG.into()),
             // Simple and bulk frames must be parsed as integers. If the parsing
             // fails, an error is returned.
             Frame::Simple(data) => atoi::<u64>(data.as_bytes()).ok_or_else(|| MSG.into()),
@@ -115,6 +116,43 @@ impl Parse {
         }
     }
 
+    /// Return the next entry as a signed integer.
+    ///
+    /// Same as `next_int`, but accepts negative values.
+    pub(crate) fn next_signed_int(&mut self) -> Result<i64, ParseError> {
+        use atoi::atoi;
+
+        const MSG: &str = "protocol error; invalid number";
+
+        match self.next()? {
+            Frame::Integer(v) => Ok(v),
+            Frame::Simple(data) => atoi::<i64>(data.as_bytes()).ok_or_else(|| MSG.into()),
+            Frame::Bulk(data) => atoi::<i64>(&data).ok_or_else(|| MSG.into()),
+            frame => Err(format!("protocol error; expected int frame but got {:?}", frame).into()),
+        }
+    }
+
+    /// Return the next entry as a floating point number.
+    ///
+    /// Integer frames are converted. `Simple` and `Bulk` frames are parsed,
+    /// which includes the special values `inf` and `-inf`.
+    pub(crate) fn next_float(&mut self) -> Result<f64, ParseError> {
+        const MSG: &str = "protocol error; invalid float";
+
+        match self.next()? {
+            Frame::Integer(v) => Ok(v as f64),
+            Frame::Double(v) => Ok(v),
+            Frame::Simple(data) => data.parse().map_err(|_| MSG.into()),
+            Frame::Bulk(data) => str::from_utf8(&data)
+                .ok()
+                .and_then(|data| data.parse().ok())
+                .ok_or_else(|| MSG.into()),
+            frame => {
+                Err(format!("protocol error; expected float frame but got {:?}", frame).into())
+            }
+        }
+    }
+
     /// Ensure there are no more entries in the array
     pub(crate) fn finish(&mut self) -> Result<(), ParseError> {
         if self.parts.next().is_none() {
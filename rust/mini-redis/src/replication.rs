@@ -0,0 +1,167 @@
+//! Master/replica replication, as configured with `REPLICAOF`.
+//!
+//! A replica connects to its master and sends `PSYNC`. The master responds
+//! with `+FULLRESYNC <replid> <offset>`, followed by a snapshot of its key
+//! space as a bulk string, in the format of the snapshot file. It then streams
+//! every command modifying the key space, in the order they are executed. The
+//! replica applies them as they are received.
+//!
+//! The replication offset counts the bytes of the command stream, with the
+//! commands encoded as arrays of bulk strings. Comparing the offsets of the
+//! master and of a replica tells how far behind the replica is.
+
+use crate::cmd::PSync;
+use crate::{tls, AsyncStream, Command, Connection, Db, Frame};
+
+use bytes::Bytes;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::SystemTime;
+use tokio::net::TcpStream;
+use tokio::time::{self, Duration};
+use tracing::{error, info};
+
+/// Time to wait before reconnecting to the master after the link is lost.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Follow the master listening on `host` and `port`.
+///
+/// Runs until the task is aborted, reconnecting whenever the connection to
+/// the master is lost. Each connection starts with a full synchronization.
+pub(crate) async fn follow(db: Db, host: String, port: u16) {
+    loop {
+        if let Err(err) = sync(&db, &host, port).await {
+            error!(cause = %err, "replication link with {}:{} is down", host, port);
+        }
+
+        db.with_state(|state| state.set_master_link_down());
+
+        time::sleep(RETRY_DELAY).await;
+    }
+}
+
+/// Synchronize with the master, then apply the commands it streams until the
+/// connection is lost.
+async fn sync(db: &Db, host: &str, port: u16) -> crate::Result<()> {
+    let tls = db.replication_tls()?;
+
+    let socket = TcpStream::connect((host, port)).await?;
+    let stream: Box<dyn AsyncStream> = match tls {
+        Some(config) => Box::new(tls::connect(socket, host, config).await?),
+        None => Box::new(socket),
+    };
+    let mut connection = Connection::new(stream);
+
+    connection.write_frame(&PSync::new().into_frame()).await?;
+
+    let (replid, offset) = match connection.read_frame().await? {
+        Some(Frame::Simple(line)) => parse_fullresync(&line)?,
+        Some(frame) => return Err(frame.to_error()),
+        None => return Err("connection closed by master".into()),
+    };
+
+    let snapshot = match connection.read_frame().await? {
+        Some(Frame::Bulk(snapshot)) => snapshot,
+        Some(frame) => return Err(frame.to_error()),
+        None => return Err("connection closed by master".into()),
+    };
+
+    let count = db.with_state(|state| state.full_sync(replid, offset, &snapshot))?;
+    info!(keys = count, "synchronized with master {}:{}", host, port);
+
+    // Commands apply to the database selected by the last `SELECT` the
+    // master sent.
+    let mut db = db.clone();
+
+    loop {
+        let frame = match connection.read_frame().await? {
+            Some(frame) => frame,
+            None => return Err("connection closed by master".into()),
+        };
+
+        let len = command_len(&frame);
+        let cmd = match Command::from_frame(frame)? {
+            Command::Select(cmd) => {
+                db = db
+                    .select(cmd.index())
+                    .map_err(|_| "DB index sent by master is out of range")?;
+                db.with_state(|state| state.advance_repl_offset(len));
+                continue;
+            }
+            cmd => cmd,
+        };
+
+        db.with_state(|state| {
+            cmd.execute(state);
+            state.advance_repl_offset(len);
+        });
+    }
+}
+
+/// Parse the `FULLRESYNC <replid> <offset>` response to `PSYNC`.
+fn parse_fullresync(line: &str) -> crate::Result<(String, u64)> {
+    let mut parts = line.split(' ');
+
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some("FULLRESYNC"), Some(replid), Some(offset), None) => {
+            let offset = offset
+                .parse()
+                .map_err(|_| "protocol error; invalid replication offset")?;
+            Ok((replid.to_string(), offset))
+        }
+        _ => Err(format!("protocol error; unexpected response to PSYNC '{}'", line).into()),
+    }
+}
+
+/// Convert all entries of a command to bulk strings, as they are counted in
+/// the replication offset.
+pub(crate) fn normalize(frame: &Frame) -> Frame {
+    match frame {
+        Frame::Array(entries) => Frame::Array(
+            entries
+                .iter()
+                .map(|entry| match entry {
+                    Frame::Bulk(data) => Frame::Bulk(data.clone()),
+                    entry => Frame::Bulk(Bytes::from(entry.to_string())),
+                })
+                .collect(),
+        ),
+        frame => frame.clone(),
+    }
+}
+
+/// Returns the number of bytes `frame`, a normalized command, adds to the
+/// replication offset.
+pub(crate) fn command_len(frame: &Frame) -> u64 {
+    let entries = match frame {
+        Frame::Array(entries) => entries,
+        _ => return 0,
+    };
+
+    let header = format!("*{}\r\n", entries.len()).len();
+
+    let body: usize = entries
+        .iter()
+        .map(|entry| {
+            let len = match entry {
+                Frame::Bulk(data) => data.len(),
+                entry => entry.to_string().len(),
+            };
+
+            format!("${}\r\n", len).len() + len + 2
+        })
+        .sum();
+
+    (header + body) as u64
+}
+
+/// Generate a random replication ID, 40 hexadecimal characters long.
+pub(crate) fn new_replid() -> String {
+    // `RandomState` is seeded with random keys, which vary from one instance
+    // to the next.
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+
+    let seed = format!("{:?} {}", SystemTime::now(), hasher.finish());
+    crate::scripting::sha1(seed.as_bytes())
+}
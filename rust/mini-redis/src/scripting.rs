@@ -0,0 +1,296 @@
+//! Lua scripting, as used by `EVAL` and `EVALSHA`.
+//!
+//! Scripts run in a fresh Lua 5.1 interpreter, with the `table`, `string` and
+//! `math` libraries available. Commands are issued with `redis.call` and
+//! `redis.pcall`, and are executed against the locked database state: the
+//! whole script is atomic with respect to other connections.
+//!
+//! Values are converted between Lua and Redis the same way Redis does it:
+//!
+//! * Integer replies become Lua numbers, and Lua numbers returned by the
+//!   script are truncated to integer replies.
+//! * Bulk strings become Lua strings, and a null reply becomes `false`.
+//! * Arrays become Lua tables, and a Lua table is returned as an array up to
+//!   its first `nil`.
+//! * Status and error replies become tables with a single `ok` or `err`
+//!   field, and are returned as such.
+
+use crate::db::State;
+use crate::frame::format_double;
+use crate::{Command, Frame};
+
+use bytes::Bytes;
+use mlua::{HookTriggers, Lua, LuaOptions, MultiValue, StdLib, Table, Value};
+use std::cell::RefCell;
+use std::error::Error as StdError;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How many Lua instructions are executed between two checks of the time
+/// limit.
+const CHECK_INTERVAL: u32 = 1000;
+
+/// Errors interrupting a script, which are reported to the client as is.
+#[derive(Debug)]
+enum ScriptError {
+    /// A command issued with `redis.call` failed.
+    Command(String),
+
+    /// The script ran for longer than the time limit.
+    Timeout(Duration),
+}
+
+/// Returns the SHA1 digest of `script`, used to refer to it in `EVALSHA`.
+pub(crate) fn sha1(script: &[u8]) -> String {
+    sha1_smol::Sha1::from(script).digest().to_string()
+}
+
+/// Run `script` against the locked database state, and return the reply to
+/// send to the client.
+///
+/// `keys` and `args` are exposed to the script as the `KEYS` and `ARGV`
+/// tables. The script is aborted once it runs for longer than `time_limit`.
+/// Commands it executed until then are not rolled back.
+pub(crate) fn eval(
+    state: &mut State,
+    script: &[u8],
+    keys: Vec<String>,
+    args: Vec<Bytes>,
+    time_limit: Duration,
+) -> Frame {
+    let lua = match Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+        LuaOptions::default(),
+    ) {
+        Ok(lua) => lua,
+        Err(err) => return Frame::Error(format!("ERR Error creating script interpreter: {}", err)),
+    };
+
+    let start = Instant::now();
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(CHECK_INTERVAL),
+        move |_, _| {
+            if start.elapsed() > time_limit {
+                Err(mlua::Error::external(ScriptError::Timeout(time_limit)))
+            } else {
+                Ok(())
+            }
+        },
+    );
+
+    // `redis.call` and `redis.pcall` both need mutable access to the state.
+    // Scripts cannot call `EVAL`, so they never borrow it twice.
+    let state = RefCell::new(state);
+
+    let result = lua.scope(|scope| {
+        let redis = lua.create_table()?;
+
+        redis.set(
+            "call",
+            scope.create_function(|lua, args: MultiValue| call(lua, &state, args, true))?,
+        )?;
+        redis.set(
+            "pcall",
+            scope.create_function(|lua, args: MultiValue| call(lua, &state, args, false))?,
+        )?;
+        redis.set(
+            "status_reply",
+            lua.create_function(|lua, msg: mlua::String| reply_table(lua, "ok", msg))?,
+        )?;
+        redis.set(
+            "error_reply",
+            lua.create_function(|lua, msg: mlua::String| reply_table(lua, "err", msg))?,
+        )?;
+
+        let globals = lua.globals();
+        globals.set("redis", redis)?;
+        globals.set("KEYS", strings_table(&lua, keys)?)?;
+        globals.set("ARGV", strings_table(&lua, args)?)?;
+
+        let value: Value = lua.load(script).set_name("@user_script").eval()?;
+
+        Ok(to_frame(value))
+    });
+
+    match result {
+        Ok(frame) => frame,
+        Err(err) => match script_error(&err) {
+            Some(ScriptError::Command(msg)) => Frame::Error(msg.clone()),
+            Some(err) => Frame::Error(err.to_string()),
+            None => Frame::Error(format!("ERR Error running script: {}", err)),
+        },
+    }
+}
+
+/// Implementation of `redis.call` and `redis.pcall`.
+///
+/// Executes the command given by `args`. If it fails, `redis.call` raises a
+/// Lua error, while `redis.pcall` returns an error table.
+fn call<'lua>(
+    lua: &'lua Lua,
+    state: &RefCell<&mut State>,
+    args: MultiValue<'lua>,
+    raise: bool,
+) -> mlua::Result<Value<'lua>> {
+    let response = match command_frame(args) {
+        Ok(frame) => match Command::from_frame(frame) {
+            Ok(Command::Unknown(_)) => {
+                Frame::Error("ERR Unknown Redis command called from script".to_string())
+            }
+            Ok(cmd) if !cmd.is_scriptable() => {
+                Frame::Error("ERR This Redis command is not allowed from script".to_string())
+            }
+            Ok(cmd) => cmd.execute(&mut state.borrow_mut()),
+            Err(err) => Frame::Error(format!("ERR {}", err)),
+        },
+        Err(msg) => Frame::Error(msg),
+    };
+
+    match response {
+        Frame::Error(msg) if raise => Err(mlua::Error::external(ScriptError::Command(msg))),
+        frame => to_lua(lua, frame),
+    }
+}
+
+/// Build the frame of a command from the arguments of `redis.call`.
+fn command_frame(args: MultiValue) -> Result<Frame, String> {
+    if args.is_empty() {
+        return Err("ERR Please specify at least one argument for this redis lib call".into());
+    }
+
+    let mut frame = Frame::array();
+
+    for arg in args {
+        let arg = match arg {
+            Value::String(s) => Bytes::copy_from_slice(s.as_bytes()),
+            Value::Integer(n) => Bytes::from(n.to_string()),
+            Value::Number(n) => Bytes::from(format_double(n)),
+            _ => {
+                return Err(
+                    "ERR Lua redis lib command arguments must be strings or integers".into(),
+                )
+            }
+        };
+
+        frame.push_bulk(arg);
+    }
+
+    Ok(frame)
+}
+
+/// Convert the reply to a command into a Lua value.
+fn to_lua(lua: &Lua, frame: Frame) -> mlua::Result<Value<'_>> {
+    let value = match frame {
+        Frame::Simple(msg) => Value::Table(reply_table(lua, "ok", lua.create_string(&msg)?)?),
+        Frame::Error(msg) => Value::Table(reply_table(lua, "err", lua.create_string(&msg)?)?),
+        Frame::Integer(n) => Value::Integer(n),
+        Frame::Bulk(data) | Frame::Verbatim(_, data) => Value::String(lua.create_string(&data)?),
+        Frame::Null => Value::Boolean(false),
+        Frame::Boolean(b) => Value::Boolean(b),
+        Frame::Double(n) => Value::String(lua.create_string(format_double(n))?),
+        Frame::BigNumber(n) => Value::String(lua.create_string(&n)?),
+        Frame::Array(frames) | Frame::Set(frames) | Frame::Push(frames) => {
+            let table = lua.create_table()?;
+
+            for frame in frames {
+                table.raw_push(to_lua(lua, frame)?)?;
+            }
+
+            Value::Table(table)
+        }
+        Frame::Map(entries) => {
+            // Flattened to alternating keys and values, as in RESP2
+            let table = lua.create_table()?;
+
+            for (key, value) in entries {
+                table.raw_push(to_lua(lua, key)?)?;
+                table.raw_push(to_lua(lua, value)?)?;
+            }
+
+            Value::Table(table)
+        }
+    };
+
+    Ok(value)
+}
+
+/// Convert the value returned by a script into the reply sent to the client.
+fn to_frame(value: Value) -> Frame {
+    match value {
+        Value::Boolean(true) => Frame::Integer(1),
+        Value::Integer(n) => Frame::Integer(n),
+        Value::Number(n) => Frame::Integer(n as i64),
+        Value::String(s) => Frame::Bulk(Bytes::copy_from_slice(s.as_bytes())),
+        Value::Table(table) => {
+            if let Ok(Value::String(msg)) = table.raw_get("err") {
+                return Frame::Error(msg.to_string_lossy().into_owned());
+            }
+
+            if let Ok(Value::String(msg)) = table.raw_get("ok") {
+                return Frame::Simple(msg.to_string_lossy().into_owned());
+            }
+
+            let mut frames = vec![];
+
+            for i in 1.. {
+                match table.raw_get(i) {
+                    Ok(Value::Nil) | Err(_) => break,
+                    Ok(value) => frames.push(to_frame(value)),
+                }
+            }
+
+            Frame::Array(frames)
+        }
+        _ => Frame::Null,
+    }
+}
+
+/// Returns a table holding a single `key`, used for status and error replies.
+fn reply_table<'lua>(
+    lua: &'lua Lua,
+    key: &str,
+    msg: mlua::String<'lua>,
+) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.raw_set(key, msg)?;
+    Ok(table)
+}
+
+/// Returns a table holding `values`, used for `KEYS` and `ARGV`.
+fn strings_table<T: AsRef<[u8]>>(lua: &Lua, values: Vec<T>) -> mlua::Result<Table<'_>> {
+    let table = lua.create_table()?;
+
+    for value in values {
+        table.raw_push(lua.create_string(value.as_ref())?)?;
+    }
+
+    Ok(table)
+}
+
+/// Find the `ScriptError` which interrupted a script, if any.
+///
+/// Errors raised from Rust callbacks are wrapped by Lua as they propagate
+/// through the script.
+fn script_error(err: &mlua::Error) -> Option<&ScriptError> {
+    match err {
+        mlua::Error::ExternalError(err) => err.downcast_ref(),
+        mlua::Error::CallbackError { cause, .. } => script_error(cause),
+        mlua::Error::WithContext { cause, .. } => script_error(cause),
+        _ => None,
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::Command(msg) => msg.fmt(fmt),
+            ScriptError::Timeout(limit) => write!(
+                fmt,
+                "ERR Script killed after exceeding the time limit of {} ms",
+                limit.as_millis()
+            ),
+        }
+    }
+}
+
+impl StdError for ScriptError {}
@@ -3,17 +3,27 @@
 //! Provides an async `run` function that listens for inbound connections,
 //! spawning a task per connection.
 
-use crate::{Command, Connection, Db, DbDropGuard, Shutdown};
-
-use std::future::Future;
+use crate::cluster::Topology;
+use crate::cmd::Transaction;
+use crate::db::aof::{self, Aof};
+use crate::{tls, AsyncStream, Command, Config, Connection, Db, DbDropGuard, Frame, Shutdown};
+
+use std::fs::{self, Permissions};
+use std::future::{self, Future};
+use std::io;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
 use tokio::sync::{broadcast, mpsc, Semaphore};
-use tokio::time::{self, Duration};
+use tokio::time::{self, Duration, Instant};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
 use tracing::{debug, error, info, instrument};
 
 /// Server listener state. Created in the `run` call. It includes a `run` method
-/// which performs the TCP listening and initialization of per-connection state.
+/// which performs the TCP and Unix socket listening and initialization of
+/// per-connection state.
 #[derive(Debug)]
 struct Listener {
     /// Shared database handle.
@@ -25,17 +35,25 @@ struct Listener {
     /// retrieved and passed into the per connection state (`Handler`).
     db_holder: DbDropGuard,
 
-    /// TCP listener supplied by the `run` caller.
-    listener: TcpListener,
+    /// TCP listener supplied by the `run` caller, if any.
+    listener: Option<TcpListener>,
+
+    /// Listener of the Unix socket at `config.unixsocket`, if set.
+    unix_listener: Option<UnixListener>,
+
+    /// Configuration of the TLS handshake of inbound connections, if TLS is
+    /// enabled. Plaintext connections are not accepted in that case.
+    tls: Option<Arc<ServerConfig>>,
 
-    /// Limit the max number of connections.
+    /// Limit the max number of connections to `maxclients`.
     ///
     /// A `Semaphore` is used to limit the max number of connections. Before
     /// attempting to accept a new connection, a permit is acquired from the
     /// semaphore. If none are available, the listener waits for one.
     ///
     /// When handlers complete processing a connection, the permit is returned
-    /// to the semaphore.
+    /// to the semaphore. The semaphore is owned by the `Db`, so `CONFIG SET`
+    /// can change the limit while the server runs.
     limit_connections: Arc<Semaphore>,
 
     /// Broadcasts a shutdown signal to all active connections.
@@ -63,6 +81,12 @@ struct Listener {
     shutdown_complete_tx: mpsc::Sender<()>,
 }
 
+/// A connection accepted by `Listener`.
+enum Inbound {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
 /// Per-connection handler. Reads requests from `connection` and applies the
 /// commands to `db`.
 #[derive(Debug)]
@@ -75,10 +99,11 @@ struct Handler {
     db: Db,
 
     /// The TCP connection decorated with the redis protocol encoder / decoder
-    /// implemented using a buffered `TcpStream`.
+    /// implemented using a buffered `TcpStream`, or TLS stream when TLS is
+    /// enabled.
     ///
-    /// When `Listener` receives an inbound connection, the `TcpStream` is
-    /// passed to `Connection::new`, which initializes the associated buffers.
+    /// When `Listener` receives an inbound connection, the stream is passed to
+    /// `Connection::new`, which initializes the associated buffers.
     /// `Connection` allows the handler to operate at the "frame" level and keep
     /// the byte level protocol parsing details encapsulated in `Connection`.
     connection: Connection,
@@ -93,23 +118,24 @@ struct Handler {
     /// which point the connection is terminated.
     shutdown: Shutdown,
 
+    /// State of the `MULTI` transaction and the keys watched on the
+    /// connection.
+    transaction: Transaction,
+
+    /// Set by `ASKING`, allowing the next command to access a slot being
+    /// imported in cluster mode.
+    asking: bool,
+
     /// Not used directly. Instead, when `Handler` is dropped...?
     _shutdown_complete: mpsc::Sender<()>,
 }
 
-/// Maximum number of concurrent connections the redis server will accept.
-///
-/// When this limit is reached, the server will stop accepting connections until
-/// an active connection terminates.
-///
-/// A real application will want to make this value configurable, but for this
-/// example, it is hard coded.
-///
-/// This is also set to a pretty low value to discourage using this in
-/// production (you'd think that all the disclaimers would make it obvious that
-/// this is not a serious project... but I thought that about mini-http as
-/// well).
-const MAX_CONNECTIONS: usize = 250;
+/// How often idle connections check whether the `timeout` setting was
+/// reached.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a client may take to complete the TLS handshake.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Run the mini-redis server.
 ///
@@ -120,7 +146,130 @@ const MAX_CONNECTIONS: usize = 250;
 ///
 /// `tokio::signal::ctrl_c()` can be used as the `shutdown` argument. This will
 /// listen for a SIGINT signal.
+///
+/// The server uses the default configuration, and starts with an empty key
+/// space: neither the snapshot nor the append-only file is loaded. Use
+/// [`run_with_config`] to configure persistence.
 pub async fn run(listener: TcpListener, shutdown: impl Future) {
+    if let Err(err) = serve(Some(listener), Config::default(), false, shutdown).await {
+        error!(cause = %err, "server error");
+    }
+}
+
+/// Run the mini-redis server using the given configuration.
+///
+/// Same as [`run`], except that persistence is configured through `config`.
+/// If the append-only file is enabled, the commands it contains are applied
+/// before accepting any connection, and all subsequent commands modifying the
+/// key space are appended to it. Otherwise, the snapshot file is loaded if it
+/// exists.
+///
+/// If `config.tls_cert_file` and `config.tls_key_file` are set, only TLS
+/// connections are accepted. If `config.replicaof` is set, the server then
+/// follows that master as a replica. If `config.cluster_enabled` is set, the
+/// server runs as the node of `config.cluster_nodes` with the address it
+/// listens on.
+///
+/// `config.bind` and `config.port` are not used to listen, as `listener` is
+/// already bound. `config.port` is replaced with the port of `listener`, so
+/// `CONFIG GET` reports the port actually used. If `config.unixsocket` is set,
+/// the server also listens on that Unix socket, which is removed on shutdown.
+///
+/// # Errors
+///
+/// Returns `Err` if `config.databases`, `config.shards`, `config.maxclients`
+/// or `config.maxmemory_samples` is zero, if the append-only file cannot be read
+/// or opened, if the snapshot file cannot be read or is corrupted, if the TLS
+/// certificates or private key cannot be loaded, or if the Unix socket cannot
+/// be bound.
+pub async fn run_with_config(
+    listener: TcpListener,
+    config: Config,
+    shutdown: impl Future,
+) -> crate::Result<()> {
+    serve(Some(listener), config, true, shutdown).await
+}
+
+/// Run the mini-redis server listening only on the Unix socket at
+/// `config.unixsocket`.
+///
+/// Same as [`run_with_config`], without a TCP listener. `config.port` is
+/// replaced with 0, as `CONFIG GET` reports for servers not listening on TCP.
+///
+/// # Errors
+///
+/// Returns `Err` for the same reasons as [`run_with_config`], or if
+/// `config.unixsocket` is not set or `config.cluster_enabled` is set, as
+/// cluster nodes are reached with their TCP address.
+pub async fn run_unix(config: Config, shutdown: impl Future) -> crate::Result<()> {
+    if config.unixsocket.is_none() {
+        return Err("the path of the Unix socket must be set".into());
+    }
+    if config.cluster_enabled {
+        return Err("cluster mode requires a TCP listener".into());
+    }
+
+    serve(None, config, true, shutdown).await
+}
+
+/// Run the server on `listener`, if any, and on the Unix socket at
+/// `config.unixsocket`, if set. The snapshot or append-only file configured
+/// by `config` is only loaded if `load` is `true`.
+async fn serve(
+    listener: Option<TcpListener>,
+    mut config: Config,
+    load: bool,
+    shutdown: impl Future,
+) -> crate::Result<()> {
+    if config.databases == 0 {
+        return Err("the number of databases must be at least 1".into());
+    }
+    if config.shards == 0 {
+        return Err("the number of shards must be at least 1".into());
+    }
+    if config.maxclients == 0 {
+        return Err("the maximum number of clients must be at least 1".into());
+    }
+    if config.maxmemory_samples == 0 {
+        return Err("the number of keys sampled for eviction must be at least 1".into());
+    }
+
+    config.port = match &listener {
+        Some(listener) => listener.local_addr()?.port(),
+        None => 0,
+    };
+
+    let tls = tls::server_config(&config)?;
+    // Report invalid replication settings on startup, rather than each time
+    // the replica connects to a master.
+    tls::replication_config(&config)?;
+
+    let db_holder = DbDropGuard::new(config.clone());
+
+    let db = db_holder.db();
+
+    // The append-only file is more complete than the snapshot, as it
+    // includes all commands up to when the server stopped. When enabled, it
+    // is the only file loaded.
+    if load && config.appendonly {
+        load_aof(&db, &config.appendfilename)?;
+    } else if load {
+        load_rdb(&db, &config.dbfilename)?;
+    }
+    if config.appendonly {
+        db.attach_aof(Aof::open(&config.appendfilename, config.appendfsync)?);
+    }
+
+    if let Some(listener) = listener.as_ref().filter(|_| config.cluster_enabled) {
+        let myself = listener.local_addr()?.to_string();
+        db.enable_cluster(Topology::new(&myself, &config.cluster_nodes)?);
+    }
+
+    // A replica replaces the loaded data once synchronized with its master.
+    if let Some(master) = config.replicaof.clone() {
+        db.replicaof(Some(master));
+    }
+
     // When the provided `shutdown` future completes, we must send a shutdown
     // message to all active connections. We use a broadcast channel for this
     // purpose. The call below ignores the receiver of the broadcast pair, and when
@@ -129,11 +278,18 @@ pub async fn run(listener: TcpListener, shutdown: impl Future) {
     let (notify_shutdown, _) = broadcast::channel(1);
     let (shutdown_complete_tx, mut shutdown_complete_rx) = mpsc::channel(1);
 
+    let unix_listener = match &config.unixsocket {
+        Some(path) => Some(bind_unix(path, config.unixsocketperm)?),
+        None => None,
+    };
+
     // Initialize the listener state
     let mut server = Listener {
         listener,
-        db_holder: DbDropGuard::new(),
-        limit_connections: Arc::new(Semaphore::new(MAX_CONNECTIONS)),
+        unix_listener,
+        tls,
+        db_holder,
+        limit_connections: db.connection_limit(),
         notify_shutdown,
         shutdown_complete_tx,
     };
@@ -179,6 +335,7 @@ pub async fn run(listener: TcpListener, shutdown: impl Future) {
     // explicitly drop `shutdown_transmitter`. This is important, as the
     // `.await` below would otherwise never complete.
     let Listener {
+        db_holder,
         shutdown_complete_tx,
         notify_shutdown,
         ..
@@ -195,6 +352,81 @@ pub async fn run(listener: TcpListener, shutdown: impl Future) {
     // `Sender` instances are held by connection handler tasks. When those drop,
     // the `mpsc` channel will close and `recv()` will return `None`.
     let _ = shutdown_complete_rx.recv().await;
+
+    // All connections are closed, so no more commands will be appended. Make
+    // sure the append-only file is complete before returning, as the process
+    // may exit right after.
+    db_holder.db().sync_aof().await;
+
+    if let Some(path) = &config.unixsocket {
+        let _ = fs::remove_file(path);
+    }
+
+    Ok(())
+}
+
+/// Bind the Unix socket at `path`, with the permissions `perm` if set.
+///
+/// A socket left over by a server which did not shut down gracefully is
+/// replaced. Any other file at `path` is kept, and binding fails.
+fn bind_unix(path: &Path, perm: Option<u32>) -> crate::Result<UnixListener> {
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)
+        .map_err(|err| format!("failed to bind Unix socket {:?}: {}", path, err))?;
+
+    if let Some(perm) = perm {
+        fs::set_permissions(path, Permissions::from_mode(perm))?;
+    }
+
+    Ok(listener)
+}
+
+/// Apply the commands stored in the append-only file at `path` to `db`.
+///
+/// This is called before `db` is shared with any connection.
+fn load_aof(db: &Db, path: &Path) -> crate::Result<()> {
+    let frames = aof::load(path)?;
+    let count = frames.len();
+
+    // Commands apply to the database selected by the last `SELECT` of the
+    // file.
+    let mut db = db.clone();
+
+    for frame in frames {
+        let cmd = match Command::from_frame(frame)? {
+            Command::Select(cmd) => {
+                db = db
+                    .select(cmd.index())
+                    .map_err(|_| "failed to load append-only file: DB index is out of range")?;
+                continue;
+            }
+            cmd => cmd,
+        };
+
+        // Commands are only appended once they succeeded, so an error means
+        // the file does not match the key space it was written for.
+        if let Frame::Error(err) = db.with_state(|state| cmd.execute(state)) {
+            return Err(format!("failed to load append-only file: {}", err).into());
+        }
+    }
+
+    info!(commands = count, path = ?path, "loaded append-only file");
+
+    Ok(())
+}
+
+/// Load the snapshot stored at `path` into `db`, if the file exists.
+///
+/// This is called before `db` is shared with any connection.
+fn load_rdb(db: &Db, path: &Path) -> crate::Result<()> {
+    if let Some(count) = db.with_state(|state| state.load_rdb(path))? {
+        info!(keys = count, path = ?path, "loaded snapshot");
+    }
+
+    Ok(())
 }
 
 impl Listener {
@@ -235,28 +467,47 @@ impl Listener {
             // Accept a new socket. This will attempt to perform error handling.
             // The `accept` method internally attempts to recover errors, so an
             // error here is non-recoverable.
-            let socket = self.accept().await?;
-
-            // Create the necessary per-connection handler state.
-            let mut handler = Handler {
-                // Get a handle to the shared database.
-                db: self.db_holder.db(),
-
-                // Initialize the connection state. This allocates read/write
-                // buffers to perform redis protocol frame parsing.
-                connection: Connection::new(socket),
+            let inbound = self.accept().await?;
 
-                // Receive shutdown notifications.
-                shutdown: Shutdown::new(self.notify_shutdown.subscribe()),
-
-                // Notifies the receiver half once all clones are
-                // dropped.
-                _shutdown_complete: self.shutdown_complete_tx.clone(),
-            };
+            // Get a handle to the shared database.
+            let db = self.db_holder.db();
+            // Receive shutdown notifications.
+            let shutdown = Shutdown::new(self.notify_shutdown.subscribe());
+            // Notifies the receiver half once all clones are dropped.
+            let shutdown_complete = self.shutdown_complete_tx.clone();
+            let tls = self.tls.clone();
 
             // Spawn a new task to process the connections. Tokio tasks are like
             // asynchronous green threads and are executed concurrently.
             tokio::spawn(async move {
+                // The TLS handshake is performed by the task, so a slow client
+                // does not delay accepting other connections.
+                let stream = match handshake(inbound, tls).await {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        debug!(cause = %err, "TLS handshake failed");
+                        return;
+                    }
+                };
+
+                // Create the necessary per-connection handler state.
+                let mut handler = Handler {
+                    db,
+
+                    // Initialize the connection state. This allocates
+                    // read/write buffers to perform redis protocol frame
+                    // parsing.
+                    connection: Connection::new(stream),
+
+                    shutdown,
+
+                    // No transaction is started and no key is watched.
+                    transaction: Transaction::default(),
+                    asking: false,
+
+                    _shutdown_complete: shutdown_complete,
+                };
+
                 // Process the connection. If an error is encountered, log it.
                 if let Err(err) = handler.run().await {
                     error!(cause = ?err, "connection error");
@@ -275,15 +526,21 @@ impl Listener {
     /// After the second failure, the task waits for 2 seconds. Each subsequent
     /// failure doubles the wait time. If accepting fails on the 6th try after
     /// waiting for 64 seconds, then this function returns with an error.
-    async fn accept(&mut self) -> crate::Result<TcpStream> {
+    async fn accept(&mut self) -> crate::Result<Inbound> {
         let mut backoff = 1;
 
         // Try to accept a few times
         loop {
-            // Perform the accept operation. If a socket is successfully
-            // accepted, return it. Otherwise, save the error.
-            match self.listener.accept().await {
-                Ok((socket, _)) => return Ok(socket),
+            // Perform the accept operation on whichever listener receives a
+            // connection first. If a socket is successfully accepted, return
+            // it. Otherwise, save the error.
+            let res = tokio::select! {
+                res = accept_tcp(self.listener.as_ref()) => res.map(Inbound::Tcp),
+                res = accept_unix(self.unix_listener.as_ref()) => res.map(Inbound::Unix),
+            };
+
+            match res {
+                Ok(inbound) => return Ok(inbound),
                 Err(err) => {
                     if backoff > 64 {
                         // Accept has failed too many times. Return the error.
@@ -301,6 +558,48 @@ impl Listener {
     }
 }
 
+/// Perform the TLS handshake of a TCP connection configured by `tls`, or use
+/// the connection as is if TLS is not enabled. Connections to the Unix socket
+/// are not encrypted, as they never leave the host.
+async fn handshake(
+    inbound: Inbound,
+    tls: Option<Arc<ServerConfig>>,
+) -> crate::Result<Box<dyn AsyncStream>> {
+    let socket = match inbound {
+        Inbound::Tcp(socket) => socket,
+        Inbound::Unix(socket) => return Ok(Box::new(socket)),
+    };
+
+    match tls {
+        Some(tls) => {
+            let accept = TlsAcceptor::from(tls).accept(socket);
+            let stream = time::timeout(TLS_HANDSHAKE_TIMEOUT, accept)
+                .await
+                .map_err(|_| "timed out")??;
+            Ok(Box::new(stream))
+        }
+        None => Ok(Box::new(socket)),
+    }
+}
+
+/// Accept a connection on `listener`, or never complete if there is no
+/// listener.
+async fn accept_tcp(listener: Option<&TcpListener>) -> io::Result<TcpStream> {
+    match listener {
+        Some(listener) => Ok(listener.accept().await?.0),
+        None => future::pending().await,
+    }
+}
+
+/// Accept a connection on the Unix socket `listener`, or never complete if
+/// there is no listener.
+async fn accept_unix(listener: Option<&UnixListener>) -> io::Result<UnixStream> {
+    match listener {
+        Some(listener) => Ok(listener.accept().await?.0),
+        None => future::pending().await,
+    }
+}
+
 impl Handler {
     /// Process a single connection.
     ///
@@ -313,14 +612,18 @@ impl Handler {
     /// https://redis.io/topics/pipelining
     ///
     /// When the shutdown signal is received, the connection is processed until
-    /// it reaches a safe state, at which point it is terminated.
+    /// it reaches a safe state, at which point it is terminated. The
+    /// connection is also closed if no request is received within the
+    /// `timeout` setting.
     #[instrument(skip(self))]
     async fn run(&mut self) -> crate::Result<()> {
         // As long as the shutdown signal has not been received, try to read a
         // new request frame.
         while !self.shutdown.is_shutdown() {
+            let since = Instant::now();
+
             // While reading a request frame, also listen for the shutdown
-            // signal.
+            // signal and for the connection staying idle for too long.
             let maybe_frame = tokio::select! {
                 res = self.connection.read_frame() => res?,
                 _ = self.shutdown.recv() => {
@@ -328,6 +631,10 @@ impl Handler {
                     // This will result in the task terminating.
                     return Ok(());
                 }
+                _ = idle(&self.db, since) => {
+                    debug!("closing idle connection");
+                    return Ok(());
+                }
             };
 
             // If `None` is returned from `read_frame()` then the peer closed
@@ -361,10 +668,41 @@ impl Handler {
             // command to write response frames directly to the connection. In
             // the case of pub/sub, multiple frames may be send back to the
             // peer.
-            cmd.apply(&self.db, &mut self.connection, &mut self.shutdown)
-                .await?;
+            cmd.apply(
+                &mut self.db,
+                &mut self.connection,
+                &mut self.shutdown,
+                &mut self.transaction,
+                &mut self.asking,
+            )
+            .await?;
         }
 
         Ok(())
     }
 }
+
+/// Completes once a connection waiting for a request since `since` has been
+/// idle for longer than the `timeout` setting.
+///
+/// The setting is checked again every second, so changing it also applies to
+/// the connections already waiting.
+async fn idle(db: &Db, since: Instant) {
+    loop {
+        let check = Instant::now() + IDLE_CHECK_INTERVAL;
+
+        match db.client_timeout() {
+            Some(timeout) if since.elapsed() >= timeout => return,
+            Some(timeout) => time::sleep_until(check.min(since + timeout)).await,
+            None => time::sleep_until(check).await,
+        }
+    }
+}
+
+impl Drop for Handler {
+    fn drop(&mut self) {
+        // Keys watched by the connection are no longer of interest once it is
+        // closed.
+        self.transaction.reset(&self.db);
+    }
+}
@@ -0,0 +1,188 @@
+//! TLS support, using `rustls`.
+//!
+//! The server accepts TLS connections only once it is given a certificate
+//! and its private key, with the `tls-cert-file` and `tls-key-file`
+//! directives. Clients connect with
+//! [`Client::connect_tls`](crate::clients::Client::connect_tls), using a
+//! configuration built by [`client_config`].
+//!
+//! Certificates and private keys are read from PEM files. A file may hold a
+//! whole certificate chain, starting with the certificate of the server or
+//! client.
+
+use crate::Config;
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::crypto::{self, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::TlsConnector;
+
+pub use tokio_rustls::rustls;
+
+/// Build the configuration of a TLS client.
+///
+/// The certificate of the server is verified against the certificate
+/// authorities in `ca_cert_file`. If the server verifies the certificates of
+/// its clients, `identity` gives the files holding the certificate chain and
+/// the private key presented by the client.
+///
+/// # Errors
+///
+/// Returns `Err` if a file cannot be read or does not hold a valid
+/// certificate or private key.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mini_redis::{clients::Client, tls};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let config = tls::client_config("ca.crt".as_ref(), None).unwrap();
+///     let client = Client::connect_tls("localhost:6379", "localhost", config)
+///         .await
+///         .unwrap();
+/// # drop(client);
+/// }
+/// ```
+pub fn client_config(
+    ca_cert_file: &Path,
+    identity: Option<(&Path, &Path)>,
+) -> crate::Result<Arc<ClientConfig>> {
+    let builder = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(root_store(ca_cert_file)?);
+
+    let config = match identity {
+        Some((cert_file, key_file)) => {
+            builder.with_client_auth_cert(load_certs(cert_file)?, load_key(key_file)?)?
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    Ok(Arc::new(config))
+}
+
+/// Build the configuration of the server from the TLS settings of `config`,
+/// or returns `None` if TLS is not enabled.
+///
+/// Clients must present a certificate issued by one of the certificate
+/// authorities in `tls_ca_cert_file` if `tls_auth_clients` is set.
+pub(crate) fn server_config(config: &Config) -> crate::Result<Option<Arc<ServerConfig>>> {
+    let (cert_file, key_file) = match (&config.tls_cert_file, &config.tls_key_file) {
+        (Some(cert_file), Some(key_file)) => (cert_file, key_file),
+        (None, None) => return Ok(None),
+        _ => return Err("tls-cert-file and tls-key-file must be set together".into()),
+    };
+
+    let builder =
+        ServerConfig::builder_with_provider(provider()).with_safe_default_protocol_versions()?;
+
+    let builder = if config.tls_auth_clients {
+        let ca_cert_file = config
+            .tls_ca_cert_file
+            .as_ref()
+            .ok_or("tls-auth-clients requires tls-ca-cert-file")?;
+        let verifier = WebPkiClientVerifier::builder_with_provider(
+            Arc::new(root_store(ca_cert_file)?),
+            provider(),
+        )
+        .build()?;
+
+        builder.with_client_cert_verifier(verifier)
+    } else {
+        builder.with_no_client_auth()
+    };
+
+    let server_config = builder.with_single_cert(load_certs(cert_file)?, load_key(key_file)?)?;
+
+    Ok(Some(Arc::new(server_config)))
+}
+
+/// Returns the configuration a replica uses to connect to its master, or
+/// `None` if `tls_replication` is not set by `config`.
+///
+/// The master is verified against `tls_ca_cert_file`, and the replica presents
+/// its own certificate, so masters verifying their clients accept it.
+pub(crate) fn replication_config(config: &Config) -> crate::Result<Option<Arc<ClientConfig>>> {
+    if !config.tls_replication {
+        return Ok(None);
+    }
+
+    let ca_cert_file = config
+        .tls_ca_cert_file
+        .as_ref()
+        .ok_or("tls-replication requires tls-ca-cert-file")?;
+    let identity = match (&config.tls_cert_file, &config.tls_key_file) {
+        (Some(cert_file), Some(key_file)) => Some((cert_file.as_path(), key_file.as_path())),
+        _ => None,
+    };
+
+    client_config(ca_cert_file, identity).map(Some)
+}
+
+/// Perform the TLS handshake of a client on `socket`, checking that the
+/// certificate of the server is valid for `server_name`.
+pub(crate) async fn connect(
+    socket: TcpStream,
+    server_name: &str,
+    config: Arc<ClientConfig>,
+) -> crate::Result<TlsStream<TcpStream>> {
+    let server_name = ServerName::try_from(server_name.to_string())
+        .map_err(|_| format!("invalid server name '{}'", server_name))?;
+
+    Ok(TlsConnector::from(config)
+        .connect(server_name, socket)
+        .await?)
+}
+
+/// The cryptography used by both ends of a connection.
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(crypto::ring::default_provider())
+}
+
+/// Load the certificate authorities in `path`.
+fn root_store(path: &Path) -> crate::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert)?;
+    }
+
+    Ok(roots)
+}
+
+/// Load the certificates in `path`.
+fn load_certs(path: &Path) -> crate::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = open(path)?;
+    let certs = rustls_pemfile::certs(&mut reader)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("invalid certificate file {:?}: {}", path, err))?;
+
+    if certs.is_empty() {
+        return Err(format!("no certificate found in {:?}", path).into());
+    }
+
+    Ok(certs)
+}
+
+/// Load the first private key in `path`.
+fn load_key(path: &Path) -> crate::Result<PrivateKeyDer<'static>> {
+    let mut reader = open(path)?;
+
+    rustls_pemfile::private_key(&mut reader)
+        .map_err(|err| format!("invalid private key file {:?}: {}", path, err))?
+        .ok_or_else(|| format!("no private key found in {:?}", path).into())
+}
+
+fn open(path: &Path) -> crate::Result<BufReader<File>> {
+    let file = File::open(path).map_err(|err| format!("failed to read {:?}: {}", path, err))?;
+    Ok(BufReader::new(file))
+}
@@ -23,6 +23,56 @@ async fn pool_key_value_get_set() {
     assert_eq!(b"world", &value[..])
 }
 
+/// Hash commands sent through the buffer are forwarded to the connection and
+/// their typed responses returned to the caller.
+#[tokio::test]
+async fn pool_hash_commands() {
+    let (addr, _) = start_server().await;
+
+    let client = Client::connect(addr).await.unwrap();
+    let mut client = BufferedClient::buffer(client);
+
+    let fields = vec![("user".to_string(), "alice".into())];
+    assert_eq!(1, client.hset("session", fields).await.unwrap());
+    assert_eq!(5, client.hincrby("session", "hits", 5).await.unwrap());
+
+    let value = client.hget("session", "user").await.unwrap().unwrap();
+    assert_eq!(b"alice", &value[..]);
+
+    let fields = client.hgetall("session").await.unwrap();
+    assert_eq!(2, fields.len());
+
+    let removed = client.hdel("session", &["user".to_string()]).await.unwrap();
+    assert_eq!(1, removed);
+}
+
+/// Concurrent requests are coalesced into pipelines, and each caller receives
+/// the reply to its own request.
+#[tokio::test]
+async fn pool_concurrent_requests() {
+    let (addr, _) = start_server().await;
+
+    let client = Client::connect(addr).await.unwrap();
+    let client = BufferedClient::buffer(client);
+
+    let mut handles = vec![];
+
+    for i in 0..100 {
+        let mut client = client.clone();
+
+        handles.push(tokio::spawn(async move {
+            let key = format!("key{}", i);
+            client.set(&key, i.to_string().into()).await.unwrap();
+            client.get(&key).await.unwrap().unwrap()
+        }));
+    }
+
+    for (i, handle) in handles.into_iter().enumerate() {
+        let value = handle.await.unwrap();
+        assert_eq!(i.to_string().as_bytes(), &value[..]);
+    }
+}
+
 async fn start_server() -> (SocketAddr, JoinHandle<()>) {
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
     let addr = listener.local_addr().unwrap();
@@ -1,7 +1,14 @@
-use mini_redis::{clients::Client, server};
+use mini_redis::{
+    clients::{Client, Pipeline},
+    cmd::{ExpireCondition, SetCondition},
+    server, Frame, Protocol,
+};
 use std::net::SocketAddr;
+use std::time::{Duration, UNIX_EPOCH};
 use tokio::net::TcpListener;
+use tokio::sync::oneshot;
 use tokio::task::JoinHandle;
+use tokio::time;
 
 /// A PING PONG test without message provided.
 /// It should return "PONG".
@@ -104,6 +111,1054 @@ async fn unsubscribes_from_channels() {
     assert_eq!(subscriber.get_subscribed().len(), 0);
 }
 
+/// Messages published on channels matching a subscribed pattern carry the
+/// pattern, and patterns can be managed alongside channels.
+#[tokio::test]
+async fn receive_message_subscribed_pattern() {
+    let (addr, _) = start_server().await;
+
+    let client = Client::connect(addr).await.unwrap();
+    let mut subscriber = client.psubscribe(vec!["orders.*".into()]).await.unwrap();
+    subscriber.subscribe(&["invoices".into()]).await.unwrap();
+
+    let mut client = Client::connect(addr).await.unwrap();
+    assert_eq!(0, client.publish("order", "41".into()).await.unwrap());
+    assert_eq!(
+        1,
+        client.publish("orders.created", "42".into()).await.unwrap()
+    );
+
+    let message = subscriber.next_message().await.unwrap().unwrap();
+    assert_eq!(Some("orders.*"), message.pattern.as_deref());
+    assert_eq!("orders.created", &message.channel);
+    assert_eq!(b"42", &message.content[..]);
+
+    assert_eq!(1, client.publish("invoices", "43".into()).await.unwrap());
+
+    let message = subscriber.next_message().await.unwrap().unwrap();
+    assert_eq!(None, message.pattern);
+    assert_eq!("invoices", &message.channel);
+
+    subscriber.punsubscribe(&[]).await.unwrap();
+    assert!(subscriber.get_subscribed_patterns().is_empty());
+    assert_eq!(["invoices"], subscriber.get_subscribed());
+}
+
+/// After switching to RESP3, replies and pub/sub messages (which are now push
+/// frames) are still understood by the client.
+#[tokio::test]
+async fn resp3_get_set_and_subscribe() {
+    let (addr, _) = start_server().await;
+
+    let mut client = Client::connect(addr).await.unwrap();
+    client.hello(Protocol::Resp3).await.unwrap();
+
+    assert!(client.get("hello").await.unwrap().is_none());
+    client.set("hello", "world".into()).await.unwrap();
+    let value = client.get("hello").await.unwrap().unwrap();
+    assert_eq!(b"world", &value[..]);
+
+    let mut subscriber = client.subscribe(vec!["hello".into()]).await.unwrap();
+
+    tokio::spawn(async move {
+        let mut client = Client::connect(addr).await.unwrap();
+        client.publish("hello", "world".into()).await.unwrap()
+    });
+
+    let message = subscriber.next_message().await.unwrap().unwrap();
+    assert_eq!("hello", &message.channel);
+    assert_eq!(b"world", &message.content[..])
+}
+
+/// Values pushed onto a list can be read back in order and popped from both
+/// ends.
+#[tokio::test]
+async fn list_push_pop_range() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    assert_eq!(
+        2,
+        client
+            .rpush("jobs", vec!["b".into(), "c".into()])
+            .await
+            .unwrap()
+    );
+    assert_eq!(3, client.lpush("jobs", vec!["a".into()]).await.unwrap());
+    assert_eq!(3, client.llen("jobs").await.unwrap());
+
+    let values = client.lrange("jobs", 0, -1).await.unwrap();
+    assert_eq!(vec!["a", "b", "c"], values);
+
+    let values = client.lrange("jobs", -2, 10).await.unwrap();
+    assert_eq!(vec!["b", "c"], values);
+
+    assert_eq!("a", client.lpop("jobs").await.unwrap().unwrap());
+    assert_eq!("c", client.rpop("jobs").await.unwrap().unwrap());
+    assert_eq!("b", client.rpop("jobs").await.unwrap().unwrap());
+
+    // Popping the last element removes the list
+    assert!(client.lpop("jobs").await.unwrap().is_none());
+    assert_eq!(0, client.llen("jobs").await.unwrap());
+}
+
+/// Using list commands on a string key, or string commands on a list key,
+/// results in a `WRONGTYPE` error. The connection remains usable.
+#[tokio::test]
+async fn list_wrong_type() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    client.set("hello", "world".into()).await.unwrap();
+    let err = client.lpush("hello", vec!["a".into()]).await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"));
+
+    client.rpush("jobs", vec!["a".into()]).await.unwrap();
+    let err = client.get("jobs").await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"));
+
+    // `SET` overwrites values of any type
+    client.set("jobs", "done".into()).await.unwrap();
+    assert_eq!("done", client.get("jobs").await.unwrap().unwrap());
+}
+
+/// A client blocked in `BLPOP` receives the value pushed by another client,
+/// while a `BRPOP` on an empty list times out.
+#[tokio::test]
+async fn blocking_pop() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let keys = vec!["other".to_string(), "jobs".to_string()];
+    let blocked = tokio::spawn(async move { client.blpop(&keys, Duration::from_secs(0)).await });
+
+    // Give the client time to block
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut pusher = Client::connect(addr).await.unwrap();
+    pusher.rpush("jobs", vec!["a".into()]).await.unwrap();
+
+    let (key, value) = blocked.await.unwrap().unwrap().unwrap();
+    assert_eq!("jobs", key);
+    assert_eq!("a", value);
+
+    // The value was consumed by the blocked client
+    assert_eq!(0, pusher.llen("jobs").await.unwrap());
+
+    let keys = vec!["jobs".to_string()];
+    let value = pusher
+        .brpop(&keys, Duration::from_millis(100))
+        .await
+        .unwrap();
+    assert!(value.is_none());
+}
+
+/// Shutting down the server releases clients blocked in `BLPOP`, rather than
+/// waiting for them forever.
+#[tokio::test]
+async fn blocking_pop_released_on_shutdown() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let (tx, rx) = oneshot::channel::<()>();
+    let server = tokio::spawn(async move { server::run(listener, rx).await });
+
+    let mut client = Client::connect(addr).await.unwrap();
+    let keys = vec!["jobs".to_string()];
+    let blocked = tokio::spawn(async move { client.blpop(&keys, Duration::from_secs(0)).await });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    tx.send(()).unwrap();
+
+    // The server completes its graceful shutdown and the client sees the
+    // connection being closed.
+    server.await.unwrap();
+    assert!(blocked.await.unwrap().is_err());
+}
+
+/// Per-session objects stored as hashes can be written, read back as a whole
+/// and updated field by field.
+#[tokio::test]
+async fn hash_set_get_del() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let fields = vec![
+        ("user".to_string(), "alice".into()),
+        ("hits".to_string(), "1".into()),
+    ];
+    assert_eq!(2, client.hset("session", fields).await.unwrap());
+
+    // Overwriting a field does not count as adding it
+    let fields = vec![("user".to_string(), "bob".into())];
+    assert_eq!(0, client.hset("session", fields).await.unwrap());
+
+    assert_eq!(
+        "bob",
+        client.hget("session", "user").await.unwrap().unwrap()
+    );
+    assert!(client.hget("session", "nope").await.unwrap().is_none());
+
+    assert_eq!(11, client.hincrby("session", "hits", 10).await.unwrap());
+    assert_eq!(-5, client.hincrby("session", "new", -5).await.unwrap());
+
+    let err = client.hincrby("session", "user", 1).await.unwrap_err();
+    assert!(err.to_string().starts_with("ERR"));
+
+    let fields = client.hgetall("session").await.unwrap();
+    assert_eq!(3, fields.len());
+    assert_eq!("bob", fields["user"]);
+    assert_eq!("11", fields["hits"]);
+
+    let names = vec!["user".to_string(), "hits".to_string(), "nope".to_string()];
+    assert_eq!(2, client.hdel("session", &names).await.unwrap());
+
+    // Removing the last field removes the key
+    assert_eq!(
+        1,
+        client.hdel("session", &["new".to_string()]).await.unwrap()
+    );
+    assert!(client.hgetall("session").await.unwrap().is_empty());
+
+    client.set("hello", "world".into()).await.unwrap();
+    let err = client.hget("hello", "user").await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"));
+
+    // RESP3 connections receive `HGETALL` as a map
+    client.hello(Protocol::Resp3).await.unwrap();
+    let fields = vec![("user".to_string(), "carol".into())];
+    client.hset("session", fields).await.unwrap();
+    let fields = client.hgetall("session").await.unwrap();
+    assert_eq!("carol", fields["user"]);
+}
+
+/// Members of a sorted set are returned ordered by score, and can be queried
+/// by rank and by score range.
+#[tokio::test]
+async fn sorted_set_leaderboard() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let members = vec![
+        (30.0, "carol".into()),
+        (10.0, "alice".into()),
+        (20.0, "bob".into()),
+        (20.0, "barry".into()),
+    ];
+    assert_eq!(4, client.zadd("scores", members).await.unwrap());
+
+    // Updating a score does not count as adding a member
+    let members = vec![(5.0, "carol".into())];
+    assert_eq!(0, client.zadd("scores", members).await.unwrap());
+
+    let members = client.zrange("scores", 0, -1).await.unwrap();
+    assert_eq!(vec!["carol", "alice", "barry", "bob"], members);
+
+    let members = client.zrange_withscores("scores", -2, -1).await.unwrap();
+    assert_eq!(vec![("barry".into(), 20.0), ("bob".into(), 20.0)], members);
+
+    let members = client.zrangebyscore("scores", 10.0, 20.0).await.unwrap();
+    assert_eq!(vec!["alice", "barry", "bob"], members);
+
+    let members = client
+        .zrangebyscore_withscores("scores", f64::NEG_INFINITY, 10.0)
+        .await
+        .unwrap();
+    assert_eq!(vec![("carol".into(), 5.0), ("alice".into(), 10.0)], members);
+
+    assert_eq!(Some(3), client.zrank("scores", "bob".into()).await.unwrap());
+    assert_eq!(None, client.zrank("scores", "dave".into()).await.unwrap());
+
+    let removed = client
+        .zrem("scores", vec!["carol".into(), "dave".into()])
+        .await
+        .unwrap();
+    assert_eq!(1, removed);
+    assert_eq!(
+        Some(0),
+        client.zrank("scores", "alice".into()).await.unwrap()
+    );
+
+    // RESP3 connections receive scores as doubles
+    client.hello(Protocol::Resp3).await.unwrap();
+    let members = client.zrange_withscores("scores", 0, 0).await.unwrap();
+    assert_eq!(vec![("alice".into(), 10.0)], members);
+}
+
+/// Entries appended to a stream get increasing IDs, can be read back by ID
+/// range, and are delivered to clients blocked in `XREAD`.
+#[tokio::test]
+async fn streams() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let fields = vec![("temp".to_string(), "20".into())];
+    assert_eq!(
+        "1-1",
+        client.xadd("sensor", "1-1", fields, None).await.unwrap()
+    );
+    let fields = vec![("temp".to_string(), "21".into())];
+    assert_eq!(
+        "1-2",
+        client.xadd("sensor", "1-*", fields, None).await.unwrap()
+    );
+    let fields = vec![("temp".to_string(), "22".into())];
+    let id = client.xadd("sensor", "*", fields, None).await.unwrap();
+    assert_eq!("stream", client.key_type("sensor").await.unwrap());
+
+    // IDs must keep increasing
+    let fields = vec![("temp".to_string(), "0".into())];
+    let err = client
+        .xadd("sensor", "1-2", fields, None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("equal or smaller"));
+
+    let entries = client.xrange("sensor", "-", "+", None).await.unwrap();
+    let ids: Vec<_> = entries.iter().map(|entry| entry.id.as_str()).collect();
+    assert_eq!(vec!["1-1", "1-2", id.as_str()], ids);
+    assert_eq!(vec![("temp".to_string(), "20".into())], entries[0].fields);
+
+    let entries = client.xrange("sensor", "(1-1", "1", None).await.unwrap();
+    assert_eq!(1, entries.len());
+    assert_eq!("1-2", entries[0].id);
+    let entries = client.xrange("sensor", "-", "+", Some(1)).await.unwrap();
+    assert_eq!("1-1", entries[0].id);
+
+    // Trimming removes the oldest entries
+    assert_eq!(3, client.xlen("sensor").await.unwrap());
+    assert_eq!(1, client.xtrim("sensor", 2).await.unwrap());
+    let fields = vec![("temp".to_string(), "23".into())];
+    client.xadd("sensor", "*", fields, Some(2)).await.unwrap();
+    assert_eq!(2, client.xlen("sensor").await.unwrap());
+
+    let streams = client
+        .xread(&[("sensor", "1-2")], None, None)
+        .await
+        .unwrap();
+    assert_eq!("sensor", streams[0].0);
+    assert_eq!(2, streams[0].1.len());
+
+    // A client reading with `$` only receives entries added afterwards
+    let mut reader = Client::connect(addr).await.unwrap();
+    let blocked = tokio::spawn(async move {
+        reader
+            .xread(&[("sensor", "$")], None, Some(Duration::from_secs(0)))
+            .await
+    });
+
+    // Give the client time to block
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let fields = vec![("temp".to_string(), "24".into())];
+    let id = client.xadd("sensor", "*", fields, None).await.unwrap();
+
+    let streams = blocked.await.unwrap().unwrap();
+    assert_eq!(1, streams.len());
+    assert_eq!(id, streams[0].1[0].id);
+
+    // Nothing is read once the timeout elapses
+    let streams = client
+        .xread(&[("sensor", "$")], None, Some(Duration::from_millis(100)))
+        .await
+        .unwrap();
+    assert!(streams.is_empty());
+}
+
+/// The ID of a new entry must be greater than the last one of the stream,
+/// even after the entries were trimmed, and than `0-0`.
+#[tokio::test]
+async fn stream_ids_must_increase() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let fields = || vec![("temp".to_string(), "20".into())];
+
+    let err = client
+        .xadd("sensor", "0-0", fields(), None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("greater than 0-0"), "{}", err);
+    assert_eq!("none", client.key_type("sensor").await.unwrap());
+
+    client.xadd("sensor", "5-5", fields(), None).await.unwrap();
+    for id in &["5-5", "5-4", "4-9", "5"] {
+        let err = client.xadd("sensor", id, fields(), None).await.unwrap_err();
+        assert!(
+            err.to_string().contains("equal or smaller"),
+            "{}: {}",
+            id,
+            err
+        );
+    }
+    assert!(client
+        .xadd("sensor", "bad-id", fields(), None)
+        .await
+        .is_err());
+    assert_eq!(1, client.xlen("sensor").await.unwrap());
+
+    // The sequence number is generated after the last one of the same time
+    assert_eq!(
+        "5-6",
+        client.xadd("sensor", "5-*", fields(), None).await.unwrap()
+    );
+    assert_eq!(
+        "6-0",
+        client.xadd("sensor", "6-*", fields(), None).await.unwrap()
+    );
+
+    // Trimming every entry does not allow IDs to go back
+    assert_eq!(3, client.xtrim("sensor", 0).await.unwrap());
+    assert!(client.xadd("sensor", "6-0", fields(), None).await.is_err());
+    assert_eq!(
+        "6-1",
+        client.xadd("sensor", "6-1", fields(), None).await.unwrap()
+    );
+}
+
+/// `XTRIM MAXLEN` and `XADD MAXLEN` keep the newest entries.
+#[tokio::test]
+async fn xtrim_maxlen_keeps_newest_entries() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    for i in 1..=10 {
+        let fields = vec![("n".to_string(), i.to_string().into())];
+        client
+            .xadd("log", &format!("{}-0", i), fields, None)
+            .await
+            .unwrap();
+    }
+
+    // Trimming to a larger length removes nothing
+    assert_eq!(0, client.xtrim("log", 20).await.unwrap());
+    assert_eq!(10, client.xlen("log").await.unwrap());
+
+    assert_eq!(6, client.xtrim("log", 4).await.unwrap());
+    let entries = client.xrange("log", "-", "+", None).await.unwrap();
+    let ids: Vec<_> = entries.iter().map(|entry| entry.id.as_str()).collect();
+    assert_eq!(vec!["7-0", "8-0", "9-0", "10-0"], ids);
+
+    let fields = vec![("n".to_string(), "11".into())];
+    client.xadd("log", "11-0", fields, Some(2)).await.unwrap();
+    let entries = client.xrange("log", "-", "+", None).await.unwrap();
+    let ids: Vec<_> = entries.iter().map(|entry| entry.id.as_str()).collect();
+    assert_eq!(vec!["10-0", "11-0"], ids);
+
+    // Trimming a missing stream does not create it
+    assert_eq!(0, client.xtrim("missing", 0).await.unwrap());
+    assert_eq!("none", client.key_type("missing").await.unwrap());
+}
+
+/// A client blocked in `XREAD` on several streams with `$` is woken by the
+/// first entry added to any of them, including streams created afterwards,
+/// and only receives entries added after it started reading.
+#[tokio::test]
+async fn xread_block_wakes_on_any_stream() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let fields = || vec![("temp".to_string(), "20".into())];
+    client
+        .xadd("sensor:a", "1-1", fields(), None)
+        .await
+        .unwrap();
+
+    // Without `BLOCK`, `$` never returns anything
+    let streams = client
+        .xread(&[("sensor:a", "$")], None, None)
+        .await
+        .unwrap();
+    assert!(streams.is_empty());
+
+    let mut reader = Client::connect(addr).await.unwrap();
+    let blocked = tokio::spawn(async move {
+        reader
+            .xread(
+                &[("sensor:a", "$"), ("sensor:b", "$"), ("sensor:c", "$")],
+                None,
+                Some(Duration::from_secs(5)),
+            )
+            .await
+    });
+
+    // Give the client time to block. Writes to other keys do not wake it.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    client.set("other", "value".into()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!blocked.is_finished());
+
+    let id = client.xadd("sensor:c", "*", fields(), None).await.unwrap();
+
+    let streams = blocked.await.unwrap().unwrap();
+    assert_eq!(1, streams.len());
+    assert_eq!("sensor:c", streams[0].0);
+    assert_eq!(1, streams[0].1.len());
+    assert_eq!(id, streams[0].1[0].id);
+}
+
+/// Entries of a stream are shared between the consumers of a group, and stay
+/// pending until acknowledged or claimed by another consumer.
+#[tokio::test]
+async fn consumer_groups() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let err = client
+        .xgroup_create("jobs", "workers", "$", false)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("requires the key to exist"));
+    client
+        .xgroup_create("jobs", "workers", "$", true)
+        .await
+        .unwrap();
+    let err = client
+        .xgroup_create("jobs", "workers", "$", false)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().starts_with("BUSYGROUP"));
+
+    for id in &["1-1", "1-2", "1-3"] {
+        let fields = vec![("task".to_string(), "build".into())];
+        client.xadd("jobs", id, fields, None).await.unwrap();
+    }
+
+    // Each new entry is delivered to a single consumer
+    let streams = client
+        .xreadgroup("workers", "alice", &[("jobs", ">")], Some(2), None, false)
+        .await
+        .unwrap();
+    assert_eq!(2, streams[0].1.len());
+    let streams = client
+        .xreadgroup("workers", "bob", &[("jobs", ">")], None, None, false)
+        .await
+        .unwrap();
+    assert_eq!("1-3", streams[0].1[0].id);
+
+    let summary = client.xpending("jobs", "workers").await.unwrap();
+    assert_eq!(3, summary.count);
+    assert_eq!(Some(("1-1".into(), "1-3".into())), summary.ids);
+    assert_eq!(
+        vec![("alice".to_string(), 2), ("bob".to_string(), 1)],
+        summary.consumers
+    );
+
+    // Acknowledged entries are no longer pending
+    assert_eq!(1, client.xack("jobs", "workers", &["1-1"]).await.unwrap());
+    assert_eq!(0, client.xack("jobs", "workers", &["1-1"]).await.unwrap());
+
+    // A consumer reads its own pending entries back with an explicit ID
+    let streams = client
+        .xreadgroup("workers", "alice", &[("jobs", "0")], None, None, false)
+        .await
+        .unwrap();
+    let ids: Vec<_> = streams[0].1.iter().map(|entry| entry.id.as_str()).collect();
+    assert_eq!(vec!["1-2"], ids);
+
+    // Entries are only claimed once idle for long enough
+    let claimed = client
+        .xclaim("jobs", "workers", "bob", Duration::from_secs(60), &["1-2"])
+        .await
+        .unwrap();
+    assert!(claimed.is_empty());
+    let claimed = client
+        .xclaim("jobs", "workers", "bob", Duration::from_millis(0), &["1-2"])
+        .await
+        .unwrap();
+    assert_eq!("1-2", claimed[0].id);
+
+    let pending = client
+        .xpending_range("jobs", "workers", "-", "+", 10, Some("bob"))
+        .await
+        .unwrap();
+    let ids: Vec<_> = pending.iter().map(|entry| entry.id.as_str()).collect();
+    assert_eq!(vec!["1-2", "1-3"], ids);
+    // Delivered to alice by `XREADGROUP` twice, then claimed by bob
+    assert_eq!(3, pending[0].delivery_count);
+
+    // A consumer blocked on the group receives the next new entry
+    let mut reader = Client::connect(addr).await.unwrap();
+    let blocked = tokio::spawn(async move {
+        reader
+            .xreadgroup(
+                "workers",
+                "carol",
+                &[("jobs", ">")],
+                None,
+                Some(Duration::from_secs(0)),
+                false,
+            )
+            .await
+    });
+
+    // Give the client time to block
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let fields = vec![("task".to_string(), "deploy".into())];
+    let id = client.xadd("jobs", "*", fields, None).await.unwrap();
+
+    let streams = blocked.await.unwrap().unwrap();
+    assert_eq!(id, streams[0].1[0].id);
+
+    assert_eq!(
+        2,
+        client
+            .xgroup_delconsumer("jobs", "workers", "bob")
+            .await
+            .unwrap()
+    );
+    assert!(client.xgroup_destroy("jobs", "workers").await.unwrap());
+    let err = client
+        .xreadgroup("workers", "alice", &[("jobs", ">")], None, None, false)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().starts_with("NOGROUP"));
+}
+
+/// Commands queued in a transaction are executed together, unless a watched
+/// key is modified by another client before `EXEC`.
+#[tokio::test]
+async fn transaction_aborted_by_watch() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+    let mut other = Client::connect(addr).await.unwrap();
+
+    client.set("balance", "10".into()).await.unwrap();
+    client.watch(&["balance".to_string()]).await.unwrap();
+
+    // Another client modifies the watched key
+    other.set("balance", "20".into()).await.unwrap();
+
+    let responses = client
+        .transaction()
+        .set("balance", "0".into())
+        .exec()
+        .await
+        .unwrap();
+    assert!(responses.is_none());
+    assert_eq!(client.get("balance").await.unwrap().unwrap(), "20");
+
+    // `EXEC` unwatched the key, so the next transaction goes through
+    let responses = client
+        .transaction()
+        .set("balance", "0".into())
+        .rpush("log", vec!["reset".into()])
+        .get("balance")
+        .exec()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(3, responses.len());
+    assert_eq!(responses[0], "OK");
+    assert!(matches!(responses[1], mini_redis::Frame::Integer(1)));
+    assert_eq!(responses[2], "0");
+}
+
+/// Timeouts can be set on keys of any type, read back, changed under
+/// conditions and removed.
+#[tokio::test]
+async fn expire_ttl_persist() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let hour = Duration::from_secs(3600);
+
+    assert_eq!(-2, client.ttl("jobs").await.unwrap());
+    assert!(!client.expire("jobs", hour, None).await.unwrap());
+
+    client.rpush("jobs", vec!["a".into()]).await.unwrap();
+    assert_eq!(-1, client.ttl("jobs").await.unwrap());
+
+    // A key without timeout never expires, so `GT` does not apply
+    let gt = Some(ExpireCondition::Gt);
+    assert!(!client.expire("jobs", hour, gt).await.unwrap());
+    assert!(client
+        .expire("jobs", hour, Some(ExpireCondition::Nx))
+        .await
+        .unwrap());
+    assert!(!client.expire("jobs", hour / 2, gt).await.unwrap());
+    assert!(client
+        .expire("jobs", hour / 2, Some(ExpireCondition::Lt))
+        .await
+        .unwrap());
+    assert_eq!(1800, client.ttl("jobs").await.unwrap());
+
+    let pttl = client.pttl("jobs").await.unwrap();
+    assert!(pttl > 1_795_000 && pttl <= 1_800_000);
+
+    // The list expires
+    client
+        .expire("jobs", Duration::from_millis(10), None)
+        .await
+        .unwrap();
+    time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(0, client.llen("jobs").await.unwrap());
+    assert_eq!(-2, client.pttl("jobs").await.unwrap());
+
+    // Removing the timeout
+    client
+        .set_expires("hello", "world".into(), hour)
+        .await
+        .unwrap();
+    assert!(client.persist("hello").await.unwrap());
+    assert!(!client.persist("hello").await.unwrap());
+    assert_eq!(-1, client.ttl("hello").await.unwrap());
+
+    // A time in the past deletes the key
+    assert!(client.expire_at("hello", UNIX_EPOCH, None).await.unwrap());
+    assert!(client.get("hello").await.unwrap().is_none());
+}
+
+/// Timeouts are rejected when the Unix time at which the key would expire
+/// overflows, and the largest one is reported without wrapping.
+#[tokio::test]
+async fn expire_overflow_rejected() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    client.set("hello", "world".into()).await.unwrap();
+
+    let max = i64::MAX.to_string();
+    let mut pipeline = Pipeline::new();
+    let pexpire = pipeline.command(vec!["PEXPIRE".into(), "hello".into(), max.clone().into()]);
+    let expire = pipeline.command(vec![
+        "EXPIRE".into(),
+        "hello".into(),
+        (i64::MAX / 1000).to_string().into(),
+    ]);
+    let set = pipeline.command(vec![
+        "SET".into(),
+        "hello".into(),
+        "again".into(),
+        "PX".into(),
+        max.clone().into(),
+    ]);
+    let getex = pipeline.command(vec![
+        "GETEX".into(),
+        "hello".into(),
+        "PX".into(),
+        max.clone().into(),
+    ]);
+
+    let mut responses = client.send_pipeline(pipeline).await.unwrap();
+    for pending in [pexpire, expire, set, getex] {
+        let err = responses.take(pending).unwrap_err().to_string();
+        assert!(err.contains("invalid expire time"), "{}", err);
+    }
+
+    // Nothing was changed by the rejected commands
+    assert_eq!("world", client.get("hello").await.unwrap().unwrap());
+    assert_eq!(-1, client.ttl("hello").await.unwrap());
+
+    // The largest Unix time is accepted, and reported as it is
+    let mut pipeline = Pipeline::new();
+    let pexpireat = pipeline.command(vec!["PEXPIREAT".into(), "hello".into(), max.into()]);
+    let pexpiretime = pipeline.command(vec!["PEXPIRETIME".into(), "hello".into()]);
+    let expiretime = pipeline.command(vec!["EXPIRETIME".into(), "hello".into()]);
+
+    let mut responses = client.send_pipeline(pipeline).await.unwrap();
+    assert!(matches!(
+        responses.take(pexpireat).unwrap(),
+        Frame::Integer(1)
+    ));
+    match responses.take(pexpiretime).unwrap() {
+        Frame::Integer(millis) => assert!(millis > i64::MAX - 1000, "{}", millis),
+        frame => panic!("unexpected response {:?}", frame),
+    }
+    match responses.take(expiretime).unwrap() {
+        Frame::Integer(secs) => assert!(secs > i64::MAX / 1000 - 1, "{}", secs),
+        frame => panic!("unexpected response {:?}", frame),
+    }
+    assert!(client.pttl("hello").await.unwrap() > 0);
+}
+
+/// `SET` options and the commands reading a value while changing the key.
+#[tokio::test]
+async fn set_options_and_get_family() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let hour = Some(Duration::from_secs(3600));
+
+    // A lock is only acquired once
+    assert!(client
+        .set_if("lock", "a".into(), SetCondition::Nx, hour)
+        .await
+        .unwrap());
+    assert!(!client
+        .set_if("lock", "b".into(), SetCondition::Nx, hour)
+        .await
+        .unwrap());
+    assert_eq!("a", client.get("lock").await.unwrap().unwrap());
+    assert_eq!(3600, client.ttl("lock").await.unwrap());
+
+    assert!(!client
+        .set_if("missing", "a".into(), SetCondition::Xx, None)
+        .await
+        .unwrap());
+    assert!(client.get("missing").await.unwrap().is_none());
+
+    // `KEEPTTL` and `GET`
+    let mut pipeline = Pipeline::new();
+    let keep_ttl = pipeline.command(vec![
+        "set".into(),
+        "lock".into(),
+        "c".into(),
+        "keepttl".into(),
+        "get".into(),
+    ]);
+    let mut responses = client.send_pipeline(pipeline).await.unwrap();
+    assert!(matches!(responses.take(keep_ttl).unwrap(), Frame::Bulk(value) if value == "a"));
+    assert_eq!(3600, client.ttl("lock").await.unwrap());
+
+    // `GETSET` discards the time to live
+    let previous = client.getset("lock", "d".into()).await.unwrap();
+    assert_eq!("c", previous.unwrap());
+    assert_eq!(-1, client.ttl("lock").await.unwrap());
+
+    client.rpush("list", vec!["a".into()]).await.unwrap();
+    assert!(client.getset("list", "a".into()).await.is_err());
+    assert_eq!(1, client.llen("list").await.unwrap());
+
+    // `GETEX` changes the time to live
+    assert_eq!("d", client.getex("lock", hour).await.unwrap().unwrap());
+    assert_eq!(3600, client.ttl("lock").await.unwrap());
+    assert_eq!("d", client.getex("lock", None).await.unwrap().unwrap());
+    assert_eq!(-1, client.ttl("lock").await.unwrap());
+
+    assert_eq!("d", client.getdel("lock").await.unwrap().unwrap());
+    assert!(client.getdel("lock").await.unwrap().is_none());
+    assert_eq!(-2, client.ttl("lock").await.unwrap());
+}
+
+/// Counters are updated atomically and keep their time to live. Values that
+/// are not numbers are reported as errors.
+#[tokio::test]
+async fn counters_and_append() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    assert_eq!(1, client.incr("counter").await.unwrap());
+    assert_eq!(-9, client.incrby("counter", -10).await.unwrap());
+    assert_eq!(-10, client.decr("counter").await.unwrap());
+    assert_eq!(0, client.decrby("counter", -10).await.unwrap());
+    assert_eq!("0", client.get("counter").await.unwrap().unwrap());
+
+    // The time to live of the key is retained
+    let hour = Duration::from_secs(3600);
+    client
+        .set_expires("counter", "41".into(), hour)
+        .await
+        .unwrap();
+    assert_eq!(42, client.incr("counter").await.unwrap());
+    assert_eq!(3600, client.ttl("counter").await.unwrap());
+
+    client.set("hello", "world".into()).await.unwrap();
+    let err = client.incr("hello").await.unwrap_err();
+    assert_eq!(
+        "ERR value is not an integer or out of range",
+        err.to_string()
+    );
+
+    client
+        .set("max", i64::MAX.to_string().into())
+        .await
+        .unwrap();
+    assert!(client.incr("max").await.is_err());
+    assert!(client.decrby("counter", i64::MIN).await.is_err());
+
+    assert_eq!(10.5, client.incrbyfloat("float", 10.5).await.unwrap());
+    assert_eq!(5.0, client.incrbyfloat("float", -5.5).await.unwrap());
+    assert_eq!("5", client.get("float").await.unwrap().unwrap());
+    assert!(client.incrbyfloat("hello", 1.0).await.is_err());
+
+    assert_eq!(5, client.append("log", "hello".into()).await.unwrap());
+    assert_eq!(11, client.append("log", " world".into()).await.unwrap());
+    assert_eq!("hello world", client.get("log").await.unwrap().unwrap());
+    assert_eq!(11, client.strlen("log").await.unwrap());
+    assert_eq!(0, client.strlen("missing").await.unwrap());
+
+    client.rpush("list", vec!["a".into()]).await.unwrap();
+    assert!(client.strlen("list").await.is_err());
+    assert!(client.append("list", "b".into()).await.is_err());
+}
+
+/// Keys can be listed, inspected, renamed and removed.
+#[tokio::test]
+async fn keyspace_commands() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    client.set("user:1", "alice".into()).await.unwrap();
+    client.set("user:2", "bob".into()).await.unwrap();
+    client.rpush("jobs", vec!["a".into()]).await.unwrap();
+    assert_eq!(3, client.dbsize().await.unwrap());
+
+    let mut keys = client.keys("user:*").await.unwrap();
+    keys.sort();
+    assert_eq!(vec!["user:1", "user:2"], keys);
+
+    assert_eq!("string", client.key_type("user:1").await.unwrap());
+    assert_eq!("list", client.key_type("jobs").await.unwrap());
+    assert_eq!("none", client.key_type("missing").await.unwrap());
+
+    let keys = [
+        "user:1".to_string(),
+        "user:1".to_string(),
+        "missing".to_string(),
+    ];
+    assert_eq!(2, client.exists(&keys).await.unwrap());
+
+    // `RENAME` overwrites the destination and keeps the time to live
+    let hour = Duration::from_secs(3600);
+    client.expire("user:1", hour, None).await.unwrap();
+    client.rename("user:1", "user:2").await.unwrap();
+    assert_eq!("alice", client.get("user:2").await.unwrap().unwrap());
+    assert_eq!(3600, client.ttl("user:2").await.unwrap());
+    let err = client.rename("user:1", "user:3").await.unwrap_err();
+    assert_eq!("ERR no such key", err.to_string());
+
+    let keys = ["user:1".to_string(), "user:2".to_string()];
+    assert_eq!(1, client.del(&keys).await.unwrap());
+    assert_eq!(0, client.exists(&keys).await.unwrap());
+
+    client.flushall().await.unwrap();
+    assert_eq!(0, client.dbsize().await.unwrap());
+    assert!(client.keys("*").await.unwrap().is_empty());
+}
+
+/// Each database has its own key space, while pub/sub channels are shared by
+/// all of them.
+#[tokio::test]
+async fn multiple_databases() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+    let mut other = Client::connect(addr).await.unwrap();
+    other.select(1).await.unwrap();
+
+    client.set("hello", "zero".into()).await.unwrap();
+    assert!(other.get("hello").await.unwrap().is_none());
+    other.set("hello", "one".into()).await.unwrap();
+    assert_eq!("zero", client.get("hello").await.unwrap().unwrap());
+
+    let err = client.select(16).await.unwrap_err();
+    assert_eq!("ERR DB index is out of range", err.to_string());
+
+    // `MOVE` does not overwrite the destination, and keeps the time to live
+    assert!(!client.move_key("hello", 1).await.unwrap());
+    client.set("counter", "1".into()).await.unwrap();
+    client
+        .expire("counter", Duration::from_secs(3600), None)
+        .await
+        .unwrap();
+    assert!(client.move_key("counter", 1).await.unwrap());
+    assert!(!client.move_key("counter", 1).await.unwrap());
+    assert_eq!(3600, other.ttl("counter").await.unwrap());
+    let err = client.move_key("hello", 0).await.unwrap_err();
+    assert_eq!(
+        "ERR source and destination objects are the same",
+        err.to_string()
+    );
+
+    // Connections keep their database number, and see the swapped data
+    client.swapdb(0, 1).await.unwrap();
+    assert_eq!("one", client.get("hello").await.unwrap().unwrap());
+    assert_eq!("zero", other.get("hello").await.unwrap().unwrap());
+    assert_eq!(2, client.dbsize().await.unwrap());
+
+    client.flushdb().await.unwrap();
+    assert_eq!(0, client.dbsize().await.unwrap());
+    assert_eq!(1, other.dbsize().await.unwrap());
+    client.flushall().await.unwrap();
+    assert_eq!(0, other.dbsize().await.unwrap());
+
+    let mut subscriber = Client::connect(addr)
+        .await
+        .unwrap()
+        .subscribe(vec!["news".into()])
+        .await
+        .unwrap();
+    assert_eq!(1, other.publish("news", "hi".into()).await.unwrap());
+    let message = subscriber.next_message().await.unwrap().unwrap();
+    assert_eq!(&message.content[..], b"hi");
+}
+
+/// A `SCAN` iteration returns every key that exists during the whole
+/// iteration exactly once, even when keys are added and removed in between
+/// calls.
+#[tokio::test]
+async fn scan_is_stable_while_keys_change() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    for i in 0..100 {
+        client.set(&format!("key:{}", i), "x".into()).await.unwrap();
+    }
+    client.rpush("list", vec!["a".into()]).await.unwrap();
+
+    let mut seen = std::collections::HashMap::new();
+    let mut cursor = 0;
+    let mut calls = 0;
+
+    loop {
+        let (next, keys) = client
+            .scan(cursor, Some("key:*"), Some(10), Some("string"))
+            .await
+            .unwrap();
+
+        for key in keys {
+            *seen.entry(key).or_insert(0) += 1;
+        }
+
+        // Grow and shrink the key space while iterating
+        calls += 1;
+        for i in 0..20 {
+            let key = format!("new:{}:{}", calls, i);
+            client.set(&key, "x".into()).await.unwrap();
+        }
+        client.del(&[format!("key:{}", 90 + calls)]).await.unwrap();
+
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+
+    for i in 0..90 {
+        assert_eq!(Some(&1), seen.get(&format!("key:{}", i)), "key:{}", i);
+    }
+    assert!(seen.values().all(|&count| count == 1));
+    assert!(!seen.contains_key("list"));
+}
+
+/// Commands sent in a pipeline are replied to in order, and a failing command
+/// does not affect the others.
+#[tokio::test]
+async fn pipeline_replies_in_order() {
+    let (addr, _) = start_server().await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let mut pipeline = Pipeline::new();
+    let set = pipeline.set("hello", "world".into());
+    let push = pipeline.rpush("jobs", vec!["a".into(), "b".into()]);
+    let wrong_type = pipeline.get("jobs");
+    let get = pipeline.get("hello");
+    let unknown = pipeline.command(vec!["FOO".into()]);
+
+    let mut responses = client.send_pipeline(pipeline).await.unwrap();
+    assert_eq!(5, responses.len());
+
+    responses.take(set).unwrap();
+    assert_eq!(2, responses.take(push).unwrap());
+    assert!(responses.take(wrong_type).is_err());
+    assert_eq!(responses.take(get).unwrap().unwrap(), "world");
+    assert!(responses.take(unknown).is_err());
+
+    // The connection is still usable
+    assert_eq!(vec!["a", "b"], client.lrange("jobs", 0, -1).await.unwrap());
+}
+
 async fn start_server() -> (SocketAddr, JoinHandle<()>) {
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
     let addr = listener.local_addr().unwrap();
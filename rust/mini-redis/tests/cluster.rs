@@ -0,0 +1,158 @@
+use mini_redis::clients::{Client, ClusterClient, Pipeline};
+use mini_redis::cluster::{key_hash_slot, ClusterNode};
+use mini_redis::{server, Config, Frame};
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+
+/// Hash slots are computed with CRC16, and only the hash tag is hashed when
+/// the key has one.
+#[test]
+fn hash_slots() {
+    assert_eq!(12739, key_hash_slot(b"123456789"));
+    assert_eq!(key_hash_slot(b"foo"), key_hash_slot(b"{foo}.bar"));
+    assert_eq!(key_hash_slot(b"{user}:a"), key_hash_slot(b"{user}:b"));
+
+    // Empty hash tags are ignored
+    assert_ne!(key_hash_slot(b"{}.a"), key_hash_slot(b"{}.b"));
+}
+
+/// Each node only serves its own slots. The cluster client sends each command
+/// to the right node.
+#[tokio::test]
+async fn cluster_client_routes_keys() {
+    let addrs = start_cluster(&["0-5460", "5461-10922", "10923-16383"]).await;
+
+    let mut client = ClusterClient::connect(&addrs[0].to_string()).await.unwrap();
+
+    for i in 0..50 {
+        let key = format!("key:{}", i);
+        client.set(&key, i.to_string().into()).await.unwrap();
+    }
+
+    for i in 0..50 {
+        let key = format!("key:{}", i);
+        let value = client.get(&key).await.unwrap().unwrap();
+        assert_eq!(i.to_string(), value);
+    }
+
+    // Keys sharing a hash tag are stored on the same node
+    client
+        .rpush("{user:1}:jobs", vec!["a".into()])
+        .await
+        .unwrap();
+    assert_eq!(1, client.llen("{user:1}:jobs").await.unwrap());
+
+    // A node redirects commands for keys it does not serve
+    let mut node = Client::connect(addrs[0]).await.unwrap();
+    let slot = key_hash_slot(b"foo");
+    assert!(slot > 10922);
+
+    let err = node.get("foo").await.unwrap_err();
+    assert_eq!(format!("MOVED {} {}", slot, addrs[2]), err.to_string());
+
+    // The slot map is reported by every node
+    let ranges = node.cluster_slots().await.unwrap();
+    assert_eq!(3, ranges.len());
+    assert_eq!(10923..=16383, ranges[2].slots);
+    assert_eq!(addrs[2].port(), ranges[2].port);
+}
+
+/// While a slot is migrated, the source node sends clients to the target node
+/// with `ASK` for the keys it does not have. Once the slot is assigned to the
+/// target, the source node replies with `MOVED`.
+#[tokio::test]
+async fn ask_redirect_during_migration() {
+    let addrs = start_cluster(&["0-8191", "8192-16383"]).await;
+
+    let key = "migrating";
+    let slot = key_hash_slot(key.as_bytes());
+    let (source, target) = if slot <= 8191 { (0, 1) } else { (1, 0) };
+
+    let mut source_node = Client::connect(addrs[source]).await.unwrap();
+    let mut target_node = Client::connect(addrs[target]).await.unwrap();
+    let source_id = myid(&mut source_node).await;
+    let target_id = myid(&mut target_node).await;
+
+    let setslot = |node: &str, action: &str| {
+        vec![
+            "cluster".into(),
+            "setslot".into(),
+            slot.to_string().into(),
+            action.to_string().into(),
+            node.to_string().into(),
+        ]
+    };
+
+    command(&mut source_node, setslot(&target_id, "migrating")).await;
+    command(&mut target_node, setslot(&source_id, "importing")).await;
+
+    let mut client = ClusterClient::connect(&addrs[0].to_string()).await.unwrap();
+
+    // The key does not exist on the source node, so it is created on the
+    // target node.
+    client.set(key, "value".into()).await.unwrap();
+    assert_eq!("value", client.get(key).await.unwrap().unwrap());
+    assert!(source_node
+        .get(key)
+        .await
+        .unwrap_err()
+        .to_string()
+        .starts_with("ASK"));
+
+    // Without `ASKING`, the target node does not serve the slot yet
+    let err = target_node.get(key).await.unwrap_err();
+    assert!(err.to_string().starts_with("MOVED"));
+
+    command(&mut source_node, setslot(&target_id, "node")).await;
+    command(&mut target_node, setslot(&target_id, "node")).await;
+
+    assert_eq!("value", client.get(key).await.unwrap().unwrap());
+    assert_eq!("value", target_node.get(key).await.unwrap().unwrap());
+}
+
+async fn myid(client: &mut Client) -> String {
+    match command(client, vec!["cluster".into(), "myid".into()]).await {
+        Frame::Bulk(id) => String::from_utf8(id.to_vec()).unwrap(),
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+async fn command(client: &mut Client, args: Vec<bytes::Bytes>) -> Frame {
+    let mut pipeline = Pipeline::new();
+    let reply = pipeline.command(args);
+    let mut responses = client.send_pipeline(pipeline).await.unwrap();
+    responses.take(reply).unwrap()
+}
+
+/// Start a node for each range of slots, and return their addresses.
+async fn start_cluster(slots: &[&str]) -> Vec<SocketAddr> {
+    let mut listeners = vec![];
+    for _ in slots {
+        listeners.push(TcpListener::bind("127.0.0.1:0").await.unwrap());
+    }
+
+    let addrs: Vec<SocketAddr> = listeners
+        .iter()
+        .map(|listener| listener.local_addr().unwrap())
+        .collect();
+
+    let nodes: Vec<ClusterNode> = addrs
+        .iter()
+        .zip(slots)
+        .map(|(addr, slots)| format!("{}={}", addr, slots).parse().unwrap())
+        .collect();
+
+    for listener in listeners {
+        let config = Config {
+            cluster_enabled: true,
+            cluster_nodes: nodes.clone(),
+            ..Config::default()
+        };
+
+        tokio::spawn(async move {
+            server::run_with_config(listener, config, tokio::signal::ctrl_c()).await
+        });
+    }
+
+    addrs
+}
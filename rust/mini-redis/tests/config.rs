@@ -0,0 +1,144 @@
+use mini_redis::{clients::Client, server, Config};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+
+/// Settings are loaded from the configuration file, can be read and changed
+/// with `CONFIG`, and written back to the file with `CONFIG REWRITE`.
+#[tokio::test]
+async fn config_file_loaded_and_rewritten() {
+    let path = temp_path("redis.conf");
+    fs::write(
+        &path,
+        "# Limits\nmaxclients 10\n\ndbfilename \"my dump.rdb\"\nlua-time-limit 100 # ms\n",
+    )
+    .unwrap();
+
+    let config = Config::load(&path).unwrap();
+    assert_eq!(10, config.maxclients);
+    assert_eq!(PathBuf::from("my dump.rdb"), config.dbfilename);
+    assert_eq!(Duration::from_millis(100), config.lua_time_limit);
+
+    let addr = start_server(config).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let settings = client.config_get("max*").await.unwrap();
+    assert_eq!(4, settings.len());
+    assert_eq!("10", settings["maxclients"]);
+    assert_eq!("0", settings["maxmemory"]);
+    let settings = client.config_get("port").await.unwrap();
+    assert_eq!(addr.port().to_string(), settings["port"]);
+
+    client.config_set("maxclients", "20").await.unwrap();
+    client.config_set("timeout", "300").await.unwrap();
+    assert_eq!(
+        "20",
+        client.config_get("maxclients").await.unwrap()["maxclients"]
+    );
+
+    // Settings only read on startup, unknown settings and invalid values are
+    // rejected
+    let err = client.config_set("databases", "4").await.unwrap_err();
+    assert!(err.to_string().contains("can't set immutable config"));
+    let err = client.config_set("nosuchsetting", "1").await.unwrap_err();
+    assert!(err.to_string().contains("Unknown option"));
+    let err = client.config_set("maxclients", "many").await.unwrap_err();
+    assert!(err.to_string().contains("maxclients"));
+    assert_eq!(
+        "20",
+        client.config_get("maxclients").await.unwrap()["maxclients"]
+    );
+
+    client.config_rewrite().await.unwrap();
+
+    // Comments are kept, and settings missing from the file are appended
+    let contents = fs::read_to_string(&path).unwrap();
+    assert!(contents.starts_with("# Limits\nmaxclients 20\n\ndbfilename \"my dump.rdb\"\n"));
+    assert!(contents.contains("\ntimeout 300\n"));
+
+    let config = Config::load(&path).unwrap();
+    assert_eq!(20, config.maxclients);
+    assert_eq!(Some(Duration::from_secs(300)), config.timeout);
+    assert_eq!(PathBuf::from("my dump.rdb"), config.dbfilename);
+
+    // Errors point at the line of the file
+    fs::write(&path, "port 7000\nappendonly maybe\n").unwrap();
+    let err = Config::load(&path).unwrap_err();
+    assert!(err.to_string().contains("line 2"));
+
+    fs::remove_file(&path).unwrap();
+}
+
+/// `CONFIG REWRITE` fails when the server was not started with a
+/// configuration file.
+#[tokio::test]
+async fn config_rewrite_requires_file() {
+    let addr = start_server(Config::default()).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let err = client.config_rewrite().await.unwrap_err();
+    assert!(err.to_string().contains("without a config file"));
+}
+
+/// New connections wait for a slot while `maxclients` are connected, and
+/// connections are closed once idle for `timeout`.
+#[tokio::test]
+async fn client_limits() {
+    let config = Config {
+        maxclients: 1,
+        ..Config::default()
+    };
+    let addr = start_server(config).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let mut waiting = TcpStream::connect(addr).await.unwrap();
+    waiting.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+
+    let mut response = [0; 7];
+    let res = time::timeout(
+        Duration::from_millis(200),
+        waiting.read_exact(&mut response),
+    )
+    .await;
+    assert!(res.is_err());
+
+    // Raising the limit lets the waiting connection in
+    client.config_set("maxclients", "2").await.unwrap();
+
+    time::timeout(Duration::from_secs(1), waiting.read_exact(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(b"+PONG\r\n", &response);
+
+    client.config_set("timeout", "1").await.unwrap();
+
+    let mut response = [0; 1];
+    let read = time::timeout(Duration::from_secs(3), waiting.read(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(0, read);
+}
+
+/// Returns a path for a file that is unique to the test.
+fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("mini-redis-{}-{}", std::process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+async fn start_server(config: Config) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        server::run_with_config(listener, config, std::future::pending::<()>()).await
+    });
+
+    addr
+}
@@ -0,0 +1,262 @@
+use mini_redis::config::EvictionPolicy;
+use mini_redis::{clients::Client, server, Config};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// With the default `noeviction` policy, commands using more memory are
+/// rejected once the limit is reached, while reads and deletions are still
+/// served.
+#[tokio::test]
+async fn noeviction_rejects_writes() {
+    let addr = start_server(config(EvictionPolicy::NoEviction)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let err = fill(&mut client, "key").await;
+    assert!(err.starts_with("OOM "), "{}", err);
+
+    assert_eq!(value(), client.get("key:0").await.unwrap().unwrap());
+    assert!(client.lpush("list", vec![value()]).await.is_err());
+
+    // Deleting keys frees memory for new ones
+    client.del(&["key:0".to_string()]).await.unwrap();
+    client.set("other", value()).await.unwrap();
+
+    let info = client.info(Some("stats")).await.unwrap();
+    assert_eq!("0", info_field(&info, "evicted_keys"));
+}
+
+/// `allkeys-lru` keeps the memory used around the limit by evicting the keys
+/// which were not accessed recently.
+#[tokio::test]
+async fn allkeys_lru_keeps_recently_used_keys() {
+    let addr = start_server(config(EvictionPolicy::AllKeysLru)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    client.set("hot", value()).await.unwrap();
+
+    for i in 0..100 {
+        client.set(&format!("key:{}", i), value()).await.unwrap();
+        client.get("hot").await.unwrap().unwrap();
+    }
+
+    let dbsize = client.dbsize().await.unwrap();
+    assert!(dbsize < 100, "{} keys", dbsize);
+
+    // Keys are evicted before a command, which then goes over the limit by
+    // the size of the key it adds
+    let info = client.info(Some("memory")).await.unwrap();
+    let used_memory: u64 = info_field(&info, "used_memory").parse().unwrap();
+    assert!(used_memory <= 22_000, "{} bytes used", used_memory);
+
+    let info = client.info(Some("stats")).await.unwrap();
+    let evicted: u64 = info_field(&info, "evicted_keys").parse().unwrap();
+    assert_eq!(101, dbsize + evicted);
+}
+
+/// `allkeys-lfu` evicts the keys which were accessed the least often, so a
+/// key read over and over stays while new keys are evicted.
+#[tokio::test]
+async fn allkeys_lfu_keeps_frequently_used_keys() {
+    let addr = start_server(sampled_config(EvictionPolicy::AllKeysLfu)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    client.set("hot", value()).await.unwrap();
+    for _ in 0..100 {
+        client.get("hot").await.unwrap().unwrap();
+    }
+
+    for i in 0..100 {
+        client.set(&format!("key:{}", i), value()).await.unwrap();
+    }
+
+    assert!(client.get("hot").await.unwrap().is_some());
+
+    let dbsize = client.dbsize().await.unwrap();
+    let info = client.info(Some("stats")).await.unwrap();
+    let evicted: u64 = info_field(&info, "evicted_keys").parse().unwrap();
+    assert!(evicted > 0);
+    assert_eq!(101, dbsize + evicted);
+}
+
+/// `volatile-lru` evicts the least recently used key with an expiration, and
+/// never keys without one.
+#[tokio::test]
+async fn volatile_lru_keeps_keys_without_expiration() {
+    let addr = start_server(sampled_config(EvictionPolicy::VolatileLru)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let hour = Duration::from_secs(3600);
+    for i in 0..5 {
+        client
+            .set(&format!("persistent:{}", i), value())
+            .await
+            .unwrap();
+    }
+    client.set_expires("hot", value(), hour).await.unwrap();
+
+    for i in 0..100 {
+        client
+            .set_expires(&format!("volatile:{}", i), value(), hour)
+            .await
+            .unwrap();
+        client.get("hot").await.unwrap().unwrap();
+    }
+
+    for i in 0..5 {
+        let key = format!("persistent:{}", i);
+        assert!(client.get(&key).await.unwrap().is_some());
+    }
+    assert!(client.get("hot").await.unwrap().is_some());
+    assert!(client.get("volatile:0").await.unwrap().is_none());
+    assert!(client.get("volatile:99").await.unwrap().is_some());
+}
+
+/// `allkeys-random` evicts any key, while `volatile-random` only evicts keys
+/// with an expiration, and rejects commands once none is left.
+#[tokio::test]
+async fn random_policies_evict_any_or_volatile_keys() {
+    let addr = start_server(sampled_config(EvictionPolicy::AllKeysRandom)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    for i in 0..100 {
+        client.set(&format!("key:{}", i), value()).await.unwrap();
+    }
+    let dbsize = client.dbsize().await.unwrap();
+    assert!(dbsize < 100, "{} keys", dbsize);
+
+    let addr = start_server(sampled_config(EvictionPolicy::VolatileRandom)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    for i in 0..5 {
+        client
+            .set(&format!("persistent:{}", i), value())
+            .await
+            .unwrap();
+    }
+    for i in 0..100 {
+        client
+            .set_expires(
+                &format!("volatile:{}", i),
+                value(),
+                Duration::from_secs(3600),
+            )
+            .await
+            .unwrap();
+    }
+
+    let err = fill(&mut client, "other").await;
+    assert!(err.starts_with("OOM "), "{}", err);
+
+    for i in 0..5 {
+        let key = format!("persistent:{}", i);
+        assert!(client.get(&key).await.unwrap().is_some());
+    }
+    let keys = client.keys("volatile:*").await.unwrap();
+    assert!(keys.is_empty(), "{:?}", keys);
+}
+
+/// The `volatile` policies only evict keys with an expiration. Once none is
+/// left, commands using more memory are rejected. The policy and limit can be
+/// changed while running.
+#[tokio::test]
+async fn volatile_ttl_only_evicts_keys_with_expiration() {
+    let addr = start_server(Config::default()).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    client.config_set("maxmemory", "20kb").await.unwrap();
+    client
+        .config_set("maxmemory-samples", "1000")
+        .await
+        .unwrap();
+    client
+        .config_set("maxmemory-policy", "volatile-ttl")
+        .await
+        .unwrap();
+    let settings = client.config_get("maxmemory*").await.unwrap();
+    assert_eq!("20480", settings["maxmemory"]);
+    assert_eq!("volatile-ttl", settings["maxmemory-policy"]);
+    assert!(client
+        .config_set("maxmemory-policy", "sometimes")
+        .await
+        .is_err());
+
+    for i in 0..5 {
+        client
+            .set(&format!("persistent:{}", i), value())
+            .await
+            .unwrap();
+    }
+
+    for i in 0..50 {
+        let ttl = Duration::from_secs(100 + i);
+        client
+            .set_expires(&format!("volatile:{}", i), value(), ttl)
+            .await
+            .unwrap();
+    }
+
+    // The key expiring last was added last, and is never a candidate as long
+    // as keys expiring earlier are left
+    assert!(client.get("volatile:49").await.unwrap().is_some());
+    assert!(client.get("volatile:0").await.unwrap().is_none());
+
+    let err = fill(&mut client, "other").await;
+    assert!(err.starts_with("OOM "), "{}", err);
+
+    for i in 0..5 {
+        let key = format!("persistent:{}", i);
+        assert!(client.get(&key).await.unwrap().is_some());
+    }
+}
+
+/// Set keys named `{prefix}:{i}` until a command fails, and returns the error.
+async fn fill(client: &mut Client, prefix: &str) -> String {
+    for i in 0..100 {
+        if let Err(err) = client.set(&format!("{}:{}", prefix, i), value()).await {
+            return err.to_string();
+        }
+    }
+
+    panic!("the memory limit was never reached");
+}
+
+/// Returns the configuration of `config`, sampling enough keys that the best
+/// candidate for eviction is always found.
+fn sampled_config(policy: EvictionPolicy) -> Config {
+    Config {
+        maxmemory_samples: 1000,
+        ..config(policy)
+    }
+}
+
+/// Returns a configuration limiting the memory to 20 KB with `policy`.
+fn config(policy: EvictionPolicy) -> Config {
+    Config {
+        maxmemory: Some(20_000),
+        maxmemory_policy: policy,
+        ..Config::default()
+    }
+}
+
+fn value() -> bytes::Bytes {
+    bytes::Bytes::from(vec![b'x'; 1000])
+}
+
+fn info_field(info: &str, field: &str) -> String {
+    info.lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+        .unwrap_or_else(|| panic!("no field {} in {:?}", field, info))
+        .to_string()
+}
+
+async fn start_server(config: Config) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        server::run_with_config(listener, config, std::future::pending::<()>()).await
+    });
+
+    addr
+}
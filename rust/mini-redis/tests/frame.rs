@@ -0,0 +1,47 @@
+use mini_redis::frame::{Error, Frame};
+use std::io::Cursor;
+
+/// Lengths too large to be allocated are reported as incomplete or invalid
+/// frames, without overflowing.
+#[test]
+fn huge_lengths_do_not_overflow() {
+    let max = u64::MAX;
+
+    for header in [format!("%{}\r\n", max), format!("*{}\r\n", max)] {
+        let mut src = Cursor::new(header.as_bytes());
+        assert!(matches!(Frame::check(&mut src), Err(Error::Incomplete)));
+    }
+
+    for header in [format!("${}\r\n", max), format!("={}\r\n", max)] {
+        let mut src = Cursor::new(header.as_bytes());
+        assert!(matches!(Frame::check(&mut src), Err(Error::Other(_))));
+    }
+}
+
+/// `check` accepts a frame only if `parse` does: `-1` is the null array, but
+/// not a valid length for sets and pushes.
+#[test]
+fn check_agrees_with_parse_on_null_lengths() {
+    let mut src = Cursor::new(&b"*-1\r\n"[..]);
+    Frame::check(&mut src).unwrap();
+    src.set_position(0);
+    assert!(matches!(Frame::parse(&mut src), Ok(Frame::Null)));
+
+    for frame in [&b"~-1\r\n"[..], &b">-1\r\n"[..]] {
+        let mut src = Cursor::new(frame);
+        assert!(matches!(Frame::check(&mut src), Err(Error::Other(_))));
+
+        let mut src = Cursor::new(frame);
+        assert!(Frame::parse(&mut src).is_err());
+    }
+}
+
+/// Each entry of a map is a key followed by a value.
+#[test]
+fn map_entries_are_checked_in_pairs() {
+    let mut src = Cursor::new(&b"%1\r\n+key\r\n"[..]);
+    assert!(matches!(Frame::check(&mut src), Err(Error::Incomplete)));
+
+    let mut src = Cursor::new(&b"%1\r\n+key\r\n:1\r\n"[..]);
+    Frame::check(&mut src).unwrap();
+}
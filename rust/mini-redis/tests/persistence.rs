@@ -0,0 +1,453 @@
+use mini_redis::config::FsyncPolicy;
+use mini_redis::{clients::Client, server, Config};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// Data written while the append-only file is enabled is available again after
+/// restarting the server, including when the file was rewritten in between.
+#[tokio::test]
+async fn aof_replayed_on_restart() {
+    let path = temp_path("replay.aof");
+
+    let (addr, shutdown, server) = start_server(aof_config(&path)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    client.set("hello", "world".into()).await.unwrap();
+    client.set("hello", "again".into()).await.unwrap();
+    client
+        .rpush("jobs", vec!["a".into(), "b".into(), "c".into()])
+        .await
+        .unwrap();
+    client.lpop("jobs").await.unwrap();
+    client
+        .hset("session", vec![("user".to_string(), "alice".into())])
+        .await
+        .unwrap();
+    client.hincrby("session", "hits", 3).await.unwrap();
+    for id in &["5-1", "5-2"] {
+        let fields = vec![("kind".to_string(), "login".into())];
+        client.xadd("events", id, fields, None).await.unwrap();
+    }
+    client.xtrim("events", 0).await.unwrap();
+    client
+        .xgroup_create("tasks", "workers", "0", true)
+        .await
+        .unwrap();
+    for id in &["6-1", "6-2", "6-3"] {
+        let fields = vec![("task".to_string(), "build".into())];
+        client.xadd("tasks", id, fields, None).await.unwrap();
+    }
+    client
+        .xreadgroup("workers", "alice", &[("tasks", ">")], Some(1), None, false)
+        .await
+        .unwrap();
+
+    client.bgrewriteaof().await.unwrap();
+
+    // Commands received after the rewrite started are kept
+    client
+        .zadd("scores", vec![(1.5, "alice".into()), (0.5, "bob".into())])
+        .await
+        .unwrap();
+    client
+        .xreadgroup("workers", "bob", &[("tasks", ">")], Some(1), None, false)
+        .await
+        .unwrap();
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+
+    let (addr, shutdown, server) = start_server(aof_config(&path)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    assert_eq!("again", client.get("hello").await.unwrap().unwrap());
+    assert_eq!(vec!["b", "c"], client.lrange("jobs", 0, -1).await.unwrap());
+    assert_eq!(
+        "alice",
+        client.hget("session", "user").await.unwrap().unwrap()
+    );
+    assert_eq!(4, client.hincrby("session", "hits", 1).await.unwrap());
+    assert_eq!(
+        vec!["bob", "alice"],
+        client.zrange("scores", 0, -1).await.unwrap()
+    );
+
+    // The trimmed stream still rejects IDs it has already handed out
+    assert_eq!(0, client.xlen("events").await.unwrap());
+    let fields = vec![("kind".to_string(), "logout".into())];
+    assert!(client.xadd("events", "5-2", fields, None).await.is_err());
+
+    // Entries delivered to the group stay pending for their consumer, and
+    // the group resumes after the last delivered entry
+    let summary = client.xpending("tasks", "workers").await.unwrap();
+    assert_eq!(
+        vec![("alice".to_string(), 1), ("bob".to_string(), 1)],
+        summary.consumers
+    );
+    let streams = client
+        .xreadgroup("workers", "alice", &[("tasks", ">")], None, None, false)
+        .await
+        .unwrap();
+    assert_eq!("6-3", streams[0].1[0].id);
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+
+    fs::remove_file(&path).unwrap();
+}
+
+/// Timeouts are appended as the time at which keys expire, including when the
+/// file is rewritten, so keys that expired while the server was down are not
+/// restored.
+#[tokio::test]
+async fn aof_expired_keys_not_restored() {
+    let path = temp_path("expired.aof");
+    let ttl = Duration::from_millis(100);
+
+    let (addr, shutdown, server) = start_server(aof_config(&path)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    for key in &["rewritten", "expire", "getex", "persistent"] {
+        client.set(key, "value".into()).await.unwrap();
+    }
+
+    // The rewritten file holds the time at which keys expire as well
+    client.expire("rewritten", ttl, None).await.unwrap();
+    client.bgrewriteaof().await.unwrap();
+
+    client.expire("expire", ttl, None).await.unwrap();
+    client.getex("getex", Some(ttl)).await.unwrap();
+    client
+        .set_expires("set", "value".into(), ttl)
+        .await
+        .unwrap();
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+    time::sleep(ttl * 2).await;
+
+    let (addr, shutdown, server) = start_server(aof_config(&path)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    for key in &["rewritten", "expire", "getex", "set"] {
+        assert!(client.get(key).await.unwrap().is_none());
+    }
+    assert_eq!("value", client.get("persistent").await.unwrap().unwrap());
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+
+    fs::remove_file(&path).unwrap();
+}
+
+/// `INCRBYFLOAT` is appended as `SET` with the resulting value, keeping the
+/// time to live of the key.
+#[tokio::test]
+async fn aof_incrbyfloat_appended_as_set() {
+    let path = temp_path("incrbyfloat.aof");
+
+    let (addr, shutdown, server) = start_server(aof_config(&path)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    client.set("price", "10.5".into()).await.unwrap();
+    client
+        .expire("price", Duration::from_secs(3600), None)
+        .await
+        .unwrap();
+    assert_eq!(10.6, client.incrbyfloat("price", 0.1).await.unwrap());
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+
+    let contents = String::from_utf8(fs::read(&path).unwrap()).unwrap();
+    assert!(!contents.contains("incrbyfloat"));
+    assert!(contents.contains("keepttl"));
+
+    let (addr, shutdown, server) = start_server(aof_config(&path)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    assert_eq!("10.6", client.get("price").await.unwrap().unwrap());
+    assert!(client.ttl("price").await.unwrap() > 0);
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+
+    fs::remove_file(&path).unwrap();
+}
+
+/// Keys are restored to the database they were written to, from both the
+/// append-only file and the snapshot.
+#[tokio::test]
+async fn databases_restored_on_restart() {
+    let aof_path = temp_path("databases.aof");
+    let rdb_path = temp_path("databases.rdb");
+    let config = Config {
+        dbfilename: rdb_path.clone(),
+        ..aof_config(&aof_path)
+    };
+
+    let (addr, shutdown, server) = start_server(config.clone()).await;
+    let mut client = Client::connect(addr).await.unwrap();
+    let mut other = Client::connect(addr).await.unwrap();
+    other.select(3).await.unwrap();
+
+    client.set("hello", "zero".into()).await.unwrap();
+    other.set("hello", "three".into()).await.unwrap();
+    client.bgrewriteaof().await.unwrap();
+    other.rpush("jobs", vec!["a".into()]).await.unwrap();
+    client.set("after", "rewrite".into()).await.unwrap();
+    client.save().await.unwrap();
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+
+    for config in [config, rdb_config(&rdb_path)].iter() {
+        let (addr, shutdown, server) = start_server(config.clone()).await;
+        let mut client = Client::connect(addr).await.unwrap();
+
+        assert_eq!("zero", client.get("hello").await.unwrap().unwrap());
+        assert_eq!("rewrite", client.get("after").await.unwrap().unwrap());
+        client.select(3).await.unwrap();
+        assert_eq!("three", client.get("hello").await.unwrap().unwrap());
+        assert_eq!(vec!["a"], client.lrange("jobs", 0, -1).await.unwrap());
+        assert_eq!(2, client.dbsize().await.unwrap());
+
+        shutdown.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    fs::remove_file(&aof_path).unwrap();
+    fs::remove_file(&rdb_path).unwrap();
+}
+
+/// A command only partially written to the append-only file, as happens when
+/// the server crashes, is discarded on startup.
+#[tokio::test]
+async fn aof_truncated_command_discarded() {
+    let path = temp_path("truncated.aof");
+
+    let (addr, shutdown, server) = start_server(aof_config(&path)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+    client.set("hello", "world".into()).await.unwrap();
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(b"*3\r\n$3\r\nset\r\n$5\r\nhel").unwrap();
+    drop(file);
+
+    let (addr, shutdown, server) = start_server(aof_config(&path)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+    assert_eq!("world", client.get("hello").await.unwrap().unwrap());
+
+    // Commands are appended after the last complete command
+    client.set("foo", "bar".into()).await.unwrap();
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+
+    let (addr, shutdown, server) = start_server(aof_config(&path)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+    assert_eq!("bar", client.get("foo").await.unwrap().unwrap());
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+
+    fs::remove_file(&path).unwrap();
+}
+
+/// `BGREWRITEAOF` fails when persistence is disabled.
+#[tokio::test]
+async fn bgrewriteaof_requires_aof() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { server::run(listener, tokio::signal::ctrl_c()).await });
+
+    let mut client = Client::connect(addr).await.unwrap();
+    assert!(client.bgrewriteaof().await.is_err());
+}
+
+/// Data saved with `SAVE` is loaded when the server restarts, including the
+/// remaining time to live of keys.
+#[tokio::test]
+async fn rdb_snapshot_loaded_on_restart() {
+    let path = temp_path("snapshot.rdb");
+
+    let (addr, shutdown, server) = start_server(rdb_config(&path)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    client.set("hello", "world".into()).await.unwrap();
+    client
+        .set_expires("session", "abc".into(), Duration::from_secs(3600))
+        .await
+        .unwrap();
+    client
+        .set_expires("gone", "soon".into(), Duration::from_millis(50))
+        .await
+        .unwrap();
+    client
+        .rpush("jobs", vec!["a".into(), "b".into()])
+        .await
+        .unwrap();
+    client
+        .hset("user", vec![("name".to_string(), "alice".into())])
+        .await
+        .unwrap();
+    client
+        .zadd("scores", vec![(2.5, "alice".into()), (-1.0, "bob".into())])
+        .await
+        .unwrap();
+    let fields = vec![("kind".to_string(), "login".into())];
+    client.xadd("events", "7-1", fields, None).await.unwrap();
+    client
+        .xgroup_create("events", "audit", "0", false)
+        .await
+        .unwrap();
+    client
+        .xreadgroup("audit", "alice", &[("events", ">")], None, None, false)
+        .await
+        .unwrap();
+
+    client.save().await.unwrap();
+
+    // Not part of the snapshot
+    client.set("hello", "again".into()).await.unwrap();
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+
+    let (addr, shutdown, server) = start_server(rdb_config(&path)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    assert_eq!("world", client.get("hello").await.unwrap().unwrap());
+    assert_eq!("abc", client.get("session").await.unwrap().unwrap());
+    assert_eq!(vec!["a", "b"], client.lrange("jobs", 0, -1).await.unwrap());
+    assert_eq!("alice", client.hget("user", "name").await.unwrap().unwrap());
+    assert_eq!(
+        vec![("bob".into(), -1.0), ("alice".into(), 2.5)],
+        client.zrange_withscores("scores", 0, -1).await.unwrap()
+    );
+    let entries = client.xrange("events", "-", "+", None).await.unwrap();
+    assert_eq!("7-1", entries[0].id);
+    assert_eq!(
+        vec![("kind".to_string(), "login".into())],
+        entries[0].fields
+    );
+    let pending = client
+        .xpending_range("events", "audit", "-", "+", 10, None)
+        .await
+        .unwrap();
+    assert_eq!("7-1", pending[0].id);
+    assert_eq!("alice", pending[0].consumer);
+    assert_eq!(1, pending[0].delivery_count);
+
+    // The key had 50ms left when saved, and expires after loading
+    time::sleep(Duration::from_millis(100)).await;
+    assert!(client.get("gone").await.unwrap().is_none());
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+
+    fs::remove_file(&path).unwrap();
+}
+
+/// `BGSAVE` writes the snapshot in the background, and rejects a second
+/// request while the first one is in progress.
+#[tokio::test]
+async fn rdb_bgsave() {
+    let path = temp_path("bgsave.rdb");
+
+    let (addr, shutdown, server) = start_server(rdb_config(&path)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    client.set("hello", "world".into()).await.unwrap();
+    client.bgsave().await.unwrap();
+
+    // The snapshot is renamed into place once complete
+    while !path.exists() {
+        time::sleep(Duration::from_millis(10)).await;
+    }
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+
+    let (addr, shutdown, server) = start_server(rdb_config(&path)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+    assert_eq!("world", client.get("hello").await.unwrap().unwrap());
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+
+    fs::remove_file(&path).unwrap();
+}
+
+/// A snapshot that does not match its checksum is not loaded.
+#[tokio::test]
+async fn rdb_corrupted_snapshot_rejected() {
+    let path = temp_path("corrupted.rdb");
+
+    let (addr, shutdown, server) = start_server(rdb_config(&path)).await;
+    let mut client = Client::connect(addr).await.unwrap();
+    client.set("hello", "world".into()).await.unwrap();
+    client.save().await.unwrap();
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+
+    let mut data = fs::read(&path).unwrap();
+    let last = data.len() - 6;
+    data[last] ^= 0xff;
+    fs::write(&path, data).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let (_tx, rx) = oneshot::channel::<()>();
+    assert!(server::run_with_config(listener, rdb_config(&path), rx)
+        .await
+        .is_err());
+
+    fs::remove_file(&path).unwrap();
+}
+
+/// Returns a path for a file that is unique to the test.
+fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("mini-redis-{}-{}", std::process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+/// Configuration appending commands to the append-only file at `path`.
+fn aof_config(path: &Path) -> Config {
+    Config {
+        appendonly: true,
+        appendfilename: path.to_path_buf(),
+        appendfsync: FsyncPolicy::Always,
+        ..Config::default()
+    }
+}
+
+/// Configuration saving snapshots to `path`.
+fn rdb_config(path: &Path) -> Config {
+    Config {
+        dbfilename: path.to_path_buf(),
+        ..Config::default()
+    }
+}
+
+async fn start_server(
+    config: Config,
+) -> (
+    SocketAddr,
+    oneshot::Sender<()>,
+    JoinHandle<mini_redis::Result<()>>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let (tx, rx) = oneshot::channel();
+    let handle = tokio::spawn(async move { server::run_with_config(listener, config, rx).await });
+
+    (addr, tx, handle)
+}
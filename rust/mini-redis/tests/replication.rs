@@ -0,0 +1,244 @@
+use mini_redis::{clients::Client, server, Config};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::time::{sleep, Instant};
+
+/// A replica receives the master's key space, then every write executed on
+/// the master. It rejects writes from clients until promoted to master.
+#[tokio::test]
+async fn replica_follows_master() {
+    let master_addr = start_server(Config::default()).await;
+    let mut master = Client::connect(master_addr).await.unwrap();
+
+    master.set("before", "sync".into()).await.unwrap();
+    master
+        .hset("user", vec![("name".into(), "alice".into())])
+        .await
+        .unwrap();
+
+    let config = Config {
+        replicaof: Some(("127.0.0.1".to_string(), master_addr.port())),
+        ..Config::default()
+    };
+    let replica_addr = start_server(config).await;
+    let mut replica = Client::connect(replica_addr).await.unwrap();
+
+    wait_for(&mut replica, "master_link_status", "up").await;
+
+    // Full synchronization
+    assert_eq!("sync", replica.get("before").await.unwrap().unwrap());
+    assert_eq!(
+        "alice",
+        replica.hget("user", "name").await.unwrap().unwrap()
+    );
+
+    // Writes executed on the master are streamed
+    master.set("after", "sync".into()).await.unwrap();
+    master
+        .rpush("list", vec!["a".into(), "b".into()])
+        .await
+        .unwrap();
+
+    let offset = info_field(&master.info(None).await.unwrap(), "master_repl_offset");
+    assert_ne!("0", offset);
+    wait_for(&mut replica, "slave_repl_offset", &offset).await;
+
+    assert_eq!("sync", replica.get("after").await.unwrap().unwrap());
+    assert_eq!(2, replica.llen("list").await.unwrap());
+
+    let info = master.info(Some("replication")).await.unwrap();
+    assert_eq!("master", info_field(&info, "role"));
+    assert_eq!("1", info_field(&info, "connected_slaves"));
+
+    // Writes from clients are rejected
+    let err = replica.set("after", "replica".into()).await.unwrap_err();
+    assert!(err.to_string().starts_with("READONLY"));
+
+    // Once promoted, the former replica accepts writes and keeps its data
+    replica.replicaof_no_one().await.unwrap();
+    replica.set("after", "promoted".into()).await.unwrap();
+
+    assert_eq!("promoted", replica.get("after").await.unwrap().unwrap());
+    assert_eq!("sync", replica.get("before").await.unwrap().unwrap());
+
+    let info = replica.info(None).await.unwrap();
+    assert_eq!("master", info_field(&info, "role"));
+}
+
+/// A replica rejects every command modifying the key space with `READONLY`,
+/// including inside transactions, while still serving reads.
+#[tokio::test]
+async fn replica_rejects_writes() {
+    let master_addr = start_server(Config::default()).await;
+    let mut master = Client::connect(master_addr).await.unwrap();
+    master.set("counter", "1".into()).await.unwrap();
+
+    let mut replica = start_replica(master_addr).await;
+    wait_for(&mut replica, "master_link_status", "up").await;
+    assert_eq!("1", replica.get("counter").await.unwrap().unwrap());
+
+    let errors = vec![
+        replica.incr("counter").await.unwrap_err(),
+        replica.del(&["counter".to_string()]).await.unwrap_err(),
+        replica
+            .expire("counter", Duration::from_secs(60), None)
+            .await
+            .unwrap_err(),
+        replica.rpush("list", vec!["a".into()]).await.unwrap_err(),
+        replica.flushall().await.unwrap_err(),
+    ];
+    for err in errors {
+        assert!(err.to_string().starts_with("READONLY"), "{}", err);
+    }
+
+    // A transaction holding a write is discarded as a whole
+    assert!(replica
+        .transaction()
+        .get("counter")
+        .set("counter", "2".into())
+        .exec()
+        .await
+        .is_err());
+
+    assert_eq!("1", replica.get("counter").await.unwrap().unwrap());
+    assert_eq!(-1, replica.ttl("counter").await.unwrap());
+    assert_eq!(1, replica.dbsize().await.unwrap());
+}
+
+/// Expirations are streamed as the time at which keys expire, and the
+/// commands of a transaction are applied together on the replica.
+#[tokio::test]
+async fn expirations_and_transactions_are_propagated() {
+    let master_addr = start_server(Config::default()).await;
+    let mut master = Client::connect(master_addr).await.unwrap();
+
+    let mut replica = start_replica(master_addr).await;
+    wait_for(&mut replica, "master_link_status", "up").await;
+
+    let hour = Duration::from_secs(3600);
+    master
+        .set_expires("session", "alice".into(), hour)
+        .await
+        .unwrap();
+    master.set("short", "lived".into()).await.unwrap();
+    master
+        .expire("short", Duration::from_millis(200), None)
+        .await
+        .unwrap();
+    master.set("kept", "value".into()).await.unwrap();
+    master.expire("kept", hour, None).await.unwrap();
+    master.persist("kept").await.unwrap();
+
+    let responses = master
+        .transaction()
+        .incrby("balance", 10)
+        .rpush("log", vec!["deposit".into()])
+        .incrby("balance", -3)
+        .exec()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(3, responses.len());
+
+    let offset = info_field(&master.info(None).await.unwrap(), "master_repl_offset");
+    wait_for(&mut replica, "slave_repl_offset", &offset).await;
+
+    let ttl = replica.pttl("session").await.unwrap();
+    assert!(ttl > 3_590_000 && ttl <= 3_600_000, "{}", ttl);
+    assert_eq!(-1, replica.ttl("kept").await.unwrap());
+    assert_eq!("7", replica.get("balance").await.unwrap().unwrap());
+    assert_eq!(vec!["deposit"], replica.lrange("log", 0, -1).await.unwrap());
+
+    // The key expires on the replica as well
+    sleep(Duration::from_millis(300)).await;
+    assert!(replica.get("short").await.unwrap().is_none());
+}
+
+/// `REPLICAOF` starts following a master at runtime, and `REPLICAOF NO ONE`
+/// stops: writes on the former master are no longer received.
+#[tokio::test]
+async fn replicaof_no_one_stops_following() {
+    let master_addr = start_server(Config::default()).await;
+    let mut master = Client::connect(master_addr).await.unwrap();
+    master.set("hello", "world".into()).await.unwrap();
+
+    let replica_addr = start_server(Config::default()).await;
+    let mut replica = Client::connect(replica_addr).await.unwrap();
+    replica.set("local", "data".into()).await.unwrap();
+
+    replica
+        .replicaof("127.0.0.1", master_addr.port())
+        .await
+        .unwrap();
+    wait_for(&mut replica, "master_link_status", "up").await;
+
+    // The key space of the master replaces the local one
+    assert_eq!("world", replica.get("hello").await.unwrap().unwrap());
+    assert!(replica.get("local").await.unwrap().is_none());
+
+    replica.replicaof_no_one().await.unwrap();
+    let info = replica.info(Some("replication")).await.unwrap();
+    assert_eq!("master", info_field(&info, "role"));
+
+    master.set("hello", "again".into()).await.unwrap();
+    wait_for(&mut master, "connected_slaves", "0").await;
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!("world", replica.get("hello").await.unwrap().unwrap());
+
+    replica.set("hello", "promoted".into()).await.unwrap();
+    assert_eq!("again", master.get("hello").await.unwrap().unwrap());
+}
+
+/// Returns the value of `field` in the output of `INFO`.
+fn info_field(info: &str, field: &str) -> String {
+    info.lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+        .unwrap_or_else(|| panic!("no field {} in {:?}", field, info))
+        .to_string()
+}
+
+/// Wait until `field` has the given value in the output of `INFO`.
+async fn wait_for(client: &mut Client, field: &str, value: &str) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+
+    loop {
+        let info = client.info(Some("replication")).await.unwrap();
+
+        if info
+            .lines()
+            .any(|line| line == format!("{}:{}", field, value))
+        {
+            return;
+        }
+
+        assert!(
+            Instant::now() < deadline,
+            "timed out, last INFO: {:?}",
+            info
+        );
+        sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Start a server following the master at `master_addr`, and connect to it.
+async fn start_replica(master_addr: SocketAddr) -> Client {
+    let config = Config {
+        replicaof: Some(("127.0.0.1".to_string(), master_addr.port())),
+        ..Config::default()
+    };
+    let addr = start_server(config).await;
+
+    Client::connect(addr).await.unwrap()
+}
+
+async fn start_server(config: Config) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        server::run_with_config(listener, config, tokio::signal::ctrl_c()).await
+    });
+
+    addr
+}
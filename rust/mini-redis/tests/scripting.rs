@@ -0,0 +1,116 @@
+use mini_redis::{clients::Client, server, Config, Frame};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// Allows up to `ARGV[1]` requests per key, counting them in a hash.
+const RATE_LIMITER: &str = r#"
+local count = redis.call('HINCRBY', KEYS[1], 'count', 1)
+if count > tonumber(ARGV[1]) then
+    return 0
+end
+return 1
+"#;
+
+/// A script calling commands through `redis.call` sees and modifies the key
+/// space, and its return value is converted to a reply.
+#[tokio::test]
+async fn eval_rate_limiter() {
+    let addr = start_server(Config::default()).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let keys = ["limit:alice".to_string()];
+
+    for _ in 0..3 {
+        let allowed = client
+            .eval(RATE_LIMITER, &keys, vec!["3".into()])
+            .await
+            .unwrap();
+        assert!(matches!(allowed, Frame::Integer(1)));
+    }
+
+    let allowed = client
+        .eval(RATE_LIMITER, &keys, vec!["3".into()])
+        .await
+        .unwrap();
+    assert!(matches!(allowed, Frame::Integer(0)));
+
+    let count = client.hget("limit:alice", "count").await.unwrap().unwrap();
+    assert_eq!("4", count);
+
+    // Errors raised by commands are returned to the client
+    let script = "return redis.call('GET', KEYS[1])";
+    let err = client.eval(script, &keys, vec![]).await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"));
+
+    // Tables are returned as arrays
+    let script = "redis.call('SET', KEYS[1], 'x') return {KEYS[1], redis.call('GET', KEYS[1])}";
+    let keys = ["greeting".to_string()];
+    let reply = client.eval(script, &keys, vec![]).await.unwrap();
+    match reply {
+        Frame::Array(frames) => {
+            assert_eq!(2, frames.len());
+            assert_eq!(frames[0], "greeting");
+            assert_eq!(frames[1], "x");
+        }
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+/// Scripts are cached by SHA1 digest, and can be run with `EVALSHA` until the
+/// cache is flushed.
+#[tokio::test]
+async fn script_cache() {
+    let addr = start_server(Config::default()).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let sha1 = client.script_load("return ARGV[1]").await.unwrap();
+    assert_eq!(40, sha1.len());
+
+    let reply = client.evalsha(&sha1, &[], vec!["hi".into()]).await.unwrap();
+    assert_eq!(reply, "hi");
+
+    let unknown = "0000000000000000000000000000000000000000".to_string();
+    let exists = client
+        .script_exists(&[sha1.clone(), unknown])
+        .await
+        .unwrap();
+    assert_eq!(vec![true, false], exists);
+
+    client.script_flush().await.unwrap();
+
+    let err = client.evalsha(&sha1, &[], vec![]).await.unwrap_err();
+    assert!(err.to_string().starts_with("NOSCRIPT"));
+}
+
+/// A script running for longer than the configured limit is aborted, and the
+/// server keeps processing commands.
+#[tokio::test]
+async fn script_time_limit() {
+    let config = Config {
+        lua_time_limit: Duration::from_millis(50),
+        ..Config::default()
+    };
+    let addr = start_server(config).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let err = client
+        .eval("while true do end", &[], vec![])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("time limit"));
+
+    client.set("hello", "world".into()).await.unwrap();
+    assert_eq!("world", client.get("hello").await.unwrap().unwrap());
+}
+
+async fn start_server(config: Config) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        server::run_with_config(listener, config, tokio::signal::ctrl_c()).await
+    });
+
+    addr
+}
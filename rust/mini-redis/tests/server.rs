@@ -331,6 +331,81 @@ async fn manage_subscription() {
     );
 }
 
+/// Pattern subscriptions receive `pmessage` frames, and count towards the same
+/// total as channel subscriptions.
+#[tokio::test]
+async fn pattern_subscription() {
+    let addr = start_server().await;
+
+    let mut publisher = TcpStream::connect(addr).await.unwrap();
+
+    let mut sub = TcpStream::connect(addr).await.unwrap();
+    sub.write_all(b"*2\r\n$9\r\nSUBSCRIBE\r\n$8\r\norders.1\r\n")
+        .await
+        .unwrap();
+
+    let mut response = [0; 37];
+    sub.read_exact(&mut response).await.unwrap();
+    assert_eq!(
+        &b"*3\r\n$9\r\nsubscribe\r\n$8\r\norders.1\r\n:1\r\n"[..],
+        &response[..]
+    );
+
+    sub.write_all(b"*2\r\n$10\r\nPSUBSCRIBE\r\n$8\r\norders.*\r\n")
+        .await
+        .unwrap();
+
+    let mut response = [0; 39];
+    sub.read_exact(&mut response).await.unwrap();
+    assert_eq!(
+        &b"*3\r\n$10\r\npsubscribe\r\n$8\r\norders.*\r\n:2\r\n"[..],
+        &response[..]
+    );
+
+    // The message is delivered once for the channel, and once for the pattern
+    publisher
+        .write_all(b"*3\r\n$7\r\nPUBLISH\r\n$8\r\norders.1\r\n$3\r\nnew\r\n")
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    publisher.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":2\r\n", &response);
+
+    // The two subscriptions are independent, so the frames may arrive in any
+    // order
+    let message = &b"*3\r\n$7\r\nmessage\r\n$8\r\norders.1\r\n$3\r\nnew\r\n"[..];
+    let pmessage =
+        &b"*4\r\n$8\r\npmessage\r\n$8\r\norders.*\r\n$8\r\norders.1\r\n$3\r\nnew\r\n"[..];
+
+    let mut response = [0; 95];
+    sub.read_exact(&mut response).await.unwrap();
+    assert!(
+        response[..] == [message, pmessage].concat()[..]
+            || response[..] == [pmessage, message].concat()[..]
+    );
+
+    // Channels not matching the pattern are not received
+    publisher
+        .write_all(b"*3\r\n$7\r\nPUBLISH\r\n$6\r\norders\r\n$3\r\nnew\r\n")
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    publisher.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":0\r\n", &response);
+
+    // Unsubscribe from all patterns
+    sub.write_all(b"*1\r\n$12\r\npunsubscribe\r\n")
+        .await
+        .unwrap();
+
+    let mut response = [0; 41];
+    sub.read_exact(&mut response).await.unwrap();
+    assert_eq!(
+        &b"*3\r\n$12\r\npunsubscribe\r\n$8\r\norders.*\r\n:1\r\n"[..],
+        &response[..]
+    );
+}
+
 // In this case we test that server Responds with an Error message if a client
 // sends an unknown command
 #[tokio::test]
@@ -397,6 +472,195 @@ async fn send_error_get_set_after_subscribe() {
     assert_eq!(b"-ERR unknown command \'get\'\r\n", &response);
 }
 
+/// Switching to RESP3 with `HELLO 3` changes the encoding of replies. The
+/// server summary is sent as a map, and a missing key is the RESP3 null.
+#[tokio::test]
+async fn hello_switches_to_resp3() {
+    let addr = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    // A missing key is the RESP2 null bulk string
+    stream
+        .write_all(b"*2\r\n$3\r\nGET\r\n$5\r\nhello\r\n")
+        .await
+        .unwrap();
+
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"$-1\r\n", &response);
+
+    // Switch to RESP3
+    stream
+        .write_all(b"*2\r\n$5\r\nHELLO\r\n$1\r\n3\r\n")
+        .await
+        .unwrap();
+
+    let version = env!("CARGO_PKG_VERSION");
+    let expected = format!(
+        "%6\r\n\
+         $6\r\nserver\r\n$10\r\nmini-redis\r\n\
+         $7\r\nversion\r\n${}\r\n{}\r\n\
+         $5\r\nproto\r\n:3\r\n\
+         $4\r\nmode\r\n$10\r\nstandalone\r\n\
+         $4\r\nrole\r\n$6\r\nmaster\r\n\
+         $7\r\nmodules\r\n*0\r\n",
+        version.len(),
+        version
+    );
+
+    let mut response = vec![0; expected.len()];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected.as_bytes(), &response[..]);
+
+    // A missing key is now the RESP3 null
+    stream
+        .write_all(b"*2\r\n$3\r\nGET\r\n$5\r\nhello\r\n")
+        .await
+        .unwrap();
+
+    let mut response = [0; 3];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"_\r\n", &response);
+
+    // Unsupported protocol versions are rejected
+    stream
+        .write_all(b"*2\r\n$5\r\nHELLO\r\n$1\r\n4\r\n")
+        .await
+        .unwrap();
+
+    let mut response = [0; 39];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"-NOPROTO unsupported protocol version\r\n", &response);
+}
+
+/// A connection blocked in `BLMOVE` is woken by a push from another
+/// connection. The element is atomically moved to the destination list.
+#[tokio::test]
+async fn blmove_wakes_on_push() {
+    let addr = start_server().await;
+
+    let mut blocked = TcpStream::connect(addr).await.unwrap();
+    blocked
+        .write_all(b"*6\r\n$6\r\nBLMOVE\r\n$3\r\nsrc\r\n$3\r\ndst\r\n$5\r\nRIGHT\r\n$4\r\nLEFT\r\n$1\r\n0\r\n")
+        .await
+        .unwrap();
+
+    // Nothing is received while the source list is empty
+    let mut response = [0; 7];
+    time::timeout(
+        Duration::from_millis(100),
+        blocked.read_exact(&mut response),
+    )
+    .await
+    .unwrap_err();
+
+    let mut pusher = TcpStream::connect(addr).await.unwrap();
+    pusher
+        .write_all(b"*3\r\n$5\r\nRPUSH\r\n$3\r\nsrc\r\n$3\r\njob\r\n")
+        .await
+        .unwrap();
+
+    let mut response = [0; 4];
+    pusher.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":1\r\n", &response);
+
+    // The blocked connection receives the moved element
+    let mut response = [0; 9];
+    blocked.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"$3\r\njob\r\n", &response);
+
+    pusher
+        .write_all(b"*4\r\n$6\r\nLRANGE\r\n$3\r\ndst\r\n$1\r\n0\r\n$2\r\n-1\r\n")
+        .await
+        .unwrap();
+
+    let mut response = [0; 13];
+    pusher.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"*1\r\n$3\r\njob\r\n", &response);
+}
+
+/// `ZRANGEBYSCORE` supports exclusive bounds, `WITHSCORES` and `LIMIT`. RESP2
+/// connections receive scores as bulk strings.
+#[tokio::test]
+async fn zrangebyscore_options() {
+    let addr = start_server().await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream
+        .write_all(b"*8\r\n$4\r\nZADD\r\n$3\r\nset\r\n$1\r\n1\r\n$1\r\na\r\n$1\r\n2\r\n$1\r\nb\r\n$3\r\n2.5\r\n$1\r\nc\r\n")
+        .await
+        .unwrap();
+
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":3\r\n", &response);
+
+    stream
+        .write_all(b"*8\r\n$13\r\nZRANGEBYSCORE\r\n$3\r\nset\r\n$2\r\n(1\r\n$4\r\n+inf\r\n$10\r\nWITHSCORES\r\n$5\r\nLIMIT\r\n$1\r\n1\r\n$1\r\n5\r\n")
+        .await
+        .unwrap();
+
+    let expected = b"*2\r\n$1\r\nc\r\n$3\r\n2.5\r\n";
+    let mut response = [0; 20];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+}
+
+/// Commands are queued between `MULTI` and `EXEC`. A command rejected while
+/// queuing causes `EXEC` to discard the transaction.
+#[tokio::test]
+async fn multi_exec_discard() {
+    let addr = start_server().await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream.write_all(b"*1\r\n$4\r\nEXEC\r\n").await.unwrap();
+
+    let expected = b"-ERR EXEC without MULTI\r\n";
+    let mut response = [0; 25];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(&expected[..], &response[..]);
+
+    stream
+        .write_all(b"*1\r\n$5\r\nMULTI\r\n*3\r\n$3\r\nSET\r\n$5\r\nhello\r\n$5\r\nworld\r\n*2\r\n$3\r\nGET\r\n$5\r\nhello\r\n")
+        .await
+        .unwrap();
+
+    let expected = b"+OK\r\n+QUEUED\r\n+QUEUED\r\n";
+    let mut response = [0; 23];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(&expected[..], &response[..]);
+
+    stream.write_all(b"*1\r\n$4\r\nEXEC\r\n").await.unwrap();
+
+    let expected = b"*2\r\n+OK\r\n$5\r\nworld\r\n";
+    let mut response = [0; 20];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(&expected[..], &response[..]);
+
+    // An unknown command aborts the transaction
+    stream
+        .write_all(b"*1\r\n$5\r\nMULTI\r\n*1\r\n$4\r\nFOOO\r\n*1\r\n$4\r\nEXEC\r\n")
+        .await
+        .unwrap();
+
+    let expected = b"+OK\r\n-ERR unknown command 'fooo'\r\n-EXECABORT Transaction discarded because of previous errors.\r\n";
+    let mut response = [0; 96];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(&expected[..], &response[..]);
+
+    // Discarded commands are not executed
+    stream
+        .write_all(b"*1\r\n$5\r\nMULTI\r\n*3\r\n$3\r\nSET\r\n$5\r\nhello\r\n$4\r\nmoon\r\n*1\r\n$7\r\nDISCARD\r\n*2\r\n$3\r\nGET\r\n$5\r\nhello\r\n")
+        .await
+        .unwrap();
+
+    let expected = b"+OK\r\n+QUEUED\r\n+OK\r\n$5\r\nworld\r\n";
+    let mut response = [0; 30];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(&expected[..], &response[..]);
+}
+
 async fn start_server() -> SocketAddr {
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
     let addr = listener.local_addr().unwrap();
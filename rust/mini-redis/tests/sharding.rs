@@ -0,0 +1,128 @@
+use mini_redis::{clients::Client, server, Config};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::time;
+
+/// Commands on the whole key space see the keys of every shard.
+#[tokio::test]
+async fn key_space_spans_all_shards() {
+    let addr = start_server(8).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    for i in 0..100 {
+        client
+            .set(&format!("key:{}", i), "value".into())
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(100, client.dbsize().await.unwrap());
+    assert_eq!(100, client.keys("key:*").await.unwrap().len());
+
+    let mut scanned = HashSet::new();
+    let mut cursor = 0;
+    loop {
+        let (next, keys) = client.scan(cursor, None, Some(10), None).await.unwrap();
+        scanned.extend(keys);
+
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+    assert_eq!(100, scanned.len());
+
+    client.flushdb().await.unwrap();
+    assert_eq!(0, client.dbsize().await.unwrap());
+}
+
+/// Commands accessing keys of several shards are atomic: a key renamed back
+/// and forth by one connection is always found under exactly one of its names
+/// by another connection.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn multi_key_commands_are_atomic_across_shards() {
+    let addr = start_server(64).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let names: Vec<String> = (0..8).map(|i| format!("name:{}", i)).collect();
+    client.set(&names[0], "value".into()).await.unwrap();
+
+    let renames = tokio::spawn({
+        let names = names.clone();
+        async move {
+            let mut client = Client::connect(addr).await.unwrap();
+
+            for i in 0..500 {
+                let from = &names[i % names.len()];
+                let to = &names[(i + 1) % names.len()];
+                client.rename(from, to).await.unwrap();
+            }
+        }
+    });
+
+    while !renames.is_finished() {
+        assert_eq!(1, client.exists(&names).await.unwrap());
+    }
+    renames.await.unwrap();
+
+    assert_eq!(1, client.exists(&names).await.unwrap());
+}
+
+/// Blocking pops and `WATCH` only lock the shards of their keys, and still
+/// see modifications made to any of them.
+#[tokio::test]
+async fn blocking_and_watch_span_shards() {
+    let addr = start_server(64).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let keys: Vec<String> = (0..8).map(|i| format!("list:{}", i)).collect();
+
+    let blocked = tokio::spawn({
+        let keys = keys.clone();
+        async move {
+            let mut client = Client::connect(addr).await.unwrap();
+            client.blpop(&keys, Duration::from_secs(5)).await.unwrap()
+        }
+    });
+
+    // Other keys are served while the connection is blocked
+    time::sleep(Duration::from_millis(50)).await;
+    client.set("other", "value".into()).await.unwrap();
+    assert!(!blocked.is_finished());
+
+    client.rpush(&keys[7], vec!["job".into()]).await.unwrap();
+    let (key, value) = blocked.await.unwrap().unwrap();
+    assert_eq!(keys[7], key);
+    assert_eq!("job", value);
+
+    client.watch(&keys).await.unwrap();
+    let mut other = Client::connect(addr).await.unwrap();
+    other.rpush(&keys[5], vec!["job".into()]).await.unwrap();
+
+    let responses = client
+        .transaction()
+        .rpush(&keys[0], vec!["job".into()])
+        .exec()
+        .await
+        .unwrap();
+    assert!(responses.is_none());
+    assert_eq!(0, client.llen(&keys[0]).await.unwrap());
+}
+
+async fn start_server(shards: usize) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let config = Config {
+        shards,
+        ..Config::default()
+    };
+
+    tokio::spawn(async move {
+        server::run_with_config(listener, config, std::future::pending::<()>()).await
+    });
+
+    addr
+}
@@ -0,0 +1,228 @@
+use mini_redis::clients::{BlockingClient, Client};
+use mini_redis::{server, tls, Config};
+
+use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::time::{sleep, Instant};
+
+/// A server with a certificate only accepts TLS connections.
+#[tokio::test]
+async fn server_only_accepts_tls() {
+    let certs = Certs::generate("only-tls");
+    let addr = start_server(certs.server_config()).await;
+
+    let config = tls::client_config(&certs.ca, None).unwrap();
+    let mut client = Client::connect_tls(addr, "localhost", config)
+        .await
+        .unwrap();
+
+    client.set("hello", "world".into()).await.unwrap();
+    assert_eq!("world", client.get("hello").await.unwrap().unwrap());
+
+    // The plaintext request fails the handshake
+    let mut client = Client::connect(addr).await.unwrap();
+    assert!(client.ping(None).await.is_err());
+
+    certs.remove();
+}
+
+/// The certificate of the server must be valid for the name the client
+/// connects to, and issued by an authority the client trusts.
+#[tokio::test]
+async fn client_verifies_server() {
+    let certs = Certs::generate("verify-server");
+    let other = Certs::generate("verify-server-other");
+    let addr = start_server(certs.server_config()).await;
+
+    let config = tls::client_config(&certs.ca, None).unwrap();
+    assert!(Client::connect_tls(addr, "example.com", config)
+        .await
+        .is_err());
+
+    let config = tls::client_config(&other.ca, None).unwrap();
+    assert!(Client::connect_tls(addr, "localhost", config)
+        .await
+        .is_err());
+
+    certs.remove();
+    other.remove();
+}
+
+/// With `tls_auth_clients`, only clients presenting a certificate issued by
+/// the configured authority may send commands.
+#[tokio::test]
+async fn server_verifies_clients() {
+    let certs = Certs::generate("verify-clients");
+    let addr = start_server(Config {
+        tls_auth_clients: true,
+        ..certs.server_config()
+    })
+    .await;
+
+    let config = tls::client_config(&certs.ca, None).unwrap();
+    let refused = async {
+        let mut client = Client::connect_tls(addr, "localhost", config).await?;
+        client.ping(None).await
+    };
+    assert!(refused.await.is_err());
+
+    let config = tls::client_config(&certs.ca, Some((&certs.cert, &certs.key))).unwrap();
+    let mut client = Client::connect_tls(addr, "localhost", config)
+        .await
+        .unwrap();
+    assert_eq!(b"PONG", &client.ping(None).await.unwrap()[..]);
+
+    certs.remove();
+}
+
+/// The blocking client connects the same way as the asynchronous one.
+#[test]
+fn blocking_client_connects_with_tls() {
+    let certs = Certs::generate("blocking");
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let addr = rt.block_on(start_server(certs.server_config()));
+
+    let config = tls::client_config(&certs.ca, None).unwrap();
+    let mut client = BlockingClient::connect_tls(addr, "127.0.0.1", config).unwrap();
+
+    client.set("hello", "world".into()).unwrap();
+    assert_eq!("world", client.get("hello").unwrap().unwrap());
+
+    certs.remove();
+}
+
+/// With `tls_replication`, a replica follows a master verifying its clients.
+#[tokio::test]
+async fn replica_follows_master_over_tls() {
+    let certs = Certs::generate("replication");
+    let master_addr = start_server(Config {
+        tls_auth_clients: true,
+        ..certs.server_config()
+    })
+    .await;
+
+    let identity = Some((certs.cert.as_path(), certs.key.as_path()));
+    let config = tls::client_config(&certs.ca, identity).unwrap();
+    let mut master = Client::connect_tls(master_addr, "localhost", config.clone())
+        .await
+        .unwrap();
+    master.set("hello", "world".into()).await.unwrap();
+
+    let replica_addr = start_server(Config {
+        replicaof: Some(("127.0.0.1".to_string(), master_addr.port())),
+        tls_replication: true,
+        ..certs.server_config()
+    })
+    .await;
+    let mut replica = Client::connect_tls(replica_addr, "localhost", config)
+        .await
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while replica.get("hello").await.unwrap().is_none() {
+        assert!(Instant::now() < deadline, "timed out waiting for the sync");
+        sleep(Duration::from_millis(10)).await;
+    }
+
+    certs.remove();
+}
+
+/// The server does not start with incomplete TLS settings.
+#[tokio::test]
+async fn invalid_settings_are_rejected() {
+    let certs = Certs::generate("invalid");
+
+    let configs = [
+        Config {
+            tls_key_file: None,
+            ..certs.server_config()
+        },
+        Config {
+            tls_ca_cert_file: None,
+            tls_auth_clients: true,
+            ..certs.server_config()
+        },
+        Config {
+            tls_key_file: Some(certs.cert.clone()),
+            ..certs.server_config()
+        },
+    ];
+
+    for config in configs {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        assert!(server::run_with_config(listener, config, async {})
+            .await
+            .is_err());
+    }
+
+    certs.remove();
+}
+
+/// PEM files of a certificate authority, and of a certificate it issued for
+/// `localhost` and `127.0.0.1`, used by both the servers and the clients.
+struct Certs {
+    ca: PathBuf,
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+impl Certs {
+    fn generate(name: &str) -> Certs {
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_key = KeyPair::generate().unwrap();
+        let ca = params.self_signed(&ca_key).unwrap();
+
+        let params =
+            CertificateParams::new(vec!["localhost".to_string(), "127.0.0.1".to_string()]).unwrap();
+        let key = KeyPair::generate().unwrap();
+        let cert = params.signed_by(&key, &ca, &ca_key).unwrap();
+
+        let certs = Certs {
+            ca: temp_path(&format!("{}-ca.crt", name)),
+            cert: temp_path(&format!("{}.crt", name)),
+            key: temp_path(&format!("{}.key", name)),
+        };
+        fs::write(&certs.ca, ca.pem()).unwrap();
+        fs::write(&certs.cert, cert.pem()).unwrap();
+        fs::write(&certs.key, key.serialize_pem()).unwrap();
+
+        certs
+    }
+
+    /// Configuration of a server presenting the certificate.
+    fn server_config(&self) -> Config {
+        Config {
+            tls_cert_file: Some(self.cert.clone()),
+            tls_key_file: Some(self.key.clone()),
+            tls_ca_cert_file: Some(self.ca.clone()),
+            ..Config::default()
+        }
+    }
+
+    fn remove(&self) {
+        for path in [&self.ca, &self.cert, &self.key] {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Returns a path for a file that is unique to the test.
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("mini-redis-{}-{}", std::process::id(), name))
+}
+
+async fn start_server(config: Config) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        server::run_with_config(listener, config, std::future::pending::<()>()).await
+    });
+
+    addr
+}
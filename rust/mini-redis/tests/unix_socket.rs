@@ -0,0 +1,132 @@
+use mini_redis::clients::{BlockingClient, Client};
+use mini_redis::{server, Config};
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+/// The server listens on the Unix socket in addition to the TCP port, and
+/// both see the same data.
+#[tokio::test]
+async fn unix_socket_alongside_tcp() {
+    let path = temp_path("alongside.sock");
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let config = Config {
+        unixsocket: Some(path.clone()),
+        unixsocketperm: Some(0o700),
+        ..Config::default()
+    };
+    tokio::spawn(async move {
+        server::run_with_config(listener, config, std::future::pending::<()>()).await
+    });
+
+    let mut tcp = Client::connect(addr).await.unwrap();
+    let mut unix = Client::connect_unix(&path).await.unwrap();
+
+    unix.set("hello", "world".into()).await.unwrap();
+    assert_eq!("world", tcp.get("hello").await.unwrap().unwrap());
+
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(0o700, mode & 0o777);
+
+    let mut client = Client::connect_addr(&format!("unix://{}", path.display()))
+        .await
+        .unwrap();
+    assert_eq!("world", client.get("hello").await.unwrap().unwrap());
+
+    let mut client = Client::connect_addr(&addr.to_string()).await.unwrap();
+    assert_eq!("world", client.get("hello").await.unwrap().unwrap());
+}
+
+/// Without a TCP listener, the server only listens on the Unix socket, which
+/// replaces a stale socket on startup and is removed on shutdown.
+#[tokio::test]
+async fn unix_socket_only() {
+    let path = temp_path("only.sock");
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+    let config = Config {
+        unixsocket: Some(path.clone()),
+        ..Config::default()
+    };
+    let (tx, rx) = oneshot::channel::<()>();
+    let server = tokio::spawn(server::run_unix(config, rx));
+
+    let mut client = wait_for_socket(&path).await;
+    client.set("hello", "world".into()).await.unwrap();
+    assert_eq!("0", client.config_get("port").await.unwrap()["port"]);
+    assert_eq!(
+        path.display().to_string(),
+        client.config_get("unixsocket").await.unwrap()["unixsocket"]
+    );
+    drop(client);
+
+    tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+    assert!(!path.exists());
+}
+
+/// The blocking client connects to the Unix socket the same way as the
+/// asynchronous one.
+#[test]
+fn blocking_client_connects_to_unix_socket() {
+    let path = temp_path("blocking.sock");
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let config = Config {
+        unixsocket: Some(path.clone()),
+        ..Config::default()
+    };
+    rt.spawn(server::run_unix(config, std::future::pending::<()>()));
+    rt.block_on(wait_for_socket(&path));
+
+    let mut client = BlockingClient::connect_unix(&path).unwrap();
+    client.set("hello", "world".into()).unwrap();
+
+    let mut client = BlockingClient::connect_addr(&format!("unix://{}", path.display())).unwrap();
+    assert_eq!("world", client.get("hello").unwrap().unwrap());
+}
+
+/// A server without a TCP listener needs a Unix socket, and cannot run as a
+/// cluster node.
+#[tokio::test]
+async fn unix_socket_only_settings_are_checked() {
+    assert!(server::run_unix(Config::default(), async {}).await.is_err());
+
+    let config = Config {
+        unixsocket: Some(temp_path("cluster.sock")),
+        cluster_enabled: true,
+        ..Config::default()
+    };
+    assert!(server::run_unix(config, async {}).await.is_err());
+
+    let mut config = Config::default();
+    assert!(config.set("unixsocketperm", "778").is_err());
+    assert!(config.set("unixsocketperm", "1777").is_err());
+    config.set("unixsocketperm", "770").unwrap();
+    assert_eq!(Some(0o770), config.unixsocketperm);
+    assert_eq!("770", config.get("unixsocketperm").unwrap());
+}
+
+/// Connect to the Unix socket at `path` once the server listens on it.
+async fn wait_for_socket(path: &Path) -> Client {
+    for _ in 0..100 {
+        if let Ok(client) = Client::connect_unix(path).await {
+            return client;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    panic!("the server is not listening on {:?}", path);
+}
+
+/// Returns a path for a file that is unique to the test.
+fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("mini-redis-{}-{}", std::process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+}